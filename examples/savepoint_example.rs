@@ -68,11 +68,15 @@ fn main() -> Result<()> {
     db.execute("ROLLBACK TO SAVEPOINT after_first_transfer")?;
     println!("✓ Rolled back to savepoint\n");
 
-    // Query balances after rollback
-    // Note: In this implementation, savepoints are parsed but not yet enforced
-    // The actual rollback behavior will be implemented in a future update
-    println!("Note: Savepoints are currently parsed and accepted.");
-    println!("Full transaction state tracking will be implemented in a future update.\n");
+    // Query balances after rollback: the second transfer is undone
+    let result = db.execute("SELECT * FROM accounts")?;
+    if let ExecutionResult::Select { rows, .. } = result {
+        println!("Balances after rollback:");
+        for row in &rows {
+            println!("  Account {}: balance = {}", row[0], row[2]);
+        }
+        println!();
+    }
 
     // Release a savepoint (commits it)
     db.execute("RELEASE SAVEPOINT before_transfer")?;
@@ -15,6 +15,7 @@ pub mod logging;
 pub mod orm;
 pub mod optimizer;
 pub mod permissions;
+pub mod statement;
 #[cfg(feature = "server")]
 pub mod server;
 mod traits;
@@ -22,6 +23,7 @@ mod types;
 pub mod utility;
 
 pub use constants::*;
+pub use types::ValueType;
 
 #[derive(Debug)]
 pub struct EPLite {}
@@ -1,6 +1,7 @@
 /// Command processing module - responsible for parsing and execution of SQL statements
 
 pub mod code_generator;
+pub mod expression;
 pub mod parser;
 pub mod processor;
 pub mod tokenizer;
@@ -0,0 +1,849 @@
+/// SQL expressions - the parse tree of conditions and selected values, and
+/// its evaluation against a row
+///
+/// Evaluation follows SQLite: NULL stands for an unknown value, so most
+/// operators given a NULL return NULL, and AND, OR and NOT use three-valued
+/// logic. A WHERE or ON condition only holds when it is true, not NULL.
+/// Aggregate functions have a value only over a group of rows.
+
+use crate::eplite::command::parser::{AggregateFunction, ColumnDefinition};
+use crate::eplite::error::{Error, Result};
+use crate::eplite::persistence::record;
+use crate::eplite::storage::Row;
+use crate::eplite::types::column::Affinity;
+use crate::eplite::types::ValueType;
+use core::cmp::Ordering;
+
+#[cfg(not(feature = "std"))]
+use alloc::{
+	boxed::Box,
+	format,
+	string::{String, ToString},
+	vec,
+	vec::Vec,
+};
+
+/// Operators taking one operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+	/// `-x`
+	Negate,
+	/// `+x`, which leaves its operand as it is
+	Plus,
+	/// `NOT x`
+	Not,
+}
+
+/// Operators taking two operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+	Or,
+	And,
+	Equal,
+	NotEqual,
+	/// `IS`, which treats two NULLs as equal
+	Is,
+	IsNot,
+	LessThan,
+	LessOrEqual,
+	GreaterThan,
+	GreaterOrEqual,
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+	Modulo,
+	/// `||`
+	Concat,
+}
+
+impl BinaryOperator {
+	/// How tightly the operator binds, as in SQLite; higher binds tighter
+	pub fn precedence(&self) -> u8 {
+		match self {
+			BinaryOperator::Or => 1,
+			BinaryOperator::And => 2,
+			BinaryOperator::Equal | BinaryOperator::NotEqual | BinaryOperator::Is | BinaryOperator::IsNot => 4,
+			BinaryOperator::LessThan
+			| BinaryOperator::LessOrEqual
+			| BinaryOperator::GreaterThan
+			| BinaryOperator::GreaterOrEqual => 5,
+			BinaryOperator::Add | BinaryOperator::Subtract => 6,
+			BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 7,
+			BinaryOperator::Concat => 8,
+		}
+	}
+}
+
+/// Precedence of NOT, between AND and the equality operators
+pub const NOT_PRECEDENCE: u8 = 3;
+/// Precedence of IN, LIKE and BETWEEN, the same as `=`
+pub const MATCH_PRECEDENCE: u8 = 4;
+/// Precedence of unary minus and plus, above every binary operator
+pub const UNARY_PRECEDENCE: u8 = 9;
+
+/// An expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+	Literal(ValueType),
+	/// A column, optionally qualified by its table
+	Column {
+		table: Option<String>,
+		name: String,
+	},
+	/// A double-quoted name: the column of that name, or else the text
+	/// itself, as in SQLite
+	Quoted(String),
+	/// A parameter of a prepared statement by its index from 1, which is
+	/// NULL until a value is bound to it
+	Parameter(usize),
+	Unary {
+		op: UnaryOperator,
+		operand: Box<Expr>,
+	},
+	Binary {
+		left: Box<Expr>,
+		op: BinaryOperator,
+		right: Box<Expr>,
+	},
+	/// `expr [NOT] BETWEEN low AND high`
+	Between {
+		expr: Box<Expr>,
+		low: Box<Expr>,
+		high: Box<Expr>,
+		negated: bool,
+	},
+	/// `expr [NOT] IN (list)`
+	InList {
+		expr: Box<Expr>,
+		list: Vec<Expr>,
+		negated: bool,
+	},
+	/// `expr [NOT] LIKE pattern`
+	Like {
+		expr: Box<Expr>,
+		pattern: Box<Expr>,
+		negated: bool,
+	},
+	/// `CASE [operand] WHEN .. THEN .. [ELSE ..] END`: the result of the
+	/// first branch whose condition is true, or that equals the operand
+	Case {
+		operand: Option<Box<Expr>>,
+		branches: Vec<(Expr, Expr)>,
+		otherwise: Option<Box<Expr>>,
+	},
+	/// An aggregate function of a value over the rows of a group, only of
+	/// distinct values if asked; `COUNT(*)` has no argument
+	Aggregate {
+		function: AggregateFunction,
+		argument: Option<Box<Expr>>,
+		distinct: bool,
+	},
+}
+
+/// The columns of the rows an expression is evaluated against, in the
+/// order of the row's values, each with the table it belongs to
+#[derive(Debug, Clone, Default)]
+pub struct Scope<'a> {
+	columns: Vec<(&'a str, &'a ColumnDefinition)>,
+}
+
+impl<'a> Scope<'a> {
+	/// The columns of one table
+	pub fn new(table: &'a str, columns: &'a [ColumnDefinition]) -> Self {
+		Scope::default().join(table, columns)
+	}
+
+	/// Add a table's columns after those already in scope, as a join does
+	pub fn join(mut self, table: &'a str, columns: &'a [ColumnDefinition]) -> Self {
+		self.columns.extend(columns.iter().map(|column| (table, column)));
+		self
+	}
+
+	/// Position in the row of a column, or None if there is no such column;
+	/// a name without a table must not be in more than one table
+	fn find(&self, table: Option<&str>, name: &str) -> Result<Option<usize>> {
+		let mut found = None;
+		for (i, (column_table, column)) in self.columns.iter().enumerate() {
+			if !column.name.eq_ignore_ascii_case(name) || table.is_some_and(|t| !t.eq_ignore_ascii_case(column_table)) {
+				continue;
+			}
+			if found.is_some() {
+				return Err(Error::Syntax(format!("Ambiguous column name: {}", name)));
+			}
+			found = Some(i);
+		}
+		Ok(found)
+	}
+
+	/// Name of each column in scope, or of those of one table, with an
+	/// expression that reads it, as `*` and `table.*` select them
+	pub fn expand(&self, table: Option<&str>) -> Result<Vec<(String, Expr)>> {
+		let columns: Vec<(String, Expr)> = self
+			.columns
+			.iter()
+			.filter(|(column_table, _)| table.is_none_or(|t| t.eq_ignore_ascii_case(column_table)))
+			.map(|(column_table, column)| {
+				let expr = Expr::Column { table: Some(column_table.to_string()), name: column.name.clone() };
+				(column.name.clone(), expr)
+			})
+			.collect();
+		match table {
+			Some(table) if columns.is_empty() => Err(Error::NotFound(format!("Table '{}' not found", table))),
+			_ => Ok(columns),
+		}
+	}
+
+	/// Position in the row of a column
+	pub fn position(&self, table: Option<&str>, name: &str) -> Result<usize> {
+		self.find(table, name)?.ok_or_else(|| match table {
+			Some(table) => Error::NotFound(format!("Column '{}.{}' not found", table, name)),
+			None => Error::NotFound(format!("Column '{}' not found", name)),
+		})
+	}
+}
+
+impl Expr {
+	pub fn column(name: &str) -> Self {
+		Expr::Column { table: None, name: name.to_string() }
+	}
+
+	pub fn binary(left: Expr, op: BinaryOperator, right: Expr) -> Self {
+		Expr::Binary { left: Box::new(left), op, right: Box::new(right) }
+	}
+
+	/// Check that every column the expression names is in scope, so a bad
+	/// name fails a statement even when there are no rows to test
+	pub fn resolve(&self, scope: &Scope) -> Result<()> {
+		match self {
+			Expr::Literal(_) | Expr::Quoted(_) | Expr::Parameter(_) => Ok(()),
+			Expr::Column { table, name } => scope.position(table.as_deref(), name).map(|_| ()),
+			Expr::Unary { operand, .. } => operand.resolve(scope),
+			Expr::Binary { left, right, .. } => {
+				left.resolve(scope)?;
+				right.resolve(scope)
+			}
+			Expr::Between { expr, low, high, .. } => {
+				expr.resolve(scope)?;
+				low.resolve(scope)?;
+				high.resolve(scope)
+			}
+			Expr::InList { expr, list, .. } => {
+				expr.resolve(scope)?;
+				list.iter().try_for_each(|item| item.resolve(scope))
+			}
+			Expr::Like { expr, pattern, .. } => {
+				expr.resolve(scope)?;
+				pattern.resolve(scope)
+			}
+			Expr::Case { operand, branches, otherwise } => {
+				operand.iter().chain(otherwise).try_for_each(|expr| expr.resolve(scope))?;
+				branches.iter().try_for_each(|(when, then)| {
+					when.resolve(scope)?;
+					then.resolve(scope)
+				})
+			}
+			Expr::Aggregate { argument, .. } => argument.iter().try_for_each(|argument| argument.resolve(scope)),
+		}
+	}
+
+	/// Replace each parameter with the value bound to it, or NULL if there
+	/// is none
+	pub fn bind(&mut self, values: &[ValueType]) {
+		match self {
+			Expr::Parameter(index) => {
+				let value = values.get(*index - 1).cloned().unwrap_or(ValueType::Null);
+				*self = Expr::Literal(value);
+			}
+			Expr::Literal(_) | Expr::Column { .. } | Expr::Quoted(_) => {}
+			Expr::Unary { operand, .. } => operand.bind(values),
+			Expr::Binary { left, right, .. } => {
+				left.bind(values);
+				right.bind(values);
+			}
+			Expr::Between { expr, low, high, .. } => {
+				expr.bind(values);
+				low.bind(values);
+				high.bind(values);
+			}
+			Expr::InList { expr, list, .. } => {
+				expr.bind(values);
+				list.iter_mut().for_each(|item| item.bind(values));
+			}
+			Expr::Like { expr, pattern, .. } => {
+				expr.bind(values);
+				pattern.bind(values);
+			}
+			Expr::Case { operand, branches, otherwise } => {
+				operand.iter_mut().chain(otherwise).for_each(|expr| expr.bind(values));
+				for (when, then) in branches {
+					when.bind(values);
+					then.bind(values);
+				}
+			}
+			Expr::Aggregate { argument, .. } => argument.iter_mut().for_each(|argument| argument.bind(values)),
+		}
+	}
+
+	/// Whether the expression holds an aggregate function
+	pub fn is_aggregate(&self) -> bool {
+		matches!(self, Expr::Aggregate { .. }) || self.operands().into_iter().any(Expr::is_aggregate)
+	}
+
+	/// The expression with each aggregate function in it replaced by its
+	/// value over a group of rows, to be evaluated against a row of the group
+	pub fn aggregated(&self, scope: &Scope, rows: &[Row]) -> Result<Expr> {
+		let mut expr = self.clone();
+		expr.replace_aggregates(scope, rows)?;
+		Ok(expr)
+	}
+
+	fn replace_aggregates(&mut self, scope: &Scope, rows: &[Row]) -> Result<()> {
+		if let Expr::Aggregate { function, argument, distinct } = self {
+			*self = Expr::Literal(aggregate(function, argument.as_deref(), *distinct, scope, rows)?);
+			return Ok(());
+		}
+		self.operands_mut().into_iter().try_for_each(|operand| operand.replace_aggregates(scope, rows))
+	}
+
+	/// The expression with each bare name that is no column in scope, but
+	/// is one of the aliases, replaced by the expression it aliases, as
+	/// HAVING can name result columns in SQLite
+	pub fn dealiased(&self, scope: &Scope, aliases: &[(&str, &Expr)]) -> Expr {
+		let mut expr = self.clone();
+		expr.replace_aliases(scope, aliases);
+		expr
+	}
+
+	fn replace_aliases(&mut self, scope: &Scope, aliases: &[(&str, &Expr)]) {
+		if let Expr::Column { table: None, name } = self {
+			if matches!(scope.find(None, name), Ok(None)) {
+				if let Some((_, expr)) = aliases.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(name)) {
+					*self = (*expr).clone();
+				}
+			}
+			return;
+		}
+		self.operands_mut().into_iter().for_each(|operand| operand.replace_aliases(scope, aliases));
+	}
+
+	/// The expressions this one is made of
+	fn operands(&self) -> Vec<&Expr> {
+		match self {
+			Expr::Literal(_) | Expr::Column { .. } | Expr::Quoted(_) | Expr::Parameter(_) => Vec::new(),
+			Expr::Unary { operand, .. } => vec![&**operand],
+			Expr::Binary { left, right, .. } => vec![&**left, &**right],
+			Expr::Between { expr, low, high, .. } => vec![&**expr, &**low, &**high],
+			Expr::InList { expr, list, .. } => core::iter::once(&**expr).chain(list).collect(),
+			Expr::Like { expr, pattern, .. } => vec![&**expr, &**pattern],
+			Expr::Case { operand, branches, otherwise } => operand
+				.iter()
+				.chain(otherwise)
+				.map(|expr| &**expr)
+				.chain(branches.iter().flat_map(|(when, then)| [when, then]))
+				.collect(),
+			Expr::Aggregate { argument, .. } => argument.iter().map(|argument| &**argument).collect(),
+		}
+	}
+
+	fn operands_mut(&mut self) -> Vec<&mut Expr> {
+		match self {
+			Expr::Literal(_) | Expr::Column { .. } | Expr::Quoted(_) | Expr::Parameter(_) => Vec::new(),
+			Expr::Unary { operand, .. } => vec![&mut **operand],
+			Expr::Binary { left, right, .. } => vec![&mut **left, &mut **right],
+			Expr::Between { expr, low, high, .. } => vec![&mut **expr, &mut **low, &mut **high],
+			Expr::InList { expr, list, .. } => core::iter::once(&mut **expr).chain(list).collect(),
+			Expr::Like { expr, pattern, .. } => vec![&mut **expr, &mut **pattern],
+			Expr::Case { operand, branches, otherwise } => operand
+				.iter_mut()
+				.chain(otherwise)
+				.map(|expr| &mut **expr)
+				.chain(branches.iter_mut().flat_map(|(when, then)| [when, then]))
+				.collect(),
+			Expr::Aggregate { argument, .. } => argument.iter_mut().map(|argument| &mut **argument).collect(),
+		}
+	}
+
+	/// Whether the expression is true for a row, as WHERE and ON test it
+	pub fn matches(&self, scope: &Scope, row: &[ValueType]) -> Result<bool> {
+		Ok(truth(&self.evaluate(scope, row)?) == Some(true))
+	}
+
+	/// Value of the expression for a row; comparisons give 1, 0 or NULL
+	pub fn evaluate(&self, scope: &Scope, row: &[ValueType]) -> Result<ValueType> {
+		Ok(match self {
+			Expr::Literal(value) => value.clone(),
+			Expr::Parameter(_) => ValueType::Null,
+			Expr::Column { table, name } => {
+				let index = scope.position(table.as_deref(), name)?;
+				row.get(index).cloned().unwrap_or(ValueType::Null)
+			}
+			Expr::Quoted(name) => match scope.find(None, name)? {
+				Some(index) => row.get(index).cloned().unwrap_or(ValueType::Null),
+				None => ValueType::StringUtf8(name.clone()),
+			},
+			Expr::Unary { op, operand } => {
+				let value = operand.evaluate(scope, row)?;
+				match op {
+					UnaryOperator::Plus => value,
+					UnaryOperator::Negate => negate(value),
+					UnaryOperator::Not => boolean(truth(&value).map(|b| !b)),
+				}
+			}
+			Expr::Binary { left, op, right } => match op {
+				BinaryOperator::And => {
+					let left = truth(&left.evaluate(scope, row)?);
+					if left == Some(false) {
+						return Ok(boolean(Some(false)));
+					}
+					match (left, truth(&right.evaluate(scope, row)?)) {
+						(_, Some(false)) => boolean(Some(false)),
+						(Some(true), Some(true)) => boolean(Some(true)),
+						_ => ValueType::Null,
+					}
+				}
+				BinaryOperator::Or => {
+					let left = truth(&left.evaluate(scope, row)?);
+					if left == Some(true) {
+						return Ok(boolean(Some(true)));
+					}
+					match (left, truth(&right.evaluate(scope, row)?)) {
+						(_, Some(true)) => boolean(Some(true)),
+						(Some(false), Some(false)) => boolean(Some(false)),
+						_ => ValueType::Null,
+					}
+				}
+				BinaryOperator::Is | BinaryOperator::IsNot => {
+					let (a, b) = compared(left, right, scope, row)?;
+					let same = match (a.is_null(), b.is_null()) {
+						(true, true) => true,
+						(false, false) => record::compare_values(&a, &b).is_eq(),
+						_ => false,
+					};
+					boolean(Some(same == (*op == BinaryOperator::Is)))
+				}
+				BinaryOperator::Equal
+				| BinaryOperator::NotEqual
+				| BinaryOperator::LessThan
+				| BinaryOperator::LessOrEqual
+				| BinaryOperator::GreaterThan
+				| BinaryOperator::GreaterOrEqual => {
+					let ordering = compare(left, right, scope, row)?;
+					boolean(ordering.map(|ordering| match op {
+						BinaryOperator::Equal => ordering.is_eq(),
+						BinaryOperator::NotEqual => ordering.is_ne(),
+						BinaryOperator::LessThan => ordering.is_lt(),
+						BinaryOperator::LessOrEqual => ordering.is_le(),
+						BinaryOperator::GreaterThan => ordering.is_gt(),
+						_ => ordering.is_ge(),
+					}))
+				}
+				BinaryOperator::Concat => {
+					let (a, b) = (left.evaluate(scope, row)?, right.evaluate(scope, row)?);
+					if a.is_null() || b.is_null() {
+						ValueType::Null
+					} else {
+						ValueType::StringUtf8(text(&a) + &text(&b))
+					}
+				}
+				_ => arithmetic(*op, left.evaluate(scope, row)?, right.evaluate(scope, row)?),
+			},
+			Expr::Between { expr, low, high, negated } => {
+				let above = compare(expr, low, scope, row)?.map(Ordering::is_ge);
+				let below = compare(expr, high, scope, row)?.map(Ordering::is_le);
+				let between = match (above, below) {
+					(Some(false), _) | (_, Some(false)) => Some(false),
+					(Some(true), Some(true)) => Some(true),
+					_ => None,
+				};
+				boolean(between.map(|b| b != *negated))
+			}
+			Expr::InList { expr, list, negated } => {
+				// No match is unknown rather than false if the list holds a NULL
+				let mut found = Some(false);
+				for item in list {
+					match compare(expr, item, scope, row)? {
+						Some(Ordering::Equal) => {
+							found = Some(true);
+							break;
+						}
+						None => found = None,
+						Some(_) => {}
+					}
+				}
+				boolean(found.map(|b| b != *negated))
+			}
+			Expr::Like { expr, pattern, negated } => {
+				let (value, pattern) = (expr.evaluate(scope, row)?, pattern.evaluate(scope, row)?);
+				if value.is_null() || pattern.is_null() {
+					ValueType::Null
+				} else {
+					let pattern: Vec<char> = text(&pattern).chars().collect();
+					let value: Vec<char> = text(&value).chars().collect();
+					boolean(Some(like(&pattern, &value) != *negated))
+				}
+			}
+			Expr::Case { operand, branches, otherwise } => {
+				for (when, then) in branches {
+					let taken = match operand {
+						Some(operand) => compare(operand, when, scope, row)? == Some(Ordering::Equal),
+						None => truth(&when.evaluate(scope, row)?) == Some(true),
+					};
+					if taken {
+						return then.evaluate(scope, row);
+					}
+				}
+				match otherwise {
+					Some(otherwise) => otherwise.evaluate(scope, row)?,
+					None => ValueType::Null,
+				}
+			}
+			Expr::Aggregate { function, .. } => {
+				return Err(Error::Syntax(format!("misuse of aggregate function {}()", function.name())));
+			}
+		})
+	}
+
+	/// Affinity of the expression's values: a column's, or none
+	fn affinity(&self, scope: &Scope) -> Option<Affinity> {
+		let index = match self {
+			Expr::Column { table, name } => scope.find(table.as_deref(), name).ok().flatten(),
+			Expr::Quoted(name) => scope.find(None, name).ok().flatten(),
+			_ => None,
+		}?;
+		Some(scope.columns[index].1.data_type.affinity())
+	}
+}
+
+/// Both operands of a comparison, converted as SQLite does: a column with a
+/// numeric affinity makes the other operand a number if it can be one, and a
+/// text column makes an operand with no affinity text
+fn compared(left: &Expr, right: &Expr, scope: &Scope, row: &[ValueType]) -> Result<(ValueType, ValueType)> {
+	let (mut a, mut b) = (left.evaluate(scope, row)?, right.evaluate(scope, row)?);
+	let numeric = |affinity: Option<Affinity>| {
+		matches!(affinity, Some(Affinity::Integer | Affinity::Real | Affinity::Numeric))
+	};
+	match (left.affinity(scope), right.affinity(scope)) {
+		(left, right) if numeric(left) && !numeric(right) => b = Affinity::Numeric.apply(b),
+		(left, right) if numeric(right) && !numeric(left) => a = Affinity::Numeric.apply(a),
+		(Some(Affinity::Text), None) => b = Affinity::Text.apply(b),
+		(None, Some(Affinity::Text)) => a = Affinity::Text.apply(a),
+		_ => {}
+	}
+	Ok((a, b))
+}
+
+/// Order of two operands, or None if either is NULL
+fn compare(left: &Expr, right: &Expr, scope: &Scope, row: &[ValueType]) -> Result<Option<Ordering>> {
+	let (a, b) = compared(left, right, scope, row)?;
+	if a.is_null() || b.is_null() {
+		return Ok(None);
+	}
+	Ok(Some(record::compare_values(&a, &b)))
+}
+
+/// Value of an aggregate function over a group of rows, as in SQLite: NULLs
+/// are skipped, SUM stays an integer until it meets a real, AVG is always a
+/// real, and MIN, MAX, SUM and AVG of no values are NULL
+fn aggregate(
+	function: &AggregateFunction,
+	argument: Option<&Expr>,
+	distinct: bool,
+	scope: &Scope,
+	rows: &[Row],
+) -> Result<ValueType> {
+	let Some(argument) = argument else {
+		return Ok(ValueType::I64(rows.len() as i64));
+	};
+	let mut values = Vec::new();
+	for row in rows {
+		let value = argument.evaluate(scope, row)?;
+		if !value.is_null() {
+			values.push(value);
+		}
+	}
+	if distinct {
+		values.sort_by(record::compare_values);
+		values.dedup_by(|a, b| record::compare_values(a, b).is_eq());
+	}
+	let values = values.into_iter();
+	Ok(match function {
+		AggregateFunction::Count => ValueType::I64(values.count() as i64),
+		AggregateFunction::Sum => values.map(|value| numeric(&value)).reduce(|total, value| match (total, value) {
+			(ValueType::I64(a), ValueType::I64(b)) => match a.checked_add(b) {
+				Some(sum) => ValueType::I64(sum),
+				None => ValueType::F64(a as f64 + b as f64),
+			},
+			(a, b) => ValueType::F64(real(&a) + real(&b)),
+		}).unwrap_or(ValueType::Null),
+		AggregateFunction::Avg => {
+			let (total, count) = values.fold((0.0, 0), |(total, count), v| (total + real(&numeric(&v)), count + 1));
+			if count == 0 {
+				ValueType::Null
+			} else {
+				ValueType::F64(total / count as f64)
+			}
+		}
+		AggregateFunction::Min => values.min_by(record::compare_values).unwrap_or(ValueType::Null),
+		AggregateFunction::Max => values.max_by(record::compare_values).unwrap_or(ValueType::Null),
+	})
+}
+
+/// Truth of a value: NULL is unknown, and anything else is true unless it
+/// is zero as a number
+pub fn truth(value: &ValueType) -> Option<bool> {
+	match numeric(value) {
+		ValueType::Null => None,
+		ValueType::F64(v) => Some(v != 0.0),
+		ValueType::I64(v) => Some(v != 0),
+		_ => Some(false),
+	}
+}
+
+fn boolean(value: Option<bool>) -> ValueType {
+	match value {
+		Some(b) => ValueType::I64(b as i64),
+		None => ValueType::Null,
+	}
+}
+
+/// A value as an integer or a real; text that is not a number counts as 0
+fn numeric(value: &ValueType) -> ValueType {
+	match value {
+		ValueType::Null => ValueType::Null,
+		ValueType::I64(_) | ValueType::F64(_) => value.clone(),
+		ValueType::F32(v) => ValueType::F64(*v as f64),
+		ValueType::Bool(v) => ValueType::I64(*v as i64),
+		other => ValueType::parse_number(&text(other)).unwrap_or(ValueType::I64(0)),
+	}
+}
+
+/// Text of a value; blobs are read as UTF-8
+fn text(value: &ValueType) -> String {
+	match value {
+		ValueType::Blob(bytes) | ValueType::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+		other => other.to_string(),
+	}
+}
+
+fn negate(value: ValueType) -> ValueType {
+	match numeric(&value) {
+		ValueType::I64(v) => v.checked_neg().map(ValueType::I64).unwrap_or(ValueType::F64(-(v as f64))),
+		ValueType::F64(v) => ValueType::F64(-v),
+		other => other,
+	}
+}
+
+/// Integer arithmetic that falls back to reals when it overflows; division
+/// by zero is NULL
+fn arithmetic(op: BinaryOperator, a: ValueType, b: ValueType) -> ValueType {
+	let (a, b) = (numeric(&a), numeric(&b));
+	match (a, b) {
+		(ValueType::I64(x), ValueType::I64(y)) => {
+			let result = match op {
+				BinaryOperator::Add => x.checked_add(y),
+				BinaryOperator::Subtract => x.checked_sub(y),
+				BinaryOperator::Multiply => x.checked_mul(y),
+				BinaryOperator::Divide | BinaryOperator::Modulo if y == 0 => return ValueType::Null,
+				BinaryOperator::Divide => x.checked_div(y),
+				_ => Some(x.wrapping_rem(y)),
+			};
+			match result {
+				Some(v) => ValueType::I64(v),
+				None => arithmetic(op, ValueType::F64(x as f64), ValueType::F64(y as f64)),
+			}
+		}
+		(ValueType::Null, _) | (_, ValueType::Null) => ValueType::Null,
+		(a, b) => {
+			let (x, y) = (real(&a), real(&b));
+			match op {
+				BinaryOperator::Add => ValueType::F64(x + y),
+				BinaryOperator::Subtract => ValueType::F64(x - y),
+				BinaryOperator::Multiply => ValueType::F64(x * y),
+				BinaryOperator::Divide if y == 0.0 => ValueType::Null,
+				BinaryOperator::Divide => ValueType::F64(x / y),
+				// Remainders are taken of whole numbers, as in SQLite
+				_ => match (x as i64, y as i64) {
+					(_, 0) => ValueType::Null,
+					(x, y) => ValueType::F64(x.wrapping_rem(y) as f64),
+				},
+			}
+		}
+	}
+}
+
+fn real(value: &ValueType) -> f64 {
+	match value {
+		ValueType::I64(v) => *v as f64,
+		ValueType::F64(v) => *v,
+		_ => 0.0,
+	}
+}
+
+/// SQL LIKE: `%` matches any run of characters and `_` any one, and ASCII
+/// letters match either case
+fn like(pattern: &[char], text: &[char]) -> bool {
+	let (mut p, mut t) = (0, 0);
+	// Where to resume after the last `%`, if the text so far fails to match
+	let mut retry = None;
+	while t < text.len() {
+		match pattern.get(p) {
+			Some('%') => {
+				p += 1;
+				retry = Some((p, t));
+			}
+			Some(&c) if c == '_' || c.eq_ignore_ascii_case(&text[t]) => {
+				p += 1;
+				t += 1;
+			}
+			_ => match retry {
+				Some((after, from)) => {
+					p = after;
+					t = from + 1;
+					retry = Some((after, from + 1));
+				}
+				None => return false,
+			},
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '%')
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::eplite::command::parser::Parser;
+	use crate::eplite::types::column::ColumnType;
+
+	fn columns() -> Vec<ColumnDefinition> {
+		[("id", ColumnType::Int32), ("name", ColumnType::Text), ("score", ColumnType::Float64)]
+			.into_iter()
+			.map(|(name, data_type)| ColumnDefinition { name: name.to_string(), data_type, constraints: Vec::new() })
+			.collect()
+	}
+
+	fn eval(sql: &str, row: &[ValueType]) -> ValueType {
+		let columns = columns();
+		let expr = Parser::new().parse_expression(sql).unwrap();
+		expr.evaluate(&Scope::new("t", &columns), row).unwrap()
+	}
+
+	fn row() -> Vec<ValueType> {
+		vec![ValueType::I64(7), ValueType::StringUtf8("a>=b".to_string()), ValueType::F64(2.5)]
+	}
+
+	#[test]
+	fn test_precedence() {
+		let int = ValueType::I64;
+		assert_eq!(eval("1 + 2 * 3", &row()), int(7));
+		assert_eq!(eval("(1 + 2) * 3", &row()), int(9));
+		assert_eq!(eval("10 - 4 - 3", &row()), int(3));
+		assert_eq!(eval("-2 * -3", &row()), int(6));
+		assert_eq!(eval("1 = 1 AND 0 OR 1", &row()), int(1));
+		assert_eq!(eval("NOT 1 = 2", &row()), int(1));
+		assert_eq!(eval("1 < 2 = 1", &row()), int(1));
+		// Concatenation binds tighter than addition
+		assert_eq!(eval("1 || 2 + 3", &row()), int(15));
+		assert_eq!(eval("'a' || 'b' || id", &row()), ValueType::StringUtf8("ab7".to_string()));
+	}
+
+	#[test]
+	fn test_arithmetic() {
+		assert_eq!(eval("7 / 2", &row()), ValueType::I64(3));
+		assert_eq!(eval("7 / 2.0", &row()), ValueType::F64(3.5));
+		assert_eq!(eval("7 % 3", &row()), ValueType::I64(1));
+		assert_eq!(eval("1 / 0", &row()), ValueType::Null);
+		assert_eq!(eval("9223372036854775807 + 1", &row()), ValueType::F64(9223372036854775808.0));
+		assert_eq!(eval("-9223372036854775808", &row()), ValueType::I64(i64::MIN));
+		assert_eq!(eval("-9223372036854775809", &row()), ValueType::F64(-9223372036854775808.0));
+		assert_eq!(eval("-(9223372036854775808)", &row()), ValueType::F64(-9223372036854775808.0));
+		assert_eq!(eval("- -3 * 2", &row()), ValueType::I64(6));
+		assert_eq!(eval("'3' + 4", &row()), ValueType::I64(7));
+		assert_eq!(eval("NULL + 1", &row()), ValueType::Null);
+	}
+
+	#[test]
+	fn test_columns_and_affinity() {
+		assert_eq!(eval("id * 2 = 14", &row()), ValueType::I64(1));
+		assert_eq!(eval("t.id > score", &row()), ValueType::I64(1));
+		// Literals take the affinity of the column they are compared with
+		assert_eq!(eval("id = '7'", &row()), ValueType::I64(1));
+		assert_eq!(eval("name = 'a>=b'", &row()), ValueType::I64(1));
+		assert_eq!(eval("\"name\" = \"a>=b\"", &row()), ValueType::I64(1));
+
+		let columns = columns();
+		let scope = Scope::new("t", &columns);
+		assert!(Parser::new().parse_expression("missing = 1").unwrap().resolve(&scope).is_err());
+		assert!(Parser::new().parse_expression("u.id = 1").unwrap().resolve(&scope).is_err());
+		let joined = Scope::new("t", &columns).join("u", &columns);
+		assert!(Parser::new().parse_expression("id = 1").unwrap().resolve(&joined).is_err());
+		assert!(Parser::new().parse_expression("u.id = t.id").unwrap().resolve(&joined).is_ok());
+	}
+
+	#[test]
+	fn test_null_logic() {
+		let null_row = vec![ValueType::Null, ValueType::Null, ValueType::Null];
+		assert_eq!(eval("id = 1", &null_row), ValueType::Null);
+		assert_eq!(eval("id IS NULL", &null_row), ValueType::I64(1));
+		assert_eq!(eval("id IS NOT NULL", &row()), ValueType::I64(1));
+		assert_eq!(eval("NULL AND 0", &row()), ValueType::I64(0));
+		assert_eq!(eval("NULL OR 1", &row()), ValueType::I64(1));
+		assert_eq!(eval("NOT NULL", &row()), ValueType::Null);
+		assert_eq!(eval("3 IN (1, NULL)", &row()), ValueType::Null);
+		assert_eq!(eval("3 NOT IN (1, 2)", &row()), ValueType::I64(1));
+	}
+
+	#[test]
+	fn test_between_in_and_like() {
+		assert_eq!(eval("id BETWEEN 5 AND 7", &row()), ValueType::I64(1));
+		assert_eq!(eval("id NOT BETWEEN 1 AND 6", &row()), ValueType::I64(1));
+		assert_eq!(eval("id BETWEEN 1 AND 2 OR 1", &row()), ValueType::I64(1));
+		assert_eq!(eval("id IN (1, 7, 9)", &row()), ValueType::I64(1));
+		assert_eq!(eval("name LIKE 'A%'", &row()), ValueType::I64(1));
+		assert_eq!(eval("name LIKE '_>=_'", &row()), ValueType::I64(1));
+		assert_eq!(eval("name NOT LIKE '%b'", &row()), ValueType::I64(0));
+		assert_eq!(eval("'a.c' LIKE 'a_c%'", &row()), ValueType::I64(1));
+		assert_eq!(eval("'abc' LIKE 'a%x%'", &row()), ValueType::I64(0));
+	}
+
+	#[test]
+	fn test_case() {
+		let text = |v: &str| ValueType::StringUtf8(v.to_string());
+		assert_eq!(eval("CASE WHEN id > 5 THEN 'big' ELSE 'small' END", &row()), text("big"));
+		assert_eq!(eval("CASE WHEN NULL THEN 1 WHEN id = 7 THEN 2 END", &row()), ValueType::I64(2));
+		assert_eq!(eval("CASE WHEN id < 0 THEN 1 END", &row()), ValueType::Null);
+		assert_eq!(eval("CASE id WHEN '7' THEN 'seven' WHEN 7 THEN 'other' END", &row()), text("seven"));
+		assert_eq!(eval("CASE NULL WHEN NULL THEN 1 ELSE 0 END", &row()), ValueType::I64(0));
+		assert_eq!(eval("1 + CASE WHEN score > 2 THEN 10 END * 2", &row()), ValueType::I64(21));
+	}
+
+	#[test]
+	fn test_aggregates() {
+		let columns = columns();
+		let scope = Scope::new("t", &columns);
+		let rows: Vec<Row> = [(1, "a", 2.0), (2, "b", 2.0), (3, "a", 0.5)]
+			.into_iter()
+			.map(|(id, name, score)| vec![ValueType::I64(id), ValueType::StringUtf8(name.to_string()), ValueType::F64(score)])
+			.chain([vec![ValueType::I64(4), ValueType::Null, ValueType::Null]])
+			.collect();
+		let aggregate = |sql: &str| {
+			let expr = Parser::new().parse_expression(sql).unwrap();
+			expr.aggregated(&scope, &rows).unwrap().evaluate(&scope, &rows[3]).unwrap()
+		};
+		assert_eq!(aggregate("COUNT(*)"), ValueType::I64(4));
+		assert_eq!(aggregate("COUNT(name)"), ValueType::I64(3));
+		assert_eq!(aggregate("COUNT(DISTINCT name)"), ValueType::I64(2));
+		assert_eq!(aggregate("SUM(id)"), ValueType::I64(10));
+		assert_eq!(aggregate("SUM(DISTINCT score)"), ValueType::F64(2.5));
+		assert_eq!(aggregate("AVG(score)"), ValueType::F64(1.5));
+		assert_eq!(aggregate("MIN(name) || MAX(name)"), ValueType::StringUtf8("ab".to_string()));
+		assert_eq!(aggregate("MAX(id) - id"), ValueType::I64(0));
+
+		// Aggregates have no value for a single row, nor inside each other
+		let expr = Parser::new().parse_expression("SUM(id) > 1").unwrap();
+		assert!(expr.is_aggregate());
+		assert!(expr.evaluate(&scope, &rows[0]).is_err());
+		let nested = Parser::new().parse_expression("SUM(COUNT(*))").unwrap();
+		assert!(nested.aggregated(&scope, &rows).is_err());
+	}
+}
@@ -1,23 +1,34 @@
 /// SQL parser - assigns meaning to tokens based on context
 
+use crate::eplite::command::expression::{
+	BinaryOperator, Expr, UnaryOperator, MATCH_PRECEDENCE, NOT_PRECEDENCE, UNARY_PRECEDENCE,
+};
 use crate::eplite::command::tokenizer::{Token, Tokenizer};
 use crate::eplite::error::{Error, Result};
 use crate::eplite::types::column::ColumnType;
-use logos::Logos;
+use crate::eplite::types::ValueType;
+use core::ops::Range;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(feature = "std"))]
-use alloc::{format, string::{String, ToString}, vec, vec::Vec};
+use alloc::{boxed::Box, format, string::{String, ToString}, vec, vec::Vec};
 
 /// Parse tree node types
 #[derive(Debug, Clone)]
 pub enum Statement {
-	Select(SelectStatement),
+	Select(Box<SelectStatement>),
 	Insert(InsertStatement),
 	Update(UpdateStatement),
 	Delete(DeleteStatement),
 	CreateTable(CreateTableStatement),
-	BeginTransaction,
+	DropTable(String),
+	/// VACUUM, or VACUUM INTO a new file
+	Vacuum(Option<String>),
+	Pragma {
+		name: String,
+		value: Option<String>,
+	},
+	BeginTransaction(TransactionMode),
 	Commit,
 	Rollback,
 	Savepoint(String),
@@ -25,6 +36,68 @@ pub enum Statement {
 	RollbackToSavepoint(String),
 }
 
+impl Statement {
+	/// Replace each parameter in the statement with the value bound to it,
+	/// where `values[0]` is bound to parameter 1, or NULL if there is none
+	pub fn bind(&mut self, values: &[ValueType]) {
+		match self {
+			Statement::Select(stmt) => {
+				for column in &mut stmt.columns {
+					if let ColumnSelection::Expr { expr, .. } = column {
+						expr.bind(values);
+					}
+				}
+				for expr in stmt.group_by.iter_mut().flatten().chain(&mut stmt.having) {
+					expr.bind(values);
+				}
+				for term in stmt.order_by.iter_mut().flatten() {
+					term.expr.bind(values);
+				}
+				for expr in stmt.limit.iter_mut().chain(&mut stmt.offset) {
+					expr.bind(values);
+				}
+				for join in &mut stmt.joins {
+					if let Some(condition) = &mut join.on_condition {
+						condition.bind(values);
+					}
+				}
+				if let Some(condition) = &mut stmt.where_clause {
+					condition.bind(values);
+				}
+			}
+			Statement::Insert(stmt) => stmt.values.iter_mut().for_each(|value| value.bind(values)),
+			Statement::Update(stmt) => {
+				stmt.set_clauses.iter_mut().for_each(|(_, value)| value.bind(values));
+				if let Some(condition) = &mut stmt.where_clause {
+					condition.bind(values);
+				}
+			}
+			Statement::Delete(stmt) => {
+				if let Some(condition) = &mut stmt.where_clause {
+					condition.bind(values);
+				}
+			}
+			_ => {}
+		}
+	}
+}
+
+/// The largest parameter index, as in SQLite
+const MAX_PARAMETERS: usize = 32766;
+
+/// When a transaction started with BEGIN takes its locks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionMode {
+	/// The write lock waits for the first change
+	#[default]
+	Deferred,
+	/// The write lock is taken at once
+	Immediate,
+	/// The write lock is taken at once and, outside WAL mode, other
+	/// connections cannot read until the transaction ends
+	Exclusive,
+}
+
 /// Aggregate function type
 #[derive(Debug, Clone, PartialEq)]
 pub enum AggregateFunction {
@@ -35,15 +108,29 @@ pub enum AggregateFunction {
 	Max,
 }
 
-/// Column selection - either a regular column or an aggregate
+impl AggregateFunction {
+	pub fn name(&self) -> &'static str {
+		match self {
+			AggregateFunction::Count => "COUNT",
+			AggregateFunction::Sum => "SUM",
+			AggregateFunction::Avg => "AVG",
+			AggregateFunction::Min => "MIN",
+			AggregateFunction::Max => "MAX",
+		}
+	}
+}
+
+/// Column selection - all columns or an expression
 #[derive(Debug, Clone)]
 pub enum ColumnSelection {
-	Column(String),
-	Aggregate {
-		function: AggregateFunction,
-		column: String,
+	/// `*`, or `table.*` for the columns of one table
+	All(Option<String>),
+	/// An expression with the name of its result column: its alias, the
+	/// column it reads, or else the expression as written
+	Expr {
+		expr: Expr,
+		name: String,
 	},
-	CountStar, // COUNT(*)
 }
 
 /// Join type
@@ -60,37 +147,58 @@ pub enum JoinType {
 pub struct JoinClause {
 	pub join_type: JoinType,
 	pub table: String,
-	pub on_condition: Option<String>, // e.g., "table1.id = table2.id"
+	pub on_condition: Option<Expr>,
 }
 
 #[derive(Debug, Clone)]
 pub struct SelectStatement {
+	/// SELECT DISTINCT, which drops rows equal to one before them
+	pub distinct: bool,
 	pub columns: Vec<ColumnSelection>,
 	pub from: String,
 	pub joins: Vec<JoinClause>,
-	pub where_clause: Option<String>,
-	pub group_by: Option<Vec<String>>,
-	pub order_by: Option<Vec<String>>,
+	pub where_clause: Option<Expr>,
+	/// Values whose rows are grouped together; an integer literal stands
+	/// for the result column of that number, and a name that is no column
+	/// for the result column it aliases
+	pub group_by: Option<Vec<Expr>>,
+	/// Condition a group must meet to give a row
+	pub having: Option<Expr>,
+	pub order_by: Option<Vec<OrderingTerm>>,
+	pub limit: Option<Expr>,
+	pub offset: Option<Expr>,
+}
+
+/// A key of ORDER BY
+#[derive(Debug, Clone)]
+pub struct OrderingTerm {
+	/// The value to sort by; an integer literal stands for the result
+	/// column of that number, and a name for the result column it aliases
+	pub expr: Expr,
+	pub descending: bool,
+	/// Whether NULLs come before other values, which by default they do
+	/// when ascending, as in SQLite
+	pub nulls_first: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct InsertStatement {
 	pub table: String,
 	pub columns: Vec<String>,
-	pub values: Vec<String>,
+	pub values: Vec<Expr>,
 }
 
 #[derive(Debug, Clone)]
 pub struct UpdateStatement {
 	pub table: String,
-	pub set_clauses: Vec<(String, String)>,
-	pub where_clause: Option<String>,
+	pub set_clauses: Vec<(String, Expr)>,
+	pub where_clause: Option<Expr>,
 }
 
 #[derive(Debug, Clone)]
 pub struct DeleteStatement {
 	pub table: String,
-	pub where_clause: Option<String>,
+	pub where_clause: Option<Expr>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,28 +218,58 @@ pub struct ColumnDefinition {
 #[derive(Debug)]
 pub struct Parser {
 	tokens: Vec<Token>,
-	position: usize,
+	/// The statement being parsed
 	source: String,
+	/// Where each token is in the source
+	spans: Vec<Range<usize>>,
+	position: usize,
+	/// Name of each parameter of the last statement parsed, or None for
+	/// one written `?`
+	parameters: Vec<Option<String>>,
 }
 
 impl Parser {
 	pub fn new() -> Self {
 		Parser {
 			tokens: Vec::new(),
-			position: 0,
 			source: String::new(),
+			spans: Vec::new(),
+			position: 0,
+			parameters: Vec::new(),
 		}
 	}
 
-	/// Parse SQL statement into a parse tree
-	pub fn parse(&mut self, sql: &str) -> Result<Statement> {
-		// Store the source for extracting actual text
-		self.source = sql.to_string();
-
-		// Tokenize the SQL
+	fn tokenize(&mut self, sql: &str) -> Result<()> {
 		let tokenizer = Tokenizer::new(sql.to_string());
-		self.tokens = tokenizer.tokenize();
+		(self.tokens, self.spans) = tokenizer.tokenize_with_spans()?.into_iter().unzip();
+		self.source = sql.to_string();
 		self.position = 0;
+		self.parameters.clear();
+		Ok(())
+	}
+
+	/// Names of the parameters of the statement or expression parsed last,
+	/// in order of their index; parameters written `?` have none
+	pub fn parameters(&self) -> &[Option<String>] {
+		&self.parameters
+	}
+
+	/// Parse an expression on its own, such as a WHERE condition
+	pub fn parse_expression(&mut self, sql: &str) -> Result<Expr> {
+		self.tokenize(sql)?;
+		let expr = self.parse_expr()?;
+		if self.position < self.tokens.len() {
+			return Err(Error::Syntax(format!(
+				"Unexpected tokens after expression: {:?}",
+				self.current_token()
+			)));
+		}
+		Ok(expr)
+	}
+
+	/// Parse SQL statement into a parse tree
+	pub fn parse(&mut self, sql: &str) -> Result<Statement> {
+		self.tokenize(sql)?;
 
 		if self.tokens.is_empty() {
 			return Err(Error::Syntax("Empty statement".to_string()));
@@ -144,9 +282,22 @@ impl Parser {
 			Some(Token::Update) => self.parse_update()?,
 			Some(Token::Delete) => self.parse_delete()?,
 			Some(Token::Create) => self.parse_create()?,
+			Some(Token::Drop) => {
+				self.advance();
+				self.expect(Token::Table)?;
+				Statement::DropTable(self.parse_identifier()?)
+			}
+			Some(Token::Vacuum) => {
+				self.advance();
+				self.parse_vacuum()?
+			}
+			Some(Token::Pragma) => {
+				self.advance();
+				self.parse_pragma()?
+			}
 			Some(Token::Begin) => {
 				self.advance();
-				Statement::BeginTransaction
+				self.parse_begin()
 			}
 			Some(Token::Commit) => {
 				self.advance();
@@ -183,6 +334,11 @@ impl Parser {
 		Ok(stmt)
 	}
 
+	/// Source text of the token at a position
+	fn text(&self, position: usize) -> &str {
+		&self.source[self.spans[position].clone()]
+	}
+
 	fn current_token(&self) -> Option<&Token> {
 		self.tokens.get(self.position)
 	}
@@ -205,26 +361,13 @@ impl Parser {
 		}
 	}
 
+	/// Parse a table or column name, which may be double-quoted
 	fn parse_identifier(&mut self) -> Result<String> {
 		match self.current_token() {
-			Some(Token::Identifier) => {
-				// Extract the actual text by re-lexing from the source
-				let mut lex = Token::lexer(&self.source);
-				let mut idx = 0;
-				while let Some(token) = lex.next() {
-					if idx == self.position {
-						if let Ok(Token::Identifier) = token {
-							let text = lex.slice().to_string();
-							self.advance();
-							return Ok(text);
-						}
-					}
-					idx += 1;
-				}
-				
-				// Fallback
+			Some(Token::Identifier | Token::QuotedIdentifier) => {
+				let text = self.name_text();
 				self.advance();
-				Ok(format!("identifier_{}", self.position))
+				Ok(text)
 			}
 			_ => Err(Error::Syntax(format!(
 				"Expected identifier, found {:?}",
@@ -233,144 +376,295 @@ impl Parser {
 		}
 	}
 
-	/// Parse WHERE clause - returns the raw text of the condition
-	fn parse_where_clause(&mut self) -> Result<String> {
-		// Collect all tokens until we hit a keyword that ends WHERE clause
-		let mut parts = Vec::new();
-		
-		// Re-lex to get actual text
-		let mut lex = Token::lexer(&self.source);
-		let mut token_texts = Vec::new();
-		while let Some(token) = lex.next() {
-			if token.is_ok() {
-				token_texts.push(lex.slice().to_string());
-			}
+	/// Parse a value token (number or string literal)
+	fn parse_value(&mut self) -> Result<String> {
+		if self.position >= self.tokens.len() {
+			return Err(Error::Syntax("Unexpected end of statement".to_string()));
 		}
-		
+		let text = self.text(self.position).to_string();
+		self.advance();
+		Ok(text)
+	}
+
+	/// Parse an expression, binding operators as tightly as SQLite does
+	fn parse_expr(&mut self) -> Result<Expr> {
+		self.parse_binary(0)
+	}
+
+	/// Parse an expression whose operators bind at least as tightly as
+	/// `min_precedence`
+	fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr> {
+		let mut left = self.parse_prefix()?;
 		loop {
-			match self.current_token() {
-				None => break,
-				Some(Token::Set) |
-				Some(Token::Order) |
-				Some(Token::Group) |
-				Some(Token::Limit) |
-				Some(Token::Offset) => break,
-				Some(_) => {
-					if self.position < token_texts.len() {
-						parts.push(token_texts[self.position].clone());
+			// NOT before BETWEEN, IN or LIKE negates them
+			let negated = matches!(self.current_token(), Some(Token::Not))
+				&& matches!(self.tokens.get(self.position + 1), Some(Token::Between | Token::In | Token::Like));
+			let operator = if negated { self.tokens.get(self.position + 1) } else { self.current_token() };
+
+			let op = match operator {
+				Some(Token::Between | Token::In | Token::Like) => {
+					if MATCH_PRECEDENCE < min_precedence {
+						break;
+					}
+					left = self.parse_match(left, negated)?;
+					continue;
+				}
+				Some(Token::Or) => BinaryOperator::Or,
+				Some(Token::And) => BinaryOperator::And,
+				Some(Token::Equals) => BinaryOperator::Equal,
+				Some(Token::NotEquals | Token::NotEquals2) => BinaryOperator::NotEqual,
+				Some(Token::Is) => {
+					if matches!(self.tokens.get(self.position + 1), Some(Token::Not)) {
+						BinaryOperator::IsNot
+					} else {
+						BinaryOperator::Is
 					}
-					self.advance();
 				}
+				Some(Token::LessThan) => BinaryOperator::LessThan,
+				Some(Token::LessThanOrEqual) => BinaryOperator::LessOrEqual,
+				Some(Token::GreaterThan) => BinaryOperator::GreaterThan,
+				Some(Token::GreaterThanOrEqual) => BinaryOperator::GreaterOrEqual,
+				Some(Token::Plus) => BinaryOperator::Add,
+				Some(Token::Minus) => BinaryOperator::Subtract,
+				Some(Token::Star) => BinaryOperator::Multiply,
+				Some(Token::Slash) => BinaryOperator::Divide,
+				Some(Token::Percent) => BinaryOperator::Modulo,
+				Some(Token::Concat) => BinaryOperator::Concat,
+				_ => break,
+			};
+			if op.precedence() < min_precedence {
+				break;
 			}
+			self.advance();
+			if op == BinaryOperator::IsNot {
+				self.advance();
+			}
+			// Operators of the same precedence group to the left
+			let right = self.parse_binary(op.precedence() + 1)?;
+			left = Expr::binary(left, op, right);
 		}
-		
-		if parts.is_empty() {
-			Err(Error::Syntax("Empty WHERE clause".to_string()))
-		} else {
-			Ok(parts.join(" "))
+		Ok(left)
+	}
+
+	/// Parse `[NOT] BETWEEN`, `[NOT] IN` or `[NOT] LIKE` after their left operand
+	fn parse_match(&mut self, expr: Expr, negated: bool) -> Result<Expr> {
+		if negated {
+			self.advance();
 		}
+		let expr = Box::new(expr);
+		let operator = self.current_token().cloned();
+		self.advance();
+		Ok(match operator {
+			Some(Token::Between) => {
+				// The AND here belongs to BETWEEN, so the bounds bind tighter
+				let low = Box::new(self.parse_binary(MATCH_PRECEDENCE + 1)?);
+				self.expect(Token::And)?;
+				let high = Box::new(self.parse_binary(MATCH_PRECEDENCE + 1)?);
+				Expr::Between { expr, low, high, negated }
+			}
+			Some(Token::In) => {
+				self.expect(Token::LeftParen)?;
+				let mut list = Vec::new();
+				if !matches!(self.current_token(), Some(Token::RightParen)) {
+					loop {
+						list.push(self.parse_expr()?);
+						if !matches!(self.current_token(), Some(Token::Comma)) {
+							break;
+						}
+						self.advance();
+					}
+				}
+				self.expect(Token::RightParen)?;
+				Expr::InList { expr, list, negated }
+			}
+			_ => {
+				let pattern = Box::new(self.parse_binary(MATCH_PRECEDENCE + 1)?);
+				Expr::Like { expr, pattern, negated }
+			}
+		})
 	}
 
-	/// Parse a value token (number or string literal)
-	fn parse_value(&mut self) -> Result<String> {
-		// Re-lex to get actual text
-		let mut lex = Token::lexer(&self.source);
-		let mut token_texts = Vec::new();
-		while let Some(token) = lex.next() {
-			if token.is_ok() {
-				token_texts.push(lex.slice().to_string());
+	/// Parse an operand, with any unary operators before it
+	fn parse_prefix(&mut self) -> Result<Expr> {
+		let (op, precedence) = match self.current_token() {
+			Some(Token::Not) => (UnaryOperator::Not, NOT_PRECEDENCE),
+			Some(Token::Minus) => (UnaryOperator::Negate, UNARY_PRECEDENCE),
+			Some(Token::Plus) => (UnaryOperator::Plus, UNARY_PRECEDENCE),
+			_ => return self.parse_primary(),
+		};
+		self.advance();
+
+		// A negative integer is one literal, so the smallest fits in an i64
+		if op == UnaryOperator::Negate && matches!(self.current_token(), Some(Token::IntegerLiteral)) {
+			let literal = ValueType::from_literal(&format!("-{}", self.text(self.position)));
+			self.advance();
+			return Ok(Expr::Literal(literal));
+		}
+		let operand = Box::new(self.parse_binary(precedence)?);
+		Ok(Expr::Unary { op, operand })
+	}
+
+	/// Parse a literal, a column, an aggregate or a parenthesized expression
+	fn parse_primary(&mut self) -> Result<Expr> {
+		let expr = match self.current_token() {
+			Some(Token::LeftParen) => {
+				self.advance();
+				let expr = self.parse_expr()?;
+				self.expect(Token::RightParen)?;
+				return Ok(expr);
 			}
+			Some(Token::Null) => Expr::Literal(ValueType::Null),
+			Some(Token::IntegerLiteral | Token::FloatLiteral | Token::StringLiteral | Token::BlobLiteral) => {
+				Expr::Literal(ValueType::from_literal(self.text(self.position)))
+			}
+			Some(Token::Case) => return self.parse_case(),
+			Some(Token::Count | Token::Sum | Token::Avg | Token::Min | Token::Max) => return self.parse_aggregate(),
+			Some(Token::Variable) => Expr::Parameter(self.parameter_index()?),
+			Some(Token::Identifier | Token::QuotedIdentifier) => {
+				let name = self.name_text();
+				self.advance();
+				if !matches!(self.current_token(), Some(Token::Dot)) {
+					if matches!(self.tokens[self.position - 1], Token::QuotedIdentifier) {
+						return Ok(Expr::Quoted(name));
+					}
+					return Ok(Expr::Column { table: None, name });
+				}
+				self.advance();
+				if !matches!(self.current_token(), Some(Token::Identifier | Token::QuotedIdentifier)) {
+					return Err(Error::Syntax(format!("Expected a column name after {}.", name)));
+				}
+				Expr::Column { table: Some(name), name: self.name_text() }
+			}
+			Some(token) => return Err(Error::Syntax(format!("Expected an expression, found {:?}", token))),
+			None => return Err(Error::Syntax("Expected an expression".to_string())),
+		};
+		self.advance();
+		Ok(expr)
+	}
+
+	/// Parse `CASE [operand] WHEN .. THEN .. [ELSE ..] END`
+	fn parse_case(&mut self) -> Result<Expr> {
+		self.expect(Token::Case)?;
+		let operand = match self.current_token() {
+			Some(Token::When) => None,
+			_ => Some(Box::new(self.parse_expr()?)),
+		};
+		let mut branches = Vec::new();
+		while matches!(self.current_token(), Some(Token::When)) {
+			self.advance();
+			let when = self.parse_expr()?;
+			self.expect(Token::Then)?;
+			branches.push((when, self.parse_expr()?));
 		}
-		
-		if self.position < token_texts.len() {
-			let val = token_texts[self.position].clone();
+		if branches.is_empty() {
+			return Err(Error::Syntax("Expected WHEN in CASE".to_string()));
+		}
+		let otherwise = if matches!(self.current_token(), Some(Token::Else)) {
 			self.advance();
-			Ok(val)
+			Some(Box::new(self.parse_expr()?))
 		} else {
+			None
+		};
+		self.expect(Token::End)?;
+		Ok(Expr::Case { operand, branches, otherwise })
+	}
+
+	/// Parse an aggregate function: `COUNT(*)`, or a function of
+	/// `[DISTINCT] expr`
+	fn parse_aggregate(&mut self) -> Result<Expr> {
+		let function = match self.current_token() {
+			Some(Token::Count) => AggregateFunction::Count,
+			Some(Token::Sum) => AggregateFunction::Sum,
+			Some(Token::Avg) => AggregateFunction::Avg,
+			Some(Token::Min) => AggregateFunction::Min,
+			_ => AggregateFunction::Max,
+		};
+		self.advance();
+		self.expect(Token::LeftParen)?;
+		if function == AggregateFunction::Count && matches!(self.current_token(), Some(Token::Star)) {
 			self.advance();
-			Ok("NULL".to_string())
+			self.expect(Token::RightParen)?;
+			return Ok(Expr::Aggregate { function, argument: None, distinct: false });
+		}
+		let distinct = matches!(self.current_token(), Some(Token::Distinct));
+		if distinct {
+			self.advance();
+		}
+		let argument = Some(Box::new(self.parse_expr()?));
+		self.expect(Token::RightParen)?;
+		Ok(Expr::Aggregate { function, argument, distinct })
+	}
+
+	/// Index of the parameter at the current token, numbered as in SQLite:
+	/// `?` takes the next index, `?NNN` the index NNN, and a name the index
+	/// it was given before or else the next
+	fn parameter_index(&mut self) -> Result<usize> {
+		let text = self.text(self.position).to_string();
+		let named = self.parameters.iter().position(|name| name.as_deref() == Some(text.as_str()));
+		let index = match text.strip_prefix('?') {
+			Some("") => self.parameters.len() + 1,
+			Some(number) => number.parse::<usize>().unwrap_or(0),
+			None => named.map_or(self.parameters.len() + 1, |i| i + 1),
+		};
+		if !(1..=MAX_PARAMETERS).contains(&index) {
+			return Err(Error::Syntax(format!(
+				"Parameter {} must be numbered from 1 to {}",
+				text, MAX_PARAMETERS
+			)));
+		}
+		if index > self.parameters.len() {
+			self.parameters.resize(index, None);
+		}
+		if text != "?" && self.parameters[index - 1].is_none() {
+			self.parameters[index - 1] = Some(text.clone());
+		}
+		Ok(index)
+	}
+
+	/// Text of an identifier token, without the quotes of a quoted one
+	fn name_text(&self) -> String {
+		let text = self.text(self.position);
+		match self.tokens[self.position] {
+			Token::QuotedIdentifier => text[1..text.len() - 1].to_string(),
+			_ => text.to_string(),
 		}
 	}
 
 	fn parse_select(&mut self) -> Result<Statement> {
 		self.expect(Token::Select)?;
 
+		let distinct = matches!(self.current_token(), Some(Token::Distinct));
+		if distinct {
+			self.advance();
+		}
+
 		let mut columns = Vec::new();
 		
-		// Parse columns (including aggregates)
-		if matches!(self.current_token(), Some(Token::Star)) {
-			columns.push(ColumnSelection::Column("*".to_string()));
-			self.advance();
-		} else {
-			loop {
-				// Check for aggregate functions
-				let col = match self.current_token() {
-					Some(Token::Count) => {
-						self.advance();
-						self.expect(Token::LeftParen)?;
-						if matches!(self.current_token(), Some(Token::Star)) {
-							self.advance();
-							self.expect(Token::RightParen)?;
-							ColumnSelection::CountStar
-						} else {
-							let col_name = self.parse_identifier()?;
-							self.expect(Token::RightParen)?;
-							ColumnSelection::Aggregate {
-								function: AggregateFunction::Count,
-								column: col_name,
-							}
-						}
-					}
-					Some(Token::Sum) => {
-						self.advance();
-						self.expect(Token::LeftParen)?;
-						let col_name = self.parse_identifier()?;
-						self.expect(Token::RightParen)?;
-						ColumnSelection::Aggregate {
-							function: AggregateFunction::Sum,
-							column: col_name,
-						}
-					}
-					Some(Token::Avg) => {
-						self.advance();
-						self.expect(Token::LeftParen)?;
-						let col_name = self.parse_identifier()?;
-						self.expect(Token::RightParen)?;
-						ColumnSelection::Aggregate {
-							function: AggregateFunction::Avg,
-							column: col_name,
-						}
-					}
-					Some(Token::Min) => {
-						self.advance();
-						self.expect(Token::LeftParen)?;
-						let col_name = self.parse_identifier()?;
-						self.expect(Token::RightParen)?;
-						ColumnSelection::Aggregate {
-							function: AggregateFunction::Min,
-							column: col_name,
-						}
-					}
-					Some(Token::Max) => {
-						self.advance();
-						self.expect(Token::LeftParen)?;
-						let col_name = self.parse_identifier()?;
-						self.expect(Token::RightParen)?;
-						ColumnSelection::Aggregate {
-							function: AggregateFunction::Max,
-							column: col_name,
-						}
-					}
-					_ => ColumnSelection::Column(self.parse_identifier()?),
-				};
-				
-				columns.push(col);
-				
-				if !matches!(self.current_token(), Some(Token::Comma)) {
-					break;
+		// Parse columns
+		loop {
+			// All columns, or an expression
+			let col = match self.current_token() {
+				Some(Token::Star) => {
+					self.advance();
+					ColumnSelection::All(None)
 				}
-				self.advance();
+				Some(Token::Identifier | Token::QuotedIdentifier)
+					if matches!(self.tokens.get(self.position + 1), Some(Token::Dot))
+						&& matches!(self.tokens.get(self.position + 2), Some(Token::Star)) =>
+				{
+					let table = self.name_text();
+					self.position += 3;
+					ColumnSelection::All(Some(table))
+				}
+				_ => self.parse_result_column()?,
+			};
+			
+			columns.push(col);
+			
+			if !matches!(self.current_token(), Some(Token::Comma)) {
+				break;
 			}
+			self.advance();
 		}
 
 		self.expect(Token::From)?;
@@ -387,7 +681,7 @@ impl Parser {
 
 		let where_clause = if matches!(self.current_token(), Some(Token::Where)) {
 			self.advance();
-			Some(self.parse_where_clause()?)
+			Some(self.parse_expr()?)
 		} else {
 			None
 		};
@@ -398,7 +692,7 @@ impl Parser {
 			self.expect(Token::By)?;
 			let mut cols = Vec::new();
 			loop {
-				cols.push(self.parse_identifier()?);
+				cols.push(self.parse_expr()?);
 				if !matches!(self.current_token(), Some(Token::Comma)) {
 					break;
 				}
@@ -409,35 +703,125 @@ impl Parser {
 			None
 		};
 
+		let having = if matches!(self.current_token(), Some(Token::Having)) {
+			self.advance();
+			Some(self.parse_expr()?)
+		} else {
+			None
+		};
+
 		// Parse ORDER BY
 		let order_by = if matches!(self.current_token(), Some(Token::Order)) {
 			self.advance();
 			self.expect(Token::By)?;
-			let mut cols = Vec::new();
+			let mut terms = Vec::new();
 			loop {
-				cols.push(self.parse_identifier()?);
-				// Skip ASC/DESC keywords if present
-				if matches!(self.current_token(), Some(Token::Asc) | Some(Token::Desc)) {
-					self.advance();
-				}
+				terms.push(self.parse_ordering_term()?);
 				if !matches!(self.current_token(), Some(Token::Comma)) {
 					break;
 				}
 				self.advance();
 			}
-			Some(cols)
+			Some(terms)
 		} else {
 			None
 		};
 
-		Ok(Statement::Select(SelectStatement {
+		// Parse LIMIT count [OFFSET skip], or LIMIT skip, count
+		let (limit, offset) = if matches!(self.current_token(), Some(Token::Limit)) {
+			self.advance();
+			let limit = self.parse_expr()?;
+			match self.current_token() {
+				Some(Token::Offset) => {
+					self.advance();
+					(Some(limit), Some(self.parse_expr()?))
+				}
+				Some(Token::Comma) => {
+					self.advance();
+					(Some(self.parse_expr()?), Some(limit))
+				}
+				_ => (Some(limit), None),
+			}
+		} else {
+			(None, None)
+		};
+
+		Ok(Statement::Select(Box::new(SelectStatement {
+			distinct,
 			columns,
 			from,
 			joins,
 			where_clause,
 			group_by,
+			having,
 			order_by,
-		}))
+			limit,
+			offset,
+		})))
+	}
+
+	/// Parse `expr [ASC | DESC] [NULLS FIRST | NULLS LAST]`
+	fn parse_ordering_term(&mut self) -> Result<OrderingTerm> {
+		let expr = self.parse_expr()?;
+		let descending = match self.current_token() {
+			Some(Token::Asc) => {
+				self.advance();
+				false
+			}
+			Some(Token::Desc) => {
+				self.advance();
+				true
+			}
+			_ => false,
+		};
+		// NULLS, FIRST and LAST are not reserved, so they can name columns
+		let mut nulls_first = !descending;
+		if self.keyword("NULLS") {
+			self.advance();
+			nulls_first = if self.keyword("FIRST") {
+				true
+			} else if self.keyword("LAST") {
+				false
+			} else {
+				return Err(Error::Syntax("Expected FIRST or LAST after NULLS".to_string()));
+			};
+			self.advance();
+		}
+		Ok(OrderingTerm { expr, descending, nulls_first })
+	}
+
+	/// Whether the current token is an identifier spelling a keyword that
+	/// is not reserved
+	fn keyword(&self, keyword: &str) -> bool {
+		matches!(self.current_token(), Some(Token::Identifier)) && self.text(self.position).eq_ignore_ascii_case(keyword)
+	}
+
+	/// Parse an expression in a select list, with its alias if it has one
+	fn parse_result_column(&mut self) -> Result<ColumnSelection> {
+		let start = self.position;
+		let expr = self.parse_expr()?;
+		let end = self.position;
+		let alias = match self.current_token() {
+			Some(Token::As) => {
+				self.advance();
+				match self.current_token() {
+					Some(Token::Identifier | Token::QuotedIdentifier) => Some(self.name_text()),
+					Some(Token::StringLiteral) => Some(ValueType::from_literal(self.text(self.position)).to_string()),
+					_ => return Err(Error::Syntax("Expected a name after AS".to_string())),
+				}
+			}
+			Some(Token::Identifier | Token::QuotedIdentifier) => Some(self.name_text()),
+			_ => None,
+		};
+		if alias.is_some() {
+			self.advance();
+		}
+		let name = match (alias, &expr) {
+			(Some(alias), _) => alias,
+			(None, Expr::Column { name, .. } | Expr::Quoted(name)) => name.clone(),
+			(None, _) => self.source[self.spans[start].start..self.spans[end - 1].end].to_string(),
+		};
+		Ok(ColumnSelection::Expr { expr, name })
 	}
 
 	fn parse_insert(&mut self) -> Result<Statement> {
@@ -468,8 +852,7 @@ impl Parser {
 		
 		let mut values = Vec::new();
 		loop {
-			// Parse actual value
-			values.push(self.parse_value()?);
+			values.push(self.parse_expr()?);
 			
 			if !matches!(self.current_token(), Some(Token::Comma)) {
 				break;
@@ -495,7 +878,7 @@ impl Parser {
 		loop {
 			let col = self.parse_identifier()?;
 			self.expect(Token::Equals)?;
-			let val = self.parse_value()?;
+			let val = self.parse_expr()?;
 			set_clauses.push((col, val));
 			
 			if !matches!(self.current_token(), Some(Token::Comma)) {
@@ -506,7 +889,7 @@ impl Parser {
 
 		let where_clause = if matches!(self.current_token(), Some(Token::Where)) {
 			self.advance();
-			Some(self.parse_where_clause()?)
+			Some(self.parse_expr()?)
 		} else {
 			None
 		};
@@ -525,7 +908,7 @@ impl Parser {
 
 		let where_clause = if matches!(self.current_token(), Some(Token::Where)) {
 			self.advance();
-			Some(self.parse_where_clause()?)
+			Some(self.parse_expr()?)
 		} else {
 			None
 		};
@@ -621,6 +1004,23 @@ impl Parser {
 		}))
 	}
 
+	fn parse_begin(&mut self) -> Statement {
+		// BEGIN [DEFERRED | IMMEDIATE | EXCLUSIVE] [TRANSACTION]
+		let mode = match self.current_token() {
+			Some(Token::Deferred) => Some(TransactionMode::Deferred),
+			Some(Token::Immediate) => Some(TransactionMode::Immediate),
+			Some(Token::Exclusive) => Some(TransactionMode::Exclusive),
+			_ => None,
+		};
+		if mode.is_some() {
+			self.advance();
+		}
+		if matches!(self.current_token(), Some(Token::Transaction)) {
+			self.advance();
+		}
+		Statement::BeginTransaction(mode.unwrap_or_default())
+	}
+
 	fn parse_rollback(&mut self) -> Result<Statement> {
 		// Check if this is "ROLLBACK TO SAVEPOINT name" or "ROLLBACK TO name"
 		if matches!(self.current_token(), Some(Token::To)) {
@@ -644,6 +1044,59 @@ impl Parser {
 		}
 	}
 
+	fn parse_vacuum(&mut self) -> Result<Statement> {
+		// VACUUM [INTO 'filename']
+		if !matches!(self.current_token(), Some(Token::Into)) {
+			return Ok(Statement::Vacuum(None));
+		}
+		self.advance();
+		if !matches!(self.current_token(), Some(Token::StringLiteral)) {
+			return Err(Error::Syntax("Expected a file name after INTO".to_string()));
+		}
+		let path = self.parse_value()?;
+		Ok(Statement::Vacuum(Some(path[1..path.len() - 1].to_string())))
+	}
+
+	fn parse_pragma(&mut self) -> Result<Statement> {
+		// PRAGMA name [= value | (value)]
+		let name = self.parse_identifier()?;
+		let value = match self.current_token() {
+			Some(Token::Equals) => {
+				self.advance();
+				Some(self.parse_pragma_value()?)
+			}
+			Some(Token::LeftParen) => {
+				self.advance();
+				let value = self.parse_pragma_value()?;
+				self.expect(Token::RightParen)?;
+				Some(value)
+			}
+			_ => None,
+		};
+		Ok(Statement::Pragma { name, value })
+	}
+
+	/// A pragma value: a keyword, name, string or signed number
+	fn parse_pragma_value(&mut self) -> Result<String> {
+		let sign = match self.current_token() {
+			Some(Token::Minus) => "-",
+			Some(Token::Plus) => "+",
+			_ => "",
+		};
+		if !sign.is_empty() {
+			self.advance();
+		}
+		if matches!(self.current_token(), None | Some(Token::RightParen)) {
+			return Err(Error::Syntax("Expected a pragma value".to_string()));
+		}
+		let value = self.parse_value()?;
+		let value = value
+			.strip_prefix('\'')
+			.and_then(|v| v.strip_suffix('\''))
+			.unwrap_or(&value);
+		Ok(format!("{}{}", sign, value))
+	}
+
 	fn parse_savepoint(&mut self) -> Result<Statement> {
 		// SAVEPOINT name
 		let name = self.parse_identifier()?;
@@ -710,7 +1163,7 @@ impl Parser {
 		// Parse ON condition (except for CROSS JOIN)
 		let on_condition = if join_type != JoinType::Cross {
 			self.expect(Token::On)?;
-			Some(self.parse_where_clause()?)
+			Some(self.parse_expr()?)
 		} else {
 			None
 		};
@@ -747,7 +1200,7 @@ mod tests {
 		match result.unwrap() {
 			Statement::Select(stmt) => {
 				assert_eq!(stmt.columns.len(), 1);
-				assert!(matches!(stmt.columns[0], ColumnSelection::Column(ref s) if s == "*"));
+				assert!(matches!(stmt.columns[0], ColumnSelection::All(None)));
 			}
 			_ => panic!("Expected Select statement"),
 		}
@@ -781,6 +1234,51 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_parse_drop_table() {
+		let mut parser = Parser::new();
+		match parser.parse("DROP TABLE users").unwrap() {
+			Statement::DropTable(name) => assert_eq!(name, "users"),
+			_ => panic!("Expected DropTable statement"),
+		}
+		assert!(parser.parse("DROP users").is_err());
+	}
+
+	#[test]
+	fn test_parse_vacuum() {
+		let mut parser = Parser::new();
+		assert!(matches!(parser.parse("VACUUM").unwrap(), Statement::Vacuum(None)));
+		match parser.parse("VACUUM INTO '/tmp/copy.db'").unwrap() {
+			Statement::Vacuum(Some(path)) => assert_eq!(path, "/tmp/copy.db"),
+			_ => panic!("Expected Vacuum statement"),
+		}
+		assert!(parser.parse("VACUUM INTO copy").is_err());
+	}
+
+	#[test]
+	fn test_parse_pragma() {
+		let mut parser = Parser::new();
+		let pragma = |parser: &mut Parser, sql: &str| match parser.parse(sql).unwrap() {
+			Statement::Pragma { name, value } => (name, value),
+			_ => panic!("Expected Pragma statement"),
+		};
+		assert_eq!(pragma(&mut parser, "PRAGMA auto_vacuum"), ("auto_vacuum".to_string(), None));
+		assert_eq!(
+			pragma(&mut parser, "PRAGMA auto_vacuum = FULL"),
+			("auto_vacuum".to_string(), Some("FULL".to_string()))
+		);
+		assert_eq!(
+			pragma(&mut parser, "PRAGMA incremental_vacuum(-5)"),
+			("incremental_vacuum".to_string(), Some("-5".to_string()))
+		);
+		assert_eq!(
+			pragma(&mut parser, "PRAGMA journal_mode = 'delete'"),
+			("journal_mode".to_string(), Some("delete".to_string()))
+		);
+		assert!(parser.parse("PRAGMA auto_vacuum =").is_err());
+		assert!(parser.parse("PRAGMA incremental_vacuum(5").is_err());
+	}
+
 	#[test]
 	fn test_parse_update() {
 		let mut parser = Parser::new();
@@ -808,13 +1306,165 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_parse_where_expression() {
+		let mut parser = Parser::new();
+		let column = |name: &str| Box::new(Expr::column(name));
+		let int = |v: i64| Box::new(Expr::Literal(ValueType::I64(v)));
+		match parser.parse("SELECT * FROM t WHERE a = 1 OR b > 2 AND NOT c ORDER BY a").unwrap() {
+			Statement::Select(stmt) => assert_eq!(
+				stmt.where_clause.unwrap(),
+				Expr::binary(
+					Expr::binary(Expr::column("a"), BinaryOperator::Equal, Expr::Literal(ValueType::I64(1))),
+					BinaryOperator::Or,
+					Expr::binary(
+						Expr::binary(Expr::column("b"), BinaryOperator::GreaterThan, Expr::Literal(ValueType::I64(2))),
+						BinaryOperator::And,
+						Expr::Unary { op: UnaryOperator::Not, operand: column("c") },
+					),
+				)
+			),
+			_ => panic!("Expected Select statement"),
+		}
+
+		assert_eq!(
+			parser.parse_expression("t.a NOT BETWEEN -1 AND 2 + 3").unwrap(),
+			Expr::Between {
+				expr: Box::new(Expr::Column { table: Some("t".to_string()), name: "a".to_string() }),
+				low: int(-1),
+				high: Box::new(Expr::binary(Expr::Literal(ValueType::I64(2)), BinaryOperator::Add, Expr::Literal(ValueType::I64(3)))),
+				negated: true,
+			}
+		);
+		// Only a minus right before an integer is part of the literal
+		assert_eq!(parser.parse_expression("-9223372036854775808").unwrap(), *int(i64::MIN));
+		assert_eq!(
+			parser.parse_expression("-(1)").unwrap(),
+			Expr::Unary { op: UnaryOperator::Negate, operand: int(1) }
+		);
+		assert_eq!(
+			parser.parse_expression("name = 'a>=b'").unwrap(),
+			Expr::binary(Expr::column("name"), BinaryOperator::Equal, Expr::Literal(ValueType::StringUtf8("a>=b".to_string())))
+		);
+		assert!(parser.parse_expression("a IS NOT NULL").is_ok());
+		assert!(parser.parse_expression("a IN ()").is_ok());
+		assert!(parser.parse_expression("a = 1 b").is_err());
+		assert!(parser.parse_expression("a = @").is_err());
+		assert!(parser.parse("DELETE FROM t WHERE").is_err());
+	}
+
+	#[test]
+	fn test_parse_order_by_and_limit() {
+		let mut parser = Parser::new();
+		match parser.parse("SELECT * FROM t ORDER BY a DESC, b + 1 NULLS LAST, 2 DESC NULLS FIRST, last LIMIT 10 OFFSET 5").unwrap() {
+			Statement::Select(stmt) => {
+				let terms = stmt.order_by.unwrap();
+				let flags: Vec<(bool, bool)> = terms.iter().map(|term| (term.descending, term.nulls_first)).collect();
+				assert_eq!(flags, vec![(true, false), (false, false), (true, true), (false, true)]);
+				assert_eq!(terms[2].expr, Expr::Literal(ValueType::I64(2)));
+				assert_eq!(terms[3].expr, Expr::column("last"));
+				assert_eq!(stmt.limit, Some(Expr::Literal(ValueType::I64(10))));
+				assert_eq!(stmt.offset, Some(Expr::Literal(ValueType::I64(5))));
+			}
+			_ => panic!("Expected Select statement"),
+		}
+
+		// LIMIT skip, count
+		match parser.parse("SELECT * FROM t LIMIT 5, 10").unwrap() {
+			Statement::Select(stmt) => {
+				assert_eq!(stmt.limit, Some(Expr::Literal(ValueType::I64(10))));
+				assert_eq!(stmt.offset, Some(Expr::Literal(ValueType::I64(5))));
+			}
+			_ => panic!("Expected Select statement"),
+		}
+		assert!(parser.parse("SELECT * FROM t ORDER BY a NULLS").is_err());
+		assert!(parser.parse("SELECT * FROM t ORDER BY a NULLS MIDDLE").is_err());
+		assert!(parser.parse("SELECT * FROM t LIMIT").is_err());
+	}
+
+	#[test]
+	fn test_parse_group_by_having_and_distinct() {
+		let mut parser = Parser::new();
+		match parser.parse("SELECT DISTINCT a, COUNT(DISTINCT b) FROM t GROUP BY a, b % 2 HAVING SUM(c) > 1").unwrap() {
+			Statement::Select(stmt) => {
+				assert!(stmt.distinct);
+				match &stmt.columns[1] {
+					ColumnSelection::Expr { expr, name } => {
+						assert_eq!(name, "COUNT(DISTINCT b)");
+						assert_eq!(
+							*expr,
+							Expr::Aggregate {
+								function: AggregateFunction::Count,
+								argument: Some(Box::new(Expr::column("b"))),
+								distinct: true,
+							}
+						);
+					}
+					other => panic!("Expected an expression, got {:?}", other),
+				}
+				let group_by = stmt.group_by.unwrap();
+				assert_eq!(group_by.len(), 2);
+				assert_eq!(group_by[0], Expr::column("a"));
+				assert!(stmt.having.unwrap().is_aggregate());
+			}
+			_ => panic!("Expected Select statement"),
+		}
+
+		match parser.parse("SELECT COUNT(*) + 1 FROM t").unwrap() {
+			Statement::Select(stmt) => assert!(!stmt.distinct),
+			_ => panic!("Expected Select statement"),
+		}
+		assert!(parser.parse("SELECT SUM(*) FROM t").is_err());
+		assert!(parser.parse("SELECT COUNT(DISTINCT *) FROM t").is_err());
+		assert!(parser.parse("SELECT MAX() FROM t").is_err());
+		assert!(parser.parse("SELECT a FROM t GROUP BY").is_err());
+		assert!(parser.parse("SELECT a FROM t HAVING").is_err());
+	}
+
+	#[test]
+	fn test_parse_parameters() {
+		let mut parser = Parser::new();
+		let mut stmt = parser
+			.parse("UPDATE t SET a = ?, b = :b WHERE c = ?5 AND d IN (?, :b, @e, ?5)")
+			.unwrap();
+		let names: Vec<Option<&str>> = parser.parameters().iter().map(|name| name.as_deref()).collect();
+		assert_eq!(names, vec![None, Some(":b"), None, None, Some("?5"), None, Some("@e")]);
+
+		stmt.bind(&[ValueType::I64(1), ValueType::StringUtf8("x".to_string())]);
+		match stmt {
+			Statement::Update(stmt) => {
+				assert_eq!(stmt.set_clauses[0].1, Expr::Literal(ValueType::I64(1)));
+				assert_eq!(stmt.set_clauses[1].1, Expr::Literal(ValueType::StringUtf8("x".to_string())));
+			}
+			_ => panic!("Expected Update statement"),
+		}
+
+		assert!(parser.parse("SELECT * FROM t WHERE a = ?0").is_err());
+		assert!(parser.parse("SELECT * FROM t WHERE a = ?32767").is_err());
+		parser.parse("SELECT * FROM t").unwrap();
+		assert!(parser.parameters().is_empty());
+	}
+
 	#[test]
 	fn test_parse_transactions() {
 		let mut parser = Parser::new();
 		
 		let result = parser.parse("BEGIN");
 		assert!(result.is_ok());
-		assert!(matches!(result.unwrap(), Statement::BeginTransaction));
+		assert!(matches!(result.unwrap(), Statement::BeginTransaction(TransactionMode::Deferred)));
+
+		for (sql, mode) in [
+			("BEGIN TRANSACTION", TransactionMode::Deferred),
+			("BEGIN DEFERRED", TransactionMode::Deferred),
+			("begin immediate transaction", TransactionMode::Immediate),
+			("BEGIN EXCLUSIVE", TransactionMode::Exclusive),
+		] {
+			match parser.parse(sql).unwrap() {
+				Statement::BeginTransaction(parsed) => assert_eq!(parsed, mode, "{}", sql),
+				other => panic!("Expected BeginTransaction for {}, got {:?}", sql, other),
+			}
+		}
+		assert!(parser.parse("BEGIN EXCLUSIVE IMMEDIATE").is_err());
 
 		let result = parser.parse("COMMIT");
 		assert!(result.is_ok());
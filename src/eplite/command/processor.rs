@@ -1,17 +1,47 @@
 /// SQL command processor - coordinates tokenization, parsing, and execution
 
-use crate::eplite::command::parser::{AggregateFunction, ColumnSelection, Parser, Statement};
+use crate::eplite::command::expression::{Expr, Scope};
+use crate::eplite::command::parser::{ColumnSelection, OrderingTerm, Parser, SelectStatement, Statement};
+use crate::eplite::constants::{AutoVacuum, JournalMode, DEFAULT_MMAP_SIZE};
 use crate::eplite::error::{Error, Result};
-use crate::eplite::storage::StorageManager;
+use crate::eplite::persistence::cache::CacheStats;
+use crate::eplite::persistence::record;
+use crate::eplite::persistence::wal::{CheckpointMode, CheckpointResult};
+use crate::eplite::storage::{Row, StorageManager};
+use crate::eplite::types::ValueType;
 
+use core::cmp::Ordering;
+
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+#[cfg(feature = "std")]
+use std::fmt;
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, collections::{BTreeMap, BTreeSet, BinaryHeap}, format, string::{String, ToString}, vec, vec::Vec};
 #[cfg(not(feature = "std"))]
-use alloc::{format, string::{String, ToString}, vec, vec::Vec};
+use core::fmt;
+
+/// Called with the number of retries so far when a statement finds the
+/// database locked by another connection; the statement runs again while it
+/// returns true
+pub type BusyHandler = Box<dyn FnMut(u32) -> bool + Send>;
 
 /// Processes SQL commands
-#[derive(Debug)]
 pub struct Processor {
 	parser: Parser,
 	storage: StorageManager,
+	busy_handler: Option<BusyHandler>,
+}
+
+impl fmt::Debug for Processor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Processor")
+			.field("parser", &self.parser)
+			.field("storage", &self.storage)
+			.field("busy_handler", &self.busy_handler.is_some())
+			.finish()
+	}
 }
 
 impl Processor {
@@ -19,6 +49,7 @@ impl Processor {
 		Processor {
 			parser: Parser::new(),
 			storage: StorageManager::new(),
+			busy_handler: None,
 		}
 	}
 
@@ -27,6 +58,7 @@ impl Processor {
 		Processor {
 			parser: Parser::new(),
 			storage,
+			busy_handler: None,
 		}
 	}
 
@@ -35,382 +67,322 @@ impl Processor {
 		self.storage.flush()
 	}
 
+	/// Flush pending changes and checkpoint the write-ahead log
+	pub fn close(&mut self) -> Result<()> {
+		self.storage.close()
+	}
+
+	/// Copy the write-ahead log into the database file, returning the
+	/// frame counts, or None when the database is not in WAL mode
+	pub fn checkpoint(&mut self, mode: CheckpointMode) -> Result<Option<CheckpointResult>> {
+		self.storage.checkpoint(mode)
+	}
+
+	/// Hit, miss and eviction counts of the page cache
+	pub fn cache_stats(&self) -> CacheStats {
+		self.storage.cache_stats()
+	}
+
+	/// The storage the processor runs statements against
+	pub fn storage(&self) -> &StorageManager {
+		&self.storage
+	}
+
+	/// Set the handler that decides whether statements finding the database
+	/// busy run again, or None to fail them at once
+	pub fn set_busy_handler(&mut self, handler: Option<BusyHandler>) {
+		self.busy_handler = handler;
+	}
+
 	/// Execute a SQL statement
 	pub fn execute(&mut self, sql: &str) -> Result<ExecutionResult> {
-		// Parse the SQL
 		let statement = self.parser.parse(sql)?;
+		self.execute_parsed(statement)
+	}
 
+	/// Execute a statement parsed beforehand, such as a prepared one
+	pub fn execute_parsed(&mut self, statement: Statement) -> Result<ExecutionResult> {
+		// A busy statement inside a transaction only runs again to commit:
+		// the transaction holds its snapshot, so the connection it waits
+		// for could be waiting for it in turn
+		let retry = !self.storage.in_transaction() || matches!(statement, Statement::Commit | Statement::Release(_));
+		if !retry || self.busy_handler.is_none() {
+			return self.execute_statement(statement);
+		}
+		let mut retries = 0;
+		loop {
+			match self.execute_statement(statement.clone()) {
+				Err(Error::Busy) if self.busy_handler.as_mut().is_some_and(|handler| handler(retries)) => {
+					retries += 1;
+				}
+				result => return result,
+			}
+		}
+	}
+
+	/// Execute a SELECT, failing any other statement without running it
+	pub fn query(&mut self, sql: &str) -> Result<ExecutionResult> {
+		match self.parser.parse(sql)? {
+			statement @ Statement::Select(_) => self.execute_statement(statement),
+			_ => Err(Error::InvalidOperation("only SELECT statements can be queried".to_string())),
+		}
+	}
+
+	/// Execute a parsed statement, which reads one snapshot of the database
+	/// throughout
+	fn execute_statement(&mut self, statement: Statement) -> Result<ExecutionResult> {
+		self.storage.begin_read()?;
+		let result = self.run(statement);
+		self.storage.end_read().and(result)
+	}
+
+	/// Execute a parsed statement
+	fn run(&mut self, statement: Statement) -> Result<ExecutionResult> {
 		// Execute based on statement type
 		match statement {
-			Statement::Select(stmt) => {
-				// Check if this is a JOIN query
-				if !stmt.joins.is_empty() {
-					self.execute_join_select(&stmt)
-				} else if let Some(table) = self.storage.get_table(&stmt.from) {
-					// Check if this is an aggregate query
-					let has_aggregates = stmt.columns.iter().any(|col| matches!(
-						col,
-						ColumnSelection::Aggregate { .. } | ColumnSelection::CountStar
-					));
-
-					if has_aggregates {
-						// Process aggregate query (GROUP BY handled inside if present)
-						self.execute_aggregate_select(table, &stmt)
-					} else {
-						// Regular SELECT - check for ORDER BY
-						let rows: Vec<Vec<String>> = if let Some(order_cols) = &stmt.order_by {
-							// ORDER BY present - use first column
-							if !order_cols.is_empty() {
-								table
-									.select_ordered(stmt.where_clause.as_deref(), &order_cols[0], true)?
-									.into_iter()
-									.map(|row| row.iter().cloned().collect())
-									.collect()
-							} else {
-								table
-									.select(stmt.where_clause.as_deref())?
-									.into_iter()
-									.map(|row| row.iter().cloned().collect())
-									.collect()
-							}
-						} else {
-							// No ORDER BY
-							table
-								.select(stmt.where_clause.as_deref())?
-								.into_iter()
-								.map(|row| row.iter().cloned().collect())
-								.collect()
-						};
-						
-						// Extract column names for display
-						let column_names: Vec<String> = stmt.columns.iter().map(|col| {
-							match col {
-								ColumnSelection::Column(name) => name.clone(),
-								_ => "*".to_string(),
-							}
-						}).collect();
-						
-						Ok(ExecutionResult::Select {
-							rows,
-							columns: column_names,
-						})
-					}
-				} else {
-					Err(Error::NotFound(format!("Table '{}' not found", stmt.from)))
-				}
+			Statement::Select(stmt) => self.execute_select(&stmt),
+			Statement::Insert(stmt) => self.execute_write(|storage| {
+				let row = stmt
+					.values
+					.iter()
+					.map(|value| value.evaluate(&Scope::default(), &[]))
+					.collect::<Result<Vec<ValueType>>>()?;
+				storage.insert(&stmt.table, row)?;
+				Ok(ExecutionResult::RowsAffected(1))
+			}),
+			Statement::Update(stmt) => self.execute_write(|storage| {
+				let count = storage.update(&stmt.table, stmt.where_clause.as_ref(), &stmt.set_clauses)?;
+				Ok(ExecutionResult::RowsAffected(count))
+			}),
+			Statement::Delete(stmt) => self.execute_write(|storage| {
+				let count = storage.delete(&stmt.table, stmt.where_clause.as_ref())?;
+				Ok(ExecutionResult::RowsAffected(count))
+			}),
+			Statement::CreateTable(stmt) => self.execute_write(|storage| {
+				storage.create_table(stmt)?;
+				Ok(ExecutionResult::Success)
+			}),
+			Statement::DropTable(name) => self.execute_write(|storage| {
+				storage.drop_table(&name)?;
+				Ok(ExecutionResult::Success)
+			}),
+			Statement::Vacuum(_) if self.storage.in_transaction() => Err(Error::InvalidOperation(
+				"cannot VACUUM from within a transaction".to_string(),
+			)),
+			Statement::Vacuum(None) => self.execute_write(|storage| {
+				storage.vacuum()?;
+				Ok(ExecutionResult::Success)
+			}),
+			#[cfg(feature = "std")]
+			Statement::Vacuum(Some(path)) => {
+				self.storage.vacuum_into(&path)?;
+				Ok(ExecutionResult::Success)
 			}
-			Statement::Insert(stmt) => {
-				// Get the table
-				if let Some(table) = self.storage.get_table_mut(&stmt.table) {
-					table.insert(stmt.values)?;
-					// Flush to disk after insert
-					self.storage.flush()?;
-					Ok(ExecutionResult::RowsAffected(1))
-				} else {
-					Err(Error::NotFound(format!("Table '{}' not found", stmt.table)))
-				}
+			#[cfg(not(feature = "std"))]
+			Statement::Vacuum(Some(_)) => Err(Error::NotSupported("VACUUM INTO requires std".to_string())),
+			Statement::Pragma { name, value } => {
+				self.execute_write(|storage| Self::execute_pragma(storage, &name, value.as_deref()))
 			}
-			Statement::Update(stmt) => {
-				// Get the table
-				if let Some(table) = self.storage.get_table_mut(&stmt.table) {
-					let count = table.update(
-						stmt.where_clause.as_deref(),
-						&stmt.set_clauses,
-					)?;
-					// Flush to disk after update
-					self.storage.flush()?;
-					Ok(ExecutionResult::RowsAffected(count))
-				} else {
-					Err(Error::NotFound(format!("Table '{}' not found", stmt.table)))
-				}
+			Statement::BeginTransaction(mode) => {
+				self.storage.begin(mode)?;
+				Ok(ExecutionResult::Success)
 			}
-			Statement::Delete(stmt) => {
-				// Get the table
-				if let Some(table) = self.storage.get_table_mut(&stmt.table) {
-					let count = table.delete(stmt.where_clause.as_deref())?;
-					// Flush to disk after delete
-					self.storage.flush()?;
-					Ok(ExecutionResult::RowsAffected(count))
-				} else {
-					Err(Error::NotFound(format!("Table '{}' not found", stmt.table)))
-				}
+			Statement::Commit => {
+				self.storage.commit()?;
+				Ok(ExecutionResult::Success)
 			}
-			Statement::CreateTable(stmt) => {
-				self.storage.create_table(stmt)?;
+			Statement::Rollback => {
+				self.storage.rollback()?;
 				Ok(ExecutionResult::Success)
 			}
-			Statement::BeginTransaction => Ok(ExecutionResult::Success),
-			Statement::Commit => Ok(ExecutionResult::Success),
-			Statement::Rollback => Ok(ExecutionResult::Success),
-			Statement::Savepoint(_name) => {
-				// For now, savepoints are accepted but not enforced
-				// Full implementation requires transaction state tracking
+			Statement::Savepoint(name) => {
+				self.storage.savepoint(&name)?;
 				Ok(ExecutionResult::Success)
 			}
-			Statement::Release(_name) => {
-				// For now, releases are accepted but not enforced
-				// Full implementation requires transaction state tracking
+			Statement::Release(name) => {
+				self.storage.release(&name)?;
 				Ok(ExecutionResult::Success)
 			}
-			Statement::RollbackToSavepoint(_name) => {
-				// For now, rollback to savepoint is accepted but not enforced
-				// Full implementation requires transaction state tracking
+			Statement::RollbackToSavepoint(name) => {
+				self.storage.rollback_to(&name)?;
 				Ok(ExecutionResult::Success)
 			}
 		}
 	}
 
-	/// Execute aggregate SELECT query
-	fn execute_aggregate_select(
-		&self,
-		table: &crate::eplite::storage::Table,
-		stmt: &crate::eplite::command::parser::SelectStatement,
-	) -> Result<ExecutionResult> {
-		// Check if GROUP BY is present
-		if let Some(group_cols) = &stmt.group_by {
-			if !group_cols.is_empty() {
-				return self.execute_grouped_aggregate_select(table, stmt, &group_cols[0]);
-			}
-		}
-
-		// Get filtered rows
-		let rows = table.select(stmt.where_clause.as_deref())?;
-
-		// Calculate aggregates
-		let mut result_row = Vec::new();
-		let mut column_names = Vec::new();
+	/// Run a statement that changes the database
+	///
+	/// Inside a transaction the change waits for COMMIT. Otherwise the
+	/// statement is its own transaction and is written to disk at once.
+	/// Either way a statement that fails part way is undone.
+	fn execute_write<F>(&mut self, run: F) -> Result<ExecutionResult>
+	where
+		F: FnOnce(&mut StorageManager) -> Result<ExecutionResult>,
+	{
+		self.storage.atomic(run)
+	}
 
-		for col_sel in &stmt.columns {
-			match col_sel {
-				ColumnSelection::CountStar => {
-					result_row.push(rows.len().to_string());
-					column_names.push("COUNT(*)".to_string());
-				}
-				ColumnSelection::Aggregate { function, column } => {
-					// Find column index
-					let col_idx = table
-						.columns
-						.iter()
-						.position(|c| c.name == *column)
-						.ok_or_else(|| Error::NotFound(format!("Column '{}' not found", column)))?;
-
-					let result = match function {
-						AggregateFunction::Count => {
-							let count = rows.iter().filter(|r| col_idx < r.len()).count();
-							count.to_string()
-						}
-						AggregateFunction::Sum => {
-							let sum: f64 = rows
-								.iter()
-								.filter_map(|r| {
-									if col_idx < r.len() {
-										r[col_idx].parse::<f64>().ok()
-									} else {
-										None
-									}
-								})
-								.sum();
-			sum.to_string()
-						}
-						AggregateFunction::Avg => {
-							let values: Vec<f64> = rows
-								.iter()
-								.filter_map(|r| {
-									if col_idx < r.len() {
-										r[col_idx].parse::<f64>().ok()
-									} else {
-										None
-									}
-								})
-								.collect();
-							if values.is_empty() {
-								"0".to_string()
-							} else {
-								let avg = values.iter().sum::<f64>() / values.len() as f64;
-								avg.to_string()
-							}
-						}
-						AggregateFunction::Min => rows
-							.iter()
-							.filter_map(|r| {
-								if col_idx < r.len() {
-									Some(r[col_idx].clone())
-								} else {
-									None
-								}
-							})
-							.min()
-							.unwrap_or_else(|| "NULL".to_string()),
-						AggregateFunction::Max => rows
-							.iter()
-							.filter_map(|r| {
-								if col_idx < r.len() {
-									Some(r[col_idx].clone())
-								} else {
-									None
-								}
-							})
-							.max()
-							.unwrap_or_else(|| "NULL".to_string()),
-					};
-
-					result_row.push(result);
-					column_names.push(format!("{}({})", 
-						match function {
-							AggregateFunction::Count => "COUNT",
-							AggregateFunction::Sum => "SUM",
-							AggregateFunction::Avg => "AVG",
-							AggregateFunction::Min => "MIN",
-							AggregateFunction::Max => "MAX",
-						},
-						column
-					));
-				}
-				ColumnSelection::Column(_) => {
-					// Regular columns in aggregate query - not supported yet
-					return Err(Error::Syntax(
-						"Non-aggregate columns require GROUP BY".to_string(),
-					));
+	/// Run a PRAGMA, returning the setting's value when it is queried
+	fn execute_pragma(storage: &mut StorageManager, name: &str, value: Option<&str>) -> Result<ExecutionResult> {
+		let result = |value: u32| ExecutionResult::Select {
+			rows: vec![vec![ValueType::I64(value as i64)]],
+			columns: vec![name.to_string()],
+		};
+		match (name.to_ascii_lowercase().as_str(), value) {
+			("auto_vacuum", None) => Ok(result(storage.auto_vacuum().to_u32())),
+			("auto_vacuum", Some(value)) => {
+				let mode = AutoVacuum::from_name(value)
+					.ok_or_else(|| Error::Syntax(format!("Invalid auto_vacuum mode: {}", value)))?;
+				storage.set_auto_vacuum(mode)?;
+				Ok(ExecutionResult::Success)
+			}
+			("incremental_vacuum", value) => {
+				let pages = value
+					.map(|v| v.parse::<i64>())
+					.transpose()
+					.map_err(|_| Error::Syntax(format!("Invalid page count: {}", value.unwrap_or_default())))?;
+				// Zero or a negative count frees the whole freelist
+				let limit = pages.filter(|&n| n > 0).map(|n| n.min(u32::MAX as i64) as u32);
+				storage.incremental_vacuum(limit)?;
+				Ok(ExecutionResult::Success)
+			}
+			("journal_mode", value) => {
+				let mode = match value {
+					Some(value) => {
+						let mode = JournalMode::from_name(value)
+							.ok_or_else(|| Error::Syntax(format!("Invalid journal_mode: {}", value)))?;
+						storage.set_journal_mode(mode)?
+					}
+					None => storage.journal_mode(),
+				};
+				Ok(ExecutionResult::Select {
+					rows: vec![vec![ValueType::StringUtf8(mode.name().to_string())]],
+					columns: vec![name.to_string()],
+				})
+			}
+			("wal_autocheckpoint", None) => Ok(result(storage.wal_autocheckpoint())),
+			("wal_autocheckpoint", Some(value)) => {
+				// SQLite treats a negative size like 0, turning checkpoints off
+				let frames = value
+					.parse::<i64>()
+					.map_err(|_| Error::Syntax(format!("Invalid frame count: {}", value)))?;
+				storage.set_wal_autocheckpoint(frames.clamp(0, u32::MAX as i64) as u32);
+				Ok(result(storage.wal_autocheckpoint()))
+			}
+			("wal_checkpoint", value) => {
+				let mode = match value {
+					Some(value) => CheckpointMode::from_name(value)
+						.ok_or_else(|| Error::Syntax(format!("Invalid checkpoint mode: {}", value)))?,
+					None => CheckpointMode::Passive,
+				};
+				// As in SQLite, both frame counts are -1 outside WAL mode, and
+				// only a blocking mode that could not finish reports busy
+				let (busy, log, checkpointed) = match storage.checkpoint(mode)? {
+					Some(result) => (
+						(mode != CheckpointMode::Passive && !result.completed) as i64,
+						result.wal_frames as i64,
+						result.checkpointed_frames as i64,
+					),
+					None => (0, -1, -1),
+				};
+				Ok(ExecutionResult::Select {
+					rows: vec![vec![ValueType::I64(busy), ValueType::I64(log), ValueType::I64(checkpointed)]],
+					columns: vec!["busy".to_string(), "log".to_string(), "checkpointed".to_string()],
+				})
+			}
+			("cache_size", None) => Ok(ExecutionResult::Select {
+				rows: vec![vec![ValueType::I64(storage.cache_size())]],
+				columns: vec![name.to_string()],
+			}),
+			("cache_size", Some(value)) => {
+				let size = value
+					.parse::<i64>()
+					.map_err(|_| Error::Syntax(format!("Invalid cache size: {}", value)))?;
+				storage.set_cache_size(size);
+				Ok(ExecutionResult::Success)
+			}
+			("cache_stats", None) => {
+				let stats = storage.cache_stats();
+				Ok(ExecutionResult::Select {
+					rows: vec![vec![
+						ValueType::I64(stats.hits as i64),
+						ValueType::I64(stats.misses as i64),
+						ValueType::I64(stats.evictions as i64),
+						ValueType::I64(stats.pages as i64),
+						ValueType::I64(stats.capacity as i64),
+					]],
+					columns: vec![
+						"hits".to_string(),
+						"misses".to_string(),
+						"evictions".to_string(),
+						"pages".to_string(),
+						"capacity".to_string(),
+					],
+				})
+			}
+			("mmap_size", value) => {
+				if let Some(value) = value {
+					// As in SQLite, a negative size restores the default
+					let size = value
+						.parse::<i64>()
+						.map_err(|_| Error::Syntax(format!("Invalid mmap size: {}", value)))?;
+					storage.set_mmap_size(u64::try_from(size).unwrap_or(DEFAULT_MMAP_SIZE))?;
 				}
+				Ok(ExecutionResult::Select {
+					rows: vec![vec![ValueType::I64(storage.mmap_size() as i64)]],
+					columns: vec![name.to_string()],
+				})
 			}
+			("freelist_count", None) => Ok(result(storage.freelist_count()?)),
+			("page_count", None) => Ok(result(storage.page_count())),
+			_ => Err(Error::NotSupported(format!("Unsupported pragma: {}", name))),
 		}
+	}
 
-		Ok(ExecutionResult::Select {
-			rows: vec![result_row],
-			columns: column_names,
-		})
-	}
-
-	/// Execute aggregates with GROUP BY
-	fn execute_grouped_aggregate_select(
-		&self,
-		table: &crate::eplite::storage::Table,
-		stmt: &crate::eplite::command::parser::SelectStatement,
-		group_col: &str,
-	) -> Result<ExecutionResult> {
-		// Get grouped rows
-		let groups = table.select_grouped(stmt.where_clause.as_deref(), group_col)?;
-
-		let mut result_rows = Vec::new();
-		let mut column_names = Vec::new();
-
-		// Build column names first
-		column_names.push(group_col.to_string());
-		for col_sel in &stmt.columns {
-			match col_sel {
-				ColumnSelection::CountStar => {
-					column_names.push("COUNT(*)".to_string());
-				}
-				ColumnSelection::Aggregate { function, column } => {
-					column_names.push(format!("{}({})", 
-						match function {
-							AggregateFunction::Count => "COUNT",
-							AggregateFunction::Sum => "SUM",
-							AggregateFunction::Avg => "AVG",
-							AggregateFunction::Min => "MIN",
-							AggregateFunction::Max => "MAX",
-						},
-						column
-					));
-				}
-				_ => {}
-			}
+	/// Execute a SELECT: read the rows of its table, or of its join, that
+	/// WHERE keeps, then compute its result from them
+	fn execute_select(&mut self, stmt: &SelectStatement) -> Result<ExecutionResult> {
+		if stmt.where_clause.as_ref().is_some_and(Expr::is_aggregate) {
+			return Err(Error::Syntax("misuse of aggregate function in WHERE".to_string()));
 		}
+		let rows = if stmt.joins.is_empty() {
+			self.storage.select(&stmt.from, stmt.where_clause.as_ref())?
+		} else {
+			self.join_rows(stmt)?
+		};
 
-		// Process each group
-		for (group_key, rows) in groups {
-			let mut result_row = vec![group_key];
+		let table = |name: &str| {
+			self.storage.get_table(name).ok_or_else(|| Error::NotFound(format!("Table '{}' not found", name)))
+		};
+		let mut scope = Scope::new(&stmt.from, &table(&stmt.from)?.columns);
+		for join in &stmt.joins {
+			scope = scope.join(&join.table, &table(&join.table)?.columns);
+		}
 
-			for col_sel in &stmt.columns {
-				match col_sel {
-					ColumnSelection::CountStar => {
-						result_row.push(rows.len().to_string());
-					}
-					ColumnSelection::Aggregate { function, column } => {
-						// Find column index
-						let col_idx = table
-							.columns
-							.iter()
-							.position(|c| c.name == *column)
-							.ok_or_else(|| Error::NotFound(format!("Column '{}' not found", column)))?;
-
-						let result = match function {
-							AggregateFunction::Count => {
-								let count = rows.iter().filter(|r| col_idx < r.len()).count();
-								count.to_string()
-							}
-							AggregateFunction::Sum => {
-								let sum: f64 = rows
-									.iter()
-									.filter_map(|r| {
-										if col_idx < r.len() {
-											r[col_idx].parse::<f64>().ok()
-										} else {
-											None
-										}
-									})
-									.sum();
-								sum.to_string()
-							}
-							AggregateFunction::Avg => {
-								let values: Vec<f64> = rows
-									.iter()
-									.filter_map(|r| {
-										if col_idx < r.len() {
-											r[col_idx].parse::<f64>().ok()
-										} else {
-											None
-										}
-									})
-									.collect();
-								if values.is_empty() {
-									"0".to_string()
-								} else {
-									let avg = values.iter().sum::<f64>() / values.len() as f64;
-									avg.to_string()
-								}
-							}
-							AggregateFunction::Min => rows
-								.iter()
-								.filter_map(|r| {
-									if col_idx < r.len() {
-										Some(r[col_idx].clone())
-									} else {
-										None
-									}
-								})
-								.min()
-								.unwrap_or_else(|| "NULL".to_string()),
-							AggregateFunction::Max => rows
-								.iter()
-								.filter_map(|r| {
-									if col_idx < r.len() {
-										Some(r[col_idx].clone())
-									} else {
-										None
-									}
-								})
-								.max()
-								.unwrap_or_else(|| "NULL".to_string()),
-						};
-
-						result_row.push(result);
+		// Apply the WHERE clause of a join, over the columns of both tables
+		let rows = match &stmt.where_clause {
+			Some(condition) if !stmt.joins.is_empty() => {
+				condition.resolve(&scope)?;
+				let mut filtered = Vec::new();
+				for row in rows {
+					if condition.matches(&scope, &row)? {
+						filtered.push(row);
 					}
-					_ => {}
 				}
+				filtered
 			}
+			_ => rows,
+		};
 
-			result_rows.push(result_row);
+		if grouped(stmt) {
+			group(stmt, &scope, rows)
+		} else {
+			project(stmt, &scope, rows)
 		}
-
-		Ok(ExecutionResult::Select {
-			rows: result_rows,
-			columns: column_names,
-		})
 	}
 
-	/// Execute SELECT with JOIN clauses
-	fn execute_join_select(&self, stmt: &crate::eplite::command::parser::SelectStatement) -> Result<ExecutionResult> {
+	/// Rows of the join of a SELECT, with the columns of both tables
+	fn join_rows(&mut self, stmt: &SelectStatement) -> Result<Vec<Row>> {
 		use crate::eplite::command::parser::JoinType;
 
 		// Currently we support single JOIN operations
@@ -424,7 +396,7 @@ impl Processor {
 		let table2_name = &join.table;
 
 		// Execute the appropriate join
-		let (rows, columns) = match join.join_type {
+		let (rows, _) = match join.join_type {
 			JoinType::Cross => {
 				self.storage.cross_join(table1_name, table2_name)?
 			}
@@ -444,20 +416,390 @@ impl Processor {
 				self.storage.right_join(table1_name, table2_name, on_condition)?
 			}
 		};
+		Ok(rows)
+	}
+}
 
-		// Apply WHERE clause if present
-		let filtered_rows = if let Some(where_clause) = &stmt.where_clause {
-			// For now, simple filtering - would need proper WHERE evaluation for joins
-			// This is a simplified implementation
-			rows
-		} else {
-			rows
+/// The result columns of a select list, with `*` and `table.*` expanded
+struct SelectList<'a> {
+	names: Vec<String>,
+	exprs: Vec<Expr>,
+	/// Aliases ORDER BY and GROUP BY can name, with their columns' positions
+	aliases: Vec<(&'a str, usize)>,
+}
+
+fn select_list<'a>(stmt: &'a SelectStatement, scope: &Scope) -> Result<SelectList<'a>> {
+	let mut names = Vec::new();
+	let mut exprs = Vec::new();
+	let mut aliases = Vec::new();
+	for column in &stmt.columns {
+		match column {
+			ColumnSelection::All(table) => {
+				for (name, expr) in scope.expand(table.as_deref())? {
+					names.push(name);
+					exprs.push(expr);
+				}
+			}
+			ColumnSelection::Expr { expr, name } => {
+				expr.resolve(scope)?;
+				aliases.push((name.as_str(), names.len()));
+				names.push(name.clone());
+				exprs.push(expr.clone());
+			}
+		}
+	}
+	Ok(SelectList { names, exprs, aliases })
+}
+
+/// Whether a query gives a row per group of rows rather than per row: it
+/// has GROUP BY or HAVING, or selects an aggregate
+fn grouped(stmt: &SelectStatement) -> bool {
+	stmt.group_by.is_some()
+		|| stmt.having.is_some()
+		|| stmt.columns.iter().any(|column| matches!(column, ColumnSelection::Expr { expr, .. } if expr.is_aggregate()))
+}
+
+/// Evaluate the select list of a query for each of its rows, naming the
+/// result's columns, then sort and cut the result as DISTINCT, ORDER BY,
+/// LIMIT and OFFSET ask
+fn project(stmt: &SelectStatement, scope: &Scope, rows: Vec<Row>) -> Result<ExecutionResult> {
+	let SelectList { names, exprs, aliases } = select_list(stmt, scope)?;
+	let terms = stmt.order_by.as_deref().unwrap_or_default();
+	let keys = terms
+		.iter()
+		.map(|term| sort_key(term, &aliases, names.len(), scope))
+		.collect::<Result<Vec<SortKey>>>()?;
+	let (skip, take) = bounds(stmt)?;
+
+	// Rows already hold every column in order when selecting `*`
+	let all = matches!(stmt.columns.as_slice(), [ColumnSelection::All(None)]);
+	let mut sorter = Sorter::new(terms, skip, take, rows.len(), stmt.distinct);
+	for row in rows {
+		if sorter.full() {
+			break;
+		}
+		let result = match all {
+			true => None,
+			false => Some(exprs.iter().map(|expr| expr.evaluate(scope, &row)).collect::<Result<Row>>()?),
 		};
+		let result_row = result.as_ref().unwrap_or(&row);
+		let values = keys
+			.iter()
+			.map(|key| match key {
+				SortKey::Column(i) => Ok(result_row[*i].clone()),
+				SortKey::Expr(expr) => expr.evaluate(scope, &row),
+			})
+			.collect::<Result<Row>>()?;
+		sorter.push(values, result.unwrap_or(row));
+	}
+	Ok(ExecutionResult::Select { rows: sorter.finish(), columns: names })
+}
+
+/// Compute an aggregate query: one result row for each group of rows with
+/// the same GROUP BY values, or for all the rows without GROUP BY, if HAVING
+/// holds for it, then sort and cut the result as `project` does
+///
+/// Groups come in the order of their GROUP BY values. Aggregates are taken
+/// over the group, and columns outside them read its last row, as in SQLite;
+/// HAVING can also name result columns by their aliases.
+fn group(stmt: &SelectStatement, scope: &Scope, rows: Vec<Row>) -> Result<ExecutionResult> {
+	let SelectList { names, exprs, aliases } = select_list(stmt, scope)?;
+	let keys = group_keys(stmt, &exprs, &aliases, scope)?;
+	let named: Vec<(&str, &Expr)> = aliases.iter().map(|(alias, i)| (*alias, &exprs[*i])).collect();
+	let having = stmt.having.as_ref().map(|having| having.dealiased(scope, &named));
+	if let Some(having) = &having {
+		having.resolve(scope)?;
+	}
+	let terms = stmt.order_by.as_deref().unwrap_or_default();
+	let sort_keys = terms
+		.iter()
+		.map(|term| sort_key(term, &aliases, names.len(), scope))
+		.collect::<Result<Vec<SortKey>>>()?;
+	let (skip, take) = bounds(stmt)?;
+
+	let mut groups: BTreeMap<Values, Vec<Row>> = BTreeMap::new();
+	if stmt.group_by.is_none() {
+		groups.insert(Values(Vec::new()), rows);
+	} else {
+		for row in rows {
+			let values = keys.iter().map(|key| key.evaluate(scope, &row)).collect::<Result<Row>>()?;
+			groups.entry(Values(values)).or_default().push(row);
+		}
+	}
+
+	let mut sorter = Sorter::new(terms, skip, take, groups.len(), stmt.distinct);
+	for rows in groups.into_values() {
+		if sorter.full() {
+			break;
+		}
+		let row = rows.last().map_or(&[][..], Vec::as_slice);
+		if let Some(having) = &having {
+			if !having.aggregated(scope, &rows)?.matches(scope, row)? {
+				continue;
+			}
+		}
+		let result = exprs
+			.iter()
+			.map(|expr| expr.aggregated(scope, &rows)?.evaluate(scope, row))
+			.collect::<Result<Row>>()?;
+		let values = sort_keys
+			.iter()
+			.map(|key| match key {
+				SortKey::Column(i) => Ok(result[*i].clone()),
+				SortKey::Expr(expr) => expr.aggregated(scope, &rows)?.evaluate(scope, row),
+			})
+			.collect::<Result<Row>>()?;
+		sorter.push(values, result);
+	}
+	Ok(ExecutionResult::Select { rows: sorter.finish(), columns: names })
+}
+
+/// Resolve the GROUP BY terms: as in SQLite, an integer literal numbers a
+/// result column from 1, and a bare name that is no column in scope but a
+/// result column's alias stands for that column's expression
+fn group_keys<'a>(
+	stmt: &'a SelectStatement,
+	exprs: &'a [Expr],
+	aliases: &[(&str, usize)],
+	scope: &Scope,
+) -> Result<Vec<&'a Expr>> {
+	let mut keys = Vec::new();
+	for term in stmt.group_by.iter().flatten() {
+		let key = match term {
+			Expr::Literal(ValueType::I64(number)) => usize::try_from(*number)
+				.ok()
+				.filter(|number| (1..=exprs.len()).contains(number))
+				.map(|number| &exprs[number - 1])
+				.ok_or_else(|| Error::Syntax(format!(
+					"GROUP BY term {} out of range - should be between 1 and {}",
+					number,
+					exprs.len()
+				)))?,
+			Expr::Column { table: None, name } if scope.position(None, name).is_err() => aliases
+				.iter()
+				.find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+				.map_or(term, |(_, i)| &exprs[*i]),
+			term => term,
+		};
+		if key.is_aggregate() {
+			return Err(Error::Syntax("aggregate functions are not allowed in the GROUP BY clause".to_string()));
+		}
+		key.resolve(scope)?;
+		keys.push(key);
+	}
+	Ok(keys)
+}
+
+/// What an ORDER BY term sorts by
+enum SortKey<'a> {
+	/// A result column, by position
+	Column(usize),
+	/// An expression over the rows the result is computed from, which in
+	/// an aggregate query may hold aggregates over each group
+	Expr(&'a Expr),
+}
+
+/// Resolve an ORDER BY term: an integer literal numbers a result column
+/// from 1, a bare name that is a result column's alias stands for it, and
+/// anything else is an expression over the scope
+fn sort_key<'a>(term: &'a OrderingTerm, aliases: &[(&str, usize)], count: usize, scope: &Scope) -> Result<SortKey<'a>> {
+	match &term.expr {
+		Expr::Literal(ValueType::I64(number)) => usize::try_from(*number)
+			.ok()
+			.filter(|number| (1..=count).contains(number))
+			.map(|number| SortKey::Column(number - 1))
+			.ok_or_else(|| Error::Syntax(format!(
+				"ORDER BY term {} out of range - should be between 1 and {}",
+				number, count
+			))),
+		expr => {
+			if let Expr::Column { table: None, name } = expr {
+				if let Some((_, i)) = aliases.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(name)) {
+					return Ok(SortKey::Column(*i));
+				}
+			}
+			expr.resolve(scope)?;
+			Ok(SortKey::Expr(expr))
+		}
+	}
+}
+
+/// Rows to skip and the most rows to return, from OFFSET and LIMIT; as in
+/// SQLite a negative LIMIT means no limit, and a negative OFFSET none
+fn bounds(stmt: &SelectStatement) -> Result<(usize, usize)> {
+	let evaluate = |expr: &Option<Expr>, clause: &str| -> Result<Option<i64>> {
+		let Some(expr) = expr else {
+			return Ok(None);
+		};
+		let value = expr.evaluate(&Scope::default(), &[])?;
+		let number = match value {
+			ValueType::I64(v) => Some(v),
+			ValueType::F64(v) if v.fract() == 0.0 => Some(v as i64),
+			ValueType::StringUtf8(ref text) => match ValueType::parse_number(text) {
+				Some(ValueType::I64(v)) => Some(v),
+				_ => None,
+			},
+			_ => None,
+		};
+		number
+			.map(Some)
+			.ok_or_else(|| Error::TypeMismatch(format!("{} must be an integer, not {}", clause, value)))
+	};
+	let take = evaluate(&stmt.limit, "LIMIT")?.map_or(usize::MAX, |v| usize::try_from(v).unwrap_or(usize::MAX));
+	let skip = evaluate(&stmt.offset, "OFFSET")?.map_or(0, |v| usize::try_from(v).unwrap_or(0));
+	Ok((skip, take))
+}
+
+/// Order two rows by their ORDER BY keys
+fn compare_keys(terms: &[OrderingTerm], a: &[ValueType], b: &[ValueType]) -> Ordering {
+	for ((term, a), b) in terms.iter().zip(a).zip(b) {
+		let ordering = match (a.is_null(), b.is_null()) {
+			(true, true) => Ordering::Equal,
+			(true, false) if term.nulls_first => Ordering::Less,
+			(true, false) => Ordering::Greater,
+			(false, true) if term.nulls_first => Ordering::Greater,
+			(false, true) => Ordering::Less,
+			(false, false) if term.descending => record::compare_values(a, b).reverse(),
+			(false, false) => record::compare_values(a, b),
+		};
+		if ordering.is_ne() {
+			return ordering;
+		}
+	}
+	Ordering::Equal
+}
+
+/// A result row with its ORDER BY keys, ordered by them and then by when
+/// it arrived, so rows with equal keys keep the order they were read in
+struct Sorted<'a> {
+	terms: &'a [OrderingTerm],
+	keys: Row,
+	sequence: usize,
+	row: Row,
+}
+
+impl Ord for Sorted<'_> {
+	fn cmp(&self, other: &Self) -> Ordering {
+		compare_keys(self.terms, &self.keys, &other.keys).then(self.sequence.cmp(&other.sequence))
+	}
+}
+
+impl PartialOrd for Sorted<'_> {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for Sorted<'_> {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other).is_eq()
+	}
+}
+
+impl Eq for Sorted<'_> {}
+
+/// A row of values ordered one value after another as ORDER BY orders
+/// them, with NULLs first and equal to each other, so rows can be told
+/// apart for DISTINCT and GROUP BY
+struct Values(Row);
+
+impl Ord for Values {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.0
+			.iter()
+			.zip(&other.0)
+			.map(|(a, b)| record::compare_values(a, b))
+			.find(|ordering| ordering.is_ne())
+			.unwrap_or_else(|| self.0.len().cmp(&other.0.len()))
+	}
+}
 
-		Ok(ExecutionResult::Select {
-			rows: filtered_rows,
-			columns,
-		})
+impl PartialOrd for Values {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for Values {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other).is_eq()
+	}
+}
+
+impl Eq for Values {}
+
+/// Rows being put in ORDER BY order, of which LIMIT and OFFSET keep some,
+/// and of which DISTINCT drops those equal to one before
+///
+/// When fewer rows are wanted than there are, only the first ones so far
+/// are kept, in a heap whose top is the last of them, rather than sorting
+/// every row.
+struct Sorter<'a> {
+	terms: &'a [OrderingTerm],
+	skip: usize,
+	take: usize,
+	/// How many rows the heap keeps, when it is used
+	wanted: Option<usize>,
+	/// Rows pushed so far
+	count: usize,
+	heap: BinaryHeap<Sorted<'a>>,
+	rows: Vec<Sorted<'a>>,
+	/// Rows pushed so far, for DISTINCT
+	seen: Option<BTreeSet<Values>>,
+}
+
+impl<'a> Sorter<'a> {
+	fn new(terms: &'a [OrderingTerm], skip: usize, take: usize, count: usize, distinct: bool) -> Self {
+		Sorter {
+			terms,
+			skip,
+			take,
+			wanted: skip.checked_add(take).filter(|&wanted| wanted < count),
+			count: 0,
+			heap: BinaryHeap::new(),
+			rows: Vec::new(),
+			seen: distinct.then(BTreeSet::new),
+		}
+	}
+
+	/// Whether no row pushed from now on can be kept: without ORDER BY,
+	/// rows keep the order they are pushed in
+	fn full(&self) -> bool {
+		self.terms.is_empty() && self.wanted.is_some_and(|wanted| self.heap.len() >= wanted)
+	}
+
+	fn push(&mut self, keys: Row, row: Row) {
+		if let Some(seen) = &mut self.seen {
+			if !seen.insert(Values(row.clone())) {
+				return;
+			}
+		}
+		let sorted = Sorted { terms: self.terms, keys, sequence: self.count, row };
+		self.count += 1;
+		match self.wanted {
+			None => self.rows.push(sorted),
+			Some(wanted) if self.heap.len() < wanted => self.heap.push(sorted),
+			Some(_) => {
+				if let Some(mut last) = self.heap.peek_mut() {
+					if sorted < *last {
+						*last = sorted;
+					}
+				}
+			}
+		}
+	}
+
+	/// The rows LIMIT and OFFSET keep, in order
+	fn finish(self) -> Vec<Row> {
+		let rows = match self.wanted {
+			Some(_) => self.heap.into_sorted_vec(),
+			None => {
+				let mut rows = self.rows;
+				rows.sort();
+				rows
+			}
+		};
+		rows.into_iter().skip(self.skip).take(self.take).map(|sorted| sorted.row).collect()
 	}
 }
 
@@ -472,7 +814,7 @@ impl Default for Processor {
 pub enum ExecutionResult {
 	/// SELECT statement result
 	Select {
-		rows: Vec<Vec<String>>,
+		rows: Vec<Vec<ValueType>>,
 		columns: Vec<String>,
 	},
 	/// Number of rows affected by INSERT/UPDATE/DELETE
@@ -506,7 +848,7 @@ mod tests {
 		assert!(result.is_ok());
 		match result.unwrap() {
 			ExecutionResult::Select { columns, .. } => {
-				assert_eq!(columns.len(), 1);
+				assert_eq!(columns, vec!["id", "name"]);
 			}
 			_ => panic!("Expected Select result"),
 		}
@@ -548,10 +890,63 @@ mod tests {
 		let result = processor.execute("COMMIT");
 		assert!(result.is_ok());
 		
+		// There is nothing left to roll back
+		let result = processor.execute("ROLLBACK");
+		assert!(result.is_err());
+
+		processor.execute("BEGIN").unwrap();
+		assert!(processor.execute("BEGIN").is_err());
 		let result = processor.execute("ROLLBACK");
 		assert!(result.is_ok());
 	}
 
+	#[test]
+	fn test_rollback_restores_rows_and_tables() {
+		let mut processor = Processor::new();
+		let count = |processor: &mut Processor| match processor.execute("SELECT COUNT(*) FROM users").unwrap() {
+			ExecutionResult::Select { rows, .. } => rows[0][0].clone(),
+			other => panic!("Expected Select result, got {:?}", other),
+		};
+		processor.execute("CREATE TABLE users (id INTEGER, name TEXT)").unwrap();
+		processor.execute("INSERT INTO users VALUES (1, 'Alice')").unwrap();
+
+		processor.execute("BEGIN").unwrap();
+		for i in 2..=500 {
+			processor.execute(&format!("INSERT INTO users VALUES ({}, 'user {}')", i, i)).unwrap();
+		}
+		processor.execute("DELETE FROM users WHERE id = 1").unwrap();
+		processor.execute("CREATE TABLE scratch (id INTEGER)").unwrap();
+		assert_eq!(count(&mut processor), ValueType::I64(499));
+		processor.execute("ROLLBACK").unwrap();
+
+		assert_eq!(count(&mut processor), ValueType::I64(1));
+		assert!(processor.execute("SELECT * FROM scratch").is_err());
+
+		processor.execute("BEGIN").unwrap();
+		processor.execute("INSERT INTO users VALUES (2, 'Bob')").unwrap();
+		processor.execute("COMMIT").unwrap();
+		assert_eq!(count(&mut processor), ValueType::I64(2));
+	}
+
+	#[test]
+	fn test_execute_pragma() {
+		let mut processor = Processor::new();
+		let value = |processor: &mut Processor, sql: &str| match processor.execute(sql).unwrap() {
+			ExecutionResult::Select { rows, .. } => rows[0][0].to_string(),
+			other => panic!("Expected Select result, got {:?}", other),
+		};
+		assert_eq!(value(&mut processor, "PRAGMA auto_vacuum"), "0");
+		processor.execute("PRAGMA auto_vacuum = INCREMENTAL").unwrap();
+		assert_eq!(value(&mut processor, "PRAGMA auto_vacuum"), "2");
+		assert!(processor.execute("PRAGMA auto_vacuum = sometimes").is_err());
+		// In-memory databases only keep their journal in memory
+		assert_eq!(value(&mut processor, "PRAGMA journal_mode = PERSIST"), "memory");
+		assert!(processor.execute("PRAGMA journal_mode = sometimes").is_err());
+		assert_eq!(value(&mut processor, "PRAGMA wal_checkpoint(FULL)"), "0");
+		assert!(processor.execute("PRAGMA wal_checkpoint(sometimes)").is_err());
+		assert!(processor.execute("PRAGMA no_such_pragma").is_err());
+	}
+
 	#[test]
 	fn test_execute_invalid_sql() {
 		let mut processor = Processor::new();
@@ -1,13 +1,15 @@
 /// SQL tokenizer - breaks SQL text into tokens
 
+use crate::eplite::error::{Error, Result};
+use core::ops::Range;
 use logos::Logos;
 
 #[cfg(not(feature = "std"))]
-use alloc::{string::String, vec::Vec};
+use alloc::{format, string::String, vec::Vec};
 
 /// SQL token types
 #[derive(Logos, Debug, Clone, PartialEq)]
-#[logos(skip r"[ \t\n\f]+")]
+#[logos(skip r"[ \t\r\n\f]+")]
 pub enum Token {
 	// Keywords - Data Manipulation
 	#[token("SELECT", ignore(ascii_case))]
@@ -132,6 +134,12 @@ pub enum Token {
 	Rollback,
 	#[token("TRANSACTION", ignore(ascii_case))]
 	Transaction,
+	#[token("DEFERRED", ignore(ascii_case))]
+	Deferred,
+	#[token("IMMEDIATE", ignore(ascii_case))]
+	Immediate,
+	#[token("EXCLUSIVE", ignore(ascii_case))]
+	Exclusive,
 	#[token("SAVEPOINT", ignore(ascii_case))]
 	Savepoint,
 	#[token("RELEASE", ignore(ascii_case))]
@@ -158,6 +166,10 @@ pub enum Token {
 	Else,
 	#[token("END", ignore(ascii_case))]
 	End,
+	#[token("PRAGMA", ignore(ascii_case))]
+	Pragma,
+	#[token("VACUUM", ignore(ascii_case))]
+	Vacuum,
 	
 	// Data Types
 	#[token("INTEGER", ignore(ascii_case))]
@@ -173,6 +185,7 @@ pub enum Token {
 	
 	// Operators
 	#[token("=")]
+	#[token("==")]
 	Equals,
 	#[token("!=")]
 	NotEquals,
@@ -214,18 +227,42 @@ pub enum Token {
 	// Identifiers and literals
 	#[regex(r"[a-zA-Z_][a-zA-Z0-9_]*")]
 	Identifier,
-	#[regex(r#"'[^']*'"#)]
+	#[token("'", string_literal)]
 	StringLiteral,
+	#[regex(r"[xX]'[0-9a-fA-F]*'")]
+	BlobLiteral,
 	#[regex(r#""[^"]*""#)]
 	QuotedIdentifier,
 	#[regex(r"[0-9]+\.[0-9]+")]
 	FloatLiteral,
 	#[regex(r"[0-9]+")]
 	IntegerLiteral,
+	/// A parameter of a prepared statement: `?`, `?NNN`, `:name`, `@name`
+	/// or `$name`
+	#[regex(r"\?[0-9]*")]
+	#[regex(r"[:@$][a-zA-Z0-9_]+")]
+	Variable,
 	#[regex(r"--[^\n]*", logos::skip)]
 	Comment,
 }
 
+/// Consume the rest of a string literal, in which '' stands for a quote
+fn string_literal(lex: &mut logos::Lexer<Token>) -> bool {
+	let rest = lex.remainder().as_bytes();
+	let mut i = 0;
+	while i < rest.len() {
+		if rest[i] == b'\'' {
+			if rest.get(i + 1) != Some(&b'\'') {
+				lex.bump(i + 1);
+				return true;
+			}
+			i += 1;
+		}
+		i += 1;
+	}
+	false
+}
+
 /// Tokenizer for SQL statements
 pub struct Tokenizer {
 	source: String,
@@ -246,6 +283,20 @@ impl Tokenizer {
 
 		tokens
 	}
+
+	/// Tokens with where each is in the source, failing on text that is
+	/// not a token
+	pub fn tokenize_with_spans(&self) -> Result<Vec<(Token, Range<usize>)>> {
+		let mut tokens = Vec::new();
+		let mut lex = Token::lexer(&self.source);
+		while let Some(token) = lex.next() {
+			match token {
+				Ok(token) => tokens.push((token, lex.span())),
+				Err(_) => return Err(Error::Syntax(format!("Unrecognized token: {}", lex.slice()))),
+			}
+		}
+		Ok(tokens)
+	}
 }
 
 #[cfg(test)]
@@ -307,11 +358,25 @@ mod tests {
 
 	#[test]
 	fn test_tokenize_literals() {
-		let tokenizer = Tokenizer::new("SELECT 42, 3.14, 'hello'".to_string());
+		let tokenizer = Tokenizer::new("SELECT 42, 3.14, 'it''s', X'00ff'".to_string());
 		let tokens = tokenizer.tokenize();
 		assert!(tokens.contains(&Token::IntegerLiteral));
 		assert!(tokens.contains(&Token::FloatLiteral));
 		assert!(tokens.contains(&Token::StringLiteral));
+		assert!(tokens.contains(&Token::BlobLiteral));
+		assert_eq!(tokens.len(), 8);
+	}
+
+	#[test]
+	fn test_tokenize_variables() {
+		let sql = "WHERE a = ? AND b = ?12 OR c IN (:c, @d_1, $e)";
+		let tokens = Tokenizer::new(sql.to_string()).tokenize_with_spans().unwrap();
+		let variables: Vec<&str> = tokens
+			.iter()
+			.filter(|(token, _)| *token == Token::Variable)
+			.map(|(_, span)| &sql[span.clone()])
+			.collect();
+		assert_eq!(variables, vec!["?", "?12", ":c", "@d_1", "$e"]);
 	}
 
 	#[test]
@@ -340,6 +405,13 @@ mod tests {
 		assert_eq!(tokens[2], Token::Identifier);
 	}
 
+	#[test]
+	fn test_tokenize_begin_immediate() {
+		let tokenizer = Tokenizer::new("begin immediate transaction".to_string());
+		let tokens = tokenizer.tokenize();
+		assert_eq!(tokens, vec![Token::Begin, Token::Immediate, Token::Transaction]);
+	}
+
 	#[test]
 	fn test_tokenize_rollback_to() {
 		let tokenizer = Tokenizer::new("ROLLBACK TO SAVEPOINT sp1".to_string());
@@ -2,6 +2,7 @@
 ///
 /// Supports JSON configuration files and environment variables with defaults
 
+use crate::eplite::constants::{DEFAULT_CACHE_SIZE, DEFAULT_WAL_AUTOCHECKPOINT};
 use config::{Config, ConfigError, Environment, File};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
@@ -12,27 +13,35 @@ pub struct DatabaseConfig {
 	/// Default page size in bytes (must be power of 2 between 512 and 65536)
 	pub page_size: usize,
 	
-	/// Page cache size in number of pages
-	pub cache_size: usize,
+	/// Page cache size in pages, or in KiB when negative
+	pub cache_size: i64,
 	
 	/// Enable Write-Ahead Logging
 	pub enable_wal: bool,
+
+	/// WAL frames after which a commit checkpoints the log (0 disables)
+	pub wal_autocheckpoint: u32,
 	
 	/// Default text encoding
 	pub text_encoding: String,
 	
 	/// Enable auto-vacuum
 	pub auto_vacuum: bool,
+
+	/// Milliseconds to wait for other connections' locks (0 fails at once)
+	pub busy_timeout: u64,
 }
 
 impl Default for DatabaseConfig {
 	fn default() -> Self {
 		DatabaseConfig {
 			page_size: 4096,
-			cache_size: 100,
+			cache_size: DEFAULT_CACHE_SIZE,
 			enable_wal: false,
+			wal_autocheckpoint: DEFAULT_WAL_AUTOCHECKPOINT,
 			text_encoding: "UTF-8".to_string(),
 			auto_vacuum: false,
+			busy_timeout: 0,
 		}
 	}
 }
@@ -96,8 +105,10 @@ impl EpilogLiteConfig {
 			.set_default("database.page_size", 4096)?
 			.set_default("database.cache_size", 100)?
 			.set_default("database.enable_wal", false)?
+			.set_default("database.wal_autocheckpoint", DEFAULT_WAL_AUTOCHECKPOINT)?
 			.set_default("database.text_encoding", "UTF-8")?
 			.set_default("database.auto_vacuum", false)?
+			.set_default("database.busy_timeout", 0)?
 			.set_default("logging.level", "info")?
 			.set_default("logging.stdout", true)?
 			.set_default("logging.stderr", false)?
@@ -119,8 +130,10 @@ impl EpilogLiteConfig {
 			.set_default("database.page_size", 4096)?
 			.set_default("database.cache_size", 100)?
 			.set_default("database.enable_wal", false)?
+			.set_default("database.wal_autocheckpoint", DEFAULT_WAL_AUTOCHECKPOINT)?
 			.set_default("database.text_encoding", "UTF-8")?
 			.set_default("database.auto_vacuum", false)?
+			.set_default("database.busy_timeout", 0)?
 			.set_default("logging.level", "info")?
 			.set_default("logging.stdout", true)?
 			.set_default("logging.stderr", false)?
@@ -172,6 +185,7 @@ mod tests {
 		assert_eq!(config.database.page_size, 4096);
 		assert_eq!(config.database.cache_size, 100);
 		assert!(!config.database.enable_wal);
+		assert_eq!(config.database.wal_autocheckpoint, 1000);
 		assert_eq!(config.logging.level, "info");
 		assert!(config.logging.stdout);
 	}
@@ -6,6 +6,9 @@ pub const EPLITE_MAGIC_HEADER_V1: &[u8; 16] = b"EPLite format 1\0";
 /// Magic header string for SQLite format 3
 pub const SQLITE_MAGIC_HEADER_V3: &[u8; 16] = b"SQLite format 3\0";
 
+/// SQLite version number recorded in the header of SQLite 3 files we write
+pub const SQLITE_VERSION_NUMBER: u32 = 3_045_000;
+
 /// Default page size (4096 bytes)
 pub const DEFAULT_PAGE_SIZE: u32 = 4096;
 
@@ -15,6 +18,25 @@ pub const MIN_PAGE_SIZE: u32 = 512;
 /// Maximum page size (65536 bytes)
 pub const MAX_PAGE_SIZE: u32 = 65536;
 
+/// Default page cache size in pages; negative sizes are in KiB, as in
+/// SQLite's cache_size pragma
+pub const DEFAULT_CACHE_SIZE: i64 = 100;
+
+/// Default bytes of the database file to memory-map; 0 reads every page
+/// into its own buffer
+pub const DEFAULT_MMAP_SIZE: u64 = 0;
+
+/// Largest mmap_size allowed, as in SQLite
+pub const MAX_MMAP_SIZE: u64 = 0x7fff0000;
+
+/// Default number of write-ahead log frames after which a commit
+/// checkpoints, as in SQLite
+pub const DEFAULT_WAL_AUTOCHECKPOINT: u32 = 1000;
+
+/// Offset of the bytes that connections lock to coordinate access to a
+/// database file, as in SQLite; the page holding them never stores data
+pub const PENDING_BYTE: u64 = 0x4000_0000;
+
 /// Text encoding values
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
@@ -72,6 +94,103 @@ impl SchemaFormat {
 	}
 }
 
+/// Auto-vacuum modes, numbered as in PRAGMA auto_vacuum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum AutoVacuum {
+	/// Free pages stay in the file until VACUUM (default)
+	#[default]
+	None = 0,
+	/// Free pages are removed from the file at every commit
+	Full = 1,
+	/// Free pages are removed by PRAGMA incremental_vacuum
+	Incremental = 2,
+}
+
+impl AutoVacuum {
+	pub fn from_u32(value: u32) -> Option<Self> {
+		match value {
+			0 => Some(AutoVacuum::None),
+			1 => Some(AutoVacuum::Full),
+			2 => Some(AutoVacuum::Incremental),
+			_ => None,
+		}
+	}
+
+	pub fn to_u32(self) -> u32 {
+		self as u32
+	}
+
+	/// Parse a PRAGMA auto_vacuum value, either a mode name or its number
+	pub fn from_name(name: &str) -> Option<Self> {
+		if let Ok(value) = name.parse() {
+			return Self::from_u32(value);
+		}
+		[
+			("none", AutoVacuum::None),
+			("full", AutoVacuum::Full),
+			("incremental", AutoVacuum::Incremental),
+		]
+		.into_iter()
+		.find(|(mode, _)| name.eq_ignore_ascii_case(mode))
+		.map(|(_, mode)| mode)
+	}
+}
+
+/// Journal modes, named as in PRAGMA journal_mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JournalMode {
+	/// The journal is deleted when each transaction ends (default)
+	#[default]
+	Delete,
+	/// The journal is truncated to zero bytes when each transaction ends
+	Truncate,
+	/// The journal stays, its header zeroed, when each transaction ends
+	Persist,
+	/// Original pages are only kept in memory; in-memory databases always
+	/// use this mode
+	Memory,
+	/// Commits are appended to a write-ahead log instead of changing the
+	/// database file, which checkpoints bring up to date
+	Wal,
+	/// No journal file is written, so a crash during commit can corrupt
+	/// the database
+	Off,
+}
+
+impl JournalMode {
+	const NAMES: [(&'static str, JournalMode); 6] = [
+		("delete", JournalMode::Delete),
+		("truncate", JournalMode::Truncate),
+		("persist", JournalMode::Persist),
+		("memory", JournalMode::Memory),
+		("wal", JournalMode::Wal),
+		("off", JournalMode::Off),
+	];
+
+	/// Parse a PRAGMA journal_mode value
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::NAMES
+			.into_iter()
+			.find(|(mode, _)| name.eq_ignore_ascii_case(mode))
+			.map(|(_, mode)| mode)
+	}
+
+	/// The mode's name as PRAGMA journal_mode reports it
+	pub fn name(self) -> &'static str {
+		Self::NAMES
+			.into_iter()
+			.find(|&(_, mode)| mode == self)
+			.map(|(name, _)| name)
+			.unwrap_or_default()
+	}
+
+	/// Whether commits write a journal file
+	pub fn uses_file(self) -> bool {
+		matches!(self, JournalMode::Delete | JournalMode::Truncate | JournalMode::Persist)
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -107,4 +226,24 @@ mod tests {
 		);
 		assert_eq!(SchemaFormat::from_u32(99), None);
 	}
+
+	#[test]
+	fn test_auto_vacuum() {
+		assert_eq!(AutoVacuum::from_name("FULL"), Some(AutoVacuum::Full));
+		assert_eq!(AutoVacuum::from_name("incremental"), Some(AutoVacuum::Incremental));
+		assert_eq!(AutoVacuum::from_name("0"), Some(AutoVacuum::None));
+		assert_eq!(AutoVacuum::from_name("3"), None);
+		assert_eq!(AutoVacuum::from_name("partial"), None);
+	}
+
+	#[test]
+	fn test_journal_mode() {
+		assert_eq!(JournalMode::from_name("TRUNCATE"), Some(JournalMode::Truncate));
+		assert_eq!(JournalMode::from_name("wal"), Some(JournalMode::Wal));
+		assert_eq!(JournalMode::from_name("wall"), None);
+		assert_eq!(JournalMode::Persist.name(), "persist");
+		assert!(JournalMode::Delete.uses_file());
+		assert!(!JournalMode::Off.uses_file());
+		assert!(!JournalMode::Wal.uses_file());
+	}
 }
@@ -1,26 +1,55 @@
 /// Database connection and management
 
+#[cfg(feature = "std")]
+use crate::eplite::command::parser::TransactionMode;
+use crate::eplite::command::parser::Parser;
 use crate::eplite::command::processor::{ExecutionResult, Processor};
-use crate::eplite::constants::DEFAULT_PAGE_SIZE;
 use crate::eplite::error::{Error, Result};
 
+#[cfg(feature = "std")]
+use crate::eplite::config::DatabaseConfig;
+#[cfg(feature = "std")]
+use crate::eplite::constants::{AutoVacuum, JournalMode, DEFAULT_PAGE_SIZE};
 #[cfg(feature = "std")]
 use crate::eplite::os::file::DefaultFile;
 #[cfg(feature = "std")]
+use crate::eplite::os::vfs::{DefaultVfs, VirtualFileSystem};
+#[cfg(feature = "std")]
+use crate::eplite::persistence::journal::Journal;
+#[cfg(feature = "std")]
 use crate::eplite::persistence::pager::Pager;
+#[cfg(feature = "std")]
+use crate::eplite::persistence::wal::WalFile;
+#[cfg(feature = "std")]
+use crate::eplite::traits::file::{File, UnlockType};
 
+use crate::eplite::persistence::cache::CacheStats;
+use crate::eplite::persistence::header::FileFormat;
+use crate::eplite::persistence::wal::{CheckpointMode, CheckpointResult};
+use crate::eplite::statement::Statement;
 use crate::eplite::storage::StorageManager;
 
 #[cfg(feature = "std")]
 use std::path::Path;
+#[cfg(feature = "std")]
+use std::time::Duration;
 
 #[cfg(not(feature = "std"))]
-use alloc::string::{String, ToString};
+use alloc::{boxed::Box, string::{String, ToString}};
+
+/// Milliseconds to sleep before each retry of a busy statement, as in
+/// SQLite; later retries sleep as long as the last
+#[cfg(feature = "std")]
+const BUSY_DELAYS: [u64; 12] = [1, 2, 5, 10, 15, 20, 25, 25, 25, 50, 50, 100];
 
 /// Database connection
 pub struct Database {
 	path: String,
 	processor: Processor,
+	/// How long statements wait for other connections' locks, as last set
+	/// by `busy_timeout`
+	#[cfg(feature = "std")]
+	busy_timeout: Duration,
 }
 
 impl Database {
@@ -39,8 +68,7 @@ impl Database {
 			Processor::new()
 		} else {
 			// File-based database
-			let file = Box::new(DefaultFile::open(&path_str, true, true, true)?);
-			let pager = Pager::with_file(DEFAULT_PAGE_SIZE, file)?;
+			let pager = open_pager(&path_str, DEFAULT_PAGE_SIZE, Duration::ZERO)?;
 			let mut storage = StorageManager::with_pager(pager);
 			
 			// Load existing data from disk
@@ -52,6 +80,75 @@ impl Database {
 		Ok(Database {
 			path: path_str,
 			processor,
+			busy_timeout: Duration::ZERO,
+		})
+	}
+
+	/// Open or create a database with the given configuration
+	///
+	/// The page size and auto-vacuum setting apply when the database is
+	/// created; an existing file keeps its own. With `enable_wal` the
+	/// database is switched to WAL mode, which it keeps when reopened. The
+	/// busy timeout covers opening the file as well as later statements.
+	#[cfg(feature = "std")]
+	pub fn open_with_config<P: AsRef<Path>>(path: P, config: &DatabaseConfig) -> Result<Self> {
+		let path_str = path
+			.as_ref()
+			.to_str()
+			.ok_or_else(|| Error::InvalidFormat("Invalid path".to_string()))?
+			.to_string();
+
+		let page_size = u32::try_from(config.page_size)
+			.map_err(|_| Error::InvalidFormat(format!("Invalid page size: {}", config.page_size)))?;
+		let busy_timeout = Duration::from_millis(config.busy_timeout);
+		let pager = if path_str == ":memory:" {
+			Pager::new(page_size)?
+		} else {
+			open_pager(&path_str, page_size, busy_timeout)?
+		};
+		let mut storage = StorageManager::with_pager(pager);
+		storage.load_from_disk()?;
+		if storage.page_count() == 0 && config.auto_vacuum {
+			storage.set_auto_vacuum(AutoVacuum::Full)?;
+		}
+		storage.set_wal_autocheckpoint(config.wal_autocheckpoint);
+		storage.set_cache_size(config.cache_size);
+		if config.enable_wal {
+			storage.atomic(|storage| storage.set_journal_mode(JournalMode::Wal))?;
+		}
+
+		let mut db = Database {
+			path: path_str,
+			processor: Processor::with_storage(storage),
+			busy_timeout: Duration::ZERO,
+		};
+		db.busy_timeout(busy_timeout);
+		Ok(db)
+	}
+
+	/// Create a new database file in the given format
+	///
+	/// `FileFormat::SQLiteV3` files can be opened by SQLite itself. The file
+	/// must not exist or be empty.
+	#[cfg(feature = "std")]
+	pub fn create_with_format<P: AsRef<Path>>(path: P, format: FileFormat) -> Result<Self> {
+		let path_str = path
+			.as_ref()
+			.to_str()
+			.ok_or_else(|| Error::InvalidFormat("Invalid path".to_string()))?
+			.to_string();
+
+		let pager = if path_str == ":memory:" {
+			Pager::default()
+		} else {
+			open_pager(&path_str, DEFAULT_PAGE_SIZE, Duration::ZERO)?
+		};
+		let storage = StorageManager::create(pager, format)?;
+
+		Ok(Database {
+			path: path_str,
+			processor: Processor::with_storage(storage),
+			busy_timeout: Duration::ZERO,
 		})
 	}
 
@@ -69,6 +166,15 @@ impl Database {
 		self.processor.execute(sql)
 	}
 
+	/// Parse a statement once so it can be run many times, with values
+	/// bound to its `?`, `?NNN`, `:name`, `@name` and `$name` parameters
+	/// rather than written into the SQL
+	pub fn prepare(&mut self, sql: &str) -> Result<Statement<'_>> {
+		let mut parser = Parser::new();
+		let statement = parser.parse(sql)?;
+		Ok(Statement::new(&mut self.processor, statement, parser.parameters().to_vec()))
+	}
+
 	/// Execute a query from a builder
 	pub fn execute_builder<B>(&mut self, builder: B) -> Result<ExecutionResult>
 	where
@@ -78,6 +184,86 @@ impl Database {
 		self.execute(&sql)
 	}
 
+	/// Copy the write-ahead log into the database file, as PRAGMA
+	/// wal_checkpoint does, returning the frame counts, or None when the
+	/// database is not in WAL mode
+	pub fn checkpoint(&mut self, mode: CheckpointMode) -> Result<Option<CheckpointResult>> {
+		self.processor.checkpoint(mode)
+	}
+
+	/// Hit, miss and eviction counts of the page cache, as PRAGMA
+	/// cache_stats returns them
+	pub fn cache_stats(&self) -> CacheStats {
+		self.processor.cache_stats()
+	}
+
+	/// Retry statements that find the database locked by another connection
+	/// until `timeout` has passed, sleeping a little longer before each
+	/// attempt; a zero timeout fails them at once
+	#[cfg(feature = "std")]
+	pub fn busy_timeout(&mut self, timeout: Duration) {
+		if timeout.is_zero() {
+			self.processor.set_busy_handler(None);
+		} else {
+			self.busy_handler(busy_waiter(timeout));
+		}
+		self.busy_timeout = timeout;
+	}
+
+	/// Call `handler` with the number of retries so far whenever a statement
+	/// finds the database locked by another connection; the statement runs
+	/// again while it returns true
+	///
+	/// Statements inside a transaction that has already started are not
+	/// retried, except COMMIT, since the other connection could be waiting
+	/// for this one.
+	pub fn busy_handler<F>(&mut self, handler: F)
+	where
+		F: FnMut(u32) -> bool + Send + 'static,
+	{
+		self.processor.set_busy_handler(Some(Box::new(handler)));
+		#[cfg(feature = "std")]
+		{
+			self.busy_timeout = Duration::ZERO;
+		}
+	}
+
+	/// Start a read transaction on its own connection to the database
+	///
+	/// Every query in the transaction reads the database as it was when
+	/// the transaction began, whatever this or other connections commit
+	/// meanwhile. In WAL mode those commits go ahead; otherwise they fail
+	/// with `Error::Busy` until the transaction ends, as the file is locked
+	/// shared until then.
+	///
+	/// The connection takes this one's page cache, memory map, journal and
+	/// busy timeout settings as they are now.
+	#[cfg(feature = "std")]
+	pub fn begin_read(&self) -> Result<ReadTransaction> {
+		if self.path == ":memory:" {
+			return Err(Error::NotSupported(
+				"read transactions need a database file".to_string(),
+			));
+		}
+		let parent = self.processor.storage();
+		let pager = open_pager(&self.path, parent.page_size(), self.busy_timeout)?;
+		let mut storage = StorageManager::with_pager(pager);
+		storage.load_from_disk()?;
+		storage.set_cache_size(parent.cache_size());
+		storage.set_mmap_size(parent.mmap_size())?;
+		storage.set_wal_autocheckpoint(parent.wal_autocheckpoint());
+		// WAL mode is kept in the file, which a snapshot must not change
+		if (parent.journal_mode() == JournalMode::Wal) == (storage.journal_mode() == JournalMode::Wal) {
+			storage.set_journal_mode(parent.journal_mode())?;
+		}
+		storage.begin(TransactionMode::Deferred)?;
+		let mut processor = Processor::with_storage(storage);
+		if !self.busy_timeout.is_zero() {
+			processor.set_busy_handler(Some(Box::new(busy_waiter(self.busy_timeout))));
+		}
+		Ok(ReadTransaction { processor })
+	}
+
 	/// Get the database file path
 	pub fn path(&self) -> &str {
 		&self.path
@@ -85,12 +271,110 @@ impl Database {
 
 	/// Close the database connection
 	pub fn close(mut self) -> Result<()> {
-		// Flush any pending writes
-		self.processor.flush()?;
+		// Flush any pending writes and checkpoint the write-ahead log
+		self.processor.close()?;
 		Ok(())
 	}
 }
 
+/// A snapshot of a database that stays the same across queries, taken by
+/// `Database::begin_read`
+///
+/// The transaction has its own connection, so it can be sent to another
+/// thread or task and read there while the database it came from writes.
+/// Dropping it ends the transaction, like `end`, but leaves any
+/// write-ahead log behind even if no other connection uses it.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ReadTransaction {
+	processor: Processor,
+}
+
+#[cfg(feature = "std")]
+impl ReadTransaction {
+	/// Run a SELECT against the snapshot; other statements fail with
+	/// `Error::InvalidOperation`
+	pub fn query(&mut self, sql: &str) -> Result<ExecutionResult> {
+		self.processor.query(sql)
+	}
+
+	/// Hit, miss and eviction counts of the snapshot's page cache
+	pub fn cache_stats(&self) -> CacheStats {
+		self.processor.cache_stats()
+	}
+
+	/// Release the snapshot and close the connection
+	pub fn end(mut self) -> Result<()> {
+		self.processor.execute("ROLLBACK")?;
+		self.processor.close()
+	}
+}
+
+/// Microseconds to sleep before retry number `retries` of a busy
+/// statement, and in total before it
+#[cfg(feature = "std")]
+fn busy_delay(retries: u32) -> (u64, u64) {
+	let retries = retries as usize;
+	let last = BUSY_DELAYS[BUSY_DELAYS.len() - 1];
+	let delay = BUSY_DELAYS.get(retries).copied().unwrap_or(last);
+	let slept = BUSY_DELAYS.iter().take(retries).sum::<u64>()
+		+ retries.saturating_sub(BUSY_DELAYS.len()) as u64 * last;
+	(delay * 1000, slept * 1000)
+}
+
+/// A busy handler that retries until `timeout` has passed, sleeping a
+/// little longer before each attempt
+#[cfg(feature = "std")]
+fn busy_waiter(timeout: Duration) -> impl FnMut(u32) -> bool + Send + 'static {
+	let timeout = u64::try_from(timeout.as_micros()).unwrap_or(u64::MAX);
+	let vfs = DefaultVfs::new();
+	move |retries| {
+		let (delay, slept) = busy_delay(retries);
+		slept < timeout && vfs.sleep(delay.min(timeout - slept)).is_ok()
+	}
+}
+
+/// Open the pager of a database file, first rolling back any hot journal a
+/// crash left beside it and replaying the commits in any write-ahead log;
+/// `page_size` applies if the file is empty
+///
+/// Rolling back a journal needs the file to itself, so while other
+/// connections hold locks on it this waits up to `busy_timeout` before
+/// failing with `Error::Busy`.
+#[cfg(feature = "std")]
+fn open_pager(path: &str, page_size: u32, busy_timeout: Duration) -> Result<Pager> {
+	let mut file = DefaultFile::open(path, true, true, true)?;
+	let journal = Journal::new(path);
+	let mut busy = busy_waiter(busy_timeout);
+	let mut retries = 0;
+	loop {
+		match journal.recover(&mut file) {
+			Err(Error::Busy) => {
+				// Wait unlocked, as a pending lock left from this attempt
+				// would keep readers out and look like a writer to the next
+				file.unlock(UnlockType::None)?;
+				if !busy(retries) {
+					return Err(Error::Busy);
+				}
+				retries += 1;
+			}
+			result => {
+				result?;
+				break;
+			}
+		}
+	}
+	WalFile::recover(&journal.wal_path(), &mut file)?;
+	file.unlock(UnlockType::None)?;
+	let mut pager = if file.file_size()? == 0 {
+		Pager::with_file(page_size, Box::new(file))?
+	} else {
+		Pager::open(Box::new(file))?
+	};
+	pager.set_journal(journal);
+	Ok(pager)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -122,6 +406,15 @@ mod tests {
 		assert!(matches!(result.unwrap(), ExecutionResult::Success));
 	}
 
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_busy_delays_back_off() {
+		assert_eq!(busy_delay(0), (1000, 0));
+		assert_eq!(busy_delay(2), (5000, 3000));
+		assert_eq!(busy_delay(11), (100_000, 228_000));
+		assert_eq!(busy_delay(13), (100_000, 428_000));
+	}
+
 	#[test]
 	fn test_database_execute_insert() {
 		let mut db = Database::open(":memory:").unwrap();
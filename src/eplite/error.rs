@@ -84,8 +84,12 @@ impl std::error::Error for Error {
 
 #[cfg(feature = "std")]
 impl From<io::Error> for Error {
+	/// File locks another connection holds surface as `Error::Busy`
 	fn from(err: io::Error) -> Self {
-		Error::Io(err)
+		match err.kind() {
+			io::ErrorKind::WouldBlock => Error::Busy,
+			_ => Error::Io(err),
+		}
 	}
 }
 
@@ -105,4 +109,11 @@ mod tests {
 		let err: Error = io_err.into();
 		assert!(matches!(err, Error::Io(_)));
 	}
+
+	#[test]
+	fn test_lock_contention_is_busy() {
+		let io_err = io::Error::new(io::ErrorKind::WouldBlock, "database is locked");
+		let err: Error = io_err.into();
+		assert!(matches!(err, Error::Busy));
+	}
 }
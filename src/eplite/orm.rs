@@ -179,7 +179,7 @@ impl<'a, T: Entity> Repository<'a, T> {
 			let mut fields = HashMap::new();
 			for (i, col) in columns.iter().enumerate() {
 				if let Some(value) = rows[0].get(i) {
-					fields.insert(col.clone(), value.clone());
+					fields.insert(col.clone(), value.to_string());
 				}
 			}
 			
@@ -203,7 +203,7 @@ impl<'a, T: Entity> Repository<'a, T> {
 				let mut fields = HashMap::new();
 				for (i, col) in columns.iter().enumerate() {
 					if let Some(value) = row.get(i) {
-						fields.insert(col.clone(), value.clone());
+						fields.insert(col.clone(), value.to_string());
 					}
 				}
 				entities.push(T::from_fields(&fields)?);
@@ -1,6 +1,8 @@
 /// OS abstraction layer - provides portability across operating systems
 
 pub mod file;
+#[cfg(feature = "mmap")]
+pub mod mmap;
 pub mod vfs;
 
 #[cfg(feature = "std")]
@@ -1,5 +1,9 @@
 /// Default file implementation using standard library
 
+#[cfg(feature = "std")]
+use crate::eplite::constants::PENDING_BYTE;
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+use crate::eplite::os::mmap::MemoryMap;
 #[cfg(feature = "std")]
 use crate::eplite::traits::file::{File, LockType, SynchronizationType, UnlockType};
 #[cfg(feature = "std")]
@@ -11,12 +15,35 @@ use std::io::{self, Read, Seek, SeekFrom, Write};
 #[cfg(feature = "std")]
 use std::path::Path;
 
+/// Byte a connection locks exclusively while it holds a reserved lock
+#[cfg(feature = "std")]
+const RESERVED_BYTE: u64 = PENDING_BYTE + 1;
+
+/// Range readers lock shared and a writer locks exclusively
+#[cfg(feature = "std")]
+const SHARED_FIRST: u64 = PENDING_BYTE + 2;
+#[cfg(feature = "std")]
+const SHARED_SIZE: u64 = 510;
+
+/// Kind of advisory lock on a range of bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeLock {
+	/// Other connections may hold shared locks too
+	Shared,
+	/// No other connection may hold any lock
+	Exclusive,
+	/// Release the lock
+	Unlocked,
+}
+
 /// Default file implementation
 #[cfg(feature = "std")]
 #[derive(Debug)]
 pub struct DefaultFile {
 	file: StdFile,
 	path: String,
+	/// Lock held on the database, if any
+	lock: Option<LockType>,
 }
 
 #[cfg(feature = "std")]
@@ -33,6 +60,7 @@ impl DefaultFile {
 		Ok(DefaultFile {
 			file,
 			path: path_str,
+			lock: None,
 		})
 	}
 
@@ -40,6 +68,55 @@ impl DefaultFile {
 	pub fn path(&self) -> &str {
 		&self.path
 	}
+
+	/// Lock or unlock `len` bytes at `offset`, returning false if another
+	/// connection holds a conflicting lock; with `wait` it waits for the
+	/// lock instead
+	///
+	/// Locks belong to this open file rather than the process, so two
+	/// connections in one process exclude each other too. They are only
+	/// enforced on Linux.
+	#[cfg(target_os = "linux")]
+	pub fn lock_range(&self, offset: u64, len: u64, lock: RangeLock, wait: bool) -> io::Result<bool> {
+		use nix::errno::Errno;
+		use nix::fcntl::{fcntl, FcntlArg};
+		use nix::libc;
+
+		let lock = libc::flock {
+			l_type: match lock {
+				RangeLock::Shared => libc::F_RDLCK,
+				RangeLock::Exclusive => libc::F_WRLCK,
+				RangeLock::Unlocked => libc::F_UNLCK,
+			} as libc::c_short,
+			l_whence: libc::SEEK_SET as libc::c_short,
+			l_start: offset as libc::off_t,
+			l_len: len as libc::off_t,
+			l_pid: 0,
+		};
+		let arg = match wait {
+			true => FcntlArg::F_OFD_SETLKW(&lock),
+			false => FcntlArg::F_OFD_SETLK(&lock),
+		};
+		match fcntl(&self.file, arg) {
+			Ok(_) => Ok(true),
+			Err(Errno::EAGAIN | Errno::EACCES) => Ok(false),
+			Err(errno) => Err(errno.into()),
+		}
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	pub fn lock_range(&self, _offset: u64, _len: u64, _lock: RangeLock, _wait: bool) -> io::Result<bool> {
+		Ok(true)
+	}
+
+	/// Lock a range of bytes without waiting, failing with `WouldBlock`
+	/// if another connection holds a conflicting lock
+	fn try_lock_range(&self, offset: u64, len: u64, lock: RangeLock) -> io::Result<()> {
+		match self.lock_range(offset, len, lock, false)? {
+			true => Ok(()),
+			false => Err(io::Error::new(io::ErrorKind::WouldBlock, "database is locked")),
+		}
+	}
 }
 
 #[cfg(feature = "std")]
@@ -56,7 +133,19 @@ impl File for DefaultFile {
 		Ok(buffer)
 	}
 
-	fn write(&mut self, data: &Vec<u8>, offset: u64) -> io::Result<()> {
+	fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+		self.file.seek(SeekFrom::Start(offset))?;
+		let mut total = 0;
+		while total < buffer.len() {
+			match self.file.read(&mut buffer[total..])? {
+				0 => break,
+				n => total += n,
+			}
+		}
+		Ok(total)
+	}
+
+	fn write(&mut self, data: &[u8], offset: u64) -> io::Result<()> {
 		self.file.seek(SeekFrom::Start(offset))?;
 		self.file.write_all(data)?;
 		Ok(())
@@ -81,20 +170,79 @@ impl File for DefaultFile {
 		Ok(self.file.metadata()?.len())
 	}
 
-	fn lock(&mut self, _lock_type: LockType) -> io::Result<()> {
-		// TODO: Implement platform-specific locking
+	/// Raise the lock on the database to `lock_type` with SQLite's
+	/// lock-byte protocol, so SQLite connections take part too
+	///
+	/// Fails with `WouldBlock` if another connection holds a conflicting
+	/// lock. A failed exclusive lock keeps the pending lock, which lets
+	/// readers finish but stops new ones.
+	fn lock(&mut self, lock_type: LockType) -> io::Result<()> {
+		if self.lock >= Some(lock_type) {
+			return Ok(());
+		}
+		match lock_type {
+			LockType::Shared => {
+				// Readers check for a pending writer before taking their lock
+				self.try_lock_range(PENDING_BYTE, 1, RangeLock::Shared)?;
+				let shared = self.try_lock_range(SHARED_FIRST, SHARED_SIZE, RangeLock::Shared);
+				self.lock_range(PENDING_BYTE, 1, RangeLock::Unlocked, false)?;
+				shared?;
+			}
+			_ if self.lock.is_none() => {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"a shared lock must be held first",
+				));
+			}
+			LockType::Reserved => self.try_lock_range(RESERVED_BYTE, 1, RangeLock::Exclusive)?,
+			LockType::Pending => self.try_lock_range(PENDING_BYTE, 1, RangeLock::Exclusive)?,
+			LockType::Exclusive => {
+				if self.lock < Some(LockType::Pending) {
+					self.try_lock_range(PENDING_BYTE, 1, RangeLock::Exclusive)?;
+					self.lock = Some(LockType::Pending);
+				}
+				self.try_lock_range(SHARED_FIRST, SHARED_SIZE, RangeLock::Exclusive)?;
+			}
+		}
+		self.lock = Some(lock_type);
 		Ok(())
 	}
 
-	fn unlock(&mut self, _unlock_type: UnlockType) -> io::Result<()> {
-		// TODO: Implement platform-specific locking
+	fn unlock(&mut self, unlock_type: UnlockType) -> io::Result<()> {
+		match unlock_type {
+			UnlockType::Shared if self.lock > Some(LockType::Shared) => {
+				if self.lock == Some(LockType::Exclusive) {
+					self.lock_range(SHARED_FIRST, SHARED_SIZE, RangeLock::Shared, false)?;
+				}
+				self.lock_range(PENDING_BYTE, 2, RangeLock::Unlocked, false)?;
+				self.lock = Some(LockType::Shared);
+			}
+			UnlockType::None if self.lock.is_some() => {
+				self.lock_range(PENDING_BYTE, SHARED_FIRST + SHARED_SIZE - PENDING_BYTE, RangeLock::Unlocked, false)?;
+				self.lock = None;
+			}
+			_ => {}
+		}
 		Ok(())
 	}
 
+	/// Whether any connection holds a reserved or stronger lock: 1 if so,
+	/// otherwise 0
 	fn check_reserved_lock(&mut self) -> io::Result<u64> {
-		// TODO: Implement platform-specific lock checking
+		if self.lock >= Some(LockType::Reserved) {
+			return Ok(1);
+		}
+		if !self.lock_range(RESERVED_BYTE, 1, RangeLock::Shared, false)? {
+			return Ok(1);
+		}
+		self.lock_range(RESERVED_BYTE, 1, RangeLock::Unlocked, false)?;
 		Ok(0)
 	}
+
+	#[cfg(all(feature = "mmap", target_os = "linux"))]
+	fn map(&self, len: usize) -> io::Result<Option<MemoryMap>> {
+		MemoryMap::new(&self.file, len).map(Some)
+	}
 }
 
 #[cfg(test)]
@@ -128,6 +276,84 @@ mod tests {
 		std::fs::remove_file(temp_path).unwrap();
 	}
 
+	#[test]
+	fn test_file_read_at() {
+		let temp_path = "/tmp/epiloglite_test_read_at.db";
+		let _ = std::fs::remove_file(temp_path);
+
+		{
+			let mut file = DefaultFile::open(temp_path, true, true, true).unwrap();
+			file.write(b"Hello, World!", 0).unwrap();
+
+			let mut buffer = [0u8; 5];
+			assert_eq!(file.read_at(7, &mut buffer).unwrap(), 5);
+			assert_eq!(&buffer, b"World");
+
+			// Short read at end of file
+			let mut buffer = [0u8; 8];
+			assert_eq!(file.read_at(10, &mut buffer).unwrap(), 3);
+			assert_eq!(&buffer[..3], b"ld!");
+		}
+
+		std::fs::remove_file(temp_path).unwrap();
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_lock_range() {
+		let temp_path = "/tmp/epiloglite_test_lock_range.db";
+		let first = DefaultFile::open(temp_path, true, true, true).unwrap();
+		let second = DefaultFile::open(temp_path, true, true, false).unwrap();
+
+		assert!(first.lock_range(10, 2, RangeLock::Shared, false).unwrap());
+		assert!(second.lock_range(11, 1, RangeLock::Shared, false).unwrap());
+		assert!(!second.lock_range(10, 1, RangeLock::Exclusive, false).unwrap());
+		assert!(second.lock_range(12, 1, RangeLock::Exclusive, false).unwrap());
+
+		// A shared lock held only by this file can be upgraded
+		first.lock_range(11, 1, RangeLock::Unlocked, false).unwrap();
+		assert!(first.lock_range(10, 1, RangeLock::Exclusive, false).unwrap());
+		assert!(!first.lock_range(11, 1, RangeLock::Exclusive, false).unwrap());
+		drop(second);
+		assert!(first.lock_range(11, 2, RangeLock::Exclusive, false).unwrap());
+
+		std::fs::remove_file(temp_path).unwrap();
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_lock_protocol() {
+		let temp_path = "/tmp/epiloglite_test_lock_protocol.db";
+		let mut first = DefaultFile::open(temp_path, true, true, true).unwrap();
+		let mut second = DefaultFile::open(temp_path, true, true, false).unwrap();
+		let mut third = DefaultFile::open(temp_path, true, true, false).unwrap();
+		let busy = |result: io::Result<()>| result.unwrap_err().kind() == io::ErrorKind::WouldBlock;
+
+		assert_eq!(first.lock(LockType::Reserved).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+		first.lock(LockType::Shared).unwrap();
+		second.lock(LockType::Shared).unwrap();
+		first.lock(LockType::Reserved).unwrap();
+		assert!(busy(second.lock(LockType::Reserved)));
+		assert_eq!(second.check_reserved_lock().unwrap(), 1);
+
+		// The writer waits for the reader, and no new readers start meanwhile
+		assert!(busy(first.lock(LockType::Exclusive)));
+		assert!(busy(third.lock(LockType::Shared)));
+		second.unlock(UnlockType::None).unwrap();
+		first.lock(LockType::Exclusive).unwrap();
+		assert!(busy(second.lock(LockType::Shared)));
+
+		first.unlock(UnlockType::Shared).unwrap();
+		second.lock(LockType::Shared).unwrap();
+		third.lock(LockType::Shared).unwrap();
+		assert_eq!(third.check_reserved_lock().unwrap(), 0);
+		first.unlock(UnlockType::None).unwrap();
+		second.lock(LockType::Reserved).unwrap();
+		assert!(busy(first.lock(LockType::Shared).and_then(|_| first.lock(LockType::Reserved))));
+
+		std::fs::remove_file(temp_path).unwrap();
+	}
+
 	#[test]
 	fn test_file_size() {
 		let temp_path = "/tmp/epiloglite_test_size.db";
@@ -0,0 +1,134 @@
+/// Read-only memory maps of database files, built with the `mmap` feature
+///
+/// Pages are copied out of a map instead of read with a system call. The
+/// bytes under a map change whenever this or another connection writes the
+/// file, so no reference into it is ever handed out; each read copies the
+/// bytes it needs. Bytes of a map past the end of its file must never be
+/// read, so the pager only maps whole pages within the file and replaces
+/// its map when the file shrinks.
+///
+/// That guard only covers truncations the pager sees. If another process
+/// truncates the file while it is mapped, as a connection running VACUUM or
+/// a checkpoint may, reading a page that was cut off raises SIGBUS and kills
+/// the process rather than returning an error. This is the same hazard
+/// SQLite documents for `PRAGMA mmap_size`, and why the feature is opt-in.
+
+use std::io;
+
+/// A read-only shared mapping of the start of a file
+#[derive(Debug)]
+pub struct MemoryMap {
+	ptr: core::ptr::NonNull<core::ffi::c_void>,
+	len: usize,
+}
+
+// SAFETY: the mapping belongs to this value alone and is only read by
+// copying out through the raw pointer, which works the same on any thread;
+// no Rust reference to the shared bytes exists to be invalidated by writes
+#[allow(unsafe_code)]
+unsafe impl Send for MemoryMap {}
+
+impl MemoryMap {
+	/// Map the first `len` bytes of a file
+	#[cfg(target_os = "linux")]
+	#[allow(unsafe_code)]
+	pub fn new<F: std::os::fd::AsFd>(file: F, len: usize) -> io::Result<Self> {
+		use nix::sys::mman::{mmap, MapFlags, ProtFlags};
+
+		let Some(length) = core::num::NonZeroUsize::new(len) else {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot map an empty range"));
+		};
+		// SAFETY: the kernel picks the address of the new mapping, so it
+		// overlaps no memory Rust owns, and it stays mapped until dropped
+		let ptr = unsafe { mmap(None, length, ProtFlags::PROT_READ, MapFlags::MAP_SHARED, file, 0)? };
+		Ok(MemoryMap { ptr, len })
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	pub fn new<F>(_file: F, _len: usize) -> io::Result<Self> {
+		Err(io::Error::new(io::ErrorKind::Unsupported, "memory maps are only supported on Linux"))
+	}
+
+	/// Number of bytes mapped
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Copy the mapped bytes at `offset` into `buf`, as they are now
+	#[allow(unsafe_code)]
+	pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+		if offset.checked_add(buf.len()).is_none_or(|end| end > self.len) {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read past the end of the map"));
+		}
+		// SAFETY: the range lies within the `len` bytes mapped readable
+		// until drop, and `buf` is Rust memory, so it cannot overlap the
+		// map. The copy goes through raw pointers and makes no reference
+		// to the mapped bytes, which other processes may write meanwhile;
+		// the file locks keep those writes away from pages being read, and
+		// the pager's own writes need it borrowed mutably.
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				(self.ptr.as_ptr() as *const u8).add(offset),
+				buf.as_mut_ptr(),
+				buf.len(),
+			);
+		}
+		Ok(())
+	}
+}
+
+impl Drop for MemoryMap {
+	#[allow(unsafe_code)]
+	fn drop(&mut self) {
+		// SAFETY: the map is unmapped once, and only reads made through
+		// `read_at`, which have all returned, ever touched it
+		#[cfg(target_os = "linux")]
+		let _ = unsafe { nix::sys::mman::munmap(self.ptr, self.len) };
+	}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+	use super::*;
+	use std::io::Write;
+
+	#[test]
+	fn test_map_sees_file_writes() {
+		let temp_path = "/tmp/epiloglite_test_mmap.bin";
+		let mut file = std::fs::OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.open(temp_path)
+			.unwrap();
+		file.write_all(&[1u8; 8192]).unwrap();
+
+		let map = MemoryMap::new(&file, 4096).unwrap();
+		assert_eq!(map.len(), 4096);
+		let mut buf = vec![0u8; 4096];
+		map.read_at(0, &mut buf).unwrap();
+		assert_eq!(buf, vec![1u8; 4096]);
+
+		// The map is shared, so writes to the file show through
+		use std::os::unix::fs::FileExt;
+		file.write_all_at(&[2u8; 16], 100).unwrap();
+		let mut buf = [0u8; 3];
+		map.read_at(99, &mut buf).unwrap();
+		assert_eq!(buf, [1, 2, 2]);
+		map.read_at(114, &mut buf).unwrap();
+		assert_eq!(buf, [2, 2, 1]);
+
+		// Nothing past the map is read
+		assert!(map.read_at(4094, &mut buf).is_err());
+		assert!(map.read_at(usize::MAX, &mut buf).is_err());
+
+		assert!(MemoryMap::new(&file, 0).is_err());
+		drop(map);
+		std::fs::remove_file(temp_path).unwrap();
+	}
+}
@@ -1,6 +1,13 @@
 /// Persistence layer - handles data storage and retrieval
 
 pub mod btree;
+pub mod cache;
 pub mod header;
+#[cfg(feature = "std")]
+pub mod journal;
 pub mod pager;
+pub mod record;
+pub mod varint;
 pub mod wal;
+#[cfg(feature = "std")]
+pub mod wal_index;
@@ -1,9 +1,18 @@
 /// B-tree implementation for database storage
+///
+/// Pages use the SQLite 3 b-tree layout: a page header, an array of cell
+/// pointers and the cells themselves packed at the end of the page. Table
+/// trees are keyed by a 64-bit rowid and keep their payloads in the leaves;
+/// index trees are keyed by the payload itself and keep entries at every level.
 
 use crate::eplite::error::{Error, Result};
+use crate::eplite::persistence::header::{PayloadFractions, DATABASE_HEADER_SIZE};
+use crate::eplite::persistence::pager::{PageRef, Pager};
+use crate::eplite::persistence::varint;
+use core::cmp::Ordering;
 
 #[cfg(not(feature = "std"))]
-use alloc::{string::ToString, vec::Vec};
+use alloc::{format, string::ToString, vec, vec::Vec};
 
 /// B-tree node type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,85 +21,1254 @@ pub enum NodeType {
 	Leaf,
 }
 
+/// Kind of b-tree stored on a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BTreeKind {
+	/// Rows keyed by rowid
+	Table,
+	/// Keys ordered by their content
+	Index,
+}
+
+/// B-tree page type, the first byte of the page header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageType {
+	InteriorIndex = 0x02,
+	InteriorTable = 0x05,
+	LeafIndex = 0x0a,
+	LeafTable = 0x0d,
+}
+
+impl PageType {
+	pub fn new(kind: BTreeKind, node_type: NodeType) -> Self {
+		match (kind, node_type) {
+			(BTreeKind::Table, NodeType::Internal) => PageType::InteriorTable,
+			(BTreeKind::Table, NodeType::Leaf) => PageType::LeafTable,
+			(BTreeKind::Index, NodeType::Internal) => PageType::InteriorIndex,
+			(BTreeKind::Index, NodeType::Leaf) => PageType::LeafIndex,
+		}
+	}
+
+	pub fn from_byte(byte: u8) -> Result<Self> {
+		match byte {
+			0x02 => Ok(PageType::InteriorIndex),
+			0x05 => Ok(PageType::InteriorTable),
+			0x0a => Ok(PageType::LeafIndex),
+			0x0d => Ok(PageType::LeafTable),
+			_ => Err(Error::Corrupt(format!("Invalid b-tree page type: {}", byte))),
+		}
+	}
+
+	pub fn kind(self) -> BTreeKind {
+		match self {
+			PageType::InteriorTable | PageType::LeafTable => BTreeKind::Table,
+			PageType::InteriorIndex | PageType::LeafIndex => BTreeKind::Index,
+		}
+	}
+
+	pub fn node_type(self) -> NodeType {
+		match self {
+			PageType::InteriorTable | PageType::InteriorIndex => NodeType::Internal,
+			PageType::LeafTable | PageType::LeafIndex => NodeType::Leaf,
+		}
+	}
+
+	fn is_leaf(self) -> bool {
+		self.node_type() == NodeType::Leaf
+	}
+
+	/// Size of the page header; interior pages also hold the right-most child
+	fn header_size(self) -> usize {
+		if self.is_leaf() {
+			8
+		} else {
+			12
+		}
+	}
+}
+
+/// Offset of the b-tree page header, which follows the database header on page 1
+fn header_offset(page_number: u32) -> usize {
+	if page_number == 1 {
+		DATABASE_HEADER_SIZE
+	} else {
+		0
+	}
+}
+
+/// Bytes of each page available to the b-tree
+fn usable_size(pager: &Pager) -> usize {
+	pager.usable_size()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> usize {
+	u16::from_be_bytes([data[offset], data[offset + 1]]) as usize
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+	u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Number of payload bytes kept on the b-tree page itself, following the
+/// SQLite 3 rules; anything beyond this spills to overflow pages
+///
+/// A table cell may fill its page, less room for the cell header. An index
+/// cell keeps at most the maximum embedded fraction of the page, and a
+/// spilling cell at least the minimum embedded fraction, or for table cells
+/// the leaf fraction. The overflow pages are filled, so the local part is
+/// whatever is left over, unless that falls outside those bounds.
+pub fn local_payload_size(kind: BTreeKind, usable: usize, fractions: PayloadFractions, payload: usize) -> usize {
+	let share = |fraction: u8| ((usable - 12) * fraction as usize / 255).saturating_sub(23);
+	let (max_local, min_local) = match kind {
+		BTreeKind::Table => (usable - 35, share(fractions.leaf)),
+		BTreeKind::Index => (share(fractions.max_embedded), share(fractions.min_embedded)),
+	};
+	if payload <= max_local {
+		return payload;
+	}
+	let local = min_local + (payload - min_local) % (usable - 4);
+	if local <= max_local {
+		local
+	} else {
+		min_local
+	}
+}
+
+/// Size in bytes of the cell at the start of `data`
+fn cell_size(page_type: PageType, data: &[u8], usable: usize, fractions: PayloadFractions) -> Result<usize> {
+	let size = match page_type {
+		PageType::InteriorTable => {
+			let (_, len) = varint::decode(data.get(4..).unwrap_or_default())?;
+			4 + len
+		}
+		PageType::LeafTable => {
+			let (payload, len) = varint::decode(data)?;
+			let (_, rowid_len) = varint::decode(&data[len..])?;
+			let local = local_payload_size(BTreeKind::Table, usable, fractions, payload as usize);
+			let overflow = if local < payload as usize { 4 } else { 0 };
+			len + rowid_len + local + overflow
+		}
+		PageType::LeafIndex | PageType::InteriorIndex => {
+			let start = if page_type.is_leaf() { 0 } else { 4 };
+			let (payload, len) = varint::decode(data.get(start..).unwrap_or_default())?;
+			let local = local_payload_size(BTreeKind::Index, usable, fractions, payload as usize);
+			let overflow = if local < payload as usize { 4 } else { 0 };
+			start + len + local + overflow
+		}
+	};
+	if size > data.len() {
+		return Err(Error::Corrupt("B-tree cell extends past end of page".to_string()));
+	}
+	Ok(size)
+}
+
+/// Rowid of a table b-tree cell
+fn cell_rowid(page_type: PageType, cell: &[u8]) -> Result<i64> {
+	let key = if page_type.is_leaf() {
+		let (_, len) = varint::decode(cell)?;
+		&cell[len..]
+	} else {
+		&cell[4..]
+	};
+	Ok(varint::decode(key)?.0 as i64)
+}
+
+/// Left child of an interior cell
+fn cell_left_child(cell: &[u8]) -> u32 {
+	read_u32(cell, 0)
+}
+
+/// Payload of a table leaf or index cell: total size, the bytes stored
+/// locally and the first overflow page (0 when the payload is all local)
+fn cell_payload(page_type: PageType, cell: &[u8], usable: usize, fractions: PayloadFractions) -> Result<(usize, &[u8], u32)> {
+	let (payload, mut offset) = match page_type {
+		PageType::LeafTable => {
+			let (payload, len) = varint::decode(cell)?;
+			let (_, rowid_len) = varint::decode(&cell[len..])?;
+			(payload as usize, len + rowid_len)
+		}
+		PageType::LeafIndex => {
+			let (payload, len) = varint::decode(cell)?;
+			(payload as usize, len)
+		}
+		PageType::InteriorIndex => {
+			let (payload, len) = varint::decode(&cell[4..])?;
+			(payload as usize, 4 + len)
+		}
+		PageType::InteriorTable => {
+			return Err(Error::Internal("Interior table cells carry no payload".to_string()));
+		}
+	};
+	let local = local_payload_size(page_type.kind(), usable, fractions, payload);
+	let data = &cell[offset..offset + local];
+	offset += local;
+	let overflow = if local < payload { read_u32(cell, offset) } else { 0 };
+	Ok((payload, data, overflow))
+}
+
+/// Table leaf cell for a payload of `size` bytes, of which `body` holds the
+/// local part and any overflow page number, as made by `BTree::spill`
+fn table_leaf_cell(rowid: i64, size: usize, body: &[u8]) -> Vec<u8> {
+	let mut cell = Vec::with_capacity(body.len() + 2 * varint::MAX_VARINT_LEN);
+	varint::encode(size as u64, &mut cell);
+	varint::encode(rowid as u64, &mut cell);
+	cell.extend_from_slice(body);
+	cell
+}
+
+fn index_leaf_cell(size: usize, body: &[u8]) -> Vec<u8> {
+	let mut cell = Vec::with_capacity(body.len() + varint::MAX_VARINT_LEN);
+	varint::encode(size as u64, &mut cell);
+	cell.extend_from_slice(body);
+	cell
+}
+
+fn rowid_key(rowid: i64) -> Vec<u8> {
+	let mut key = Vec::with_capacity(varint::MAX_VARINT_LEN);
+	varint::encode(rowid as u64, &mut key);
+	key
+}
+
+/// Interior cell: the left child pointer followed by the divider key, which
+/// is a rowid varint for table trees or an index leaf cell for index trees
+fn interior_cell(left_child: u32, key: &[u8]) -> Vec<u8> {
+	let mut cell = Vec::with_capacity(key.len() + 4);
+	cell.extend_from_slice(&left_child.to_be_bytes());
+	cell.extend_from_slice(key);
+	cell
+}
+
+/// Read-only view of a b-tree page
+struct NodeView<'a> {
+	data: &'a [u8],
+	usable: usize,
+	fractions: PayloadFractions,
+	hdr: usize,
+	page_type: PageType,
+	cell_count: usize,
+}
+
+impl<'a> NodeView<'a> {
+	fn new(data: &'a [u8], page_number: u32, usable: usize, fractions: PayloadFractions) -> Result<Self> {
+		let hdr = header_offset(page_number);
+		let page_type = PageType::from_byte(data[hdr])?;
+		let cell_count = read_u16(data, hdr + 3);
+		if hdr + page_type.header_size() + 2 * cell_count > usable {
+			return Err(Error::Corrupt(format!(
+				"Page {} claims {} cells",
+				page_number, cell_count
+			)));
+		}
+		Ok(NodeView {
+			data,
+			usable,
+			fractions,
+			hdr,
+			page_type,
+			cell_count,
+		})
+	}
+
+	/// Offset of a cell within the page
+	fn cell_offset(&self, index: usize) -> Result<usize> {
+		let pointer = read_u16(self.data, self.hdr + self.page_type.header_size() + 2 * index);
+		if pointer < self.hdr + self.page_type.header_size() || pointer >= self.usable {
+			return Err(Error::Corrupt(format!("Invalid cell pointer: {}", pointer)));
+		}
+		Ok(pointer)
+	}
+
+	fn cell(&self, index: usize) -> Result<&'a [u8]> {
+		let pointer = self.cell_offset(index)?;
+		let size = cell_size(self.page_type, &self.data[pointer..self.usable], self.usable, self.fractions)?;
+		Ok(&self.data[pointer..pointer + size])
+	}
+
+	/// Child page `index`, where `cell_count` selects the right-most child
+	fn child(&self, index: usize) -> Result<u32> {
+		if index < self.cell_count {
+			Ok(cell_left_child(self.cell(index)?))
+		} else {
+			Ok(read_u32(self.data, self.hdr + 8))
+		}
+	}
+
+	/// Binary search for a rowid, as with `slice::binary_search`
+	fn find_rowid(&self, rowid: i64) -> Result<core::result::Result<usize, usize>> {
+		let (mut low, mut high) = (0, self.cell_count);
+		while low < high {
+			let mid = (low + high) / 2;
+			match cell_rowid(self.page_type, self.cell(mid)?)?.cmp(&rowid) {
+				Ordering::Less => low = mid + 1,
+				Ordering::Greater => high = mid,
+				Ordering::Equal => return Ok(Ok(mid)),
+			}
+		}
+		Ok(Err(low))
+	}
+}
+
+/// Decoded b-tree page, used while modifying the tree
+#[derive(Debug, Clone)]
+struct Node {
+	page_type: PageType,
+	cells: Vec<Vec<u8>>,
+	right_child: u32,
+}
+
+impl Node {
+	fn from_view(view: &NodeView) -> Result<Self> {
+		let mut cells = Vec::with_capacity(view.cell_count);
+		for i in 0..view.cell_count {
+			cells.push(view.cell(i)?.to_vec());
+		}
+		let right_child = if view.page_type.is_leaf() {
+			0
+		} else {
+			read_u32(view.data, view.hdr + 8)
+		};
+		Ok(Node {
+			page_type: view.page_type,
+			cells,
+			right_child,
+		})
+	}
+
+	fn is_leaf(&self) -> bool {
+		self.page_type.is_leaf()
+	}
+
+	fn child(&self, index: usize) -> u32 {
+		if index < self.cells.len() {
+			cell_left_child(&self.cells[index])
+		} else {
+			self.right_child
+		}
+	}
+
+	/// Bytes needed to store the node on a page with its header at `hdr`
+	fn size(&self, hdr: usize) -> usize {
+		hdr + self.page_type.header_size() + self.cells.iter().map(|c| c.len() + 2).sum::<usize>()
+	}
+
+	fn fits(&self, hdr: usize, usable: usize) -> bool {
+		self.size(hdr) <= usable
+	}
+
+	/// Encode the node into a page, packing cells at the end of the page
+	fn write_to(&self, data: &mut [u8], hdr: usize, usable: usize) {
+		data[hdr..usable].fill(0);
+		data[hdr] = self.page_type as u8;
+		data[hdr + 3..hdr + 5].copy_from_slice(&(self.cells.len() as u16).to_be_bytes());
+		if !self.is_leaf() {
+			data[hdr + 8..hdr + 12].copy_from_slice(&self.right_child.to_be_bytes());
+		}
+
+		let pointers = hdr + self.page_type.header_size();
+		let mut content = usable;
+		for (i, cell) in self.cells.iter().enumerate() {
+			content -= cell.len();
+			data[content..content + cell.len()].copy_from_slice(cell);
+			data[pointers + 2 * i..pointers + 2 * i + 2].copy_from_slice(&(content as u16).to_be_bytes());
+		}
+		// A content area starting at 65536 is stored as zero
+		data[hdr + 5..hdr + 7].copy_from_slice(&(content as u16).to_be_bytes());
+	}
+}
+
 /// B-tree cursor for traversing the tree
+///
+/// A cursor is invalidated by any change to the tree.
+#[derive(Debug, Clone)]
 pub struct Cursor {
-	page_number: u32,
-	cell_index: u16,
+	root_page: u32,
+	/// Pages from the root down to the current entry, with the cell index
+	/// (or, on interior pages passed through, the child index) on each
+	stack: Vec<(u32, usize)>,
+	eof: bool,
 }
 
 impl Cursor {
 	pub fn new(page_number: u32) -> Self {
 		Cursor {
-			page_number,
-			cell_index: 0,
+			root_page: page_number,
+			stack: Vec::new(),
+			eof: false,
 		}
 	}
 
 	pub fn page_number(&self) -> u32 {
-		self.page_number
+		self.stack.last().map_or(self.root_page, |&(page, _)| page)
 	}
 
 	pub fn cell_index(&self) -> u16 {
-		self.cell_index
+		self.stack.last().map_or(0, |&(_, index)| index as u16)
+	}
+
+	/// Whether the cursor has moved past the last entry
+	pub fn is_eof(&self) -> bool {
+		self.eof
 	}
 }
 
 /// B-tree manager
+///
+/// The root page never moves: when the root splits its content is pushed
+/// down into a new child page.
+#[derive(Debug, Clone, Copy)]
 pub struct BTree {
 	root_page: u32,
+	kind: BTreeKind,
+	compare: fn(&[u8], &[u8]) -> Ordering,
 }
 
 impl BTree {
+	/// Open the table b-tree rooted at `root_page`
 	pub fn new(root_page: u32) -> Self {
-		BTree { root_page }
+		BTree {
+			root_page,
+			kind: BTreeKind::Table,
+			compare: compare_bytes,
+		}
+	}
+
+	/// Open the index b-tree rooted at `root_page`
+	pub fn new_index(root_page: u32) -> Self {
+		BTree {
+			root_page,
+			kind: BTreeKind::Index,
+			compare: compare_bytes,
+		}
+	}
+
+	/// Order index keys with `compare` instead of byte-wise comparison
+	pub fn with_comparator(mut self, compare: fn(&[u8], &[u8]) -> Ordering) -> Self {
+		self.compare = compare;
+		self
+	}
+
+	/// Create an empty b-tree on a newly allocated page
+	pub fn create(pager: &mut Pager, kind: BTreeKind) -> Result<Self> {
+		let root_page = pager.allocate_page()?;
+		Self::create_at(pager, root_page, kind)
+	}
+
+	/// Create an empty b-tree on an existing page, discarding its contents
+	///
+	/// On page 1 the database header is left in place.
+	pub fn create_at(pager: &mut Pager, root_page: u32, kind: BTreeKind) -> Result<Self> {
+		let tree = match kind {
+			BTreeKind::Table => BTree::new(root_page),
+			BTreeKind::Index => BTree::new_index(root_page),
+		};
+		tree.write_node(pager, root_page, &Node {
+			page_type: PageType::new(kind, NodeType::Leaf),
+			cells: Vec::new(),
+			right_child: 0,
+		})?;
+		Ok(tree)
+	}
+
+	/// Open an existing tree, taking its kind from the root page
+	pub fn open(pager: &mut Pager, root_page: u32) -> Result<Self> {
+		let (usable, fractions) = (usable_size(pager), pager.payload_fractions());
+		let page = pager.get_page(root_page)?;
+		let view = NodeView::new(&page.data, root_page, usable, fractions)?;
+		Ok(match view.page_type.kind() {
+			BTreeKind::Table => Self::new(root_page),
+			BTreeKind::Index => Self::new_index(root_page),
+		})
 	}
 
 	pub fn root_page(&self) -> u32 {
 		self.root_page
 	}
 
+	pub fn kind(&self) -> BTreeKind {
+		self.kind
+	}
+
 	/// Open a cursor at the beginning of the tree
 	pub fn cursor(&self) -> Cursor {
 		Cursor::new(self.root_page)
 	}
 
-	/// Move cursor to next entry
-	pub fn next(&self, _cursor: &mut Cursor) -> Result<bool> {
-		// TODO: Implement cursor movement
-		Err(Error::NotSupported(
-			"B-tree traversal not yet implemented".to_string(),
-		))
+	/// Position the cursor on the first entry
+	pub fn first(&self, pager: &mut Pager, cursor: &mut Cursor) -> Result<bool> {
+		cursor.stack.clear();
+		let found = self.descend_leftmost(pager, cursor, self.root_page)?;
+		cursor.eof = !found;
+		Ok(found)
+	}
+
+	/// Move cursor to next entry, starting with the first entry for a new
+	/// cursor. Returns false once the cursor has passed the last entry.
+	pub fn next(&self, pager: &mut Pager, cursor: &mut Cursor) -> Result<bool> {
+		if cursor.eof {
+			return Ok(false);
+		}
+		let Some(&(page_number, index)) = cursor.stack.last() else {
+			return self.first(pager, cursor);
+		};
+
+		let view = self.view(pager, page_number)?;
+		let found = if view.page_type.is_leaf() {
+			if index + 1 < view.cell_count {
+				cursor.stack.last_mut().unwrap().1 = index + 1;
+				true
+			} else {
+				self.pop_exhausted(pager, cursor)?
+			}
+		} else {
+			// On an interior index entry: continue with the subtree to its right
+			let child = view.child(index + 1)?;
+			cursor.stack.last_mut().unwrap().1 = index + 1;
+			self.descend_leftmost(pager, cursor, child)?
+		};
+		cursor.eof = !found;
+		Ok(found)
+	}
+
+	/// Position a table cursor on the first entry with a rowid of at least `rowid`
+	pub fn seek(&self, pager: &mut Pager, cursor: &mut Cursor, rowid: i64) -> Result<bool> {
+		self.expect_kind(BTreeKind::Table)?;
+		cursor.stack.clear();
+		let mut page_number = self.root_page;
+		let found = loop {
+			let view = self.view(pager, page_number)?;
+			let index = view.find_rowid(rowid)?.unwrap_or_else(|i| i);
+			cursor.stack.push((page_number, index));
+			if view.page_type.is_leaf() {
+				break index < view.cell_count || self.pop_exhausted(pager, cursor)?;
+			}
+			page_number = view.child(index)?;
+		};
+		cursor.eof = !found;
+		Ok(found)
+	}
+
+	/// Position an index cursor on the first key not less than `key`
+	pub fn seek_key(&self, pager: &mut Pager, cursor: &mut Cursor, key: &[u8]) -> Result<bool> {
+		self.expect_kind(BTreeKind::Index)?;
+		cursor.stack.clear();
+		let mut page_number = self.root_page;
+		let found = loop {
+			let node = self.read_node(pager, page_number)?;
+			let (index, exact) = match self.find_key(pager, &node, key)? {
+				Ok(i) => (i, true),
+				Err(i) => (i, false),
+			};
+			cursor.stack.push((page_number, index));
+			if exact {
+				break true;
+			}
+			if node.is_leaf() {
+				break index < node.cells.len() || self.pop_exhausted(pager, cursor)?;
+			}
+			page_number = node.child(index);
+		};
+		cursor.eof = !found;
+		Ok(found)
 	}
 
-	/// Insert a record into the tree
-	pub fn insert(&mut self, _key: &[u8], _value: &[u8]) -> Result<()> {
-		// TODO: Implement insertion
-		Err(Error::NotSupported(
-			"B-tree insertion not yet implemented".to_string(),
-		))
+	/// Rowid of the entry under a table cursor
+	pub fn rowid(&self, pager: &mut Pager, cursor: &Cursor) -> Result<i64> {
+		self.expect_kind(BTreeKind::Table)?;
+		let (page_type, cell) = self.current_cell(pager, cursor)?;
+		cell_rowid(page_type, &cell)
 	}
 
-	/// Search for a record in the tree
-	pub fn search(&self, _key: &[u8]) -> Result<Option<Vec<u8>>> {
-		// TODO: Implement search
-		Err(Error::NotSupported(
-			"B-tree search not yet implemented".to_string(),
-		))
+	/// Payload of the entry under the cursor: the row data of a table entry
+	/// or the key of an index entry
+	pub fn payload(&self, pager: &mut Pager, cursor: &Cursor) -> Result<Vec<u8>> {
+		let (page_type, cell) = self.current_cell(pager, cursor)?;
+		self.read_payload(pager, page_type, &cell)
 	}
 
-	/// Delete a record from the tree
-	pub fn delete(&mut self, _key: &[u8]) -> Result<()> {
-		// TODO: Implement deletion
-		Err(Error::NotSupported(
-			"B-tree deletion not yet implemented".to_string(),
-		))
+	/// Largest rowid in a table tree
+	pub fn last_rowid(&self, pager: &mut Pager) -> Result<Option<i64>> {
+		self.expect_kind(BTreeKind::Table)?;
+		let mut page_number = self.root_page;
+		loop {
+			let view = self.view(pager, page_number)?;
+			if view.page_type.is_leaf() {
+				if view.cell_count == 0 {
+					return Ok(None);
+				}
+				return Ok(Some(cell_rowid(view.page_type, view.cell(view.cell_count - 1)?)?));
+			}
+			page_number = view.child(view.cell_count)?;
+		}
+	}
+
+	/// Insert a record into a table tree, replacing any record with the same rowid
+	pub fn insert(&mut self, pager: &mut Pager, rowid: i64, payload: &[u8]) -> Result<()> {
+		self.expect_kind(BTreeKind::Table)?;
+
+		let (leaf, path) = self.descend_to_rowid(pager, rowid)?;
+		let body = self.spill(pager, payload)?;
+		let cell = table_leaf_cell(rowid, payload.len(), &body);
+		if let Err(index) = self.view(pager, leaf)?.find_rowid(rowid)? {
+			if self.insert_in_place(pager, leaf, index, &cell)? {
+				return Ok(());
+			}
+		}
+		let mut node = self.read_node(pager, leaf)?;
+		let appended = match self.find_rowid(&node, rowid)? {
+			Ok(i) => {
+				let old = core::mem::replace(&mut node.cells[i], cell);
+				self.free_overflow(pager, node.page_type, &old)?;
+				false
+			}
+			Err(i) => {
+				node.cells.insert(i, cell);
+				i + 1 == node.cells.len()
+			}
+		};
+		self.store(pager, leaf, node, path, appended)
+	}
+
+	/// Search for a record in a table tree
+	pub fn search(&self, pager: &mut Pager, rowid: i64) -> Result<Option<Vec<u8>>> {
+		self.expect_kind(BTreeKind::Table)?;
+		let (leaf, _) = self.descend_to_rowid(pager, rowid)?;
+		let view = self.view(pager, leaf)?;
+		let Ok(index) = view.find_rowid(rowid)? else {
+			return Ok(None);
+		};
+		let (page_type, cell) = (view.page_type, view.cell(index)?.to_vec());
+		Ok(Some(self.read_payload(pager, page_type, &cell)?))
+	}
+
+	/// Delete a record from a table tree, returning whether it existed
+	pub fn delete(&mut self, pager: &mut Pager, rowid: i64) -> Result<bool> {
+		self.expect_kind(BTreeKind::Table)?;
+		let (leaf, path) = self.descend_to_rowid(pager, rowid)?;
+		let mut node = self.read_node(pager, leaf)?;
+		let Ok(index) = self.find_rowid(&node, rowid)? else {
+			return Ok(false);
+		};
+		let cell = node.cells.remove(index);
+		self.free_overflow(pager, node.page_type, &cell)?;
+		self.write_node(pager, leaf, &node)?;
+		self.rebalance(pager, leaf, path)?;
+		Ok(true)
+	}
+
+	/// Insert a key into an index tree; inserting an existing key does nothing
+	pub fn insert_key(&mut self, pager: &mut Pager, key: &[u8]) -> Result<()> {
+		self.expect_kind(BTreeKind::Index)?;
+
+		let mut path = Vec::new();
+		let mut page_number = self.root_page;
+		loop {
+			let mut node = self.read_node(pager, page_number)?;
+			let index = match self.find_key(pager, &node, key)? {
+				Ok(_) => return Ok(()),
+				Err(index) => index,
+			};
+			if node.is_leaf() {
+				let body = self.spill(pager, key)?;
+				let cell = index_leaf_cell(key.len(), &body);
+				if self.insert_in_place(pager, page_number, index, &cell)? {
+					return Ok(());
+				}
+				let appended = index == node.cells.len();
+				node.cells.insert(index, cell);
+				return self.store(pager, page_number, node, path, appended);
+			}
+			path.push((page_number, index));
+			page_number = node.child(index);
+		}
+	}
+
+	/// Check whether an index tree contains a key
+	pub fn contains_key(&self, pager: &mut Pager, key: &[u8]) -> Result<bool> {
+		self.expect_kind(BTreeKind::Index)?;
+		let mut page_number = self.root_page;
+		loop {
+			let node = self.read_node(pager, page_number)?;
+			match self.find_key(pager, &node, key)? {
+				Ok(_) => return Ok(true),
+				Err(_) if node.is_leaf() => return Ok(false),
+				Err(index) => page_number = node.child(index),
+			}
+		}
+	}
+
+	/// Delete a key from an index tree, returning whether it existed
+	pub fn delete_key(&mut self, pager: &mut Pager, key: &[u8]) -> Result<bool> {
+		self.expect_kind(BTreeKind::Index)?;
+		let usable = usable_size(pager);
+		let mut path = Vec::new();
+		let mut page_number = self.root_page;
+		loop {
+			let mut node = self.read_node(pager, page_number)?;
+			let index = match self.find_key(pager, &node, key)? {
+				Ok(index) => index,
+				Err(_) if node.is_leaf() => return Ok(false),
+				Err(index) => {
+					path.push((page_number, index));
+					page_number = node.child(index);
+					continue;
+				}
+			};
+
+			let page_type = node.page_type;
+			if node.is_leaf() {
+				let cell = node.cells.remove(index);
+				self.free_overflow(pager, page_type, &cell)?;
+				self.write_node(pager, page_number, &node)?;
+				self.rebalance(pager, page_number, path)?;
+				return Ok(true);
+			}
+
+			// Replace the interior entry with its predecessor, the last key
+			// of the right-most leaf in its left subtree
+			let parent_path = path.clone();
+			let left_child = node.child(index);
+			path.push((page_number, index));
+			let mut leaf_page = left_child;
+			let mut leaf = loop {
+				let candidate = self.read_node(pager, leaf_page)?;
+				if candidate.is_leaf() {
+					break candidate;
+				}
+				path.push((leaf_page, candidate.cells.len()));
+				leaf_page = candidate.right_child;
+			};
+			let predecessor = leaf
+				.cells
+				.pop()
+				.ok_or_else(|| Error::Corrupt(format!("Empty index leaf page {}", leaf_page)))?;
+			self.write_node(pager, leaf_page, &leaf)?;
+
+			let old = core::mem::replace(&mut node.cells[index], interior_cell(left_child, &predecessor));
+			self.free_overflow(pager, page_type, &old)?;
+			if node.fits(header_offset(page_number), usable) {
+				self.write_node(pager, page_number, &node)?;
+				self.rebalance(pager, leaf_page, path)?;
+			} else {
+				self.store(pager, page_number, node, parent_path, false)?;
+			}
+			return Ok(true);
+		}
+	}
+
+	/// Free every page of the tree, including its root and overflow pages
+	pub fn destroy(&self, pager: &mut Pager) -> Result<()> {
+		let mut pending = vec![self.root_page];
+		while let Some(page_number) = pending.pop() {
+			let node = self.read_node(pager, page_number)?;
+			if node.is_leaf() || node.page_type == PageType::InteriorIndex {
+				for cell in &node.cells {
+					self.free_overflow(pager, node.page_type, cell)?;
+				}
+			}
+			if !node.is_leaf() {
+				pending.extend((0..=node.cells.len()).map(|i| node.child(i)));
+			}
+			pager.free_page(page_number)?;
+		}
+		Ok(())
+	}
+
+	/// Copy every entry into a new tree in another pager
+	///
+	/// Entries go in in order, so the copy's pages end up nearly full.
+	pub fn copy(&self, pager: &mut Pager, target: &mut Pager) -> Result<Self> {
+		let mut copy = Self::create(target, self.kind)?.with_comparator(self.compare);
+		let mut cursor = self.cursor();
+		while self.next(pager, &mut cursor)? {
+			let payload = self.payload(pager, &cursor)?;
+			match self.kind {
+				BTreeKind::Table => copy.insert(target, self.rowid(pager, &cursor)?, &payload)?,
+				BTreeKind::Index => copy.insert_key(target, &payload)?,
+			}
+		}
+		Ok(copy)
+	}
+
+	/// List every page of the tree, including overflow pages, with where
+	/// its page number is recorded
+	pub fn page_refs(&self, pager: &mut Pager, refs: &mut Vec<(u32, PageRef)>) -> Result<()> {
+		let usable = usable_size(pager);
+		let mut pending = vec![(self.root_page, PageRef::Root)];
+		while let Some((page_number, page_ref)) = pending.pop() {
+			// Each page is listed once, so a longer list means a cycle
+			if refs.len() >= pager.page_count() as usize {
+				return Err(Error::Corrupt(format!("B-tree page {} is referenced twice", page_number)));
+			}
+			refs.push((page_number, page_ref));
+
+			let view = self.view(pager, page_number)?;
+			let mut overflows = Vec::new();
+			for i in 0..view.cell_count {
+				let offset = view.cell_offset(i)?;
+				let cell = view.cell(i)?;
+				if !view.page_type.is_leaf() {
+					pending.push((cell_left_child(cell), PageRef::Pointer(page_number, offset)));
+				}
+				if view.page_type != PageType::InteriorTable {
+					let (total, local, overflow) = cell_payload(view.page_type, cell, usable, view.fractions)?;
+					if local.len() < total {
+						// The overflow page number ends the cell
+						let pointer = PageRef::Pointer(page_number, offset + cell.len() - 4);
+						overflows.push((overflow, pointer, total - local.len()));
+					}
+				}
+			}
+			if !view.page_type.is_leaf() {
+				let offset = view.hdr + 8;
+				pending.push((read_u32(view.data, offset), PageRef::Pointer(page_number, offset)));
+			}
+
+			// Each overflow page starts with the number of the next
+			for (mut overflow, mut pointer, mut remaining) in overflows {
+				while remaining > 0 {
+					if overflow <= 1 || overflow > pager.page_count() {
+						return Err(Error::Corrupt(format!("Invalid overflow page {}", overflow)));
+					}
+					refs.push((overflow, pointer));
+					pointer = PageRef::Pointer(overflow, 0);
+					overflow = read_u32(&pager.get_page(overflow)?.data, 0);
+					remaining = remaining.saturating_sub(usable - 4);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn expect_kind(&self, kind: BTreeKind) -> Result<()> {
+		if self.kind != kind {
+			return Err(Error::InvalidOperation(format!(
+				"Operation requires a {:?} b-tree, but page {} holds a {:?} b-tree",
+				kind, self.root_page, self.kind
+			)));
+		}
+		Ok(())
+	}
+
+	/// The part of a payload kept in its cell: the bytes that stay on the
+	/// page, followed, if the payload spills, by the number of the first
+	/// overflow page
+	///
+	/// The rest of the payload is written to a new chain of overflow pages,
+	/// each starting with the number of the next, or 0 on the last.
+	fn spill(&self, pager: &mut Pager, payload: &[u8]) -> Result<Vec<u8>> {
+		let usable = usable_size(pager);
+		let local = local_payload_size(self.kind, usable, pager.payload_fractions(), payload.len());
+		let mut body = payload[..local].to_vec();
+		if local == payload.len() {
+			return Ok(body);
+		}
+
+		let chunks: Vec<&[u8]> = payload[local..].chunks(usable - 4).collect();
+		let pages = chunks.iter().map(|_| pager.allocate_page()).collect::<Result<Vec<u32>>>()?;
+		for (i, chunk) in chunks.iter().enumerate() {
+			let next = pages.get(i + 1).copied().unwrap_or(0);
+			let page = pager.get_page_mut(pages[i])?;
+			page.write(0, &next.to_be_bytes())?;
+			page.write(4, chunk)?;
+		}
+		body.extend_from_slice(&pages[0].to_be_bytes());
+		Ok(body)
+	}
+
+	fn view<'a>(&self, pager: &'a mut Pager, page_number: u32) -> Result<NodeView<'a>> {
+		let (usable, fractions) = (usable_size(pager), pager.payload_fractions());
+		let page = pager.get_page(page_number)?;
+		let view = NodeView::new(&page.data, page_number, usable, fractions)?;
+		if view.page_type.kind() != self.kind {
+			return Err(Error::Corrupt(format!(
+				"Page {} does not belong to a {:?} b-tree",
+				page_number, self.kind
+			)));
+		}
+		Ok(view)
+	}
+
+	fn read_node(&self, pager: &mut Pager, page_number: u32) -> Result<Node> {
+		Node::from_view(&self.view(pager, page_number)?)
+	}
+
+	fn write_node(&self, pager: &mut Pager, page_number: u32, node: &Node) -> Result<()> {
+		let usable = usable_size(pager);
+		let hdr = header_offset(page_number);
+		if !node.fits(hdr, usable) {
+			return Err(Error::Internal(format!(
+				"B-tree node of {} bytes does not fit on page {}",
+				node.size(hdr),
+				page_number
+			)));
+		}
+		let page = pager.get_page_mut(page_number)?;
+		node.write_to(&mut page.data, hdr, usable);
+		page.mark_dirty();
+		Ok(())
+	}
+
+	/// Add a cell to a page without rewriting the cells already on it,
+	/// returning false if the gap between the cell pointers and the cell
+	/// content is too small for it
+	fn insert_in_place(&self, pager: &mut Pager, page_number: u32, index: usize, cell: &[u8]) -> Result<bool> {
+		let view = self.view(pager, page_number)?;
+		let (hdr, cell_count) = (view.hdr, view.cell_count);
+		let pointers = hdr + view.page_type.header_size();
+		let pointers_end = pointers + 2 * cell_count;
+		// A content area starting at 65536 is stored as zero
+		let content = match read_u16(view.data, hdr + 5) {
+			0 => 65536,
+			content => content,
+		};
+		if content > view.usable || content < pointers_end + 2 + cell.len() {
+			return Ok(false);
+		}
+
+		let page = pager.get_page_mut(page_number)?;
+		let content = content - cell.len();
+		page.data[content..content + cell.len()].copy_from_slice(cell);
+		page.data.copy_within(pointers + 2 * index..pointers_end, pointers + 2 * index + 2);
+		page.data[pointers + 2 * index..pointers + 2 * index + 2].copy_from_slice(&(content as u16).to_be_bytes());
+		page.data[hdr + 3..hdr + 5].copy_from_slice(&(cell_count as u16 + 1).to_be_bytes());
+		page.data[hdr + 5..hdr + 7].copy_from_slice(&(content as u16).to_be_bytes());
+		page.mark_dirty();
+		Ok(true)
+	}
+
+	/// Free the overflow pages of a cell that is leaving the tree
+	fn free_overflow(&self, pager: &mut Pager, page_type: PageType, cell: &[u8]) -> Result<()> {
+		if page_type == PageType::InteriorTable {
+			return Ok(());
+		}
+		let usable = usable_size(pager);
+		let (total, local, mut overflow) = cell_payload(page_type, cell, usable, pager.payload_fractions())?;
+		let mut remaining = total - local.len();
+		while remaining > 0 {
+			if overflow <= 1 || overflow > pager.page_count() {
+				return Err(Error::Corrupt(format!("Invalid overflow page {}", overflow)));
+			}
+			let next = read_u32(&pager.get_page(overflow)?.data, 0);
+			pager.free_page(overflow)?;
+			remaining = remaining.saturating_sub(usable - 4);
+			overflow = next;
+		}
+		Ok(())
+	}
+
+	/// Assemble the full payload of a cell, following its overflow chain
+	///
+	/// Each overflow page starts with the number of the next page in the
+	/// chain, followed by as much of the remaining payload as fits.
+	fn read_payload(&self, pager: &mut Pager, page_type: PageType, cell: &[u8]) -> Result<Vec<u8>> {
+		let usable = usable_size(pager);
+		let (total, local, mut overflow) = cell_payload(page_type, cell, usable, pager.payload_fractions())?;
+		let mut payload = Vec::with_capacity(total);
+		payload.extend_from_slice(local);
+
+		let mut visited = 0;
+		while payload.len() < total {
+			visited += 1;
+			if overflow == 0 || overflow > pager.page_count() || visited > pager.page_count() {
+				return Err(Error::Corrupt(format!("Invalid overflow page {}", overflow)));
+			}
+			let page = pager.get_page(overflow)?;
+			let chunk = (total - payload.len()).min(usable - 4);
+			payload.extend_from_slice(&page.data[4..4 + chunk]);
+			overflow = read_u32(&page.data, 0);
+		}
+		Ok(payload)
+	}
+
+	fn current_cell(&self, pager: &mut Pager, cursor: &Cursor) -> Result<(PageType, Vec<u8>)> {
+		let &(page_number, index) = cursor
+			.stack
+			.last()
+			.filter(|_| !cursor.eof)
+			.ok_or_else(|| Error::InvalidOperation("Cursor is not on an entry".to_string()))?;
+		let view = self.view(pager, page_number)?;
+		Ok((view.page_type, view.cell(index)?.to_vec()))
+	}
+
+	/// Walk down to the leaf that holds (or would hold) `rowid`, recording
+	/// the interior pages and child indexes passed through
+	fn descend_to_rowid(&self, pager: &mut Pager, rowid: i64) -> Result<(u32, Vec<(u32, usize)>)> {
+		let mut path = Vec::new();
+		let mut page_number = self.root_page;
+		loop {
+			let view = self.view(pager, page_number)?;
+			if view.page_type.is_leaf() {
+				return Ok((page_number, path));
+			}
+			let index = view.find_rowid(rowid)?.unwrap_or_else(|i| i);
+			path.push((page_number, index));
+			page_number = view.child(index)?;
+		}
+	}
+
+	fn find_rowid(&self, node: &Node, rowid: i64) -> Result<core::result::Result<usize, usize>> {
+		let (mut low, mut high) = (0, node.cells.len());
+		while low < high {
+			let mid = (low + high) / 2;
+			match cell_rowid(node.page_type, &node.cells[mid])?.cmp(&rowid) {
+				Ordering::Less => low = mid + 1,
+				Ordering::Greater => high = mid,
+				Ordering::Equal => return Ok(Ok(mid)),
+			}
+		}
+		Ok(Err(low))
+	}
+
+	fn find_key(&self, pager: &mut Pager, node: &Node, key: &[u8]) -> Result<core::result::Result<usize, usize>> {
+		let (mut low, mut high) = (0, node.cells.len());
+		while low < high {
+			let mid = (low + high) / 2;
+			let cell_key = self.read_payload(pager, node.page_type, &node.cells[mid])?;
+			match (self.compare)(&cell_key, key) {
+				Ordering::Less => low = mid + 1,
+				Ordering::Greater => high = mid,
+				Ordering::Equal => return Ok(Ok(mid)),
+			}
+		}
+		Ok(Err(low))
+	}
+
+	/// Push the path to the left-most entry below `page_number`
+	fn descend_leftmost(&self, pager: &mut Pager, cursor: &mut Cursor, page_number: u32) -> Result<bool> {
+		let mut page_number = page_number;
+		loop {
+			let view = self.view(pager, page_number)?;
+			cursor.stack.push((page_number, 0));
+			if view.page_type.is_leaf() {
+				if view.cell_count > 0 {
+					return Ok(true);
+				}
+				return self.pop_exhausted(pager, cursor);
+			}
+			page_number = view.child(0)?;
+		}
+	}
+
+	/// Leave the exhausted page on top of the stack and move on to the next entry
+	fn pop_exhausted(&self, pager: &mut Pager, cursor: &mut Cursor) -> Result<bool> {
+		loop {
+			cursor.stack.pop();
+			let Some(&(page_number, index)) = cursor.stack.last() else {
+				return Ok(false);
+			};
+			let view = self.view(pager, page_number)?;
+			if index < view.cell_count {
+				// Index entries sit between their left and right subtrees
+				if self.kind == BTreeKind::Index {
+					return Ok(true);
+				}
+				let child = view.child(index + 1)?;
+				cursor.stack.last_mut().unwrap().1 = index + 1;
+				return self.descend_leftmost(pager, cursor, child);
+			}
+		}
+	}
+
+	/// Write a node back, splitting it (and its ancestors) if it has grown
+	/// too large for its page; `appended` says the node's last cell is new
+	fn store(&self, pager: &mut Pager, page_number: u32, node: Node, mut path: Vec<(u32, usize)>, appended: bool) -> Result<()> {
+		let usable = usable_size(pager);
+		if node.fits(header_offset(page_number), usable) {
+			return self.write_node(pager, page_number, &node);
+		}
+
+		let (page_number, (parent_page, index)) = match path.pop() {
+			Some(parent) => (page_number, parent),
+			None => {
+				// Keep the root in place by moving its content to a new child
+				let child = pager.allocate_page()?;
+				let root = Node {
+					page_type: PageType::new(self.kind, NodeType::Internal),
+					cells: Vec::new(),
+					right_child: child,
+				};
+				self.write_node(pager, page_number, &root)?;
+				(child, (page_number, 0))
+			}
+		};
+
+		// Appending to the right-most child looks like a sequential insert,
+		// so the new entry goes alone to the right and the left stays full
+		let mut parent = self.read_node(pager, parent_page)?;
+		let append = appended && index == parent.cells.len();
+
+		// The existing page keeps the upper part, so the parent's pointer to
+		// it stays valid; the lower part moves to a new page on its left
+		let (left, divider, right) = self.divide(node, append)?;
+		let left_page = pager.allocate_page()?;
+		self.write_node(pager, left_page, &left)?;
+		self.write_node(pager, page_number, &right)?;
+
+		parent.cells.insert(index, interior_cell(left_page, &divider));
+		self.store(pager, parent_page, parent, path, append)
+	}
+
+	/// Split a node into two halves of similar size and the divider key
+	/// between them, or with `append`, everything but the last cell and that cell
+	fn divide(&self, node: Node, append: bool) -> Result<(Node, Vec<u8>, Node)> {
+		let page_type = node.page_type;
+		let count = node.cells.len();
+		// Except in table leaves, the divider moves up out of the node
+		let moves_up = page_type != PageType::LeafTable;
+		let minimum = if moves_up { 3 } else { 2 };
+		if count < minimum {
+			return Err(Error::Internal(format!("Cannot split a node with {} cells", count)));
+		}
+
+		let total: usize = node.cells.iter().map(|c| c.len() + 2).sum();
+		let mut split = 0;
+		let mut size = 0;
+		while split < count && size + node.cells[split].len() + 2 <= total / 2 {
+			size += node.cells[split].len() + 2;
+			split += 1;
+		}
+		let split = if append { count } else { split };
+		let split = split.clamp(1, if moves_up { count - 2 } else { count - 1 });
+
+		let mut left_cells = node.cells;
+		let mut right_cells = left_cells.split_off(split);
+		let (left_right_child, divider) = match page_type {
+			PageType::LeafTable => {
+				let rowid = cell_rowid(page_type, left_cells.last().unwrap())?;
+				(0, rowid_key(rowid))
+			}
+			PageType::LeafIndex => (0, right_cells.remove(0)),
+			PageType::InteriorTable | PageType::InteriorIndex => {
+				let up = right_cells.remove(0);
+				(cell_left_child(&up), up[4..].to_vec())
+			}
+		};
+
+		let left = Node {
+			page_type,
+			cells: left_cells,
+			right_child: left_right_child,
+		};
+		let right = Node {
+			page_type,
+			cells: right_cells,
+			right_child: node.right_child,
+		};
+		Ok((left, divider, right))
+	}
+
+	/// Join two sibling nodes and the divider key between them into one node
+	fn combine(&self, left: Node, divider: &[u8], right: Node) -> Node {
+		let page_type = left.page_type;
+		let mut cells = left.cells;
+		match page_type {
+			PageType::LeafTable => {}
+			PageType::LeafIndex => cells.push(divider.to_vec()),
+			PageType::InteriorTable | PageType::InteriorIndex => {
+				cells.push(interior_cell(left.right_child, divider));
+			}
+		}
+		cells.extend(right.cells);
+		Node {
+			page_type,
+			cells,
+			right_child: right.right_child,
+		}
+	}
+
+	/// Merge or redistribute an under-full node with a sibling after a deletion
+	fn rebalance(&self, pager: &mut Pager, page_number: u32, mut path: Vec<(u32, usize)>) -> Result<()> {
+		let usable = usable_size(pager);
+		let node = self.read_node(pager, page_number)?;
+
+		let Some((parent_page, index)) = path.pop() else {
+			// A root left with a single child absorbs that child
+			if !node.is_leaf() && node.cells.is_empty() {
+				let child = self.read_node(pager, node.right_child)?;
+				if child.fits(header_offset(page_number), usable) {
+					self.write_node(pager, page_number, &child)?;
+					pager.free_page(node.right_child)?;
+				}
+			}
+			return Ok(());
+		};
+
+		if node.size(0) >= usable / 3 {
+			return Ok(());
+		}
+
+		let mut parent = self.read_node(pager, parent_page)?;
+		if parent.cells.is_empty() {
+			return Ok(());
+		}
+
+		// Pair the node with its right sibling, or its left one if it is the last child
+		let divider_index = index.min(parent.cells.len() - 1);
+		let left_page = parent.child(divider_index);
+		let right_page = parent.child(divider_index + 1);
+		let left = self.read_node(pager, left_page)?;
+		let right = self.read_node(pager, right_page)?;
+		let divider = parent.cells[divider_index][4..].to_vec();
+		let merged = self.combine(left, &divider, right);
+
+		if merged.fits(0, usable) {
+			// The right page takes everything; the left page drops out of the tree
+			self.write_node(pager, right_page, &merged)?;
+			parent.cells.remove(divider_index);
+			self.write_node(pager, parent_page, &parent)?;
+			pager.free_page(left_page)?;
+			return self.rebalance(pager, parent_page, path);
+		}
+
+		// Too much for one page, so share the cells out evenly instead
+		let (left, divider, right) = self.divide(merged, false)?;
+		if !left.fits(0, usable) || !right.fits(0, usable) {
+			return Ok(());
+		}
+		self.write_node(pager, left_page, &left)?;
+		self.write_node(pager, right_page, &right)?;
+		parent.cells[divider_index] = interior_cell(left_page, &divider);
+		self.store(pager, parent_page, parent, path, false)
 	}
 }
 
+/// Default ordering for index keys
+fn compare_bytes(a: &[u8], b: &[u8]) -> Ordering {
+	a.cmp(b)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	/// Visit the numbers below `count` in a scrambled but repeatable order
+	fn scrambled(count: i64) -> impl Iterator<Item = i64> {
+		(0..count).map(move |i| (i * 7919) % count)
+	}
+
+	fn collect_rowids(tree: &BTree, pager: &mut Pager) -> Vec<i64> {
+		let mut cursor = tree.cursor();
+		let mut rowids = Vec::new();
+		while tree.next(pager, &mut cursor).unwrap() {
+			rowids.push(tree.rowid(pager, &cursor).unwrap());
+		}
+		rowids
+	}
+
+	fn collect_keys(tree: &BTree, pager: &mut Pager) -> Vec<Vec<u8>> {
+		let mut cursor = tree.cursor();
+		let mut keys = Vec::new();
+		while tree.next(pager, &mut cursor).unwrap() {
+			keys.push(tree.payload(pager, &cursor).unwrap());
+		}
+		keys
+	}
+
+	fn root_type(tree: &BTree, pager: &mut Pager) -> PageType {
+		let page = pager.get_page(tree.root_page()).unwrap();
+		PageType::from_byte(page.data[header_offset(tree.root_page())]).unwrap()
+	}
+
 	#[test]
 	fn test_btree_creation() {
 		let btree = BTree::new(1);
@@ -110,4 +1288,303 @@ mod tests {
 		assert_eq!(NodeType::Internal, NodeType::Internal);
 		assert_ne!(NodeType::Internal, NodeType::Leaf);
 	}
+
+	#[test]
+	fn test_local_payload_size() {
+		let fractions = PayloadFractions::default();
+		assert_eq!(local_payload_size(BTreeKind::Table, 4096, fractions, 100), 100);
+		assert_eq!(local_payload_size(BTreeKind::Table, 4096, fractions, 4061), 4061);
+		assert!(local_payload_size(BTreeKind::Table, 4096, fractions, 5000) < 4061);
+		assert_eq!(local_payload_size(BTreeKind::Index, 4096, fractions, 1002), 1002);
+		assert!(local_payload_size(BTreeKind::Index, 4096, fractions, 1003) < 1003);
+
+		// The rest fills whole overflow pages, unless that leaves too little
+		assert_eq!(local_payload_size(BTreeKind::Table, 4096, fractions, 5000), 908);
+		assert_eq!(local_payload_size(BTreeKind::Table, 4096, fractions, 4092 + 1000), 1000);
+
+		// The header fractions move both bounds
+		let fractions = PayloadFractions { max_embedded: 48, min_embedded: 40, leaf: 100 };
+		assert_eq!(local_payload_size(BTreeKind::Index, 4096, fractions, 745), 745);
+		assert_eq!(local_payload_size(BTreeKind::Index, 4096, fractions, 746), 617);
+		assert_eq!(local_payload_size(BTreeKind::Table, 4096, fractions, 5000), 1578);
+	}
+
+	#[test]
+	fn test_table_insert_and_search() {
+		let mut pager = Pager::new(4096).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		tree.insert(&mut pager, 2, b"two").unwrap();
+		tree.insert(&mut pager, 1, b"one").unwrap();
+
+		assert_eq!(tree.search(&mut pager, 1).unwrap(), Some(b"one".to_vec()));
+		assert_eq!(tree.search(&mut pager, 2).unwrap(), Some(b"two".to_vec()));
+		assert_eq!(tree.search(&mut pager, 3).unwrap(), None);
+		assert_eq!(tree.last_rowid(&mut pager).unwrap(), Some(2));
+
+		// Inserting an existing rowid replaces the record
+		tree.insert(&mut pager, 1, b"uno").unwrap();
+		assert_eq!(tree.search(&mut pager, 1).unwrap(), Some(b"uno".to_vec()));
+		assert_eq!(collect_rowids(&tree, &mut pager), vec![1, 2]);
+	}
+
+	#[test]
+	fn test_insert_fills_free_space_in_place() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		tree.insert(&mut pager, 5, b"five").unwrap();
+		tree.insert(&mut pager, 3, b"three").unwrap();
+		tree.insert(&mut pager, 4, b"four").unwrap();
+
+		// Each cell went below the last one, with its pointer in rowid order,
+		// so rowid 4 inserted last starts the content
+		let root = tree.root_page();
+		let data = pager.get_page(root).unwrap().data.clone();
+		let view = NodeView::new(&data, root, 1024, PayloadFractions::default()).unwrap();
+		let offsets: Vec<usize> = (0..3).map(|i| view.cell_offset(i).unwrap()).collect();
+		assert_eq!(read_u16(&data, view.hdr + 5), offsets[1]);
+		assert!(offsets[1] < offsets[0] && offsets[0] < offsets[2]);
+		assert_eq!(collect_rowids(&tree, &mut pager), vec![3, 4, 5]);
+
+		// Once the page is full the tree splits as before
+		for rowid in scrambled(200) {
+			tree.insert(&mut pager, rowid + 10, format!("row-{:020}", rowid).as_bytes()).unwrap();
+		}
+		assert_eq!(root_type(&tree, &mut pager), PageType::InteriorTable);
+		assert_eq!(collect_rowids(&tree, &mut pager).len(), 203);
+		assert_eq!(tree.search(&mut pager, 4).unwrap(), Some(b"four".to_vec()));
+	}
+
+	#[test]
+	fn test_table_splits_and_traverses_in_order() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		for rowid in scrambled(2000) {
+			tree.insert(&mut pager, rowid, format!("row-{:040}", rowid).as_bytes()).unwrap();
+		}
+
+		assert_eq!(root_type(&tree, &mut pager), PageType::InteriorTable);
+		assert!(pager.page_count() > 100);
+		assert_eq!(collect_rowids(&tree, &mut pager), (0..2000).collect::<Vec<_>>());
+		for rowid in [0, 1, 999, 1999] {
+			let payload = tree.search(&mut pager, rowid).unwrap().unwrap();
+			assert_eq!(payload, format!("row-{:040}", rowid).into_bytes());
+		}
+		assert_eq!(tree.last_rowid(&mut pager).unwrap(), Some(1999));
+	}
+
+	#[test]
+	fn test_table_seek() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		for rowid in 0..500 {
+			tree.insert(&mut pager, rowid * 2, &[0u8; 40]).unwrap();
+		}
+
+		let mut cursor = tree.cursor();
+		assert!(tree.seek(&mut pager, &mut cursor, 301).unwrap());
+		assert_eq!(tree.rowid(&mut pager, &cursor).unwrap(), 302);
+		assert!(tree.next(&mut pager, &mut cursor).unwrap());
+		assert_eq!(tree.rowid(&mut pager, &cursor).unwrap(), 304);
+
+		assert!(!tree.seek(&mut pager, &mut cursor, 999).unwrap());
+		assert!(cursor.is_eof());
+	}
+
+	#[test]
+	fn test_table_delete_merges_pages() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		for rowid in 0..2000 {
+			tree.insert(&mut pager, rowid, &[7u8; 60]).unwrap();
+		}
+
+		for rowid in scrambled(2000).filter(|r| r % 2 == 0) {
+			assert!(tree.delete(&mut pager, rowid).unwrap());
+		}
+		assert!(!tree.delete(&mut pager, 0).unwrap());
+		let expected: Vec<i64> = (0..2000).filter(|r| r % 2 == 1).collect();
+		assert_eq!(collect_rowids(&tree, &mut pager), expected);
+
+		for rowid in expected {
+			assert!(tree.delete(&mut pager, rowid).unwrap());
+		}
+		assert!(collect_rowids(&tree, &mut pager).is_empty());
+		// Merging collapses the tree back to a single leaf
+		assert_eq!(root_type(&tree, &mut pager), PageType::LeafTable);
+	}
+
+	#[test]
+	fn test_index_insert_delete_and_order() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Index).unwrap();
+		for i in scrambled(1500) {
+			tree.insert_key(&mut pager, format!("key-{:05}", i).as_bytes()).unwrap();
+		}
+		assert_eq!(root_type(&tree, &mut pager), PageType::InteriorIndex);
+
+		let keys = collect_keys(&tree, &mut pager);
+		let expected: Vec<Vec<u8>> = (0..1500).map(|i| format!("key-{:05}", i).into_bytes()).collect();
+		assert_eq!(keys, expected);
+		assert!(tree.contains_key(&mut pager, b"key-00042").unwrap());
+		assert!(!tree.contains_key(&mut pager, b"key-99999").unwrap());
+
+		// Deleting removes entries from interior pages as well as leaves
+		for i in scrambled(1500).filter(|i| i % 3 != 0) {
+			assert!(tree.delete_key(&mut pager, format!("key-{:05}", i).as_bytes()).unwrap());
+		}
+		let expected: Vec<Vec<u8>> = (0..1500)
+			.filter(|i| i % 3 == 0)
+			.map(|i| format!("key-{:05}", i).into_bytes())
+			.collect();
+		assert_eq!(collect_keys(&tree, &mut pager), expected);
+		assert!(!tree.delete_key(&mut pager, b"key-00001").unwrap());
+	}
+
+	#[test]
+	fn test_index_seek() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Index).unwrap();
+		for i in 0..800 {
+			tree.insert_key(&mut pager, format!("{:04}", i * 2).as_bytes()).unwrap();
+		}
+
+		let mut cursor = tree.cursor();
+		assert!(tree.seek_key(&mut pager, &mut cursor, b"0777").unwrap());
+		assert_eq!(tree.payload(&mut pager, &cursor).unwrap(), b"0778");
+		assert!(tree.next(&mut pager, &mut cursor).unwrap());
+		assert_eq!(tree.payload(&mut pager, &cursor).unwrap(), b"0780");
+		assert!(tree.seek_key(&mut pager, &mut cursor, b"0100").unwrap());
+		assert_eq!(tree.payload(&mut pager, &cursor).unwrap(), b"0100");
+	}
+
+	#[test]
+	fn test_sequential_inserts_fill_pages() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		for rowid in 0..2000 {
+			tree.insert(&mut pager, rowid, &[7u8; 20]).unwrap();
+		}
+		// 40 of these cells fit on a leaf, so 50 full leaves hold them all
+		assert!(pager.page_count() <= 53, "{} pages", pager.page_count());
+		assert_eq!(collect_rowids(&tree, &mut pager), (0..2000).collect::<Vec<i64>>());
+	}
+
+	#[test]
+	fn test_copy_and_page_refs() {
+		let mut pager = Pager::new(1024).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Index).unwrap();
+		for i in scrambled(1500) {
+			tree.insert_key(&mut pager, format!("key-{:05}", i).as_bytes()).unwrap();
+		}
+		let mut refs = Vec::new();
+		tree.page_refs(&mut pager, &mut refs).unwrap();
+		assert_eq!(refs.len() as u32, pager.page_count());
+		assert_eq!(refs.iter().filter(|(_, r)| *r == PageRef::Root).count(), 1);
+
+		// The copy is packed more tightly than a tree built in random order
+		let mut target = Pager::new(1024).unwrap();
+		let copy = tree.copy(&mut pager, &mut target).unwrap();
+		assert_eq!(collect_keys(&copy, &mut target), collect_keys(&tree, &mut pager));
+		assert!(target.page_count() < pager.page_count());
+		let opened = BTree::open(&mut target, copy.root_page()).unwrap();
+		assert_eq!(opened.kind(), BTreeKind::Index);
+	}
+
+	#[test]
+	fn test_kind_mismatch() {
+		let mut pager = Pager::new(4096).unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Index).unwrap();
+		assert!(tree.insert(&mut pager, 1, b"x").is_err());
+	}
+
+	#[test]
+	fn test_overflow_pages() {
+		let mut pager = Pager::new(1024).unwrap();
+		pager.allocate_page().unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		let payload = |rowid: i64, len: usize| (0..len).map(|i| (i as i64 * 31 + rowid) as u8).collect::<Vec<u8>>();
+		for rowid in 1..=20 {
+			tree.insert(&mut pager, rowid, &payload(rowid, rowid as usize * 1000)).unwrap();
+		}
+		for rowid in 1..=20 {
+			assert_eq!(tree.search(&mut pager, rowid).unwrap().unwrap(), payload(rowid, rowid as usize * 1000));
+		}
+		let mut refs = Vec::new();
+		tree.page_refs(&mut pager, &mut refs).unwrap();
+		assert_eq!(refs.len() as u32, pager.page_count() - 1);
+
+		// Replacing or deleting a record frees its overflow pages
+		let grown = pager.page_count();
+		tree.insert(&mut pager, 20, b"small").unwrap();
+		assert_eq!(pager.freelist_count().unwrap(), 19);
+		tree.insert(&mut pager, 21, &payload(21, 20_000)).unwrap();
+		assert_eq!(pager.page_count(), grown);
+		for rowid in 1..=21 {
+			assert!(tree.delete(&mut pager, rowid).unwrap());
+		}
+		assert_eq!(pager.freelist_count().unwrap(), grown - 2);
+
+		// Index keys spill too, and still sort by their whole content
+		let mut index = BTree::create(&mut pager, BTreeKind::Index).unwrap();
+		let key = |i: i64| format!("{}-{:03}", "k".repeat(3000), i).into_bytes();
+		for i in scrambled(50) {
+			index.insert_key(&mut pager, &key(i)).unwrap();
+		}
+		assert_eq!(collect_keys(&index, &mut pager), (0..50).map(key).collect::<Vec<_>>());
+		assert!(index.contains_key(&mut pager, &key(42)).unwrap());
+		let mut target = Pager::new(1024).unwrap();
+		let copy = index.copy(&mut pager, &mut target).unwrap();
+		assert_eq!(collect_keys(&copy, &mut target), collect_keys(&index, &mut pager));
+		for i in scrambled(50) {
+			assert!(index.delete_key(&mut pager, &key(i)).unwrap());
+		}
+		index.destroy(&mut pager).unwrap();
+		assert_eq!(pager.freelist_count().unwrap(), pager.page_count() - 2);
+	}
+
+	#[test]
+	fn test_payload_fractions_govern_spilling() {
+		let mut pager = Pager::new(1024).unwrap();
+		pager.allocate_page().unwrap();
+		pager.set_payload_fractions(PayloadFractions { max_embedded: 64, min_embedded: 32, leaf: 200 });
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		tree.insert(&mut pager, 1, &[1u8; 3300]).unwrap();
+
+		// The record keeps (1012 * 200 / 255) - 23 bytes on its page, so three
+		// overflow pages of 1020 bytes hold the rest
+		let view = tree.view(&mut pager, tree.root_page()).unwrap();
+		let (total, local, _) = cell_payload(view.page_type, view.cell(0).unwrap(), 1024, view.fractions).unwrap();
+		assert_eq!((total, local.len()), (3300, 770));
+		assert_eq!(pager.page_count(), 5);
+		assert_eq!(tree.search(&mut pager, 1).unwrap().unwrap(), vec![1u8; 3300]);
+
+		// The default fractions keep only what does not fill a whole page
+		assert_eq!(local_payload_size(BTreeKind::Table, 1024, PayloadFractions::default(), 3300), 240);
+	}
+
+	#[test]
+	fn test_freed_pages_are_reused() {
+		let mut pager = Pager::new(1024).unwrap();
+		pager.allocate_page().unwrap();
+		let mut tree = BTree::create(&mut pager, BTreeKind::Table).unwrap();
+		for rowid in 0..2000 {
+			tree.insert(&mut pager, rowid, &[7u8; 40]).unwrap();
+		}
+		let grown = pager.page_count();
+
+		for rowid in scrambled(2000) {
+			assert!(tree.delete(&mut pager, rowid).unwrap());
+		}
+		assert_eq!(root_type(&tree, &mut pager), PageType::LeafTable);
+		assert_eq!(pager.freelist_count().unwrap(), grown - 2);
+
+		// Refilling the tree takes its pages from the freelist
+		for rowid in 0..2000 {
+			tree.insert(&mut pager, rowid, &[7u8; 40]).unwrap();
+		}
+		assert_eq!(pager.page_count(), grown);
+
+		tree.destroy(&mut pager).unwrap();
+		assert_eq!(pager.freelist_count().unwrap(), grown - 1);
+	}
 }
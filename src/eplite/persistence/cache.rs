@@ -0,0 +1,270 @@
+/// Page cache with least-recently-used eviction
+///
+/// As in SQLite, only clean pages are candidates for eviction. A page handed
+/// out for writing is pinned until `mark_clean` records that it has reached
+/// the file, so a change cannot be dropped before it is written.
+
+use crate::eplite::persistence::pager::Page;
+
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(not(feature = "std"))]
+use alloc::{collections::BTreeMap, collections::BTreeMap as HashMap, vec::Vec};
+
+/// Counters describing how well the page cache is doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+	/// Page requests answered from the cache
+	pub hits: u64,
+	/// Page requests that had to read the page
+	pub misses: u64,
+	/// Pages dropped to make room for others
+	pub evictions: u64,
+	/// Pages in the cache now
+	pub pages: usize,
+	/// Pages the cache holds before it starts evicting
+	pub capacity: usize,
+}
+
+/// Cached pages, with the clean ones ordered by when they were last used
+#[derive(Debug)]
+pub struct PageCache {
+	/// Each page with the tick of its last use
+	pages: HashMap<u32, (Page, u64)>,
+	/// Unpinned pages by the tick of their last use, oldest first
+	lru: BTreeMap<u64, u32>,
+	tick: u64,
+	capacity: usize,
+	hits: u64,
+	misses: u64,
+	evictions: u64,
+}
+
+impl PageCache {
+	pub fn new(capacity: usize) -> Self {
+		PageCache {
+			pages: HashMap::new(),
+			lru: BTreeMap::new(),
+			tick: 0,
+			capacity,
+			hits: 0,
+			misses: 0,
+			evictions: 0,
+		}
+	}
+
+	/// Pages the cache holds before it starts evicting
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	pub fn set_capacity(&mut self, capacity: usize) {
+		self.capacity = capacity;
+	}
+
+	/// Whether adding a page should first evict one
+	pub fn is_full(&self) -> bool {
+		self.pages.len() >= self.capacity
+	}
+
+	pub fn len(&self) -> usize {
+		self.pages.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.pages.is_empty()
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			hits: self.hits,
+			misses: self.misses,
+			evictions: self.evictions,
+			pages: self.pages.len(),
+			capacity: self.capacity,
+		}
+	}
+
+	/// Record a request for a page, counting a hit or a miss and marking
+	/// the page as the most recently used; returns whether it is cached
+	pub fn lookup(&mut self, page_number: u32) -> bool {
+		let tick = self.next_tick();
+		let Some((_, used)) = self.pages.get_mut(&page_number) else {
+			self.misses += 1;
+			return false;
+		};
+		self.hits += 1;
+		if self.lru.remove(used).is_some() {
+			self.lru.insert(tick, page_number);
+		}
+		*used = tick;
+		true
+	}
+
+	pub fn contains_key(&self, page_number: &u32) -> bool {
+		self.pages.contains_key(page_number)
+	}
+
+	pub fn get(&self, page_number: &u32) -> Option<&Page> {
+		self.pages.get(page_number).map(|(page, _)| page)
+	}
+
+	/// Get a page to change, pinning it until `mark_clean`
+	pub fn get_mut(&mut self, page_number: &u32) -> Option<&mut Page> {
+		let (page, used) = self.pages.get_mut(page_number)?;
+		self.lru.remove(used);
+		Some(page)
+	}
+
+	/// Add or replace a page as the most recently used; a dirty page is
+	/// pinned
+	pub fn insert(&mut self, page_number: u32, page: Page) {
+		let tick = self.next_tick();
+		if let Some((_, used)) = self.pages.get(&page_number) {
+			self.lru.remove(used);
+		}
+		if !page.dirty {
+			self.lru.insert(tick, page_number);
+		}
+		self.pages.insert(page_number, (page, tick));
+	}
+
+	pub fn remove(&mut self, page_number: &u32) -> Option<Page> {
+		let (page, used) = self.pages.remove(page_number)?;
+		self.lru.remove(&used);
+		Some(page)
+	}
+
+	/// Drop the least recently used clean page, returning false if every
+	/// page is pinned
+	pub fn evict(&mut self) -> bool {
+		let Some((_, page_number)) = self.lru.pop_first() else {
+			return false;
+		};
+		self.pages.remove(&page_number);
+		self.evictions += 1;
+		true
+	}
+
+	/// Mark every page clean, once the changes have reached the file, and
+	/// unpin them
+	pub fn mark_clean(&mut self) {
+		for (&page_number, (page, used)) in self.pages.iter_mut() {
+			page.dirty = false;
+			self.lru.insert(*used, page_number);
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.pages.clear();
+		self.lru.clear();
+	}
+
+	/// Keep only the pages for which `keep` returns true
+	pub fn retain<F: FnMut(u32) -> bool>(&mut self, mut keep: F) {
+		let lru = &mut self.lru;
+		self.pages.retain(|&page_number, (_, used)| {
+			let kept = keep(page_number);
+			if !kept {
+				lru.remove(used);
+			}
+			kept
+		});
+	}
+
+	pub fn extend<I: IntoIterator<Item = (u32, Page)>>(&mut self, pages: I) {
+		for (page_number, page) in pages {
+			self.insert(page_number, page);
+		}
+	}
+
+	pub fn keys(&self) -> impl Iterator<Item = &u32> {
+		self.pages.keys()
+	}
+
+	pub fn values(&self) -> impl Iterator<Item = &Page> {
+		self.pages.values().map(|(page, _)| page)
+	}
+
+	/// Numbers of the pages changed since they were last written, in order
+	pub fn dirty_pages(&self) -> Vec<u32> {
+		let mut dirty: Vec<u32> = self.values().filter(|page| page.dirty).map(|page| page.page_number).collect();
+		dirty.sort_unstable();
+		dirty
+	}
+
+	fn next_tick(&mut self) -> u64 {
+		self.tick += 1;
+		self.tick
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn page(page_number: u32) -> Page {
+		Page::new(page_number, 512)
+	}
+
+	#[test]
+	fn test_evicts_least_recently_used() {
+		let mut cache = PageCache::new(3);
+		for page_number in 1..=3 {
+			assert!(!cache.lookup(page_number));
+			cache.insert(page_number, page(page_number));
+		}
+		assert!(cache.is_full());
+
+		// Using page 1 leaves page 2 the oldest
+		assert!(cache.lookup(1));
+		assert!(cache.evict());
+		assert!(!cache.contains_key(&2));
+		assert!(cache.evict());
+		assert!(!cache.contains_key(&3));
+		assert_eq!(
+			cache.stats(),
+			CacheStats { hits: 1, misses: 3, evictions: 2, pages: 1, capacity: 3 }
+		);
+	}
+
+	#[test]
+	fn test_dirty_pages_are_pinned() {
+		let mut cache = PageCache::new(2);
+		cache.insert(1, page(1));
+		cache.insert(2, page(2));
+		cache.get_mut(&1).unwrap().mark_dirty();
+		let mut new = page(3);
+		new.mark_dirty();
+		cache.insert(3, new);
+		assert_eq!(cache.dirty_pages(), vec![1, 3]);
+
+		assert!(cache.evict());
+		assert!(!cache.contains_key(&2));
+		assert!(!cache.evict());
+		assert_eq!(cache.len(), 2);
+
+		// Once written they can go, oldest first
+		cache.mark_clean();
+		assert!(cache.dirty_pages().is_empty());
+		assert!(cache.evict());
+		assert!(!cache.contains_key(&1));
+	}
+
+	#[test]
+	fn test_retain_and_replace() {
+		let mut cache = PageCache::new(10);
+		cache.extend((1..=5).map(|page_number| (page_number, page(page_number))));
+		cache.retain(|page_number| page_number <= 3);
+		assert_eq!(cache.len(), 3);
+
+		// A page replaced by a clean copy is no longer pinned
+		cache.get_mut(&1).unwrap().mark_dirty();
+		cache.insert(1, page(1));
+		assert!(cache.evict());
+		assert!(cache.evict());
+		assert!(cache.evict());
+		assert!(cache.is_empty());
+	}
+}
@@ -6,17 +6,55 @@ use crate::eplite::constants::{
 use crate::eplite::error::{Error, Result};
 
 #[cfg(not(feature = "std"))]
-use alloc::{string::ToString, vec, vec::Vec};
+use alloc::{format, string::ToString, vec, vec::Vec};
+
+/// Size of the database header at the start of page 1
+pub const DATABASE_HEADER_SIZE: usize = 100;
 
 /// Database file format type
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum FileFormat {
 	/// EPLite format version 1
+	#[default]
 	EPLiteV1,
 	/// SQLite format version 3
 	SQLiteV3,
 }
 
+/// Shares of a page, out of 255, that decide how much of a record stays on
+/// its b-tree page; the rest spills to overflow pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadFractions {
+	/// Most of a page an index cell keeps
+	pub max_embedded: u8,
+	/// Least of a page a spilling index cell keeps
+	pub min_embedded: u8,
+	/// Least of a page a spilling table cell keeps
+	pub leaf: u8,
+}
+
+impl PayloadFractions {
+	/// Index cells larger than this share of a page would leave too few
+	/// on each page to split it
+	pub const MAX_EMBEDDED_LIMIT: u8 = 64;
+
+	/// Whether b-trees can be built with these fractions
+	pub fn is_valid(&self) -> bool {
+		self.min_embedded <= self.max_embedded && self.max_embedded <= Self::MAX_EMBEDDED_LIMIT
+	}
+}
+
+/// SQLite requires these values, and writes them in every file
+impl Default for PayloadFractions {
+	fn default() -> Self {
+		PayloadFractions {
+			max_embedded: 64,
+			min_embedded: 32,
+			leaf: 32,
+		}
+	}
+}
+
 /// Database file header (first 100 bytes of the database file)
 #[derive(Debug, Clone)]
 pub struct DatabaseHeader {
@@ -30,11 +68,11 @@ pub struct DatabaseHeader {
 	pub read_version: u8,
 	/// Reserved space at end of each page
 	pub reserved_space: u8,
-	/// Maximum embedded payload fraction (must be 64)
+	/// Maximum embedded payload fraction (64 in SQLite)
 	pub max_embedded_payload: u8,
-	/// Minimum embedded payload fraction (must be 32)
+	/// Minimum embedded payload fraction (32 in SQLite)
 	pub min_embedded_payload: u8,
-	/// Leaf payload fraction (must be 32)
+	/// Leaf payload fraction (32 in SQLite)
 	pub leaf_payload: u8,
 	/// File change counter
 	pub change_counter: u32,
@@ -137,6 +175,15 @@ impl DatabaseHeader {
 		let schema_format = SchemaFormat::from_u32(schema_format_raw)
 			.ok_or_else(|| Error::InvalidFormat("Invalid schema format".to_string()))?;
 
+		let fractions = PayloadFractions {
+			max_embedded: bytes[21],
+			min_embedded: bytes[22],
+			leaf: bytes[23],
+		};
+		if !fractions.is_valid() {
+			return Err(Error::InvalidFormat(format!("Invalid payload fractions: {:?}", fractions)));
+		}
+
 		Ok(DatabaseHeader {
 			format,
 			page_size,
@@ -164,6 +211,15 @@ impl DatabaseHeader {
 		})
 	}
 
+	/// The fractions that decide how records spill to overflow pages
+	pub fn payload_fractions(&self) -> PayloadFractions {
+		PayloadFractions {
+			max_embedded: self.max_embedded_payload,
+			min_embedded: self.min_embedded_payload,
+			leaf: self.leaf_payload,
+		}
+	}
+
 	/// Serialize the header to bytes
 	pub fn to_bytes(&self) -> Vec<u8> {
 		let mut bytes = vec![0u8; 100];
@@ -253,6 +309,26 @@ mod tests {
 		assert!(result.is_err());
 	}
 
+	#[test]
+	fn test_payload_fractions() {
+		let mut header = DatabaseHeader::new_eplite();
+		assert_eq!(header.payload_fractions(), PayloadFractions::default());
+
+		header.max_embedded_payload = 48;
+		header.leaf_payload = 100;
+		let parsed = DatabaseHeader::from_bytes(&header.to_bytes()).unwrap();
+		assert_eq!(
+			parsed.payload_fractions(),
+			PayloadFractions { max_embedded: 48, min_embedded: 32, leaf: 100 }
+		);
+
+		// Index cells must stay small enough to split pages
+		header.max_embedded_payload = 65;
+		assert!(DatabaseHeader::from_bytes(&header.to_bytes()).is_err());
+		header.max_embedded_payload = 16;
+		assert!(DatabaseHeader::from_bytes(&header.to_bytes()).is_err());
+	}
+
 	#[test]
 	fn test_invalid_magic() {
 		let mut bytes = vec![0u8; 100];
@@ -0,0 +1,350 @@
+/// Rollback journal - keeps the original contents of pages a commit
+/// overwrites, so a crash part way through can be undone
+///
+/// The file follows SQLite's journal format: a header padded to one sector,
+/// then for each page its number, its original contents and a checksum.
+
+use crate::eplite::constants::{JournalMode, MAX_PAGE_SIZE, MIN_PAGE_SIZE};
+use crate::eplite::error::{Error, Result};
+use crate::eplite::os;
+use crate::eplite::os::file::DefaultFile;
+use crate::eplite::traits::file::{File, LockType, SynchronizationType, UnlockType};
+use flagset::FlagSet;
+use std::collections::HashSet;
+use std::io;
+
+/// Bytes that open every journal header
+const JOURNAL_MAGIC: [u8; 8] = [0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+
+/// Size of the journal header, which is padded to this sector size
+const SECTOR_SIZE: usize = 512;
+
+/// Bytes of the header that hold its fields; PERSIST mode zeroes them
+const HEADER_FIELDS_SIZE: usize = 28;
+
+/// The rollback journal of one database file
+#[derive(Debug, Clone)]
+pub struct Journal {
+	db_path: String,
+	path: String,
+	mode: JournalMode,
+	/// The journal of the commit in progress, until it finishes
+	open: Option<OpenJournal>,
+}
+
+/// Header values and pages of a journal still being written
+#[derive(Debug, Clone)]
+struct OpenJournal {
+	nonce: u32,
+	/// Size of the database before the commit
+	page_count: u32,
+	/// Pages with a record
+	pages: HashSet<u32>,
+}
+
+impl Journal {
+	/// The journal of the database at `db_path`, kept beside it
+	pub fn new(db_path: &str) -> Self {
+		Journal {
+			db_path: db_path.to_string(),
+			path: format!("{}-journal", db_path),
+			mode: JournalMode::Delete,
+			open: None,
+		}
+	}
+
+	/// Path of the journal file
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	/// Path of the write-ahead log the database uses in WAL mode
+	pub fn wal_path(&self) -> String {
+		format!("{}-wal", self.db_path)
+	}
+
+	pub fn mode(&self) -> JournalMode {
+		self.mode
+	}
+
+	/// Change the journal mode, removing a journal left by PERSIST or
+	/// TRUNCATE mode when the new mode would not reuse it
+	pub fn set_mode(&mut self, mode: JournalMode) -> Result<()> {
+		if self.open.is_some() && mode != self.mode {
+			return Err(Error::InvalidOperation(
+				"cannot change the journal mode once a transaction has written to the database file".to_string(),
+			));
+		}
+		if !matches!(mode, JournalMode::Truncate | JournalMode::Persist) {
+			self.remove()?;
+		}
+		self.mode = mode;
+		Ok(())
+	}
+
+	/// Size of the database before the commit in progress, if its journal
+	/// has been started
+	pub fn page_count(&self) -> Option<u32> {
+		self.open.as_ref().map(|open| open.page_count)
+	}
+
+	/// Whether the commit in progress has journaled this page
+	pub fn contains(&self, page_number: u32) -> bool {
+		self.open.as_ref().is_some_and(|open| open.pages.contains(&page_number))
+	}
+
+	/// Write and sync the original contents of the pages a commit is about
+	/// to overwrite; `page_count` is the size of the database beforehand
+	///
+	/// A commit may write pages more than once before it finishes. Later
+	/// writes add records to its journal, keeping the size first given and
+	/// the first record of each page.
+	pub fn write(&mut self, page_size: u32, page_count: u32, pages: &[(u32, Vec<u8>)]) -> Result<()> {
+		let mut file = DefaultFile::open(&self.path, true, true, true)?;
+		let open = match &mut self.open {
+			Some(open) => open,
+			None => {
+				file.truncate(0)?;
+				let nonce = u32::from_be_bytes(os::random_bytes(4).try_into().unwrap_or_default());
+				self.open.insert(OpenJournal { nonce, page_count, pages: HashSet::new() })
+			}
+		};
+
+		let record_size = page_size as usize + 8;
+		let offset = SECTOR_SIZE + open.pages.len() * record_size;
+		let mut data = Vec::with_capacity(pages.len() * record_size);
+		for (page_number, contents) in pages {
+			if open.pages.insert(*page_number) {
+				data.extend_from_slice(&page_number.to_be_bytes());
+				data.extend_from_slice(contents);
+				data.extend_from_slice(&checksum(open.nonce, contents).to_be_bytes());
+			}
+		}
+		file.write(&data, offset as u64)?;
+		file.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+
+		// The header is written once its records are on disk, so it never
+		// counts one that is not there
+		let mut header = Vec::with_capacity(SECTOR_SIZE);
+		header.extend_from_slice(&JOURNAL_MAGIC);
+		header.extend_from_slice(&(open.pages.len() as u32).to_be_bytes());
+		header.extend_from_slice(&open.nonce.to_be_bytes());
+		header.extend_from_slice(&open.page_count.to_be_bytes());
+		header.extend_from_slice(&(SECTOR_SIZE as u32).to_be_bytes());
+		header.extend_from_slice(&page_size.to_be_bytes());
+		header.resize(SECTOR_SIZE, 0);
+		file.write(&header, 0)?;
+		file.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+		Ok(())
+	}
+
+	/// Undo the commit in progress, writing the journaled pages back to the
+	/// database file, which must be locked exclusive, and finishing the
+	/// journal
+	pub fn rollback(&mut self, db: &mut dyn File) -> Result<()> {
+		self.restore(db)?;
+		self.finish()
+	}
+
+	/// End the commit the journal was written for, once the database file
+	/// holds all of its pages
+	pub fn finish(&mut self) -> Result<()> {
+		self.open = None;
+		self.end()
+	}
+
+	/// Remove, truncate or zero the journal file as the mode says
+	fn end(&self) -> Result<()> {
+		match self.mode {
+			JournalMode::Truncate => {
+				let mut file = DefaultFile::open(&self.path, true, true, false)?;
+				file.truncate(0)?;
+				file.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+			}
+			JournalMode::Persist => {
+				let mut file = DefaultFile::open(&self.path, true, true, false)?;
+				file.write(&[0; HEADER_FIELDS_SIZE], 0)?;
+				file.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+			}
+			_ => self.remove()?,
+		}
+		Ok(())
+	}
+
+	/// Roll back a hot journal left by a crash, restoring the database to
+	/// its state before the interrupted commit
+	///
+	/// Returns whether there was a hot journal. Records after the first one
+	/// that fails its checksum were never completely written, so they are
+	/// ignored.
+	///
+	/// A journal is not hot while a connection holds a reserved lock, as it
+	/// belongs to a commit still in progress. The database is left locked
+	/// shared.
+	pub fn recover(&self, db: &mut dyn File) -> Result<bool> {
+		db.lock(LockType::Shared)?;
+		if db.check_reserved_lock()? != 0 {
+			return Ok(false);
+		}
+		if !self.restore(db)? {
+			return Ok(false);
+		}
+		self.end()?;
+		db.unlock(UnlockType::Shared)?;
+		Ok(true)
+	}
+
+	/// Write the pages of a valid journal back to the database file and
+	/// cut it to its earlier size, returning whether there was one
+	fn restore(&self, db: &mut dyn File) -> Result<bool> {
+		let mut file = match DefaultFile::open(&self.path, true, true, false) {
+			Ok(file) => file,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+			Err(err) => return Err(err.into()),
+		};
+		let mut header = [0u8; HEADER_FIELDS_SIZE];
+		if file.read_at(0, &mut header)? < HEADER_FIELDS_SIZE || header[..8] != JOURNAL_MAGIC {
+			return Ok(false);
+		}
+		let field = |index: usize| u32::from_be_bytes(header[8 + index * 4..12 + index * 4].try_into().unwrap());
+		let (records, nonce, page_count, sector_size, page_size) = (field(0), field(1), field(2), field(3), field(4));
+		if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+			return Ok(false);
+		}
+
+		db.lock(LockType::Exclusive)?;
+		let page_size = page_size as usize;
+		let mut offset = sector_size as u64;
+		let mut record = vec![0u8; page_size + 8];
+		for _ in 0..records {
+			if file.read_at(offset, &mut record)? < record.len() {
+				break;
+			}
+			let page_number = u32::from_be_bytes(record[..4].try_into().unwrap());
+			let contents = &record[4..4 + page_size];
+			let sum = u32::from_be_bytes(record[4 + page_size..].try_into().unwrap());
+			if page_number == 0 || sum != checksum(nonce, contents) {
+				break;
+			}
+			if page_number <= page_count {
+				db.write(contents, (page_number as u64 - 1) * page_size as u64)?;
+			}
+			offset += record.len() as u64;
+		}
+		db.truncate(page_count as u64 * page_size as u64)?;
+		db.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+		Ok(true)
+	}
+
+	/// Delete the journal file if there is one
+	fn remove(&self) -> Result<()> {
+		match std::fs::remove_file(&self.path) {
+			Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// SQLite's journal record checksum: the nonce plus every 200th byte of the
+/// page, counting back from the end
+fn checksum(nonce: u32, contents: &[u8]) -> u32 {
+	let mut sum = nonce;
+	let mut i = contents.len() as isize - 200;
+	while i > 0 {
+		sum = sum.wrapping_add(contents[i as usize] as u32);
+		i -= 200;
+	}
+	sum
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn fresh_path(name: &str) -> String {
+		let path = format!("/tmp/epiloglite_test_journal_{}.db", name);
+		let _ = std::fs::remove_file(&path);
+		let _ = std::fs::remove_file(format!("{}-journal", path));
+		path
+	}
+
+	#[test]
+	fn test_recover_restores_pages() {
+		let path = fresh_path("recover");
+		let mut db = DefaultFile::open(&path, true, true, true).unwrap();
+		db.write(&vec![1; 1024], 0).unwrap();
+		db.write(&vec![2; 1024], 1024).unwrap();
+
+		// The commit overwrote page 2 and added page 3 before the crash
+		let mut journal = Journal::new(&path);
+		journal.write(1024, 2, &[(2, vec![2; 1024])]).unwrap();
+		db.write(&vec![9; 1024], 1024).unwrap();
+		db.write(&vec![9; 1024], 2048).unwrap();
+
+		assert!(journal.recover(&mut db).unwrap());
+		assert_eq!(db.read(0).unwrap(), [vec![1; 1024], vec![2; 1024]].concat());
+		assert!(!std::path::Path::new(journal.path()).exists());
+		assert!(!journal.recover(&mut db).unwrap());
+	}
+
+	#[test]
+	fn test_later_writes_add_records() {
+		let path = fresh_path("append");
+		let mut db = DefaultFile::open(&path, true, true, true).unwrap();
+		db.write(&[vec![1; 1024], vec![2; 1024]].concat(), 0).unwrap();
+
+		// A transaction writes page 1, then pages 1 and 2, then rolls back
+		let mut journal = Journal::new(&path);
+		journal.write(1024, 2, &[(1, vec![1; 1024])]).unwrap();
+		db.write(&vec![8; 1024], 0).unwrap();
+		assert!(journal.contains(1));
+		journal.write(1024, 3, &[(1, vec![8; 1024]), (2, vec![2; 1024])]).unwrap();
+		db.write(&vec![9; 3072], 0).unwrap();
+		assert_eq!(journal.page_count(), Some(2));
+
+		db.lock(LockType::Shared).unwrap();
+		db.lock(LockType::Exclusive).unwrap();
+		journal.rollback(&mut db).unwrap();
+		assert_eq!(db.read(0).unwrap(), [vec![1; 1024], vec![2; 1024]].concat());
+		assert_eq!(journal.page_count(), None);
+		assert!(!std::path::Path::new(journal.path()).exists());
+	}
+
+	#[test]
+	fn test_torn_record_is_ignored() {
+		let path = fresh_path("torn");
+		let mut db = DefaultFile::open(&path, true, true, true).unwrap();
+		db.write(&vec![5; 2048], 0).unwrap();
+
+		let mut journal = Journal::new(&path);
+		journal.write(1024, 2, &[(1, vec![1; 1024]), (2, vec![2; 1024])]).unwrap();
+		// Damage a byte of the second record that the checksum covers
+		let mut file = DefaultFile::open(journal.path(), true, true, false).unwrap();
+		file.write(&[7], (SECTOR_SIZE + 1032 + 4 + 824) as u64).unwrap();
+
+		assert!(journal.recover(&mut db).unwrap());
+		assert_eq!(db.read(0).unwrap(), [vec![1; 1024], vec![5; 1024]].concat());
+	}
+
+	#[test]
+	fn test_finished_journal_is_not_hot() {
+		for mode in [JournalMode::Delete, JournalMode::Truncate, JournalMode::Persist] {
+			let path = fresh_path(mode.name());
+			let mut db = DefaultFile::open(&path, true, true, true).unwrap();
+			db.write(&vec![3; 1024], 0).unwrap();
+
+			let mut journal = Journal::new(&path);
+			journal.set_mode(mode).unwrap();
+			journal.write(1024, 1, &[(1, vec![1; 1024])]).unwrap();
+			journal.finish().unwrap();
+			let kept = std::fs::metadata(journal.path()).map(|meta| meta.len()).ok();
+			match mode {
+				JournalMode::Delete => assert_eq!(kept, None),
+				JournalMode::Truncate => assert_eq!(kept, Some(0)),
+				_ => assert!(kept.unwrap() > 1024),
+			}
+			assert!(!journal.recover(&mut db).unwrap());
+			assert_eq!(db.read(0).unwrap(), vec![3; 1024]);
+		}
+	}
+}
@@ -1,10 +1,24 @@
 /// Page cache - responsible for reading, writing, and caching database pages
 
-use crate::eplite::constants::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, MIN_PAGE_SIZE};
+use crate::eplite::constants::{
+	JournalMode, DEFAULT_CACHE_SIZE, DEFAULT_MMAP_SIZE, DEFAULT_PAGE_SIZE, DEFAULT_WAL_AUTOCHECKPOINT, MAX_MMAP_SIZE,
+	MAX_PAGE_SIZE, MIN_PAGE_SIZE, PENDING_BYTE,
+};
 use crate::eplite::error::{Error, Result};
+use crate::eplite::persistence::cache::{CacheStats, PageCache};
+use crate::eplite::persistence::header::PayloadFractions;
+#[cfg(feature = "mmap")]
+use crate::eplite::os::mmap::MemoryMap;
+#[cfg(feature = "std")]
+use crate::eplite::persistence::header::{DatabaseHeader, DATABASE_HEADER_SIZE};
 
 #[cfg(feature = "std")]
-use crate::eplite::traits::file::File;
+use crate::eplite::persistence::journal::Journal;
+use crate::eplite::persistence::wal::{CheckpointMode, CheckpointResult};
+#[cfg(feature = "std")]
+use crate::eplite::persistence::wal::WalFile;
+#[cfg(feature = "std")]
+use crate::eplite::traits::file::{File, LockType, UnlockType};
 #[cfg(feature = "std")]
 use std::collections::HashMap;
 
@@ -17,6 +31,26 @@ use alloc::{
 	vec::Vec,
 };
 
+/// Offset in the database header of the first freelist trunk page number
+const FREELIST_TRUNK_OFFSET: usize = 32;
+
+/// Offset in the database header of the number of free pages
+const FREELIST_COUNT_OFFSET: usize = 36;
+
+/// Offset in the database header of the change counter and the fields
+/// after it, which every commit changes
+#[cfg(feature = "std")]
+const FILE_VERSION_OFFSET: u64 = 24;
+
+/// Where the number of a page in use is recorded, so the page can be moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageRef {
+	/// A b-tree root page, whose number is kept by the schema
+	Root,
+	/// A big-endian page number at a byte offset within another page
+	Pointer(u32, usize),
+}
+
 /// A single page in the database
 #[derive(Debug, Clone)]
 pub struct Page {
@@ -66,14 +100,63 @@ impl Page {
 	}
 }
 
+/// State to return to when a transaction or savepoint rolls back
+#[derive(Debug)]
+struct Savepoint {
+	/// Page count when the savepoint was taken
+	page_count: u32,
+	/// Contents of each existing page before it first changed after the
+	/// savepoint was taken; the transaction itself keeps none when there is
+	/// a file, which holds them or else the journal does
+	originals: HashMap<u32, Page>,
+}
+
 /// Page cache manager
+///
+/// Pages are numbered from 1, as in SQLite. Page 1 starts at file offset 0
+/// and carries the 100-byte database header ahead of its content.
 #[derive(Debug)]
 pub struct Pager {
 	page_size: u32,
-	cache: HashMap<u32, Page>,
-	max_cache_size: usize,
+	cache: PageCache,
+	/// The cache size as set: pages, or KiB when negative
+	cache_size: i64,
+	/// Number of pages in the database (including pages not yet flushed)
+	page_count: u32,
+	/// Bytes reserved at the end of each page, unavailable to b-trees
+	reserved_space: u8,
+	/// How much of a record b-trees keep on the page, from the header
+	payload_fractions: PayloadFractions,
+	/// The open transaction followed by any savepoints inside it; changed
+	/// pages stay in the cache until the transaction commits, unless the
+	/// cache fills and they spill to the file
+	savepoints: Vec<Savepoint>,
+	/// Whether the open transaction has written pages to the file
+	spilled: bool,
 	#[cfg(feature = "std")]
 	file: Option<Box<dyn File>>,
+	/// Header bytes at `FILE_VERSION_OFFSET` as this connection last saw
+	/// them; they differ once another connection commits
+	#[cfg(feature = "std")]
+	file_version: [u8; 16],
+	/// Rollback journal written ahead of each flush to the file
+	#[cfg(feature = "std")]
+	journal: Option<Journal>,
+	/// Write-ahead log that commits go to in WAL mode
+	#[cfg(feature = "std")]
+	wal: Option<WalFile>,
+	/// Whether WAL mode is wanted; the log opens or closes when the open
+	/// transaction commits
+	#[cfg(feature = "std")]
+	wal_requested: bool,
+	/// Log frames after which a commit checkpoints, or 0 for never
+	wal_autocheckpoint: u32,
+	/// Bytes of the file to memory-map, or 0 to read pages with system calls
+	mmap_size: u64,
+	/// Map of the start of the file that pages are copied from; pages past
+	/// its end are read as usual
+	#[cfg(feature = "mmap")]
+	mmap: Option<MemoryMap>,
 }
 
 impl Pager {
@@ -94,32 +177,370 @@ impl Pager {
 
 		Ok(Pager {
 			page_size,
-			cache: HashMap::new(),
-			max_cache_size: 100,
+			cache: PageCache::new(cache_pages(DEFAULT_CACHE_SIZE, page_size)),
+			cache_size: DEFAULT_CACHE_SIZE,
+			page_count: 0,
+			reserved_space: 0,
+			payload_fractions: PayloadFractions::default(),
+			savepoints: Vec::new(),
+			spilled: false,
 			#[cfg(feature = "std")]
 			file: None,
+			#[cfg(feature = "std")]
+			file_version: [0; 16],
+			#[cfg(feature = "std")]
+			journal: None,
+			#[cfg(feature = "std")]
+			wal: None,
+			#[cfg(feature = "std")]
+			wal_requested: false,
+			wal_autocheckpoint: DEFAULT_WAL_AUTOCHECKPOINT,
+			mmap_size: DEFAULT_MMAP_SIZE,
+			#[cfg(feature = "mmap")]
+			mmap: None,
 		})
 	}
 
 	/// Create a pager with a file backend
 	#[cfg(feature = "std")]
-	pub fn with_file(page_size: u32, file: Box<dyn File>) -> Result<Self> {
+	pub fn with_file(page_size: u32, mut file: Box<dyn File>) -> Result<Self> {
 		let mut pager = Self::new(page_size)?;
+		let file_size = file.file_size()?;
+		pager.page_count = file_size.div_ceil(page_size as u64) as u32;
+		file.read_at(FILE_VERSION_OFFSET, &mut pager.file_version)?;
 		pager.file = Some(file);
 		Ok(pager)
 	}
 
+	/// Open a pager on a file, taking the page size from the database header
+	/// if the file already contains one
+	#[cfg(feature = "std")]
+	pub fn open(mut file: Box<dyn File>) -> Result<Self> {
+		let mut header = [0u8; DATABASE_HEADER_SIZE];
+		let page_size = if file.read_at(0, &mut header)? == DATABASE_HEADER_SIZE {
+			match DatabaseHeader::from_bytes(&header) {
+				Ok(header) => header.page_size,
+				Err(_) => DEFAULT_PAGE_SIZE,
+			}
+		} else {
+			DEFAULT_PAGE_SIZE
+		};
+		Self::with_file(page_size, file)
+	}
+
+	/// Protect flushes to the file with a rollback journal
+	#[cfg(feature = "std")]
+	pub fn set_journal(&mut self, journal: Journal) {
+		self.journal = Some(journal);
+	}
+
+	/// The journal mode; without a journal file it is always MEMORY
+	pub fn journal_mode(&self) -> JournalMode {
+		#[cfg(feature = "std")]
+		if let Some(journal) = &self.journal {
+			return match self.wal_requested {
+				true => JournalMode::Wal,
+				false => journal.mode(),
+			};
+		}
+		JournalMode::Memory
+	}
+
+	/// Change the journal mode, returning the mode now in effect
+	///
+	/// Inside a transaction, entering or leaving WAL mode happens when the
+	/// transaction commits, so the commit itself goes to the old journal.
+	pub fn set_journal_mode(&mut self, mode: JournalMode) -> Result<JournalMode> {
+		#[cfg(feature = "std")]
+		if let Some(journal) = &mut self.journal {
+			// Leaving WAL mode needs the log to have no other connections
+			if let (true, Some(wal)) = (mode != JournalMode::Wal, &mut self.wal) {
+				if !wal.claim()? {
+					return Err(Error::Busy);
+				}
+			}
+			// The rollback journal keeps its mode for the commit entering WAL
+			if mode != JournalMode::Wal {
+				journal.set_mode(mode)?;
+			}
+			self.wal_requested = mode == JournalMode::Wal;
+			if self.savepoints.is_empty() {
+				self.switch_wal()?;
+			}
+		}
+		Ok(self.journal_mode())
+	}
+
+	/// The cache size as last set: a number of pages, or of KiB when
+	/// negative
+	pub fn cache_size(&self) -> i64 {
+		self.cache_size
+	}
+
+	/// Set how many pages the cache holds, or with a negative size how
+	/// many KiB of pages; clean pages over the new size are evicted
+	pub fn set_cache_size(&mut self, size: i64) {
+		self.cache_size = size;
+		self.cache.set_capacity(cache_pages(size, self.page_size));
+		self.trim_cache();
+	}
+
+	/// Evict clean pages until the cache is back within its size
+	fn trim_cache(&mut self) {
+		while self.has_file() && self.cache.len() > self.cache.capacity() && self.cache.evict() {}
+	}
+
+	/// Hit, miss and eviction counts of the page cache
+	pub fn cache_stats(&self) -> CacheStats {
+		self.cache.stats()
+	}
+
+	/// Bytes of the file read through a memory map, as last set
+	pub fn mmap_size(&self) -> u64 {
+		self.mmap_size
+	}
+
+	/// Copy pages from a memory map of up to `size` bytes of the file
+	/// rather than reading them with system calls, or stop with 0; sizes
+	/// are capped at `MAX_MMAP_SIZE`
+	///
+	/// Files that cannot be mapped are read as usual. Without the `mmap`
+	/// feature nothing is mapped and the size stays 0, as in a SQLite built
+	/// with `SQLITE_MAX_MMAP_SIZE=0`.
+	pub fn set_mmap_size(&mut self, size: u64) -> Result<u64> {
+		self.mmap_size = if cfg!(feature = "mmap") { size.min(MAX_MMAP_SIZE) } else { 0 };
+		#[cfg(feature = "std")]
+		self.remap()?;
+		Ok(self.mmap_size)
+	}
+
+	/// Map as much of the file as `mmap_size` allows, in whole pages, if
+	/// that differs from the current map
+	#[cfg(feature = "mmap")]
+	fn remap(&mut self) -> Result<()> {
+		let Some(file) = &mut self.file else {
+			return Ok(());
+		};
+		let page_size = self.page_size as u64;
+		let len = (file.file_size()?.min(self.mmap_size) / page_size * page_size) as usize;
+		if self.mmap.as_ref().map_or(0, |map| map.len()) == len {
+			return Ok(());
+		}
+		self.mmap = match len {
+			0 => None,
+			_ => file.map(len)?,
+		};
+		Ok(())
+	}
+
+	/// Without the `mmap` feature there is never a map to remake
+	#[cfg(all(feature = "std", not(feature = "mmap")))]
+	fn remap(&mut self) -> Result<()> {
+		Ok(())
+	}
+
+	/// Log frames after which a commit in WAL mode checkpoints
+	pub fn wal_autocheckpoint(&self) -> u32 {
+		self.wal_autocheckpoint
+	}
+
+	/// Set the frames after which a commit checkpoints; 0 turns automatic
+	/// checkpoints off
+	pub fn set_wal_autocheckpoint(&mut self, frames: u32) {
+		self.wal_autocheckpoint = frames;
+	}
+
+	/// Open or close the write-ahead log to match the journal mode
+	#[cfg(feature = "std")]
+	fn switch_wal(&mut self) -> Result<()> {
+		match (&self.journal, self.wal_requested, self.wal.is_some()) {
+			(Some(journal), true, false) => {
+				// As in SQLite, the database stays locked shared in WAL mode,
+				// which stops a SQLite connection that closes from deleting
+				// the log
+				if let Some(file) = &mut self.file {
+					file.lock(LockType::Shared)?;
+				}
+				self.wal = Some(WalFile::open(&journal.wal_path(), self.page_size)?);
+			}
+			(_, false, true) => self.close()?,
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// Start reading the latest snapshot of the database, returning whether
+	/// another connection changed it since the last one, in which case the
+	/// cache is dropped
+	///
+	/// In WAL mode the snapshot lasts until `end_read`. Otherwise the file
+	/// is locked shared until then, after rolling back any hot journal a
+	/// crashed writer left, and fails with `Error::Busy` while another
+	/// connection writes it.
+	pub fn begin_read(&mut self) -> Result<bool> {
+		#[cfg(feature = "std")]
+		if let Some(wal) = &mut self.wal {
+			if wal.begin_read()? {
+				self.reload()?;
+				return Ok(true);
+			}
+		} else if let Some(file) = &mut self.file {
+			let recovered = match &self.journal {
+				Some(journal) => journal.recover(&mut **file)?,
+				None => {
+					file.lock(LockType::Shared)?;
+					false
+				}
+			};
+			let mut version = [0u8; 16];
+			file.read_at(FILE_VERSION_OFFSET, &mut version)?;
+			if recovered || version != self.file_version {
+				self.file_version = version;
+				self.reload()?;
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	}
+
+	/// Release the snapshot taken by `begin_read`, or outside WAL mode the
+	/// lock on the file once no transaction is open
+	pub fn end_read(&mut self) -> Result<()> {
+		#[cfg(feature = "std")]
+		if let Some(wal) = &mut self.wal {
+			wal.end_read()?;
+		} else if let (Some(file), true) = (&mut self.file, self.savepoints.is_empty()) {
+			file.unlock(UnlockType::None)?;
+		}
+		Ok(())
+	}
+
+	/// Take the write lock before the first change of a transaction,
+	/// failing with `Error::Busy` if another connection holds it; in WAL
+	/// mode also if another connection committed since the snapshot was
+	/// taken
+	fn begin_write(&mut self) -> Result<()> {
+		#[cfg(feature = "std")]
+		if self.wal.as_ref().is_some_and(|wal| !wal.is_writing()) {
+			self.begin_read()?;
+			if let Some(wal) = &mut self.wal {
+				wal.begin_write()?;
+			}
+		} else if let (None, Some(file)) = (&self.wal, &mut self.file) {
+			file.lock(LockType::Shared)?;
+			file.lock(LockType::Reserved)?;
+		}
+		Ok(())
+	}
+
+	/// Take the write lock at the start of a transaction rather than at its
+	/// first change; `exclusive` also keeps other connections from reading
+	/// the file until it ends, except in WAL mode
+	pub fn lock_for_write(&mut self, exclusive: bool) -> Result<()> {
+		self.begin_write()?;
+		#[cfg(feature = "std")]
+		if let (true, None, Some(file)) = (exclusive, &self.wal, &mut self.file) {
+			file.lock(LockType::Exclusive)?;
+		}
+		#[cfg(not(feature = "std"))]
+		let _ = exclusive;
+		Ok(())
+	}
+
+	/// Release the write lock once the transaction ends
+	fn end_write(&mut self) -> Result<()> {
+		// Pages a transaction kept past the cache size are clean again
+		self.trim_cache();
+		#[cfg(feature = "std")]
+		if let Some(wal) = &mut self.wal {
+			wal.end_write()?;
+		} else if let Some(file) = &mut self.file {
+			file.unlock(UnlockType::Shared)?;
+		}
+		Ok(())
+	}
+
+	/// Drop the cache and take the database size from a new snapshot
+	#[cfg(feature = "std")]
+	fn reload(&mut self) -> Result<()> {
+		self.cache.clear();
+		let page_count = match (self.wal.as_ref().and_then(|wal| wal.page_count()), &mut self.file) {
+			(Some(page_count), _) => page_count,
+			(None, Some(file)) => file.file_size()?.div_ceil(self.page_size as u64) as u32,
+			(None, None) => self.page_count,
+		};
+		self.page_count = page_count;
+		self.remap()
+	}
+
+	/// Copy the write-ahead log into the database file, returning the
+	/// frame counts, or None when the database is not in WAL mode
+	pub fn checkpoint(&mut self, mode: CheckpointMode) -> Result<Option<CheckpointResult>> {
+		#[cfg(feature = "std")]
+		if let (Some(wal), Some(file)) = (&mut self.wal, &mut self.file) {
+			let result = wal.checkpoint(&mut **file, mode)?;
+			self.remap()?;
+			return Ok(Some(result));
+		}
+		#[cfg(not(feature = "std"))]
+		let _ = mode;
+		Ok(None)
+	}
+
+	/// Close the write-ahead log, which is checkpointed and deleted if no
+	/// other connection uses it
+	#[cfg(feature = "std")]
+	pub fn close(&mut self) -> Result<()> {
+		if let (Some(wal), Some(file)) = (self.wal.take(), &mut self.file) {
+			wal.close(&mut **file)?;
+			file.unlock(UnlockType::None)?;
+			self.remap()?;
+		}
+		Ok(())
+	}
+
 	pub fn page_size(&self) -> u32 {
 		self.page_size
 	}
 
+	/// Set the number of bytes reserved at the end of each page
+	pub fn set_reserved_space(&mut self, reserved_space: u8) {
+		self.reserved_space = reserved_space;
+	}
+
+	/// Bytes of each page available for content
+	pub fn usable_size(&self) -> usize {
+		self.page_size as usize - self.reserved_space as usize
+	}
+
+	/// Fractions that decide how much of a record stays on a b-tree page
+	pub fn payload_fractions(&self) -> PayloadFractions {
+		self.payload_fractions
+	}
+
+	/// Set the payload fractions, as recorded in the database header
+	pub fn set_payload_fractions(&mut self, fractions: PayloadFractions) {
+		self.payload_fractions = fractions;
+	}
+
+	/// Number of pages in the database
+	pub fn page_count(&self) -> u32 {
+		self.page_count
+	}
+
+	/// Byte offset of a page within the database file
+	#[cfg(feature = "std")]
+	fn page_offset(&self, page_number: u32) -> u64 {
+		(page_number as u64 - 1) * (self.page_size as u64)
+	}
+
 	/// Get a page from cache or load it
 	pub fn get_page(&mut self, page_number: u32) -> Result<&Page> {
-		if !self.cache.contains_key(&page_number) {
+		if !self.cache.lookup(page_number) {
 			let page = self.load_page(page_number)?;
 			
 			// Evict pages if cache is too large
-			if self.cache.len() >= self.max_cache_size {
+			if self.cache.is_full() {
 				self.evict_page()?;
 			}
 			
@@ -130,11 +551,17 @@ impl Pager {
 
 	/// Get a mutable page from cache or load it
 	pub fn get_page_mut(&mut self, page_number: u32) -> Result<&mut Page> {
-		if !self.cache.contains_key(&page_number) {
+		self.begin_write()?;
+		// Writing to a page past the end extends the database
+		if page_number > self.page_count {
+			self.page_count = page_number;
+		}
+		self.save_original(page_number)?;
+		if !self.cache.lookup(page_number) {
 			let page = self.load_page(page_number)?;
 			
 			// Evict pages if cache is too large
-			if self.cache.len() >= self.max_cache_size {
+			if self.cache.is_full() {
 				self.evict_page()?;
 			}
 			
@@ -144,88 +571,347 @@ impl Pager {
 	}
 
 	fn load_page(&mut self, page_number: u32) -> Result<Page> {
+		if page_number == 0 {
+			return Err(Error::Internal("Page numbers start at 1".to_string()));
+		}
+
+		#[cfg_attr(not(feature = "std"), allow(unused_mut))]
 		let mut page = Page::new(page_number, self.page_size as usize);
 		
 		#[cfg(feature = "std")]
-		if let Some(file) = &mut self.file {
-			// Calculate offset in file
-			let offset = (page_number as u64) * (self.page_size as u64);
-			
-			// Try to read the page from disk
-			match file.read(offset) {
-				Ok(data) if !data.is_empty() => {
-					let len = data.len().min(page.data.len());
-					page.data[..len].copy_from_slice(&data[..len]);
-				}
-				Ok(_) => {
-					// Empty file or beyond EOF, page stays zeroed
-				}
-				Err(e) => {
-					// If read fails, treat as empty page
-					eprintln!("Warning: Failed to read page {}: {}", page_number, e);
+		if page_number <= self.page_count {
+			if let Some(wal) = &mut self.wal {
+				if wal.read_page(page_number, &mut page.data)? {
+					return Ok(page);
 				}
 			}
+			let offset = self.page_offset(page_number);
+			// In mmap mode pages are copied from the map
+			#[cfg(feature = "mmap")]
+			if let Some(map) = self.mmap.as_ref().filter(|map| offset as usize + page.data.len() <= map.len()) {
+				map.read_at(offset as usize, &mut page.data)?;
+				return Ok(page);
+			}
+			if let Some(file) = &mut self.file {
+				// A short read means the page lies beyond EOF; the rest stays zeroed
+				file.read_at(offset, &mut page.data)?;
+			}
 		}
 		
 		Ok(page)
 	}
 
-	/// Evict a clean page from cache (LRU-like policy)
+	/// Whether the pager is backed by a file; without one the cache holds
+	/// the only copy of each page
+	pub fn has_file(&self) -> bool {
+		#[cfg(feature = "std")]
+		{
+			self.file.is_some()
+		}
+		#[cfg(not(feature = "std"))]
+		{
+			false
+		}
+	}
+
+	/// Evict the least recently used clean page from the cache
 	fn evict_page(&mut self) -> Result<()> {
-		// Find first non-dirty page to evict
-		if let Some(page_num) = self.cache.iter()
-			.find(|(_, p)| !p.dirty)
-			.map(|(k, _)| *k) {
-			self.cache.remove(&page_num);
+		// Without a file the cache holds the only copy of each page
+		if !self.has_file() {
+			return Ok(());
+		}
+		if self.cache.evict() {
+			return Ok(());
+		}
+
+		// Dirty pages are written back to make room. Inside a transaction
+		// that needs a rollback journal on disk to undo them; without one
+		// the cache grows instead.
+		if self.savepoints.is_empty() {
+			self.flush()?;
 		} else {
-			// If all pages are dirty, flush one
-			if let Some((&page_num, _)) = self.cache.iter().next() {
-				// Write the page first
-				if let Some(page) = self.cache.get(&page_num) {
-					#[cfg(feature = "std")]
-					if let Some(file) = &mut self.file {
-						let offset = (page.page_number as u64) * (self.page_size as u64);
-						file.write(&page.data, offset)?;
-					}
-				}
-				// Then mark it clean
-				if let Some(page) = self.cache.get_mut(&page_num) {
-					page.dirty = false;
-				}
+			#[cfg(feature = "std")]
+			if self.can_spill() {
+				self.spill()?;
 			}
 		}
+		self.cache.evict();
 		Ok(())
 	}
 
-	/// Flush dirty pages to disk
-	pub fn flush(&mut self) -> Result<()> {
-		// Collect dirty pages to write
-		let dirty_pages: Vec<(u32, Vec<u8>)> = self.cache.iter()
-			.filter(|(_, p)| p.dirty)
-			.map(|(num, p)| (*num, p.data.clone()))
-			.collect();
-		
-		// Write them
-		for (page_num, data) in dirty_pages {
-			#[cfg(feature = "std")]
+	/// Whether the open transaction can write pages to the file before it
+	/// commits: only with a rollback journal file, as the write-ahead log
+	/// takes whole commits
+	#[cfg(feature = "std")]
+	fn can_spill(&self) -> bool {
+		self.file.is_some() && self.wal.is_none() && self.journal.as_ref().is_some_and(|journal| journal.mode().uses_file())
+	}
+
+	/// Write the open transaction's dirty pages to the file, journaling the
+	/// contents they replace first
+	#[cfg(feature = "std")]
+	fn spill(&mut self) -> Result<()> {
+		self.lock_exclusive()?;
+		self.write_journal()?;
+		self.write_dirty_pages()?;
+		self.spilled = true;
+		Ok(())
+	}
+
+	/// Lock the file so it can be written, waiting for no reader
+	#[cfg(feature = "std")]
+	fn lock_exclusive(&mut self) -> Result<()> {
+		if self.file.is_some() {
+			self.begin_write()?;
 			if let Some(file) = &mut self.file {
-				let offset = (page_num as u64) * (self.page_size as u64);
-				file.write(&data, offset)?;
+				file.lock(LockType::Exclusive)?;
 			}
-			// Mark as clean
-			if let Some(page) = self.cache.get_mut(&page_num) {
-				page.dirty = false;
+		}
+		Ok(())
+	}
+
+	/// Write every dirty page to the file and mark the cache clean
+	#[cfg(feature = "std")]
+	fn write_dirty_pages(&mut self) -> Result<()> {
+		for page_num in self.cache.dirty_pages() {
+			let offset = self.page_offset(page_num);
+			if let (Some(file), Some(page)) = (&mut self.file, self.cache.get(&page_num)) {
+				file.write(&page.data, offset)?;
 			}
 		}
+		self.cache.mark_clean();
+		Ok(())
+	}
+
+	/// Flush dirty pages to disk, first saving the pages they replace in
+	/// the journal
+	pub fn flush(&mut self) -> Result<()> {
+		#[cfg(feature = "std")]
+		if self.wal.is_some() {
+			return self.flush_to_wal();
+		}
+		// Readers have to finish before the file changes under them
+		#[cfg(feature = "std")]
+		self.lock_exclusive()?;
+		#[cfg(feature = "std")]
+		let journaled = self.write_journal()?;
+
+		// Write the dirty pages
+		#[cfg(feature = "std")]
+		self.write_dirty_pages()?;
+		#[cfg(not(feature = "std"))]
+		self.cache.mark_clean();
 		
-		// Sync file to disk
+		// Sync file to disk, dropping any pages cut off by truncate()
 		#[cfg(feature = "std")]
 		if let Some(file) = &mut self.file {
+			let size = self.page_count as u64 * self.page_size as u64;
+			if file.file_size()? > size {
+				file.truncate(size)?;
+			}
 			use crate::eplite::traits::file::SynchronizationType;
 			use flagset::FlagSet;
 			file.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+			file.read_at(FILE_VERSION_OFFSET, &mut self.file_version)?;
+			self.remap()?;
+		}
+
+		#[cfg(feature = "std")]
+		if let (true, Some(journal)) = (journaled, &mut self.journal) {
+			journal.finish()?;
+		}
+		self.spilled = false;
+		Ok(())
+	}
+
+	/// Commit the dirty pages to the write-ahead log, checkpointing once the
+	/// log reaches the auto-checkpoint size
+	#[cfg(feature = "std")]
+	fn flush_to_wal(&mut self) -> Result<()> {
+		let pages: Vec<(u32, Vec<u8>)> = self.cache.dirty_pages()
+			.into_iter()
+			.filter_map(|number| self.cache.get(&number).map(|page| (number, page.data.to_vec())))
+			.collect();
+		if pages.is_empty() {
+			return Ok(());
+		}
+		let Some(wal) = &mut self.wal else {
+			return Ok(());
+		};
+		wal.commit(pages, self.page_count)?;
+		let frames = wal.frame_count();
+		self.cache.mark_clean();
+		if self.savepoints.is_empty() {
+			self.end_write()?;
+		}
+		if self.wal_autocheckpoint > 0 && frames >= self.wal_autocheckpoint as usize {
+			self.checkpoint(CheckpointMode::Passive)?;
+		}
+		Ok(())
+	}
+
+	/// Write the file's current contents of every page the next flush
+	/// overwrites or truncates to the journal, returning whether the
+	/// transaction has a journal
+	///
+	/// Pages the transaction already wrote to the file were journaled then,
+	/// and pages past the size it started with need no record.
+	#[cfg(feature = "std")]
+	fn write_journal(&mut self) -> Result<bool> {
+		let (Some(journal), Some(file)) = (&mut self.journal, &mut self.file) else {
+			return Ok(false);
+		};
+		if !journal.mode().uses_file() {
+			return Ok(false);
+		}
+		let file_pages = file.file_size()?.div_ceil(self.page_size as u64) as u32;
+		let mut changed = self.cache.dirty_pages();
+		if changed.is_empty() && self.page_count >= file_pages {
+			return Ok(journal.page_count().is_some());
+		}
+		let original_pages = journal.page_count().unwrap_or(file_pages);
+		changed.extend(self.page_count + 1..=file_pages);
+		changed.retain(|&page_number| page_number <= original_pages && !journal.contains(page_number));
+		changed.sort_unstable();
+		changed.dedup();
+		if changed.is_empty() && journal.page_count().is_some() {
+			return Ok(true);
+		}
+
+		let mut originals = Vec::with_capacity(changed.len());
+		for page_number in changed {
+			let mut data = vec![0; self.page_size as usize];
+			file.read_at((page_number as u64 - 1) * self.page_size as u64, &mut data)?;
+			originals.push((page_number, data));
+		}
+		journal.write(self.page_size, file_pages, &originals)?;
+		Ok(true)
+	}
+
+	/// Start a transaction: until it commits, changed pages are held in the
+	/// cache, and reach the file early only when the cache fills and the
+	/// journal can undo them
+	pub fn begin_transaction(&mut self) -> Result<()> {
+		if !self.savepoints.is_empty() {
+			return Err(Error::InvalidOperation("Pager transaction already open".to_string()));
+		}
+		self.savepoint();
+		Ok(())
+	}
+
+	/// Take a savepoint inside the open transaction, returning its depth;
+	/// the transaction itself is depth 0
+	pub fn savepoint(&mut self) -> usize {
+		self.savepoints.push(Savepoint {
+			page_count: self.page_count,
+			originals: HashMap::new(),
+		});
+		self.savepoints.len() - 1
+	}
+
+	/// Write the transaction's changes to the file and end it
+	pub fn commit_transaction(&mut self) -> Result<()> {
+		if self.spilled || self.cache.values().any(|page| page.dirty) {
+			self.flush()?;
+		}
+		self.savepoints.clear();
+		self.end_write()?;
+		#[cfg(feature = "std")]
+		self.switch_wal()?;
+		Ok(())
+	}
+
+	/// End the transaction, restoring every page it changed
+	pub fn rollback_transaction(&mut self) -> Result<()> {
+		if !self.savepoints.is_empty() {
+			self.rollback_to(0)?;
+			self.savepoints.clear();
+		}
+		self.end_write()
+	}
+
+	/// Restore every page changed since the savepoint at `depth`, which
+	/// stays open; savepoints taken after it are discarded
+	pub fn rollback_to(&mut self, depth: usize) -> Result<()> {
+		if self.savepoints.is_empty() {
+			return Ok(());
+		}
+		let depth = depth.min(self.savepoints.len());
+		for savepoint in self.savepoints.drain(depth..).rev() {
+			self.cache.retain(|page_number| page_number <= savepoint.page_count);
+			// The file may hold a later version of a restored page
+			self.cache.extend(savepoint.originals.into_iter().map(|(page_number, mut page)| {
+				page.mark_dirty();
+				(page_number, page)
+			}));
+			self.page_count = savepoint.page_count;
+		}
+		if depth == 0 && self.has_file() {
+			self.discard_changes()?;
+		}
+		// Restored pages past the cache size go back to the file
+		#[cfg(feature = "std")]
+		if self.cache.len() > self.cache.capacity() && self.can_spill() {
+			self.spill()?;
+			self.trim_cache();
+		}
+		self.savepoint();
+		Ok(())
+	}
+
+	/// Drop the transaction's changes from the cache, and from the file
+	/// too if any reached it, leaving the file as the transaction found it
+	fn discard_changes(&mut self) -> Result<()> {
+		for page_number in self.cache.dirty_pages() {
+			self.cache.remove(&page_number);
+		}
+		self.cache.mark_clean();
+		#[cfg(feature = "std")]
+		if self.spilled {
+			if let (Some(journal), Some(file)) = (&mut self.journal, &mut self.file) {
+				journal.rollback(&mut **file)?;
+			}
+			self.spilled = false;
+			self.cache.clear();
+			self.remap()?;
+		}
+		Ok(())
+	}
+
+	/// Discard the savepoint at `depth` and those after it, keeping their
+	/// changes in the enclosing transaction
+	pub fn release(&mut self, depth: usize) {
+		// Each savepoint saves pages on its own, so the enclosing ones
+		// already hold what they need
+		self.savepoints.truncate(depth.max(1));
+	}
+
+	/// Whether a transaction is open
+	pub fn in_transaction(&self) -> bool {
+		!self.savepoints.is_empty()
+	}
+
+	/// Keep the contents of a page the open transaction may change, for
+	/// each savepoint that has not yet saved it
+	///
+	/// With a file the transaction itself needs no copies: rolling it back
+	/// rereads the file, after restoring any pages it wrote there from the
+	/// journal.
+	fn save_original(&mut self, page_number: u32) -> Result<()> {
+		let first = if self.has_file() { 1 } else { 0 };
+		let needed = |savepoint: &Savepoint| {
+			page_number <= savepoint.page_count && !savepoint.originals.contains_key(&page_number)
+		};
+		if !self.savepoints.iter().skip(first).any(needed) {
+			return Ok(());
+		}
+		let page = match self.cache.get(&page_number) {
+			Some(page) => page.clone(),
+			None => self.load_page(page_number)?,
+		};
+		for savepoint in self.savepoints.iter_mut().skip(first).filter(|savepoint| needed(savepoint)) {
+			savepoint.originals.insert(page_number, page.clone());
 		}
-		
 		Ok(())
 	}
 
@@ -236,23 +922,240 @@ impl Pager {
 		Ok(())
 	}
 
-	/// Allocate a new page
+	/// Allocate a page, reusing one from the freelist if possible and
+	/// otherwise growing the database
 	pub fn allocate_page(&mut self) -> Result<u32> {
-		// Find the first unused page number
-		let mut page_num = 1u32;
-		while self.cache.contains_key(&page_num) {
+		self.begin_write()?;
+		if let Some(page_num) = self.take_free_page()? {
+			return Ok(page_num);
+		}
+
+		let mut page_num = self
+			.page_count
+			.checked_add(1)
+			.ok_or_else(|| Error::Internal("Page number overflow".to_string()))?;
+		if page_num == self.lock_byte_page() {
 			page_num += 1;
-			if page_num == 0 {
-				return Err(Error::Internal("Page number overflow".to_string()));
-			}
 		}
-		
-		// Create the new page
-		let page = Page::new(page_num, self.page_size as usize);
+
+		if self.cache.is_full() {
+			self.evict_page()?;
+		}
+
+		// New pages are dirty so the file grows to cover them on flush
+		let mut page = Page::new(page_num, self.page_size as usize);
+		page.mark_dirty();
 		self.cache.insert(page_num, page);
+		self.page_count = page_num;
 		
 		Ok(page_num)
 	}
+
+	/// Shrink the database to `page_count` pages, discarding the pages
+	/// beyond it; the file itself is truncated by the next flush
+	pub fn truncate(&mut self, page_count: u32) -> Result<()> {
+		self.begin_write()?;
+		if page_count < self.page_count {
+			// Without a file the cache holds the only copy of these pages
+			let dropped: Vec<u32> = self.cache.keys().copied().filter(|&n| n > page_count).collect();
+			for page_number in dropped {
+				self.save_original(page_number)?;
+			}
+			self.cache.retain(|page_number| page_number <= page_count);
+			self.page_count = page_count;
+		}
+		Ok(())
+	}
+
+	/// Move pages in use into free pages nearer the start of the database
+	/// and truncate the free pages this leaves at the end
+	///
+	/// `refs` must name every page in use other than page 1. At most `limit`
+	/// pages are removed, or all free pages if None; free pages that remain
+	/// go back on the freelist. Returns the old and new numbers of each root
+	/// page that moved, for the caller to update the schema.
+	pub fn relocate(&mut self, refs: &HashMap<u32, PageRef>, limit: Option<u32>) -> Result<Vec<(u32, u32)>> {
+		let page_count = self.page_count;
+		if let Some(page) = refs.keys().find(|&&page| page <= 1 || page > page_count) {
+			return Err(Error::Corrupt(format!("Page {} is out of range", page)));
+		}
+		// Every page must be either in use or on the freelist
+		let lock_byte_page = self.lock_byte_page();
+		let free: Vec<u32> = (2..=page_count)
+			.filter(|page| !refs.contains_key(page) && *page != lock_byte_page)
+			.collect();
+		let freelist_count = self.freelist_count()?;
+		if free.len() != freelist_count as usize {
+			return Err(Error::Corrupt(format!(
+				"Found {} unused pages but the freelist holds {}",
+				free.len(),
+				freelist_count
+			)));
+		}
+
+		let removed = limit.map_or(free.len(), |limit| free.len().min(limit as usize));
+		if removed == 0 {
+			return Ok(Vec::new());
+		}
+		let new_count = page_count - removed as u32;
+
+		let mut targets = free.iter().copied().filter(|&page| page <= new_count);
+		let mut moved = HashMap::new();
+		let mut roots = Vec::new();
+		for page_number in new_count + 1..=page_count {
+			let Some(&page_ref) = refs.get(&page_number) else {
+				continue;
+			};
+			let target = targets
+				.next()
+				.ok_or_else(|| Error::Internal("No free page to move into".to_string()))?;
+			moved.insert(page_number, target);
+
+			// A pointer's page may itself have moved already; if it moves
+			// later, it carries the new pointer along
+			match page_ref {
+				PageRef::Root => roots.push((page_number, target)),
+				PageRef::Pointer(owner, offset) => {
+					let owner = moved.get(&owner).copied().unwrap_or(owner);
+					self.get_page_mut(owner)?.write(offset, &target.to_be_bytes())?;
+				}
+			}
+			let data = self.get_page(page_number)?.data.clone();
+			self.get_page_mut(target)?.write(0, &data)?;
+		}
+
+		// Rebuild the freelist from the free pages that are left
+		let remaining: Vec<u32> = targets.collect();
+		self.set_freelist_head(0, 0)?;
+		self.truncate(new_count)?;
+		for page_number in remaining {
+			self.free_page(page_number)?;
+		}
+		Ok(roots)
+	}
+
+	/// The page holding the bytes connections lock, which never stores data
+	/// so that files over a gigabyte stay readable by SQLite
+	fn lock_byte_page(&self) -> u32 {
+		(PENDING_BYTE / self.page_size as u64) as u32 + 1
+	}
+
+	/// Number of pages on the freelist
+	pub fn freelist_count(&mut self) -> Result<u32> {
+		Ok(self.freelist_head()?.1)
+	}
+
+	/// Return a page to the freelist for reuse by later allocations
+	///
+	/// The freelist has the SQLite 3 layout: the header names the first
+	/// trunk page, and each trunk page holds the next trunk page number, a
+	/// count of leaf pages and the leaf page numbers.
+	pub fn free_page(&mut self, page_number: u32) -> Result<()> {
+		if page_number <= 1 || page_number > self.page_count {
+			return Err(Error::Internal(format!("Cannot free page {}", page_number)));
+		}
+		let (trunk, count) = self.freelist_head()?;
+		let max_leaves = self.max_trunk_leaves();
+
+		if trunk != 0 {
+			let page = self.get_page_mut(trunk)?;
+			let leaves = read_u32(&page.data, 4);
+			if leaves < max_leaves {
+				page.write(8 + 4 * leaves as usize, &page_number.to_be_bytes())?;
+				page.write(4, &(leaves + 1).to_be_bytes())?;
+				// The contents of a free leaf page no longer matter
+				self.save_original(page_number)?;
+				self.cache.remove(&page_number);
+				return self.set_freelist_head(trunk, count + 1);
+			}
+		}
+
+		// The trunk is full, so the freed page becomes the new first trunk
+		let page = self.get_page_mut(page_number)?;
+		page.data.fill(0);
+		page.write(0, &trunk.to_be_bytes())?;
+		self.set_freelist_head(page_number, count + 1)
+	}
+
+	/// Take a page off the freelist, returning it zeroed
+	fn take_free_page(&mut self) -> Result<Option<u32>> {
+		let (trunk, count) = self.freelist_head()?;
+		if trunk == 0 {
+			return Ok(None);
+		}
+		let corrupt = |page: u32| Error::Corrupt(format!("Invalid freelist page {}", page));
+		if trunk > self.page_count || count == 0 {
+			return Err(corrupt(trunk));
+		}
+
+		let max_leaves = self.max_trunk_leaves();
+		let page_count = self.page_count;
+		let page = self.get_page_mut(trunk)?;
+		let next = read_u32(&page.data, 0);
+		let leaves = read_u32(&page.data, 4);
+		let page_num = if leaves > 0 {
+			if leaves > max_leaves {
+				return Err(corrupt(trunk));
+			}
+			let leaf = read_u32(&page.data, 8 + 4 * (leaves as usize - 1));
+			if leaf <= 1 || leaf > page_count {
+				return Err(corrupt(leaf));
+			}
+			page.write(4, &(leaves - 1).to_be_bytes())?;
+			self.set_freelist_head(trunk, count - 1)?;
+			leaf
+		} else {
+			// An empty trunk is itself the last free page it accounts for
+			self.set_freelist_head(next, count - 1)?;
+			trunk
+		};
+
+		if !self.cache.contains_key(&page_num) && self.cache.is_full() {
+			self.evict_page()?;
+		}
+		self.save_original(page_num)?;
+		let mut page = Page::new(page_num, self.page_size as usize);
+		page.mark_dirty();
+		self.cache.insert(page_num, page);
+		Ok(Some(page_num))
+	}
+
+	/// First trunk page and total page count of the freelist, from the header
+	fn freelist_head(&mut self) -> Result<(u32, u32)> {
+		if self.page_count == 0 {
+			return Ok((0, 0));
+		}
+		let page = self.get_page(1)?;
+		Ok((
+			read_u32(&page.data, FREELIST_TRUNK_OFFSET),
+			read_u32(&page.data, FREELIST_COUNT_OFFSET),
+		))
+	}
+
+	fn set_freelist_head(&mut self, trunk: u32, count: u32) -> Result<()> {
+		let page = self.get_page_mut(1)?;
+		page.write(FREELIST_TRUNK_OFFSET, &trunk.to_be_bytes())?;
+		page.write(FREELIST_COUNT_OFFSET, &count.to_be_bytes())
+	}
+
+	/// Leaf page numbers stored per trunk page; SQLite writes no more than
+	/// this for compatibility with older readers
+	fn max_trunk_leaves(&self) -> u32 {
+		(self.usable_size() / 4 - 8) as u32
+	}
+}
+
+/// Pages in a cache of `size` pages, or of `-size` KiB when negative
+fn cache_pages(size: i64, page_size: u32) -> usize {
+	let pages = match size {
+		0.. => size as u64,
+		_ => size.unsigned_abs().saturating_mul(1024) / page_size as u64,
+	};
+	usize::try_from(pages).unwrap_or(usize::MAX)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+	u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
 }
 
 impl Default for Pager {
@@ -320,5 +1223,254 @@ mod tests {
 		let page1 = pager.allocate_page().unwrap();
 		let page2 = pager.allocate_page().unwrap();
 		assert_ne!(page1, page2);
+		assert_eq!(pager.page_count(), 2);
+	}
+
+	#[test]
+	fn test_freed_pages_are_reused() {
+		let mut pager = Pager::new(4096).unwrap();
+		for _ in 0..5 {
+			pager.allocate_page().unwrap();
+		}
+		pager.get_page_mut(4).unwrap().write(0, &[9; 4]).unwrap();
+		pager.free_page(3).unwrap();
+		pager.free_page(4).unwrap();
+		assert_eq!(pager.freelist_count().unwrap(), 2);
+		assert!(pager.free_page(1).is_err());
+
+		// The header records the first trunk page and the free page count
+		let header = pager.get_page(1).unwrap().read(32, 8).unwrap().to_vec();
+		assert_eq!(header, vec![0, 0, 0, 3, 0, 0, 0, 2]);
+
+		let mut reused = vec![pager.allocate_page().unwrap(), pager.allocate_page().unwrap()];
+		reused.sort();
+		assert_eq!(reused, vec![3, 4]);
+		assert_eq!(pager.get_page(4).unwrap().read(0, 4).unwrap(), &[0; 4]);
+		assert_eq!(pager.freelist_count().unwrap(), 0);
+		assert_eq!(pager.allocate_page().unwrap(), 6);
+	}
+
+	#[test]
+	fn test_freelist_spans_trunk_pages() {
+		let mut pager = Pager::new(512).unwrap();
+		for _ in 0..301 {
+			pager.allocate_page().unwrap();
+		}
+		// A 512 byte trunk page lists at most 120 leaves
+		for page in 2..=301 {
+			pager.free_page(page).unwrap();
+		}
+		assert_eq!(pager.freelist_count().unwrap(), 300);
+
+		let mut reused: Vec<u32> = (0..300).map(|_| pager.allocate_page().unwrap()).collect();
+		reused.sort();
+		assert_eq!(reused, (2..=301).collect::<Vec<u32>>());
+		assert_eq!(pager.freelist_count().unwrap(), 0);
+		assert_eq!(pager.page_count(), 301);
+	}
+
+	#[test]
+	fn test_relocate_fills_free_pages() {
+		let mut pager = Pager::new(512).unwrap();
+		for _ in 0..6 {
+			pager.allocate_page().unwrap();
+		}
+		// Page 2 points to page 6, which points to page 5
+		pager.get_page_mut(2).unwrap().write(8, &6u32.to_be_bytes()).unwrap();
+		pager.get_page_mut(6).unwrap().write(0, &5u32.to_be_bytes()).unwrap();
+		pager.get_page_mut(5).unwrap().write(4, &[7; 4]).unwrap();
+		pager.free_page(3).unwrap();
+		pager.free_page(4).unwrap();
+
+		let refs = HashMap::from([
+			(2, PageRef::Root),
+			(5, PageRef::Pointer(6, 0)),
+			(6, PageRef::Pointer(2, 8)),
+		]);
+		assert!(pager.relocate(&refs, Some(0)).unwrap().is_empty());
+		assert!(pager.relocate(&HashMap::new(), None).is_err());
+
+		assert!(pager.relocate(&refs, None).unwrap().is_empty());
+		assert_eq!(pager.page_count(), 4);
+		assert_eq!(pager.freelist_count().unwrap(), 0);
+		assert_eq!(pager.get_page(2).unwrap().read(8, 4).unwrap(), &4u32.to_be_bytes());
+		assert_eq!(pager.get_page(4).unwrap().read(0, 4).unwrap(), &3u32.to_be_bytes());
+		assert_eq!(pager.get_page(3).unwrap().read(4, 4).unwrap(), &[7; 4]);
+	}
+
+	#[test]
+	fn test_page_zero_is_invalid() {
+		let mut pager = Pager::new(4096).unwrap();
+		assert!(pager.get_page(0).is_err());
+	}
+
+	#[test]
+	fn test_pages_persist_to_file() {
+		use crate::eplite::os::file::DefaultFile;
+
+		let temp_path = "/tmp/epiloglite_test_pager_file.db";
+		let _ = std::fs::remove_file(temp_path);
+
+		{
+			let file = Box::new(DefaultFile::open(temp_path, true, true, true).unwrap());
+			let mut pager = Pager::with_file(1024, file).unwrap();
+			for i in 1..=3u8 {
+				let page_num = pager.allocate_page().unwrap();
+				pager.get_page_mut(page_num).unwrap().write(0, &[i; 4]).unwrap();
+			}
+			pager.flush().unwrap();
+		}
+
+		{
+			let file = Box::new(DefaultFile::open(temp_path, true, true, false).unwrap());
+			let mut pager = Pager::with_file(1024, file).unwrap();
+			assert_eq!(pager.page_count(), 3);
+			assert_eq!(pager.get_page(2).unwrap().read(0, 4).unwrap(), &[2u8; 4]);
+			assert_eq!(pager.get_page(3).unwrap().read(0, 4).unwrap(), &[3u8; 4]);
+		}
+
+		std::fs::remove_file(temp_path).unwrap();
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_cache_evicts_least_recently_used() {
+		use crate::eplite::os::file::DefaultFile;
+
+		let temp_path = "/tmp/epiloglite_test_pager_cache.db";
+		let _ = std::fs::remove_file(temp_path);
+		let file = Box::new(DefaultFile::open(temp_path, true, true, true).unwrap());
+		let mut pager = Pager::with_file(1024, file).unwrap();
+		for i in 1..=10u8 {
+			let page_num = pager.allocate_page().unwrap();
+			pager.get_page_mut(page_num).unwrap().write(0, &[i; 4]).unwrap();
+		}
+
+		// Shrinking the cache writes back nothing until the pages are clean
+		pager.set_cache_size(4);
+		assert_eq!(pager.cache_stats().pages, 10);
+		pager.flush().unwrap();
+		pager.set_cache_size(4);
+		assert_eq!(pager.cache_stats().pages, 4);
+
+		// Page 7 was used more recently than page 8, so 8 makes way for 1
+		let before = pager.cache_stats();
+		pager.get_page(7).unwrap();
+		pager.get_page(1).unwrap();
+		assert_eq!(pager.get_page(7).unwrap().read(0, 4).unwrap(), &[7u8; 4]);
+		let stats = pager.cache_stats();
+		assert_eq!(stats.hits - before.hits, 2);
+		assert_eq!(stats.misses - before.misses, 1);
+		assert_eq!(stats.evictions - before.evictions, 1);
+		pager.get_page(8).unwrap();
+		assert_eq!(pager.cache_stats().misses - before.misses, 2);
+
+		// Negative sizes are in KiB
+		pager.set_cache_size(-2);
+		assert_eq!(pager.cache_size(), -2);
+		assert_eq!(pager.cache_stats().capacity, 2);
+		assert_eq!(pager.cache_stats().pages, 2);
+
+		// Uncommitted pages stay cached however small the cache
+		pager.begin_transaction().unwrap();
+		for page_num in 1..=10 {
+			pager.get_page_mut(page_num).unwrap().write(4, &[0xff; 4]).unwrap();
+		}
+		assert_eq!(pager.cache_stats().pages, 10);
+		pager.rollback_transaction().unwrap();
+		for page_num in 1..=10u8 {
+			let page = pager.get_page(page_num as u32).unwrap();
+			assert_eq!(page.read(0, 8).unwrap(), &[page_num, page_num, page_num, page_num, 0, 0, 0, 0]);
+		}
+		assert!(pager.cache_stats().pages <= 3);
+
+		std::fs::remove_file(temp_path).unwrap();
+	}
+
+	#[test]
+	#[cfg(all(feature = "mmap", target_os = "linux"))]
+	fn test_mmap_copies_pages_from_the_map() {
+		use crate::eplite::os::file::DefaultFile;
+
+		let temp_path = "/tmp/epiloglite_test_pager_mmap.db";
+		let _ = std::fs::remove_file(temp_path);
+		let file = Box::new(DefaultFile::open(temp_path, true, true, true).unwrap());
+		let mut pager = Pager::with_file(1024, file).unwrap();
+		for i in 1..=4u8 {
+			let page_num = pager.allocate_page().unwrap();
+			pager.get_page_mut(page_num).unwrap().write(0, &[i; 4]).unwrap();
+		}
+		pager.flush().unwrap();
+
+		// Only whole pages within the file are mapped
+		assert_eq!(pager.set_mmap_size(3 * 1024 + 100).unwrap(), 3 * 1024 + 100);
+		pager.set_cache_size(0);
+		assert_eq!(pager.mmap.as_ref().unwrap().len(), 3 * 1024);
+		assert_eq!(pager.get_page(3).unwrap().read(0, 4).unwrap(), &[3u8; 4]);
+		assert_eq!(pager.get_page(4).unwrap().read(0, 4).unwrap(), &[4u8; 4]);
+
+		// A change stays in the cache, leaving the file alone until the flush
+		pager.begin_transaction().unwrap();
+		pager.get_page_mut(2).unwrap().write(0, &[9; 4]).unwrap();
+		assert_eq!(std::fs::read(temp_path).unwrap()[1024..1028], [2u8; 4]);
+		pager.commit_transaction().unwrap();
+		assert_eq!(std::fs::read(temp_path).unwrap()[1024..1028], [9u8; 4]);
+
+		// Pages beyond the map are read as usual until the file grows
+		pager.set_mmap_size(MAX_MMAP_SIZE * 2).unwrap();
+		assert_eq!(pager.mmap_size(), MAX_MMAP_SIZE);
+		let page_num = pager.allocate_page().unwrap();
+		pager.get_page_mut(page_num).unwrap().write(0, &[5; 4]).unwrap();
+		pager.flush().unwrap();
+		pager.set_cache_size(100);
+		pager.set_cache_size(0);
+		assert_eq!(pager.mmap.as_ref().unwrap().len(), 5 * 1024);
+		assert_eq!(pager.get_page(5).unwrap().read(0, 4).unwrap(), &[5u8; 4]);
+
+		// Truncating the file shrinks the map with it
+		pager.truncate(2).unwrap();
+		pager.flush().unwrap();
+		assert_eq!(std::fs::metadata(temp_path).unwrap().len(), 2048);
+		assert_eq!(pager.mmap.as_ref().unwrap().len(), 2048);
+		assert_eq!(pager.get_page(2).unwrap().read(0, 4).unwrap(), &[9u8; 4]);
+
+		pager.set_mmap_size(0).unwrap();
+		assert!(pager.mmap.is_none());
+		assert_eq!(pager.get_page(2).unwrap().read(0, 4).unwrap(), &[9u8; 4]);
+		std::fs::remove_file(temp_path).unwrap();
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_journal_covers_overwritten_and_truncated_pages() {
+		use crate::eplite::os::file::DefaultFile;
+
+		let temp_path = "/tmp/epiloglite_test_pager_journal.db";
+		let journal_path = format!("{}-journal", temp_path);
+		let _ = std::fs::remove_file(temp_path);
+		let open = || Box::new(DefaultFile::open(temp_path, true, true, true).unwrap());
+
+		let mut pager = Pager::with_file(1024, open()).unwrap();
+		pager.set_journal(Journal::new(temp_path));
+		for i in 1..=3u8 {
+			let page_num = pager.allocate_page().unwrap();
+			pager.get_page_mut(page_num).unwrap().write(0, &[i; 4]).unwrap();
+		}
+		pager.flush().unwrap();
+		let committed = std::fs::read(temp_path).unwrap();
+
+		pager.get_page_mut(1).unwrap().write(0, &[9; 4]).unwrap();
+		pager.truncate(1).unwrap();
+		assert!(pager.write_journal().unwrap());
+		let journal = std::fs::read(&journal_path).unwrap();
+		pager.flush().unwrap();
+		assert!(std::fs::metadata(&journal_path).is_err());
+		assert_eq!(std::fs::metadata(temp_path).unwrap().len(), 1024);
+
+		// Had the flush been cut short, the journal restores all three pages
+		drop(pager);
+		std::fs::write(&journal_path, journal).unwrap();
+		assert!(Journal::new(temp_path).recover(&mut *open()).unwrap());
+		assert_eq!(std::fs::read(temp_path).unwrap(), committed);
 	}
 }
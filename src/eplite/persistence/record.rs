@@ -0,0 +1,292 @@
+/// Record format - encoding of row and index payloads
+///
+/// A record is a header of serial types followed by the values they
+/// describe, as in SQLite 3. The header starts with its own size as a varint;
+/// each serial type is a varint giving the type and size of one value.
+
+use crate::eplite::error::{Error, Result};
+use crate::eplite::persistence::varint;
+use crate::eplite::types::ValueType;
+use core::cmp::Ordering;
+
+#[cfg(not(feature = "std"))]
+use alloc::{
+	format,
+	string::{String, ToString},
+	vec::Vec,
+};
+
+/// Serial type of a value and the number of body bytes it occupies
+fn serial_type(value: &ValueType) -> Result<(u64, usize)> {
+	let serial = match value {
+		ValueType::Null => return Ok((0, 0)),
+		ValueType::Bool(false) => return Ok((8, 0)),
+		ValueType::Bool(true) => return Ok((9, 0)),
+		ValueType::F32(_) | ValueType::F64(_) => return Ok((7, 8)),
+		ValueType::StringUtf8(text) => return Ok((text.len() as u64 * 2 + 13, text.len())),
+		ValueType::StringUtf16Le(_) | ValueType::StringUtf16Be(_) => {
+			let len = text_bytes(value)?.len();
+			return Ok((len as u64 * 2 + 13, len));
+		}
+		ValueType::Blob(bytes) | ValueType::Bytes(bytes) => {
+			return Ok((bytes.len() as u64 * 2 + 12, bytes.len()));
+		}
+		_ => integer_value(value)?,
+	};
+	Ok(match serial {
+		0 => (8, 0),
+		1 => (9, 0),
+		-0x80..=0x7f => (1, 1),
+		-0x8000..=0x7fff => (2, 2),
+		-0x80_0000..=0x7f_ffff => (3, 3),
+		-0x8000_0000..=0x7fff_ffff => (4, 4),
+		-0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+		_ => (6, 8),
+	})
+}
+
+/// Integer value of an integer variant
+fn integer_value(value: &ValueType) -> Result<i64> {
+	let out_of_range = || Error::TypeMismatch(format!("{:?} does not fit in a 64-bit integer", value));
+	Ok(match value {
+		ValueType::I8(v) => *v as i64,
+		ValueType::U8(v) => *v as i64,
+		ValueType::I16(v) => *v as i64,
+		ValueType::U16(v) => *v as i64,
+		ValueType::I32(v) => *v as i64,
+		ValueType::U32(v) => *v as i64,
+		ValueType::I64(v) => *v,
+		ValueType::U64(v) => i64::try_from(*v).map_err(|_| out_of_range())?,
+		ValueType::I128(v) => i64::try_from(*v).map_err(|_| out_of_range())?,
+		ValueType::U128(v) => i64::try_from(*v).map_err(|_| out_of_range())?,
+		ValueType::ISize(v) => *v as i64,
+		ValueType::USize(v) => i64::try_from(*v).map_err(|_| out_of_range())?,
+		_ => return Err(Error::Internal(format!("{} is not an integer", value.type_name()))),
+	})
+}
+
+/// UTF-8 bytes of a text value; the database text encoding is UTF-8
+fn text_bytes(value: &ValueType) -> Result<Vec<u8>> {
+	let (bytes, little_endian) = match value {
+		ValueType::StringUtf8(text) => return Ok(text.as_bytes().to_vec()),
+		ValueType::StringUtf16Le(bytes) => (bytes, true),
+		ValueType::StringUtf16Be(bytes) => (bytes, false),
+		_ => return Err(Error::Internal(format!("{} is not text", value.type_name()))),
+	};
+	let units = bytes.chunks_exact(2).map(|pair| {
+		if little_endian {
+			u16::from_le_bytes([pair[0], pair[1]])
+		} else {
+			u16::from_be_bytes([pair[0], pair[1]])
+		}
+	});
+	let text: core::result::Result<String, _> = char::decode_utf16(units).collect();
+	text.map(|t| t.into_bytes())
+		.map_err(|_| Error::TypeMismatch("Invalid UTF-16 text".to_string()))
+}
+
+/// Encode values as a record
+pub fn encode(values: &[ValueType]) -> Result<Vec<u8>> {
+	let mut types = Vec::with_capacity(values.len() * 2);
+	let mut body = Vec::new();
+	for value in values {
+		let (serial, size) = serial_type(value)?;
+		varint::encode(serial, &mut types);
+		match value {
+			ValueType::Null | ValueType::Bool(_) => {}
+			ValueType::F32(v) => body.extend_from_slice(&(*v as f64).to_be_bytes()),
+			ValueType::F64(v) => body.extend_from_slice(&v.to_be_bytes()),
+			ValueType::StringUtf8(text) => body.extend_from_slice(text.as_bytes()),
+			ValueType::StringUtf16Le(_) | ValueType::StringUtf16Be(_) => {
+				body.extend_from_slice(&text_bytes(value)?);
+			}
+			ValueType::Blob(bytes) | ValueType::Bytes(bytes) => body.extend_from_slice(bytes),
+			_ => {
+				let bytes = integer_value(value)?.to_be_bytes();
+				body.extend_from_slice(&bytes[8 - size..]);
+			}
+		}
+	}
+
+	// The header size counts its own varint, whose length depends on the total
+	let mut size_len = 1;
+	while varint::encoded_len((types.len() + size_len) as u64) > size_len {
+		size_len += 1;
+	}
+	let header_size = types.len() + size_len;
+	let mut record = Vec::with_capacity(header_size + body.len());
+	varint::encode(header_size as u64, &mut record);
+	record.extend_from_slice(&types);
+	record.extend_from_slice(&body);
+	Ok(record)
+}
+
+/// Decode a record into its values
+///
+/// Integers decode as `I64`, floating point values as `F64`, text as
+/// `StringUtf8` and blobs as `Blob`.
+pub fn decode(data: &[u8]) -> Result<Vec<ValueType>> {
+	let corrupt = || Error::Corrupt("Malformed record".to_string());
+	let (header_size, mut header_pos) = varint::decode(data)?;
+	let header_size = header_size as usize;
+	if header_size > data.len() || header_size < header_pos {
+		return Err(corrupt());
+	}
+
+	let mut values = Vec::new();
+	let mut body_pos = header_size;
+	while header_pos < header_size {
+		let (serial, len) = varint::decode(&data[header_pos..header_size])?;
+		header_pos += len;
+
+		let size = match serial {
+			0 | 8 | 9 => 0,
+			1..=4 => serial as usize,
+			5 => 6,
+			6 | 7 => 8,
+			10 | 11 => return Err(Error::Corrupt(format!("Reserved serial type {}", serial))),
+			_ => ((serial - 12) / 2) as usize,
+		};
+		let bytes = data.get(body_pos..body_pos + size).ok_or_else(corrupt)?;
+		body_pos += size;
+
+		values.push(match serial {
+			0 => ValueType::Null,
+			1..=6 => {
+				// Sign-extend the big-endian integer
+				let mut value = if bytes[0] & 0x80 != 0 { -1i64 } else { 0 };
+				for byte in bytes {
+					value = (value << 8) | *byte as i64;
+				}
+				ValueType::I64(value)
+			}
+			7 => {
+				let mut raw = [0u8; 8];
+				raw.copy_from_slice(bytes);
+				ValueType::F64(f64::from_be_bytes(raw))
+			}
+			8 => ValueType::I64(0),
+			9 => ValueType::I64(1),
+			_ if serial % 2 == 0 => ValueType::Blob(bytes.to_vec()),
+			_ => ValueType::StringUtf8(
+				String::from_utf8(bytes.to_vec())
+					.map_err(|_| Error::Corrupt("Invalid UTF-8 text in record".to_string()))?,
+			),
+		});
+	}
+	Ok(values)
+}
+
+/// Order values as SQLite does: NULL, then numbers, then text, then blobs
+pub fn compare_values(a: &ValueType, b: &ValueType) -> Ordering {
+	fn class(value: &ValueType) -> u8 {
+		match value {
+			ValueType::Null => 0,
+			ValueType::StringUtf8(_) | ValueType::StringUtf16Le(_) | ValueType::StringUtf16Be(_) => 2,
+			ValueType::Blob(_) | ValueType::Bytes(_) => 3,
+			_ => 1,
+		}
+	}
+
+	match (class(a), class(b)) {
+		(1, 1) => match (integer_value(a), integer_value(b)) {
+			(Ok(x), Ok(y)) => x.cmp(&y),
+			_ => as_f64(a).partial_cmp(&as_f64(b)).unwrap_or(Ordering::Equal),
+		},
+		(2, 2) => match (text_bytes(a), text_bytes(b)) {
+			(Ok(x), Ok(y)) => x.cmp(&y),
+			_ => Ordering::Equal,
+		},
+		(3, 3) => match (a, b) {
+			(ValueType::Blob(x) | ValueType::Bytes(x), ValueType::Blob(y) | ValueType::Bytes(y)) => x.cmp(y),
+			_ => Ordering::Equal,
+		},
+		(x, y) => x.cmp(&y),
+	}
+}
+
+fn as_f64(value: &ValueType) -> f64 {
+	match value {
+		ValueType::F32(v) => *v as f64,
+		ValueType::F64(v) => *v,
+		ValueType::Bool(v) => *v as u8 as f64,
+		ValueType::U64(v) => *v as f64,
+		ValueType::I128(v) => *v as f64,
+		ValueType::U128(v) => *v as f64,
+		ValueType::USize(v) => *v as f64,
+		other => integer_value(other).map(|v| v as f64).unwrap_or(0.0),
+	}
+}
+
+/// Compare two encoded records value by value; a record that is a prefix of
+/// another sorts first. Usable as an index b-tree comparator.
+pub fn compare(a: &[u8], b: &[u8]) -> Ordering {
+	let (Ok(left), Ok(right)) = (decode(a), decode(b)) else {
+		return a.cmp(b);
+	};
+	for (x, y) in left.iter().zip(right.iter()) {
+		let ordering = compare_values(x, y);
+		if ordering != Ordering::Equal {
+			return ordering;
+		}
+	}
+	left.len().cmp(&right.len())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_record_round_trip() {
+		let values = vec![
+			ValueType::Null,
+			ValueType::I64(0),
+			ValueType::I64(1),
+			ValueType::I64(-100),
+			ValueType::I64(40_000),
+			ValueType::I64(-3_000_000),
+			ValueType::I64(1 << 40),
+			ValueType::I64(i64::MIN),
+			ValueType::F64(3.25),
+			ValueType::StringUtf8("hello".to_string()),
+			ValueType::Blob(vec![1, 2, 3]),
+		];
+		let record = encode(&values).unwrap();
+		let decoded = decode(&record).unwrap();
+		assert_eq!(format!("{:?}", decoded), format!("{:?}", values));
+	}
+
+	#[test]
+	fn test_record_known_encoding() {
+		// Header: size 3, serial types 1 (8-bit int) and 19 (3-byte text)
+		let record = encode(&[ValueType::I32(7), ValueType::StringUtf8("abc".to_string())]).unwrap();
+		assert_eq!(record, vec![3, 1, 19, 7, b'a', b'b', b'c']);
+	}
+
+	#[test]
+	fn test_record_large_header() {
+		let values: Vec<ValueType> = (0..200).map(|_| ValueType::Null).collect();
+		let record = encode(&values).unwrap();
+		assert_eq!(decode(&record).unwrap().len(), 200);
+	}
+
+	#[test]
+	fn test_record_corrupt() {
+		assert!(decode(&[5, 1]).is_err());
+		assert!(decode(&[2, 1]).is_err());
+	}
+
+	#[test]
+	fn test_record_compare() {
+		let a = encode(&[ValueType::I64(1), ValueType::StringUtf8("b".to_string())]).unwrap();
+		let b = encode(&[ValueType::I64(1), ValueType::StringUtf8("c".to_string())]).unwrap();
+		let c = encode(&[ValueType::StringUtf8("a".to_string())]).unwrap();
+		let d = encode(&[ValueType::Null]).unwrap();
+		assert_eq!(compare(&a, &b), Ordering::Less);
+		assert_eq!(compare(&c, &a), Ordering::Greater);
+		assert_eq!(compare(&d, &a), Ordering::Less);
+		assert_eq!(compare(&a, &a), Ordering::Equal);
+		assert_eq!(compare_values(&ValueType::I64(2), &ValueType::F64(2.5)), Ordering::Less);
+	}
+}
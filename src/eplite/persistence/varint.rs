@@ -0,0 +1,114 @@
+/// Variable-length integers in the SQLite 3 encoding
+///
+/// A varint is 1 to 9 bytes long. The first eight bytes each contribute their
+/// low seven bits, with the high bit set when another byte follows; a ninth
+/// byte contributes all eight of its bits.
+
+use crate::eplite::error::{Error, Result};
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::ToString, vec::Vec};
+
+/// Maximum encoded length of a varint
+pub const MAX_VARINT_LEN: usize = 9;
+
+/// Decode a varint from the start of `data`, returning the value and the
+/// number of bytes consumed
+pub fn decode(data: &[u8]) -> Result<(u64, usize)> {
+	let mut value = 0u64;
+	for i in 0..MAX_VARINT_LEN {
+		let byte = *data
+			.get(i)
+			.ok_or_else(|| Error::Corrupt("Truncated varint".to_string()))?;
+		if i == MAX_VARINT_LEN - 1 {
+			return Ok(((value << 8) | byte as u64, MAX_VARINT_LEN));
+		}
+		value = (value << 7) | (byte & 0x7f) as u64;
+		if byte & 0x80 == 0 {
+			return Ok((value, i + 1));
+		}
+	}
+	unreachable!()
+}
+
+/// Append the varint encoding of `value` to `out`
+pub fn encode(value: u64, out: &mut Vec<u8>) {
+	if value & 0xff00_0000_0000_0000 != 0 {
+		// Nine bytes: the last byte carries a full eight bits
+		let mut bytes = [0u8; MAX_VARINT_LEN];
+		bytes[8] = value as u8;
+		let mut rest = value >> 8;
+		for i in (0..8).rev() {
+			bytes[i] = (rest as u8 & 0x7f) | 0x80;
+			rest >>= 7;
+		}
+		out.extend_from_slice(&bytes);
+		return;
+	}
+
+	let mut groups = [0u8; MAX_VARINT_LEN];
+	let mut count = 0;
+	let mut rest = value;
+	loop {
+		groups[count] = (rest & 0x7f) as u8;
+		count += 1;
+		rest >>= 7;
+		if rest == 0 {
+			break;
+		}
+	}
+	for i in (0..count).rev() {
+		let continuation = if i > 0 { 0x80 } else { 0 };
+		out.push(groups[i] | continuation);
+	}
+}
+
+/// Number of bytes needed to encode `value`
+pub fn encoded_len(value: u64) -> usize {
+	if value & 0xff00_0000_0000_0000 != 0 {
+		return MAX_VARINT_LEN;
+	}
+	let bits = 64 - value.leading_zeros() as usize;
+	bits.div_ceil(7).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(value: u64) -> usize {
+		let mut bytes = Vec::new();
+		encode(value, &mut bytes);
+		assert_eq!(bytes.len(), encoded_len(value));
+		let (decoded, len) = decode(&bytes).unwrap();
+		assert_eq!(decoded, value);
+		assert_eq!(len, bytes.len());
+		len
+	}
+
+	#[test]
+	fn test_varint_lengths() {
+		assert_eq!(round_trip(0), 1);
+		assert_eq!(round_trip(127), 1);
+		assert_eq!(round_trip(128), 2);
+		assert_eq!(round_trip(16383), 2);
+		assert_eq!(round_trip(16384), 3);
+		assert_eq!(round_trip(0x00ff_ffff_ffff_ffff), 8);
+		assert_eq!(round_trip(0x0100_0000_0000_0000), 9);
+		assert_eq!(round_trip(u64::MAX), 9);
+		assert_eq!(round_trip(-1i64 as u64), 9);
+	}
+
+	#[test]
+	fn test_varint_known_encodings() {
+		let mut bytes = Vec::new();
+		encode(300, &mut bytes);
+		assert_eq!(bytes, vec![0x82, 0x2c]);
+	}
+
+	#[test]
+	fn test_varint_truncated() {
+		assert!(decode(&[0x81]).is_err());
+		assert!(decode(&[]).is_err());
+	}
+}
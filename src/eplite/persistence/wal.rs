@@ -5,6 +5,14 @@ use crate::eplite::error::{Error, Result};
 #[cfg(not(feature = "std"))]
 use alloc::{format, string::ToString, vec, vec::Vec};
 
+#[cfg(feature = "std")]
+use crate::eplite::os::file::DefaultFile;
+#[cfg(feature = "std")]
+use crate::eplite::persistence::wal_index::{IndexHeader, IndexLock, WalIndex, READ_MARKS, READ_MARK_UNUSED};
+#[cfg(feature = "std")]
+use crate::eplite::traits::file::{File, SynchronizationType};
+#[cfg(feature = "std")]
+use flagset::FlagSet;
 #[cfg(feature = "std")]
 use std::collections::HashMap;
 
@@ -23,6 +31,10 @@ const WAL_HEADER_SIZE: usize = 32;
 /// WAL frame header size in bytes
 const WAL_FRAME_HEADER_SIZE: usize = 24;
 
+/// Attempts at a consistent snapshot before a reader gives up as busy
+#[cfg(feature = "std")]
+const READ_RETRIES: usize = 100;
+
 /// WAL file header (32 bytes)
 #[derive(Debug, Clone)]
 pub struct WalHeader {
@@ -238,7 +250,7 @@ impl WalFrame {
 /// 
 /// The checksum is computed by interpreting input as an even number of u32 integers.
 /// Returns (s0, s1) checksum values.
-fn compute_checksum(data: &[u8], s0: u32, s1: u32, big_endian: bool) -> (u32, u32) {
+pub(crate) fn compute_checksum(data: &[u8], s0: u32, s1: u32, big_endian: bool) -> (u32, u32) {
 	let mut sum0 = s0;
 	let mut sum1 = s1;
 	
@@ -471,6 +483,21 @@ pub enum CheckpointMode {
 	Truncate,
 }
 
+impl CheckpointMode {
+	/// Parse a PRAGMA wal_checkpoint mode
+	pub fn from_name(name: &str) -> Option<Self> {
+		[
+			("passive", CheckpointMode::Passive),
+			("full", CheckpointMode::Full),
+			("restart", CheckpointMode::Restart),
+			("truncate", CheckpointMode::Truncate),
+		]
+		.into_iter()
+		.find(|(mode, _)| name.eq_ignore_ascii_case(mode))
+		.map(|(_, mode)| mode)
+	}
+}
+
 /// Checkpoint result information
 #[derive(Debug, Clone)]
 pub struct CheckpointResult {
@@ -569,6 +596,17 @@ impl WalRecovery {
 		Ok(updates)
 	}
 
+	/// Copy the committed frames of a log left by a crash into the database
+	/// file; frames after the last valid commit are discarded
+	#[cfg(feature = "std")]
+	pub fn replay(&self, wal_bytes: &[u8], db: &mut dyn File) -> Result<CheckpointResult> {
+		let reader = WalReader::from_bytes(wal_bytes)?;
+		let checkpoint = WalCheckpoint::new(self.page_size);
+		let (updates, result) = checkpoint.checkpoint(&reader, CheckpointMode::Full)?;
+		backfill(&reader, &updates, result.checkpointed_frames, db)?;
+		Ok(result)
+	}
+
 	/// Check if WAL needs recovery
 	pub fn needs_recovery(wal_bytes: &[u8]) -> bool {
 		if wal_bytes.len() < WAL_HEADER_SIZE {
@@ -719,6 +757,516 @@ impl WalReader {
 	}
 }
 
+/// The `-wal` file of a database in WAL mode
+///
+/// Each commit appends its pages as frames, the last marked as the commit,
+/// and records them in the wal-index that every connection to the database
+/// shares. A reader takes a snapshot of the log's committed frames and
+/// looks up the latest frame of a page before the database file. A
+/// checkpoint copies frames back into the database file, stopping at the
+/// oldest snapshot still being read, so the log can start over.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct WalFile {
+	path: String,
+	file: DefaultFile,
+	index: WalIndex,
+	page_size: u32,
+	/// The index header of the open snapshot, or of the last one taken
+	snapshot: IndexHeader,
+	/// Read mark slot locked while a snapshot is open
+	read_lock: Option<usize>,
+	/// Whether this connection holds the write lock
+	writing: bool,
+}
+
+#[cfg(feature = "std")]
+impl WalFile {
+	/// Open the log at `path` and the wal-index beside it
+	///
+	/// The first connection to open the index rebuilds it from the commits
+	/// in the log; later ones share it.
+	pub fn open(path: &str, page_size: u32) -> Result<Self> {
+		let (index, alone) = WalIndex::open(&format!("{}-shm", database_path(path)))?;
+		let mut wal = WalFile {
+			path: path.to_string(),
+			file: DefaultFile::open(path, true, true, true)?,
+			index,
+			page_size,
+			snapshot: IndexHeader::default(),
+			read_lock: None,
+			writing: false,
+		};
+		if alone {
+			wal.rebuild_index()?;
+			wal.index.share()?;
+		}
+		Ok(wal)
+	}
+
+	/// Replay a log left beside the database at `path` by a crash, then
+	/// delete it
+	///
+	/// Returns None if there is no log, or if other connections are still
+	/// using it. A log whose header is damaged never had a frame committed,
+	/// so nothing is replayed from it.
+	pub fn recover(path: &str, db: &mut dyn File) -> Result<Option<CheckpointResult>> {
+		if std::fs::metadata(path).is_err() {
+			return Ok(None);
+		}
+		let (mut index, alone) = WalIndex::open(&format!("{}-shm", database_path(path)))?;
+		if !alone {
+			return Ok(None);
+		}
+		let bytes = match std::fs::read(path) {
+			Ok(bytes) => bytes,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err.into()),
+		};
+		let mut result = CheckpointResult {
+			wal_frames: 0,
+			checkpointed_frames: 0,
+			completed: true,
+		};
+		if WalRecovery::needs_recovery(&bytes) {
+			let page_size = WalHeader::from_bytes(&bytes)?.page_size;
+			match WalRecovery::new(page_size).replay(&bytes, db) {
+				Ok(replayed) => result = replayed,
+				Err(Error::InvalidFormat(_)) => {}
+				Err(err) => return Err(err),
+			}
+		}
+		remove_log(path)?;
+		index.empty()?;
+		Ok(Some(result))
+	}
+
+	/// Path of the log file
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	/// Number of committed frames in the snapshot, including those already
+	/// checkpointed until a commit starts the log over
+	pub fn frame_count(&self) -> usize {
+		self.snapshot.max_frame as usize
+	}
+
+	/// Size of the database in pages as of the snapshot, or None if the log
+	/// holds no commits and the database file has the latest pages
+	pub fn page_count(&self) -> Option<u32> {
+		(self.snapshot.max_frame > 0).then_some(self.snapshot.page_count)
+	}
+
+	/// Take a snapshot of the committed frames to read from, returning
+	/// whether another connection committed or checkpointed since the last
+	/// snapshot this connection took
+	///
+	/// The snapshot's read mark stops checkpoints from copying later frames
+	/// into the database file until `end_read`.
+	pub fn begin_read(&mut self) -> Result<bool> {
+		if self.read_lock.is_some() {
+			return Ok(false);
+		}
+		for _ in 0..READ_RETRIES {
+			if let Some(header) = self.index.read_header()? {
+				if let Some(slot) = self.lock_read_mark(header.max_frame)? {
+					// The header and mark may have changed before the lock
+					let mark = self.index.read_marks()?[slot];
+					if self.index.read_header()? == Some(header) && mark <= header.max_frame {
+						let changed = header != self.snapshot;
+						self.snapshot = header;
+						self.read_lock = Some(slot);
+						return Ok(changed);
+					}
+					self.index.unlock(IndexLock::Read(slot))?;
+				}
+			}
+			std::thread::yield_now();
+		}
+		Err(Error::Busy)
+	}
+
+	/// Release the snapshot taken by `begin_read`
+	pub fn end_read(&mut self) -> Result<()> {
+		if let Some(slot) = self.read_lock.take() {
+			self.index.unlock(IndexLock::Read(slot))?;
+		}
+		Ok(())
+	}
+
+	/// Take the write lock, so the next commit can append to the log
+	///
+	/// Fails with `Error::Busy` if another connection is writing, or if
+	/// another connection committed after the snapshot being read, whose
+	/// changes this connection has not seen.
+	pub fn begin_write(&mut self) -> Result<()> {
+		if self.writing {
+			return Ok(());
+		}
+		self.begin_read()?;
+		if !self.index.lock(IndexLock::Write, true)? {
+			return Err(Error::Busy);
+		}
+		if self.index.read_header()? != Some(self.snapshot) {
+			self.index.unlock(IndexLock::Write)?;
+			return Err(Error::Busy);
+		}
+		self.writing = true;
+		Ok(())
+	}
+
+	/// Release the write lock
+	pub fn end_write(&mut self) -> Result<()> {
+		if self.writing {
+			self.index.unlock(IndexLock::Write)?;
+			self.writing = false;
+		}
+		Ok(())
+	}
+
+	/// Whether this connection holds the write lock
+	pub fn is_writing(&self) -> bool {
+		self.writing
+	}
+
+	/// Whether no other connection uses the log; if so, none can start to
+	/// until this one closes
+	pub fn claim(&mut self) -> Result<bool> {
+		self.index.claim()
+	}
+
+	/// Read the contents of a page as of the snapshot into `buffer`,
+	/// returning false if the snapshot's frames do not hold the page
+	pub fn read_page(&mut self, page_number: u32, buffer: &mut [u8]) -> Result<bool> {
+		let Some(frame) = self.index.find(page_number, self.snapshot.max_frame)? else {
+			return Ok(false);
+		};
+		self.file.read_at(self.frame_offset(frame) + WAL_FRAME_HEADER_SIZE as u64, buffer)?;
+		Ok(true)
+	}
+
+	/// Append a transaction's pages and sync them; `db_size` is the size
+	/// of the database in pages once it commits
+	///
+	/// Takes the write lock if it is not held; it is kept until `end_write`.
+	pub fn commit(&mut self, pages: Vec<(u32, Vec<u8>)>, db_size: u32) -> Result<()> {
+		self.begin_write()?;
+		if self.snapshot.max_frame > 0 && self.index.backfill()? == self.snapshot.max_frame {
+			self.restart(self.snapshot)?;
+		}
+		let mut header = self.snapshot;
+		let mut data = Vec::new();
+		if header.max_frame == 0 {
+			let mut wal_header = WalHeader::new(self.page_size);
+			wal_header.checkpoint_seq = self.checkpoint_seq()?;
+			if header.salt != (0, 0) {
+				(wal_header.salt1, wal_header.salt2) = header.salt;
+			}
+			wal_header.update_checksums();
+			data.extend_from_slice(&wal_header.to_bytes());
+			header.big_endian = wal_header.magic == WAL_MAGIC_BE;
+			header.page_size = self.page_size;
+			header.salt = (wal_header.salt1, wal_header.salt2);
+			header.frame_checksum = (wal_header.checksum1, wal_header.checksum2);
+		}
+
+		let mut checksum = header.frame_checksum;
+		let count = pages.len();
+		for (i, (page_number, page)) in pages.iter().enumerate() {
+			let commit_size = if i + 1 == count { db_size } else { 0 };
+			let mut frame = WalFrameHeader::new(*page_number, commit_size, header.salt.0, header.salt.1);
+			checksum = compute_checksum(&frame.to_bytes()[..8], checksum.0, checksum.1, header.big_endian);
+			checksum = compute_checksum(page, checksum.0, checksum.1, header.big_endian);
+			(frame.checksum1, frame.checksum2) = checksum;
+			data.extend_from_slice(&frame.to_bytes());
+			data.extend_from_slice(page);
+		}
+		let offset = match header.max_frame {
+			0 => 0,
+			frames => self.frame_offset(frames + 1),
+		};
+		self.file.write(&data, offset)?;
+		self.file.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+
+		// Readers see the frames once the new header is published
+		for (i, (page_number, _)) in pages.iter().enumerate() {
+			self.index.append(header.max_frame + 1 + i as u32, *page_number)?;
+		}
+		header.change = header.change.wrapping_add(1);
+		header.max_frame += count as u32;
+		header.page_count = db_size;
+		header.frame_checksum = checksum;
+		self.index.write_header(&header)?;
+		self.snapshot = header;
+		Ok(())
+	}
+
+	/// Copy committed frames not yet in the database file into it, up to
+	/// the oldest snapshot another connection is reading
+	///
+	/// Every mode but PASSIVE needs the write lock, and RESTART and TRUNCATE
+	/// only complete once no other connection is reading the log. TRUNCATE
+	/// then starts the log over and cuts the file to zero bytes; otherwise
+	/// the next commit starts it over.
+	pub fn checkpoint(&mut self, db: &mut dyn File, mode: CheckpointMode) -> Result<CheckpointResult> {
+		if !self.index.lock(IndexLock::Checkpoint, true)? {
+			let backfill = self.index.backfill()?;
+			return Ok(CheckpointResult {
+				wal_frames: self.index.read_header()?.unwrap_or(self.snapshot).max_frame as usize,
+				checkpointed_frames: backfill as usize,
+				completed: false,
+			});
+		}
+		let locked_write = mode != CheckpointMode::Passive && !self.writing;
+		let writer = !locked_write || self.index.lock(IndexLock::Write, true)?;
+		let result = self.checkpoint_locked(db, mode, writer);
+		if locked_write && writer {
+			self.index.unlock(IndexLock::Write)?;
+		}
+		self.index.unlock(IndexLock::Checkpoint)?;
+		result
+	}
+
+	fn checkpoint_locked(&mut self, db: &mut dyn File, mode: CheckpointMode, writer: bool) -> Result<CheckpointResult> {
+		let header = self.index.read_header()?.unwrap_or(self.snapshot);
+		let backfill = self.backfill(db, &header)?;
+		let mut result = CheckpointResult {
+			wal_frames: header.max_frame as usize,
+			checkpointed_frames: backfill as usize,
+			completed: writer && backfill == header.max_frame,
+		};
+		if result.completed && matches!(mode, CheckpointMode::Restart | CheckpointMode::Truncate) {
+			result.completed = match mode {
+				// A snapshot older than the log would lose its frames
+				CheckpointMode::Truncate => {
+					(self.read_lock.is_none() || header == self.snapshot) && self.restart(header)?
+				}
+				_ => self.readers_done()?,
+			};
+		}
+		if result.completed && mode == CheckpointMode::Truncate {
+			self.file.truncate(0)?;
+			self.file.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+			result.wal_frames = 0;
+			result.checkpointed_frames = 0;
+		}
+		Ok(result)
+	}
+
+	/// Copy frames into the database file up to the last one every reader's
+	/// snapshot includes, returning how many frames it now holds
+	fn backfill(&mut self, db: &mut dyn File, header: &IndexHeader) -> Result<u32> {
+		let marks = self.index.read_marks()?;
+		let mut safe = header.max_frame;
+		for (slot, &mark) in marks.iter().enumerate().skip(1) {
+			if mark >= safe {
+				continue;
+			}
+			// A slot no other connection reads from can take a later mark,
+			// up to this connection's own snapshot if it reads from it
+			if !self.index.lock(IndexLock::Read(slot), true)? {
+				safe = mark;
+			} else if Some(slot) == self.read_lock {
+				safe = safe.min(self.snapshot.max_frame);
+				self.index.set_read_mark(slot, safe)?;
+				self.index.lock(IndexLock::Read(slot), false)?;
+			} else {
+				self.index.set_read_mark(slot, if slot == 1 { safe } else { READ_MARK_UNUSED })?;
+				self.index.unlock(IndexLock::Read(slot))?;
+			}
+		}
+
+		let backfill = self.index.backfill()?;
+		// Readers of the database file alone hold the first read lock
+		if backfill >= safe || !self.index.lock(IndexLock::Read(0), true)? {
+			return Ok(backfill);
+		}
+		let copied = self.copy_frames(db, safe);
+		self.index.unlock(IndexLock::Read(0))?;
+		copied?;
+		self.index.set_backfill(safe)?;
+		Ok(safe)
+	}
+
+	/// Write the latest of the first `frames` frames of each page into the
+	/// database file
+	fn copy_frames(&mut self, db: &mut dyn File, frames: u32) -> Result<()> {
+		let mut bytes = vec![0u8; self.frame_offset(frames + 1) as usize];
+		self.file.read_at(0, &mut bytes)?;
+		let reader = WalReader::from_bytes(&bytes)?;
+		let (updates, result) = WalCheckpoint::new(self.page_size).checkpoint(&reader, CheckpointMode::Full)?;
+		backfill(&reader, &updates, result.checkpointed_frames, db)
+	}
+
+	/// Whether no other connection is reading the log
+	fn readers_done(&mut self) -> Result<bool> {
+		for slot in (1..READ_MARKS).filter(|&slot| Some(slot) != self.read_lock) {
+			if !self.index.lock(IndexLock::Read(slot), true)? {
+				return Ok(false);
+			}
+			self.index.unlock(IndexLock::Read(slot))?;
+		}
+		Ok(true)
+	}
+
+	/// Start the log described by `header` over, returning false if
+	/// another connection is still reading it; the database file must hold
+	/// every frame, and this connection must hold the write lock
+	///
+	/// The next commit writes a new log header, whose salts void the old
+	/// frames.
+	fn restart(&mut self, header: IndexHeader) -> Result<bool> {
+		let mut locked = Vec::new();
+		for slot in 1..READ_MARKS {
+			if !self.index.lock(IndexLock::Read(slot), true)? {
+				break;
+			}
+			locked.push(slot);
+		}
+		let restarted = locked.len() == READ_MARKS - 1;
+		if restarted {
+			let restarted = IndexHeader {
+				change: header.change.wrapping_add(1),
+				max_frame: 0,
+				frame_checksum: (0, 0),
+				salt: (header.salt.0.wrapping_add(1), WalHeader::generate_salt()),
+				..header
+			};
+			self.index.reset(&restarted)?;
+			if let Some(slot) = self.read_lock {
+				self.index.set_read_mark(slot, 0)?;
+			}
+			// The cache still matches the database if the snapshot did
+			if header == self.snapshot {
+				self.snapshot = restarted;
+			}
+		}
+		for slot in locked {
+			if Some(slot) == self.read_lock {
+				self.index.lock(IndexLock::Read(slot), false)?;
+			} else {
+				self.index.unlock(IndexLock::Read(slot))?;
+			}
+		}
+		Ok(restarted)
+	}
+
+	/// Checkpoint and delete the log if no other connection uses it, as
+	/// when the last connection to a database in WAL mode closes
+	pub fn close(mut self, db: &mut dyn File) -> Result<()> {
+		self.end_write()?;
+		self.end_read()?;
+		if !self.index.claim()? {
+			return Ok(());
+		}
+		if self.checkpoint(db, CheckpointMode::Full)?.completed {
+			drop(self.file);
+			remove_log(&self.path)?;
+			self.index.empty()?;
+		}
+		Ok(())
+	}
+
+	/// Fill a wal-index no other connection uses from the commits in the log
+	fn rebuild_index(&mut self) -> Result<()> {
+		let mut header = IndexHeader {
+			page_size: self.page_size,
+			..IndexHeader::default()
+		};
+		let bytes = self.file.read(0)?;
+		if let Ok(reader) = WalReader::from_bytes(&bytes) {
+			if reader.header().page_size == self.page_size {
+				let frames = reader.frames();
+				if let Some(last) = frames.iter().rposition(|frame| frame.header.is_commit()) {
+					for (i, frame) in frames[..=last].iter().enumerate() {
+						self.index.append(i as u32 + 1, frame.header.page_number)?;
+					}
+					let commit = &frames[last].header;
+					header.max_frame = last as u32 + 1;
+					header.page_count = commit.db_size;
+					header.frame_checksum = (commit.checksum1, commit.checksum2);
+				}
+				header.big_endian = reader.header().magic == WAL_MAGIC_BE;
+				header.salt = (reader.header().salt1, reader.header().salt2);
+			}
+		}
+		self.index.reset(&header)?;
+		let mark = if header.max_frame > 0 { header.max_frame } else { READ_MARK_UNUSED };
+		self.index.set_read_mark(1, mark)
+	}
+
+	/// Lock the read mark of a snapshot ending at `max_frame` for reading,
+	/// moving a mark no one reads from up to it if none matches, and
+	/// returning its slot
+	fn lock_read_mark(&mut self, max_frame: u32) -> Result<Option<usize>> {
+		let marks = self.index.read_marks()?;
+		let best = (1..READ_MARKS)
+			.filter(|&slot| marks[slot] <= max_frame)
+			.max_by_key(|&slot| marks[slot]);
+		if best.map(|slot| marks[slot]) != Some(max_frame) {
+			for slot in 1..READ_MARKS {
+				if self.index.lock(IndexLock::Read(slot), true)? {
+					self.index.set_read_mark(slot, max_frame)?;
+					// Turning the exclusive lock shared keeps the mark
+					self.index.lock(IndexLock::Read(slot), false)?;
+					return Ok(Some(slot));
+				}
+			}
+		}
+		let Some(slot) = best else {
+			return Ok(None);
+		};
+		Ok(self.index.lock(IndexLock::Read(slot), false)?.then_some(slot))
+	}
+
+	/// Checkpoint sequence number for a new log header, one more than the
+	/// last log's
+	fn checkpoint_seq(&mut self) -> Result<u32> {
+		let mut bytes = [0u8; WAL_HEADER_SIZE];
+		if self.file.read_at(0, &mut bytes)? < WAL_HEADER_SIZE {
+			return Ok(0);
+		}
+		Ok(WalHeader::from_bytes(&bytes).map_or(0, |header| header.checkpoint_seq.wrapping_add(1)))
+	}
+
+	/// Offset in the log of a frame, counting from 1
+	fn frame_offset(&self, frame: u32) -> u64 {
+		WAL_HEADER_SIZE as u64 + (frame as u64 - 1) * (WAL_FRAME_HEADER_SIZE as u64 + self.page_size as u64)
+	}
+}
+
+/// Path of the database whose log is at `path`; SQLite names the log and
+/// the wal-index after it with `-wal` and `-shm` suffixes
+#[cfg(feature = "std")]
+fn database_path(path: &str) -> &str {
+	path.strip_suffix("-wal").unwrap_or(path)
+}
+
+/// Write checkpointed pages into the database file, then cut it to the size
+/// recorded by the last of the `frames` checkpointed and sync it
+#[cfg(feature = "std")]
+fn backfill(reader: &WalReader, updates: &[(u32, Vec<u8>)], frames: usize, db: &mut dyn File) -> Result<()> {
+	let page_size = reader.header().page_size as u64;
+	for (page_number, data) in updates {
+		db.write(data, (*page_number as u64 - 1) * page_size)?;
+	}
+	if let Some(commit) = reader.frames()[..frames].last() {
+		db.truncate(commit.header.db_size as u64 * page_size)?;
+	}
+	db.sync(FlagSet::from(SynchronizationType::SqliteSyncFull))?;
+	Ok(())
+}
+
+/// Delete a log file if there is one
+#[cfg(feature = "std")]
+fn remove_log(path: &str) -> Result<()> {
+	match std::fs::remove_file(path) {
+		Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+		_ => Ok(()),
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -1085,4 +1633,69 @@ mod tests {
 		assert_eq!(result.checkpointed_frames, 0);
 		assert!(!result.completed);
 	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_wal_file_commit_and_checkpoint() {
+		let db_path = "/tmp/epiloglite_test_wal_file.db";
+		let wal_path = "/tmp/epiloglite_test_wal_file.db-wal";
+		let _ = std::fs::remove_file(db_path);
+		let _ = std::fs::remove_file(wal_path);
+		let mut db = DefaultFile::open(db_path, true, true, true).unwrap();
+		db.write(&vec![1u8; 3 * 1024], 0).unwrap();
+
+		let mut wal = WalFile::open(wal_path, 1024).unwrap();
+		wal.commit(vec![(2, vec![2u8; 1024])], 3).unwrap();
+		wal.commit(vec![(2, vec![3u8; 1024]), (1, vec![4u8; 1024])], 2).unwrap();
+		assert_eq!(wal.frame_count(), 3);
+		assert_eq!(wal.page_count(), Some(2));
+		let mut page = vec![0u8; 1024];
+		assert!(wal.read_page(2, &mut page).unwrap());
+		assert_eq!(page, vec![3u8; 1024]);
+		assert!(!wal.read_page(3, &mut page).unwrap());
+
+		// The file holds a valid log of both commits
+		let reader = WalReader::from_bytes(&std::fs::read(wal_path).unwrap()).unwrap();
+		assert_eq!(reader.frame_count(), 3);
+
+		let result = wal.checkpoint(&mut db, CheckpointMode::Passive).unwrap();
+		assert_eq!((result.wal_frames, result.checkpointed_frames), (3, 3));
+		assert_eq!(db.read(0).unwrap(), [vec![4u8; 1024], vec![3u8; 1024]].concat());
+
+		// The log keeps its frames until the next commit starts it over
+		assert_eq!(wal.checkpoint(&mut db, CheckpointMode::Full).unwrap().wal_frames, 3);
+		wal.commit(vec![(1, vec![5u8; 1024])], 2).unwrap();
+		assert_eq!(wal.frame_count(), 1);
+		assert!(!wal.read_page(2, &mut page).unwrap());
+
+		let result = wal.checkpoint(&mut db, CheckpointMode::Truncate).unwrap();
+		assert_eq!((result.wal_frames, result.checkpointed_frames), (0, 0));
+		assert_eq!(std::fs::metadata(wal_path).unwrap().len(), 0);
+		assert_eq!(db.read(0).unwrap(), [vec![5u8; 1024], vec![3u8; 1024]].concat());
+		wal.close(&mut db).unwrap();
+		assert!(std::fs::metadata(wal_path).is_err());
+	}
+
+	#[test]
+	fn test_wal_file_recover_discards_torn_commit() {
+		let db_path = "/tmp/epiloglite_test_wal_recover.db";
+		let wal_path = "/tmp/epiloglite_test_wal_recover.db-wal";
+		let _ = std::fs::remove_file(db_path);
+		let mut db = DefaultFile::open(db_path, true, true, true).unwrap();
+		db.write(&vec![1u8; 2 * 1024], 0).unwrap();
+		assert!(WalFile::recover(wal_path, &mut db).unwrap().is_none());
+
+		let mut wal = WalFile::open(wal_path, 1024).unwrap();
+		wal.commit(vec![(2, vec![2u8; 1024])], 2).unwrap();
+		wal.commit(vec![(1, vec![3u8; 1024]), (3, vec![3u8; 1024])], 3).unwrap();
+		drop(wal);
+		// The crash cut the second commit's last frame short
+		let bytes = std::fs::read(wal_path).unwrap();
+		std::fs::write(wal_path, &bytes[..bytes.len() - 100]).unwrap();
+
+		let result = WalFile::recover(wal_path, &mut db).unwrap().unwrap();
+		assert_eq!((result.wal_frames, result.checkpointed_frames), (2, 1));
+		assert_eq!(db.read(0).unwrap(), [vec![1u8; 1024], vec![2u8; 1024]].concat());
+		assert!(std::fs::metadata(wal_path).is_err());
+	}
 }
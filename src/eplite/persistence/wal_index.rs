@@ -0,0 +1,469 @@
+/// Wal-index - the `-shm` file that every connection to a database in WAL
+/// mode shares
+///
+/// The file has SQLite's layout, so SQLite connections can share it too: two
+/// copies of a header describing the last commit, the checkpoint state with
+/// each reader's mark, then hash tables from page numbers to the frames that
+/// hold them. Connections coordinate through advisory locks on its bytes.
+
+use crate::eplite::error::{Error, Result};
+use crate::eplite::os::file::{DefaultFile, RangeLock};
+use crate::eplite::persistence::wal::compute_checksum;
+use crate::eplite::traits::file::File;
+
+/// Version number the header records
+const INDEX_VERSION: u32 = 3007000;
+
+/// Bytes in each copy of the header
+const HEADER_SIZE: usize = 48;
+
+/// Bytes of both headers and the checkpoint state, ahead of the first hash
+/// table's page numbers
+const INDEX_HEADER_SIZE: usize = 136;
+
+/// Offset of the checkpoint state: frames copied into the database file,
+/// then the read marks
+const BACKFILL_OFFSET: u64 = 96;
+const READ_MARK_OFFSET: u64 = 100;
+
+/// Number of read marks; the first is for readers that ignore the log
+pub const READ_MARKS: usize = 5;
+
+/// Read mark of a slot no reader has claimed
+pub const READ_MARK_UNUSED: u32 = 0xffff_ffff;
+
+/// Bytes in each block of the index, which indexes `BLOCK_FRAMES` frames
+const BLOCK_SIZE: u64 = 32768;
+const BLOCK_FRAMES: u32 = 4096;
+
+/// The first block also holds the headers, so it indexes fewer frames
+const FIRST_BLOCK_FRAMES: u32 = BLOCK_FRAMES - (INDEX_HEADER_SIZE / 4) as u32;
+
+/// Slots in each block's hash table
+const HASH_SLOTS: usize = 8192;
+
+/// Offset of the first lock byte
+const LOCK_OFFSET: u64 = 120;
+
+/// Byte every connection holds a shared lock on, so the first to open the
+/// index can tell that no other connection uses it
+const DMS_OFFSET: u64 = 128;
+
+/// Locks on bytes of the index, numbered as in SQLite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexLock {
+	/// Held exclusively by the one connection appending to the log
+	Write,
+	/// Held exclusively while copying frames into the database file
+	Checkpoint,
+	/// Held shared by a reader on its read mark's slot
+	Read(usize),
+}
+
+impl IndexLock {
+	fn offset(self) -> u64 {
+		LOCK_OFFSET
+			+ match self {
+				IndexLock::Write => 0,
+				IndexLock::Checkpoint => 1,
+				IndexLock::Read(slot) => 3 + slot as u64,
+			}
+	}
+}
+
+/// What the index records about the last commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IndexHeader {
+	/// Incremented by every commit
+	pub change: u32,
+	/// Whether the log's checksums read big-endian words
+	pub big_endian: bool,
+	pub page_size: u32,
+	/// Last committed frame, or 0 if the log holds no commits
+	pub max_frame: u32,
+	/// Size of the database in pages after that commit
+	pub page_count: u32,
+	/// Running checksum after the last committed frame
+	pub frame_checksum: (u32, u32),
+	/// Salts of the log header
+	pub salt: (u32, u32),
+}
+
+impl IndexHeader {
+	/// Serialize the header in native byte order, as SQLite keeps it
+	fn to_bytes(self) -> [u8; HEADER_SIZE] {
+		let mut bytes = [0u8; HEADER_SIZE];
+		bytes[0..4].copy_from_slice(&INDEX_VERSION.to_ne_bytes());
+		bytes[8..12].copy_from_slice(&self.change.to_ne_bytes());
+		bytes[12] = 1;
+		bytes[13] = self.big_endian as u8;
+		// 65536 does not fit, so it is stored as 1
+		let page_size = (self.page_size & 0xff00) | (self.page_size >> 16);
+		bytes[14..16].copy_from_slice(&(page_size as u16).to_ne_bytes());
+		bytes[16..20].copy_from_slice(&self.max_frame.to_ne_bytes());
+		bytes[20..24].copy_from_slice(&self.page_count.to_ne_bytes());
+		bytes[24..28].copy_from_slice(&self.frame_checksum.0.to_ne_bytes());
+		bytes[28..32].copy_from_slice(&self.frame_checksum.1.to_ne_bytes());
+		// The salts are copied from the log header as they are stored there
+		bytes[32..36].copy_from_slice(&self.salt.0.to_be_bytes());
+		bytes[36..40].copy_from_slice(&self.salt.1.to_be_bytes());
+		let (s0, s1) = compute_checksum(&bytes[..40], 0, 0, cfg!(target_endian = "big"));
+		bytes[40..44].copy_from_slice(&s0.to_ne_bytes());
+		bytes[44..48].copy_from_slice(&s1.to_ne_bytes());
+		bytes
+	}
+
+	/// Deserialize a header, or None if it was never written or fails its
+	/// checksum
+	fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		let word = |offset: usize| u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap());
+		if bytes[12] == 0 || word(0) != INDEX_VERSION {
+			return None;
+		}
+		if compute_checksum(&bytes[..40], 0, 0, cfg!(target_endian = "big")) != (word(40), word(44)) {
+			return None;
+		}
+		let page_size = u16::from_ne_bytes([bytes[14], bytes[15]]) as u32;
+		Some(IndexHeader {
+			change: word(8),
+			big_endian: bytes[13] != 0,
+			page_size: (page_size & 0xfe00) + ((page_size & 1) << 16),
+			max_frame: word(16),
+			page_count: word(20),
+			frame_checksum: (word(24), word(28)),
+			salt: (
+				u32::from_be_bytes(bytes[32..36].try_into().unwrap()),
+				u32::from_be_bytes(bytes[36..40].try_into().unwrap()),
+			),
+		})
+	}
+}
+
+/// The `-shm` file of one database
+#[derive(Debug)]
+pub struct WalIndex {
+	file: DefaultFile,
+}
+
+impl WalIndex {
+	/// Open the index at `path`, returning it with whether no other
+	/// connection uses it
+	///
+	/// The first connection finds the index emptied and holds it alone until
+	/// it calls `share`, so it can rebuild the index from the log first.
+	pub fn open(path: &str) -> Result<(Self, bool)> {
+		loop {
+			let mut file = DefaultFile::open(path, true, true, true)?;
+			if file.lock_range(DMS_OFFSET, 1, RangeLock::Exclusive, false)? {
+				file.truncate(0)?;
+				file.write(&vec![0; BLOCK_SIZE as usize], 0)?;
+				return Ok((WalIndex { file }, true));
+			}
+			// Wait for a first connection still building the index. The
+			// last connection empties it as it closes, and then it must be
+			// built again.
+			file.lock_range(DMS_OFFSET, 1, RangeLock::Shared, true)?;
+			if file.file_size()? > 0 {
+				return Ok((WalIndex { file }, false));
+			}
+		}
+	}
+
+	/// Let other connections use the index once it is built
+	pub fn share(&mut self) -> Result<()> {
+		self.file.lock_range(DMS_OFFSET, 1, RangeLock::Shared, false)?;
+		Ok(())
+	}
+
+	/// Whether no other connection uses the index; if so, none can start
+	/// to until the index is dropped
+	pub fn claim(&mut self) -> Result<bool> {
+		Ok(self.file.lock_range(DMS_OFFSET, 1, RangeLock::Exclusive, false)?)
+	}
+
+	/// Empty the index once the log is gone, so connections waiting to
+	/// open it build it again; this connection must have claimed it
+	pub fn empty(&mut self) -> Result<()> {
+		self.file.truncate(0)?;
+		Ok(())
+	}
+
+	/// Take a lock without waiting, returning false if another connection
+	/// holds a conflicting one
+	pub fn lock(&mut self, lock: IndexLock, exclusive: bool) -> Result<bool> {
+		let kind = if exclusive { RangeLock::Exclusive } else { RangeLock::Shared };
+		Ok(self.file.lock_range(lock.offset(), 1, kind, false)?)
+	}
+
+	pub fn unlock(&mut self, lock: IndexLock) -> Result<()> {
+		self.file.lock_range(lock.offset(), 1, RangeLock::Unlocked, false)?;
+		Ok(())
+	}
+
+	/// Read the header, or None if its two copies differ, as while another
+	/// connection writes it, or it has not been written
+	pub fn read_header(&mut self) -> Result<Option<IndexHeader>> {
+		let mut bytes = [0u8; 2 * HEADER_SIZE];
+		if self.file.read_at(0, &mut bytes)? < bytes.len() {
+			return Ok(None);
+		}
+		if bytes[..HEADER_SIZE] != bytes[HEADER_SIZE..] {
+			return Ok(None);
+		}
+		Ok(IndexHeader::from_bytes(&bytes[..HEADER_SIZE]))
+	}
+
+	/// Publish a new header; the second copy is written first, so a reader
+	/// that finds both copies equal never sees part of a header
+	pub fn write_header(&mut self, header: &IndexHeader) -> Result<()> {
+		let bytes = header.to_bytes().to_vec();
+		self.file.write(&bytes, HEADER_SIZE as u64)?;
+		self.file.write(&bytes, 0)?;
+		Ok(())
+	}
+
+	/// Number of frames already copied into the database file
+	pub fn backfill(&mut self) -> Result<u32> {
+		self.read_u32(BACKFILL_OFFSET)
+	}
+
+	pub fn set_backfill(&mut self, frames: u32) -> Result<()> {
+		self.write_u32(BACKFILL_OFFSET, frames)
+	}
+
+	/// The last frame each slot's readers may read, which checkpoints must
+	/// not copy past while they read
+	pub fn read_marks(&mut self) -> Result<[u32; READ_MARKS]> {
+		let mut marks = [0; READ_MARKS];
+		for (slot, mark) in marks.iter_mut().enumerate() {
+			*mark = self.read_u32(READ_MARK_OFFSET + slot as u64 * 4)?;
+		}
+		Ok(marks)
+	}
+
+	pub fn set_read_mark(&mut self, slot: usize, frame: u32) -> Result<()> {
+		self.write_u32(READ_MARK_OFFSET + slot as u64 * 4, frame)
+	}
+
+	/// Start the index over for a log with no frames
+	pub fn reset(&mut self, header: &IndexHeader) -> Result<()> {
+		self.write_header(header)?;
+		self.set_backfill(0)?;
+		for slot in 0..READ_MARKS {
+			self.set_read_mark(slot, if slot <= 1 { 0 } else { READ_MARK_UNUSED })?;
+		}
+		Ok(())
+	}
+
+	/// Record that `frame` holds `page`; frames are appended in order after
+	/// the last committed frame
+	pub fn append(&mut self, frame: u32, page: u32) -> Result<()> {
+		let (block, first) = block_of(frame);
+		let entry = frame - first;
+		if entry == 1 {
+			let start = entry_offset(block, 1);
+			self.file.write(&vec![0; ((block + 1) as u64 * BLOCK_SIZE - start) as usize], start)?;
+		} else if self.read_u32(entry_offset(block, entry))? != 0 {
+			// A writer that failed before committing left entries behind
+			self.discard_after(block, entry - 1)?;
+		}
+		self.write_u32(entry_offset(block, entry), page)?;
+
+		let slots = self.read_slots(block)?;
+		let mut slot = hash(page);
+		for _ in 0..HASH_SLOTS {
+			if slots[slot] == 0 {
+				self.file.write(&(entry as u16).to_ne_bytes(), slot_offset(block, slot))?;
+				return Ok(());
+			}
+			slot = (slot + 1) % HASH_SLOTS;
+		}
+		Err(Error::Corrupt("Wal-index hash table is full".to_string()))
+	}
+
+	/// The last frame up to `max_frame` that holds `page`
+	pub fn find(&mut self, page: u32, max_frame: u32) -> Result<Option<u32>> {
+		if max_frame == 0 {
+			return Ok(None);
+		}
+		for block in (0..=block_of(max_frame).0).rev() {
+			let mut data = vec![0u8; BLOCK_SIZE as usize];
+			self.file.read_at(block as u64 * BLOCK_SIZE, &mut data)?;
+			let first = block_first_frame(block);
+			let base = entry_offset(block, 1) - block as u64 * BLOCK_SIZE;
+			let slots = slots_from(&data[BLOCK_FRAMES as usize * 4..]);
+
+			let mut found = None;
+			let mut slot = hash(page);
+			for _ in 0..HASH_SLOTS {
+				let entry = slots[slot] as u32;
+				if entry == 0 {
+					break;
+				}
+				let offset = base as usize + (entry as usize - 1) * 4;
+				let entry_page = u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+				let frame = first + entry;
+				if entry_page == page && frame <= max_frame {
+					found = found.max(Some(frame));
+				}
+				slot = (slot + 1) % HASH_SLOTS;
+			}
+			if found.is_some() {
+				return Ok(found);
+			}
+		}
+		Ok(None)
+	}
+
+	/// Remove a block's entries after its first `keep` frames
+	fn discard_after(&mut self, block: u32, keep: u32) -> Result<()> {
+		let mut slots = self.read_slots(block)?;
+		for slot in slots.iter_mut().filter(|slot| **slot as u32 > keep) {
+			*slot = 0;
+		}
+		let bytes: Vec<u8> = slots.iter().flat_map(|slot| slot.to_ne_bytes()).collect();
+		self.file.write(&bytes, slot_offset(block, 0))?;
+		let start = entry_offset(block, keep + 1);
+		self.file.write(&vec![0; (slot_offset(block, 0) - start) as usize], start)?;
+		Ok(())
+	}
+
+	fn read_slots(&mut self, block: u32) -> Result<Vec<u16>> {
+		let mut data = vec![0u8; HASH_SLOTS * 2];
+		self.file.read_at(slot_offset(block, 0), &mut data)?;
+		Ok(slots_from(&data))
+	}
+
+	fn read_u32(&mut self, offset: u64) -> Result<u32> {
+		let mut bytes = [0u8; 4];
+		self.file.read_at(offset, &mut bytes)?;
+		Ok(u32::from_ne_bytes(bytes))
+	}
+
+	fn write_u32(&mut self, offset: u64, value: u32) -> Result<()> {
+		self.file.write(&value.to_ne_bytes(), offset)?;
+		Ok(())
+	}
+}
+
+/// The block indexing a frame, with the frame before the block's first
+fn block_of(frame: u32) -> (u32, u32) {
+	if frame <= FIRST_BLOCK_FRAMES {
+		return (0, 0);
+	}
+	let block = (frame - FIRST_BLOCK_FRAMES - 1) / BLOCK_FRAMES + 1;
+	(block, block_first_frame(block))
+}
+
+fn block_first_frame(block: u32) -> u32 {
+	match block {
+		0 => 0,
+		_ => FIRST_BLOCK_FRAMES + (block - 1) * BLOCK_FRAMES,
+	}
+}
+
+/// Offset of a block's page number for its `entry`th frame, counting from 1
+fn entry_offset(block: u32, entry: u32) -> u64 {
+	let start = if block == 0 { INDEX_HEADER_SIZE as u64 } else { 0 };
+	block as u64 * BLOCK_SIZE + start + (entry as u64 - 1) * 4
+}
+
+/// Offset of a slot of a block's hash table, which follows its page numbers
+fn slot_offset(block: u32, slot: usize) -> u64 {
+	block as u64 * BLOCK_SIZE + BLOCK_FRAMES as u64 * 4 + slot as u64 * 2
+}
+
+fn slots_from(bytes: &[u8]) -> Vec<u16> {
+	bytes[..HASH_SLOTS * 2]
+		.chunks_exact(2)
+		.map(|slot| u16::from_ne_bytes([slot[0], slot[1]]))
+		.collect()
+}
+
+/// First hash table slot to try for a page, as in SQLite
+fn hash(page: u32) -> usize {
+	(page.wrapping_mul(383) as usize) & (HASH_SLOTS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn fresh_path(name: &str) -> String {
+		let path = format!("/tmp/epiloglite_test_wal_index_{}.db-shm", name);
+		let _ = std::fs::remove_file(&path);
+		path
+	}
+
+	fn fresh_index(name: &str) -> WalIndex {
+		let (index, alone) = WalIndex::open(&fresh_path(name)).unwrap();
+		assert!(alone);
+		index
+	}
+
+	#[test]
+	fn test_header_round_trip() {
+		let mut index = fresh_index("header");
+		assert_eq!(index.read_header().unwrap(), None);
+
+		let header = IndexHeader {
+			change: 7,
+			big_endian: true,
+			page_size: 65536,
+			max_frame: 12,
+			page_count: 40,
+			frame_checksum: (1, 2),
+			salt: (3, 4),
+		};
+		index.write_header(&header).unwrap();
+		assert_eq!(index.read_header().unwrap(), Some(header));
+
+		// A header caught half written is not trusted
+		index.file.write(&[9], 16).unwrap();
+		assert_eq!(index.read_header().unwrap(), None);
+	}
+
+	#[test]
+	fn test_find_latest_frame() {
+		let mut index = fresh_index("find");
+		let frames = FIRST_BLOCK_FRAMES + 10;
+		for frame in 1..=frames {
+			index.append(frame, frame % 7 + 1).unwrap();
+		}
+		assert_eq!(index.find(3, frames).unwrap(), Some(FIRST_BLOCK_FRAMES + 7));
+		assert_eq!(index.find(3, 20).unwrap(), Some(16));
+		assert_eq!(index.find(3, 1).unwrap(), None);
+		assert_eq!(index.find(9, frames).unwrap(), None);
+
+		// Appending over frames that never committed replaces them
+		index.append(FIRST_BLOCK_FRAMES + 5, 100).unwrap();
+		assert_eq!(index.find(3, FIRST_BLOCK_FRAMES + 5).unwrap(), Some(FIRST_BLOCK_FRAMES));
+		assert_eq!(index.find(100, frames).unwrap(), Some(FIRST_BLOCK_FRAMES + 5));
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_connections_share_the_index() {
+		let path = fresh_path("share");
+		let (mut first, _) = WalIndex::open(&path).unwrap();
+		first.share().unwrap();
+		let (mut second, alone) = WalIndex::open(&path).unwrap();
+		assert!(!alone);
+		assert!(!first.claim().unwrap());
+
+		assert!(first.lock(IndexLock::Write, true).unwrap());
+		assert!(!second.lock(IndexLock::Write, true).unwrap());
+		assert!(second.lock(IndexLock::Read(1), false).unwrap());
+		assert!(first.lock(IndexLock::Read(1), false).unwrap());
+		assert!(!first.lock(IndexLock::Read(1), true).unwrap());
+
+		drop(second);
+		assert!(first.claim().unwrap());
+
+		// A connection waiting to open the index after the last one closed
+		// builds it again
+		first.empty().unwrap();
+		drop(first);
+		assert!(WalIndex::open(&path).unwrap().1);
+	}
+}
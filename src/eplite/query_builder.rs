@@ -109,12 +109,12 @@ impl SelectBuilder {
 			sql.push_str(&format!(" ORDER BY {}", self.order_by.join(", ")));
 		}
 
-		if let Some(limit) = self.limit {
-			sql.push_str(&format!(" LIMIT {}", limit));
-		}
-
-		if let Some(offset) = self.offset {
-			sql.push_str(&format!(" OFFSET {}", offset));
+		// OFFSET needs a LIMIT before it; -1 is no limit
+		match (self.limit, self.offset) {
+			(Some(limit), Some(offset)) => sql.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset)),
+			(Some(limit), None) => sql.push_str(&format!(" LIMIT {}", limit)),
+			(None, Some(offset)) => sql.push_str(&format!(" LIMIT -1 OFFSET {}", offset)),
+			(None, None) => {}
 		}
 
 		Ok(sql)
@@ -472,6 +472,11 @@ mod tests {
 			.build()
 			.unwrap();
 		assert_eq!(sql, "SELECT * FROM users ORDER BY name LIMIT 10");
+
+		let sql = SelectBuilder::new().from("users").offset(5).build().unwrap();
+		assert_eq!(sql, "SELECT * FROM users LIMIT -1 OFFSET 5");
+		let sql = SelectBuilder::new().from("users").limit(2).offset(5).build().unwrap();
+		assert_eq!(sql, "SELECT * FROM users LIMIT 2 OFFSET 5");
 	}
 
 	#[test]
@@ -0,0 +1,143 @@
+/// Prepared statements, parsed once and run as many times as needed with
+/// values bound to their parameters
+
+use crate::eplite::command::parser;
+use crate::eplite::command::processor::{ExecutionResult, Processor};
+use crate::eplite::error::{Error, Result};
+use crate::eplite::storage::Row;
+use crate::eplite::types::ValueType;
+
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::{String, ToString}, vec, vec::Vec};
+
+#[cfg(feature = "std")]
+use std::vec::IntoIter;
+#[cfg(not(feature = "std"))]
+use alloc::vec::IntoIter;
+
+/// A parameter of a prepared statement, given by its index from 1 or by
+/// its name with the `:`, `@`, `$` or `?` before it
+pub trait ParameterIndex {
+	/// Index of the parameter among the names of a statement's parameters
+	fn parameter_index(&self, names: &[Option<String>]) -> Result<usize>;
+}
+
+impl ParameterIndex for usize {
+	fn parameter_index(&self, names: &[Option<String>]) -> Result<usize> {
+		if *self == 0 || *self > names.len() {
+			return Err(Error::InvalidOperation(format!(
+				"Parameter index {} out of range for a statement with {} parameters",
+				self,
+				names.len()
+			)));
+		}
+		Ok(*self)
+	}
+}
+
+impl ParameterIndex for &str {
+	fn parameter_index(&self, names: &[Option<String>]) -> Result<usize> {
+		names
+			.iter()
+			.position(|name| name.as_deref() == Some(*self))
+			.map(|i| i + 1)
+			.ok_or_else(|| Error::NotFound(format!("Parameter '{}' not found", self)))
+	}
+}
+
+/// A statement prepared by `Database::prepare`
+///
+/// Values bound to its parameters stay bound when the statement is reset
+/// and run again, until they are bound anew or cleared. Parameters with no
+/// value bound are NULL. The statement borrows its database, which is free
+/// again once the statement is dropped.
+#[derive(Debug)]
+pub struct Statement<'a> {
+	processor: &'a mut Processor,
+	statement: parser::Statement,
+	/// Name of each parameter, or None for one written `?`
+	names: Vec<Option<String>>,
+	values: Vec<ValueType>,
+	/// Rows still to be stepped through, once the statement has run
+	rows: Option<IntoIter<Row>>,
+}
+
+impl<'a> Statement<'a> {
+	pub(crate) fn new(processor: &'a mut Processor, statement: parser::Statement, names: Vec<Option<String>>) -> Self {
+		let values = vec![ValueType::Null; names.len()];
+		Statement {
+			processor,
+			statement,
+			names,
+			values,
+			rows: None,
+		}
+	}
+
+	/// Number of parameters, which is the largest index among them
+	pub fn parameter_count(&self) -> usize {
+		self.names.len()
+	}
+
+	/// Name of the parameter at an index from 1, or None if it was written
+	/// `?` or there is no such parameter
+	pub fn parameter_name(&self, index: usize) -> Option<&str> {
+		self.names.get(index.checked_sub(1)?)?.as_deref()
+	}
+
+	/// Bind a value to a parameter, given by index or name, for the next
+	/// runs of the statement
+	///
+	/// A statement that has started stepping must be reset first.
+	pub fn bind<P: ParameterIndex>(&mut self, parameter: P, value: ValueType) -> Result<()> {
+		if self.rows.is_some() {
+			return Err(Error::InvalidOperation(
+				"cannot bind a statement that is running; reset it first".to_string(),
+			));
+		}
+		let index = parameter.parameter_index(&self.names)?;
+		self.values[index - 1] = value;
+		Ok(())
+	}
+
+	/// Set every parameter back to NULL
+	pub fn clear_bindings(&mut self) {
+		self.values.iter_mut().for_each(|value| *value = ValueType::Null);
+	}
+
+	/// Run the statement with the values bound now if it has not run since
+	/// it was prepared or reset, and return its next row, or None once it
+	/// has no more
+	///
+	/// A statement other than a SELECT returns no rows; it is done after
+	/// the first step.
+	pub fn step(&mut self) -> Result<Option<Row>> {
+		if self.rows.is_none() {
+			let rows = match self.run()? {
+				ExecutionResult::Select { rows, .. } => rows,
+				_ => Vec::new(),
+			};
+			self.rows = Some(rows.into_iter());
+		}
+		Ok(self.rows.as_mut().and_then(Iterator::next))
+	}
+
+	/// Run the statement with the values bound now and return its whole
+	/// result, as `Database::execute` does, leaving it reset
+	pub fn execute(&mut self) -> Result<ExecutionResult> {
+		self.reset();
+		self.run()
+	}
+
+	/// Drop any rows not yet stepped through so the next step runs the
+	/// statement again; bound values are kept
+	pub fn reset(&mut self) {
+		self.rows = None;
+	}
+
+	fn run(&mut self) -> Result<ExecutionResult> {
+		let mut statement = self.statement.clone();
+		statement.bind(&self.values);
+		self.processor.execute_parsed(statement)
+	}
+}
@@ -1,10 +1,22 @@
-/// In-memory table storage with disk persistence support
+/// Table storage in b-trees with disk persistence support
 
-use crate::eplite::command::parser::{ColumnDefinition, CreateTableStatement};
+use crate::eplite::command::expression::{Expr, Scope};
+use crate::eplite::command::parser::{ColumnDefinition, CreateTableStatement, TransactionMode};
 use crate::eplite::error::{Error, Result};
-use crate::eplite::persistence::pager::Pager;
+use crate::eplite::persistence::btree::{BTree, BTreeKind};
+use crate::eplite::persistence::cache::CacheStats;
+use crate::eplite::constants::{AutoVacuum, JournalMode, TextEncoding, SQLITE_VERSION_NUMBER};
+use crate::eplite::persistence::header::{DatabaseHeader, FileFormat, DATABASE_HEADER_SIZE};
+use crate::eplite::persistence::pager::{PageRef, Pager};
+use crate::eplite::persistence::record;
+use crate::eplite::persistence::wal::{CheckpointMode, CheckpointResult};
+use crate::eplite::types::ValueType;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "std")]
+use crate::eplite::os::file::DefaultFile;
+#[cfg(feature = "std")]
+use crate::eplite::traits::file::File;
 #[cfg(feature = "std")]
 use std::collections::HashMap;
 
@@ -13,158 +25,496 @@ use alloc::{
 	collections::BTreeMap as HashMap,
 	format,
 	string::{String, ToString},
+	vec,
 	vec::Vec,
 };
 
-/// WHERE clause evaluator
-mod where_clause {
+/// Page chains - byte streams spread over a linked list of pages
+mod chain {
 	use super::*;
 
-	/// Comparison operators
-	#[derive(Debug, Clone, PartialEq)]
-	pub enum CompOp {
-		Equal,
-		NotEqual,
-		LessThan,
-		GreaterThan,
-		LessOrEqual,
-		GreaterOrEqual,
-		Like,
-	}
-
-	/// WHERE clause condition
-	#[derive(Debug, Clone)]
-	pub struct Condition {
-		pub column: String,
-		pub operator: CompOp,
-		pub value: String,
-	}
-
-	impl Condition {
-		/// Parse a simple WHERE clause (e.g., "id = 1", "name = 'Alice'", "age > 25")
-		pub fn parse(clause: &str) -> Result<Self> {
-			let clause = clause.trim();
-
-			// Try each operator in order of length (to match >= before >)
-			let operators = [
-				(">=", CompOp::GreaterOrEqual),
-				("<=", CompOp::LessOrEqual),
-				("<>", CompOp::NotEqual),
-				("!=", CompOp::NotEqual),
-				("=", CompOp::Equal),
-				("<", CompOp::LessThan),
-				(">", CompOp::GreaterThan),
-				(" LIKE ", CompOp::Like),
-			];
-
-			for (op_str, op) in &operators {
-				if let Some(pos) = clause.find(op_str) {
-					let column = clause[..pos].trim().to_string();
-					let value = clause[pos + op_str.len()..].trim().to_string();
-
-					if column.is_empty() || value.is_empty() {
-						return Err(Error::Syntax(format!("Invalid WHERE clause: {}", clause)));
-					}
+	/// Size of the chain header at the start of each chain page: the next
+	/// page number followed by the number of payload bytes on this page
+	const CHAIN_HEADER_SIZE: usize = 8;
+
+	/// Write `data` as a chain starting at `offset` within `first_page`.
+	///
+	/// Pages already linked from `first_page` are reused in order; further
+	/// pages are allocated as needed, and any left over are freed.
+	pub fn write(pager: &mut Pager, first_page: u32, offset: usize, data: &[u8]) -> Result<()> {
+		let mut page_num = first_page;
+		let mut start = offset;
+		let mut remaining = data;
+
+		loop {
+			let page = pager.get_page_mut(page_num)?;
+			let capacity = page.data.len() - start - CHAIN_HEADER_SIZE;
+			let existing_next = read_u32(&page.data, start);
+			let used = remaining.len().min(capacity);
+			let (chunk, rest) = remaining.split_at(used);
+			remaining = rest;
+
+			let next = if remaining.is_empty() {
+				0
+			} else if existing_next != 0 {
+				existing_next
+			} else {
+				pager.allocate_page()?
+			};
 
-					return Ok(Condition {
-						column,
-						operator: op.clone(),
-						value,
-					});
-				}
+			let page = pager.get_page_mut(page_num)?;
+			page.write(start, &next.to_be_bytes())?;
+			page.write(start + 4, &(used as u32).to_be_bytes())?;
+			page.write(start + CHAIN_HEADER_SIZE, chunk)?;
+
+			if next == 0 {
+				return free(pager, existing_next);
+			}
+			page_num = next;
+			start = 0;
+		}
+	}
+
+	/// Free the chain pages starting at `page_num`, which begin with their
+	/// next page number
+	fn free(pager: &mut Pager, mut page_num: u32) -> Result<()> {
+		while page_num != 0 {
+			let next = read_u32(&pager.get_page(page_num)?.data, 0);
+			pager.free_page(page_num)?;
+			page_num = next;
+		}
+		Ok(())
+	}
+
+	/// List the pages linked from `first_page` with where their numbers are kept
+	pub fn page_refs(pager: &mut Pager, first_page: u32, offset: usize, refs: &mut Vec<(u32, PageRef)>) -> Result<()> {
+		let (mut page_num, mut start) = (first_page, offset);
+		loop {
+			let next = read_u32(&pager.get_page(page_num)?.data, start);
+			if next == 0 {
+				return Ok(());
+			}
+			if refs.len() >= pager.page_count() as usize {
+				return Err(Error::Corrupt(format!(
+					"Page chain starting at page {} contains a cycle",
+					first_page
+				)));
+			}
+			refs.push((next, PageRef::Pointer(page_num, start)));
+			page_num = next;
+			start = 0;
+		}
+	}
+
+	/// Read the chain starting at `offset` within `first_page`
+	pub fn read(pager: &mut Pager, first_page: u32, offset: usize) -> Result<Vec<u8>> {
+		let mut data = Vec::new();
+		let mut page_num = first_page;
+		let mut start = offset;
+		let mut visited = 0u32;
+
+		while page_num != 0 {
+			visited += 1;
+			if visited > pager.page_count() {
+				return Err(Error::Corrupt(format!(
+					"Page chain starting at page {} contains a cycle",
+					first_page
+				)));
+			}
+
+			let page = pager.get_page(page_num)?;
+			let next = read_u32(&page.data, start);
+			let used = read_u32(&page.data, start + 4) as usize;
+			let payload_start = start + CHAIN_HEADER_SIZE;
+			if payload_start + used > page.data.len() {
+				return Err(Error::Corrupt(format!(
+					"Page {} claims {} bytes of chain data",
+					page_num, used
+				)));
 			}
+			data.extend_from_slice(&page.data[payload_start..payload_start + used]);
 
-			Err(Error::Syntax(format!("Invalid WHERE clause: {}", clause)))
+			page_num = next;
+			start = 0;
 		}
 
-		/// Evaluate condition against a row
-		pub fn evaluate(&self, row: &[String], columns: &[ColumnDefinition]) -> bool {
-			// Find column index
-			let col_idx = match columns.iter().position(|c| c.name == self.column) {
-				Some(idx) => idx,
-				None => return false,
+		Ok(data)
+	}
+
+	fn read_u32(data: &[u8], offset: usize) -> u32 {
+		u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+	}
+}
+
+/// Table definitions recovered from the SQL text kept in a SQLite 3 schema
+mod schema {
+	use super::*;
+	use crate::eplite::command::parser::Parser;
+	use crate::eplite::types::column::ColumnType;
+
+	/// Keywords that end a column's declared type
+	const COLUMN_CONSTRAINTS: [&str; 11] = [
+		"CONSTRAINT", "PRIMARY", "NOT", "NULL", "UNIQUE", "CHECK", "DEFAULT", "COLLATE",
+		"REFERENCES", "GENERATED", "AS",
+	];
+
+	/// Keywords that start a table constraint rather than a column
+	const TABLE_CONSTRAINTS: [&str; 5] = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"];
+
+	/// Columns of a table as declared by its CREATE TABLE statement
+	#[derive(Debug)]
+	pub struct TableDefinition {
+		pub columns: Vec<ColumnDefinition>,
+		/// Value of each column's DEFAULT clause
+		pub defaults: Vec<ValueType>,
+		/// Column declared INTEGER PRIMARY KEY, which stores the rowid
+		pub rowid_alias: Option<usize>,
+		/// Columns of each PRIMARY KEY or UNIQUE constraint, in the order
+		/// SQLite numbers the automatic indexes that enforce them
+		pub unique_keys: Vec<Vec<usize>>,
+		pub without_rowid: bool,
+	}
+
+	/// Parse a CREATE TABLE statement as written by SQLite
+	pub fn parse_create_table(sql: &str) -> Result<TableDefinition> {
+		let invalid = || Error::Corrupt(format!("Unreadable table definition: {}", sql));
+		let open = sql.find('(').ok_or_else(invalid)?;
+		let close = closing_paren(sql, open).ok_or_else(invalid)?;
+		let tail = sql[close + 1..].to_uppercase();
+		let without_rowid = tail
+			.split_whitespace()
+			.collect::<Vec<_>>()
+			.windows(2)
+			.any(|w| w == ["WITHOUT", "ROWID"]);
+
+		let mut columns = Vec::new();
+		let mut defaults = Vec::new();
+		let mut declared_types = Vec::new();
+		// Column names of each key, and whether it is the primary key
+		let mut keys: Vec<(Vec<String>, bool)> = Vec::new();
+		for definition in split_top_level(&sql[open + 1..close]) {
+			let tokens = words(definition);
+			let Some(first) = tokens.first() else {
+				continue;
 			};
 
-			if col_idx >= row.len() {
-				return false;
+			if TABLE_CONSTRAINTS.contains(&first.to_uppercase().as_str()) {
+				let primary = tokens.iter().any(|w| w.eq_ignore_ascii_case("PRIMARY"));
+				let unique = tokens.iter().any(|w| w.eq_ignore_ascii_case("UNIQUE"));
+				if let Some(list) = tokens.iter().find(|w| w.starts_with('(')).filter(|_| primary || unique) {
+					let names = split_top_level(&list[1..list.len() - 1])
+						.into_iter()
+						.filter_map(|column| words(column).first().map(|name| unquote(name)))
+						.collect();
+					keys.push((names, primary));
+				}
+				continue;
+			}
+
+			let rest = &tokens[1..];
+			let type_len = rest
+				.iter()
+				.position(|w| COLUMN_CONSTRAINTS.contains(&w.to_uppercase().as_str()))
+				.unwrap_or(rest.len());
+			let declared = rest[..type_len].join(" ");
+			let constraint_text = rest[type_len..].join(" ").to_uppercase();
+
+			let name = unquote(first);
+			let mut constraints = Vec::new();
+			for (constraint, primary) in [("PRIMARY KEY", true), ("NOT NULL", false), ("UNIQUE", false)] {
+				if constraint_text.contains(constraint) {
+					constraints.push(constraint.to_string());
+					if constraint != "NOT NULL" {
+						keys.push((vec![name.clone()], primary));
+					}
+				}
 			}
 
-			let row_value = &row[col_idx];
-			let compare_value = self.value.trim_matches('\'').trim_matches('"');
+			columns.push(ColumnDefinition {
+				name,
+				data_type: ColumnType::from_declared_type(&declared),
+				constraints,
+			});
+			defaults.push(default_value(&rest[type_len..]));
+			declared_types.push(declared);
+		}
 
-			match self.operator {
-				CompOp::Equal => row_value == compare_value,
-				CompOp::NotEqual => row_value != compare_value,
-				CompOp::LessThan => {
-					// Try numeric comparison first
-					if let (Ok(a), Ok(b)) = (row_value.parse::<f64>(), compare_value.parse::<f64>()) {
-						a < b
-					} else {
-						row_value.as_str() < compare_value
+		let mut rowid_alias = None;
+		let mut unique_keys = Vec::new();
+		for (names, primary) in keys {
+			let key: Option<Vec<usize>> = names
+				.iter()
+				.map(|name| columns.iter().position(|c| c.name.eq_ignore_ascii_case(name)))
+				.collect();
+			let Some(key) = key else {
+				continue;
+			};
+			if primary {
+				for &index in &key {
+					let constraints = &mut columns[index].constraints;
+					if !constraints.iter().any(|c| c == "PRIMARY KEY") {
+						constraints.push("PRIMARY KEY".to_string());
 					}
 				}
-				CompOp::GreaterThan => {
-					if let (Ok(a), Ok(b)) = (row_value.parse::<f64>(), compare_value.parse::<f64>()) {
-						a > b
-					} else {
-						row_value.as_str() > compare_value
+				// An INTEGER PRIMARY KEY is the rowid itself and needs no index
+				if !without_rowid && key.len() == 1 && declared_types[key[0]].eq_ignore_ascii_case("INTEGER") {
+					rowid_alias = Some(key[0]);
+					continue;
+				}
+			}
+			if !unique_keys.contains(&key) {
+				unique_keys.push(key);
+			}
+		}
+
+		Ok(TableDefinition {
+			columns,
+			defaults,
+			rowid_alias,
+			unique_keys,
+			without_rowid,
+		})
+	}
+
+	/// Value of the DEFAULT clause among a column's constraints; NULL if it
+	/// has none, or if its value is not a constant
+	fn default_value(constraints: &[String]) -> ValueType {
+		let Some(at) = constraints.iter().position(|w| w.eq_ignore_ascii_case("DEFAULT")) else {
+			return ValueType::Null;
+		};
+		let mut text = constraints.get(at + 1).cloned().unwrap_or_default();
+		if text == "-" || text == "+" {
+			text.push_str(constraints.get(at + 2).map_or("", String::as_str));
+		}
+		Parser::new()
+			.parse_expression(&text)
+			.and_then(|expr| expr.evaluate(&Scope::default(), &[]))
+			.unwrap_or(ValueType::Null)
+	}
+
+	/// Key column names of a CREATE INDEX statement and whether it is UNIQUE
+	///
+	/// Returns None for indexes whose keys are not plain ascending columns,
+	/// such as partial indexes and indexes on expressions.
+	pub fn parse_create_index(sql: &str) -> Option<(Vec<String>, bool)> {
+		let open = sql.find('(')?;
+		let close = closing_paren(sql, open)?;
+		if !sql[close + 1..].trim().is_empty() {
+			return None;
+		}
+		let unique = words(&sql[..open]).get(1).is_some_and(|w| w.eq_ignore_ascii_case("UNIQUE"));
+
+		let mut columns = Vec::new();
+		for part in split_top_level(&sql[open + 1..close]) {
+			match words(part).as_slice() {
+				[name] => columns.push(unquote(name)),
+				[name, order] if order.eq_ignore_ascii_case("ASC") => columns.push(unquote(name)),
+				_ => return None,
+			}
+		}
+		Some((columns, unique))
+	}
+
+	/// CREATE TABLE statement recorded in the schema for a new table
+	pub fn create_table_sql(name: &str, columns: &[ColumnDefinition]) -> String {
+		let is_primary = |c: &&ColumnDefinition| c.constraints.iter().any(|k| k == "PRIMARY KEY");
+		let primary: Vec<String> = columns.iter().filter(is_primary).map(|c| quote(&c.name)).collect();
+
+		let mut definitions: Vec<String> = columns
+			.iter()
+			.map(|column| {
+				let mut definition = format!("{} {}", quote(&column.name), column.data_type.sql_name());
+				for constraint in &column.constraints {
+					match constraint.as_str() {
+						// A composite key is written as a table constraint
+						"PRIMARY KEY" if primary.len() > 1 => {}
+						"PRIMARY KEY" | "NOT NULL" | "UNIQUE" => {
+							definition.push(' ');
+							definition.push_str(constraint);
+						}
+						_ => {}
 					}
 				}
-				CompOp::LessOrEqual => {
-					if let (Ok(a), Ok(b)) = (row_value.parse::<f64>(), compare_value.parse::<f64>()) {
-						a <= b
-					} else {
-						row_value.as_str() <= compare_value
+				definition
+			})
+			.collect();
+		if primary.len() > 1 {
+			definitions.push(format!("PRIMARY KEY ({})", primary.join(", ")));
+		}
+		format!("CREATE TABLE {} ({})", quote(name), definitions.join(", "))
+	}
+
+	/// Quote an identifier unless it is a plain word
+	fn quote(name: &str) -> String {
+		let plain = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+			&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+		if plain {
+			name.to_string()
+		} else {
+			format!("\"{}\"", name.replace('"', "\"\""))
+		}
+	}
+
+	/// Position of the parenthesis closing the one at `open`
+	fn closing_paren(sql: &str, open: usize) -> Option<usize> {
+		let mut depth = 0;
+		let mut quote = None;
+		for (i, c) in sql.char_indices().skip_while(|&(i, _)| i < open) {
+			match (quote, c) {
+				(Some(q), c) if c == q => quote = None,
+				(Some(_), _) => {}
+				(None, '\'' | '"' | '`') => quote = Some(c),
+				(None, '[') => quote = Some(']'),
+				(None, '(') => depth += 1,
+				(None, ')') => {
+					depth -= 1;
+					if depth == 0 {
+						return Some(i);
 					}
 				}
-				CompOp::GreaterOrEqual => {
-					if let (Ok(a), Ok(b)) = (row_value.parse::<f64>(), compare_value.parse::<f64>()) {
-						a >= b
-					} else {
-						row_value.as_str() >= compare_value
+				_ => {}
+			}
+		}
+		None
+	}
+
+	/// Split on commas that are outside quotes and parentheses
+	fn split_top_level(text: &str) -> Vec<&str> {
+		let mut parts = Vec::new();
+		let mut depth = 0;
+		let mut quote = None;
+		let mut start = 0;
+		for (i, c) in text.char_indices() {
+			match (quote, c) {
+				(Some(q), c) if c == q => quote = None,
+				(Some(_), _) => {}
+				(None, '\'' | '"' | '`') => quote = Some(c),
+				(None, '[') => quote = Some(']'),
+				(None, '(') => depth += 1,
+				(None, ')') => depth -= 1,
+				(None, ',') if depth == 0 => {
+					parts.push(text[start..i].trim());
+					start = i + 1;
+				}
+				_ => {}
+			}
+		}
+		parts.push(text[start..].trim());
+		parts.retain(|p| !p.is_empty());
+		parts
+	}
+
+	/// Split on whitespace outside quotes; a parenthesised group is a word
+	/// of its own
+	fn words(text: &str) -> Vec<String> {
+		let mut words = Vec::new();
+		let mut current = String::new();
+		let mut depth = 0;
+		let mut quote = None;
+		for c in text.chars() {
+			match (quote, c) {
+				(Some(q), c) if c == q => quote = None,
+				(Some(_), _) => {}
+				(None, '\'' | '"' | '`') => quote = Some(c),
+				(None, '[') => quote = Some(']'),
+				(None, '(') => {
+					if depth == 0 && !current.is_empty() {
+						words.push(core::mem::take(&mut current));
 					}
+					depth += 1;
 				}
-				CompOp::Like => {
-					// Simple LIKE implementation (% = wildcard)
-					let pattern = compare_value.replace('%', ".*");
-					if let Ok(re) = regex::Regex::new(&format!("^{}$", pattern)) {
-						re.is_match(row_value)
-					} else {
-						false
+				(None, ')') => depth -= 1,
+				(None, c) if c.is_whitespace() && depth == 0 => {
+					if !current.is_empty() {
+						words.push(core::mem::take(&mut current));
 					}
+					continue;
 				}
+				_ => {}
 			}
+			current.push(c);
+		}
+		if !current.is_empty() {
+			words.push(current);
+		}
+		words
+	}
+
+	/// Strip identifier quoting
+	fn unquote(name: &str) -> String {
+		let quoted = name.len() >= 2
+			&& matches!(
+				(name.chars().next(), name.chars().last()),
+				(Some('"'), Some('"')) | (Some('`'), Some('`')) | (Some('['), Some(']'))
+			);
+		if quoted {
+			name[1..name.len() - 1].to_string()
+		} else {
+			name.to_string()
 		}
 	}
 }
 
 /// Represents a row of data
-pub type Row = Vec<String>;
+pub type Row = Vec<ValueType>;
 
 /// Table definition
+///
+/// Rows are not held in memory; they live in the table's b-tree, keyed by rowid.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Table {
 	pub name: String,
 	pub columns: Vec<ColumnDefinition>,
-	pub rows: Vec<Row>,
+	/// Root page of the table's b-tree
+	pub root_page: u32,
+	/// Column declared INTEGER PRIMARY KEY in a SQLite 3 schema; its value is
+	/// the rowid and the record stores NULL in its place
+	pub rowid_alias: Option<usize>,
+	/// Value of each column for records written before it was added by
+	/// ALTER TABLE, which SQLite leaves out of them
+	#[serde(skip)]
+	pub defaults: Vec<ValueType>,
+	/// Indexes updated along with the table's rows
+	#[serde(skip)]
+	pub indexes: Vec<SchemaIndex>,
+	/// Format of the file holding the table; SQLite 3 files store values
+	/// converted to the column's affinity
+	#[serde(skip)]
+	pub format: FileFormat,
 }
 
 impl Table {
-	pub fn new(name: String, columns: Vec<ColumnDefinition>) -> Self {
+	pub fn new(name: String, columns: Vec<ColumnDefinition>, root_page: u32) -> Self {
 		Table {
 			name,
 			columns,
-			rows: Vec::new(),
+			root_page,
+			rowid_alias: None,
+			defaults: Vec::new(),
+			indexes: Vec::new(),
+			format: FileFormat::EPLiteV1,
 		}
 	}
 
+	fn btree(&self) -> BTree {
+		BTree::new(self.root_page)
+	}
+
 	/// Get the number of rows
-	pub fn row_count(&self) -> usize {
-		self.rows.len()
+	pub fn row_count(&self, pager: &mut Pager) -> Result<usize> {
+		let btree = self.btree();
+		let mut cursor = btree.cursor();
+		let mut count = 0;
+		while btree.next(pager, &mut cursor)? {
+			count += 1;
+		}
+		Ok(count)
 	}
 
-	/// Insert a row
-	pub fn insert(&mut self, row: Row) -> Result<()> {
+	/// Insert a row, assigning it the next rowid
+	pub fn insert(&self, pager: &mut Pager, row: Row) -> Result<()> {
+		self.check_writable()?;
+
 		// Validate row length
 		if row.len() != self.columns.len() && !row.is_empty() {
 			return Err(Error::Constraint(format!(
@@ -173,38 +523,77 @@ impl Table {
 				row.len()
 			)));
 		}
-		
-		self.rows.push(row);
-		Ok(())
+
+		let mut values = self.to_values(row);
+		let rowid = match self.take_rowid(&mut values)? {
+			Some(rowid) => {
+				if self.btree().search(pager, rowid)?.is_some() {
+					return Err(self.unique_violation(&[self.rowid_alias.unwrap_or(0)]));
+				}
+				rowid
+			}
+			None => match self.btree().last_rowid(pager)? {
+				Some(last) => last
+					.checked_add(1)
+					.ok_or_else(|| Error::Constraint(format!("Table '{}' is out of rowids", self.name)))?,
+				None => 1,
+			},
+		};
+		self.store(pager, rowid, &values)
 	}
 
 	/// Select all rows
-	pub fn select_all(&self) -> Vec<Row> {
-		self.rows.clone()
+	pub fn select_all(&self, pager: &mut Pager) -> Result<Vec<Row>> {
+		self.select(pager, None)
 	}
 
-	/// Select rows with WHERE clause filtering
-	pub fn select(&self, where_clause: Option<&str>) -> Result<Vec<Row>> {
-		// If no WHERE clause, return all rows
-		let Some(clause) = where_clause else {
-			return Ok(self.rows.clone());
-		};
+	/// Scan the table, returning matching rows along with their rowids
+	fn scan(&self, pager: &mut Pager, condition: Option<&Expr>) -> Result<Vec<(i64, Row)>> {
+		let scope = Scope::new(&self.name, &self.columns);
+		if let Some(condition) = condition {
+			condition.resolve(&scope)?;
+		}
 
-		// Parse and evaluate WHERE clause
-		let condition = where_clause::Condition::parse(clause)?;
-		let filtered: Vec<Row> = self
-			.rows
-			.iter()
-			.filter(|row| condition.evaluate(row, &self.columns))
-			.cloned()
-			.collect();
+		let btree = self.btree();
+		let mut cursor = btree.cursor();
+		let mut rows = Vec::new();
+		while btree.next(pager, &mut cursor)? {
+			let rowid = btree.rowid(pager, &cursor)?;
+			let mut row = self.decode(&btree.payload(pager, &cursor)?)?;
+			if let Some(value) = self.rowid_alias.and_then(|i| row.get_mut(i)) {
+				if value.is_null() {
+					*value = ValueType::I64(rowid);
+				}
+			}
+			if condition.map_or(Ok(true), |c| c.matches(&scope, &row))? {
+				rows.push((rowid, row));
+			}
+		}
+		Ok(rows)
+	}
 
-		Ok(filtered)
+	/// Values of a stored record, with any columns it lacks filled in from
+	/// their defaults, or NULL
+	///
+	/// Values take their column's affinity, as SQLite applies it on read:
+	/// a REAL column stores whole numbers as integers, which read back as
+	/// floating point numbers.
+	fn decode(&self, payload: &[u8]) -> Result<Row> {
+		let mut row = record::decode(payload)?;
+		for i in row.len()..self.columns.len() {
+			row.push(self.defaults.get(i).cloned().unwrap_or(ValueType::Null));
+		}
+		Ok(self.to_values(row))
+	}
+
+	/// Select rows with WHERE clause filtering
+	pub fn select(&self, pager: &mut Pager, condition: Option<&Expr>) -> Result<Vec<Row>> {
+		Ok(self.scan(pager, condition)?.into_iter().map(|(_, row)| row).collect())
 	}
 
 	/// Select rows with ORDER BY support
-	pub fn select_ordered(&self, where_clause: Option<&str>, order_by_column: &str, ascending: bool) -> Result<Vec<Row>> {
-		let mut rows = self.select(where_clause)?;
+	pub fn select_ordered(&self, pager: &mut Pager, condition: Option<&Expr>, order_by_column: &str, ascending: bool) -> Result<Vec<Row>> {
+		let mut rows = self.select(pager, condition)?;
 		
 		// Find column index
 		let col_index = self.columns.iter()
@@ -213,185 +602,1036 @@ impl Table {
 		
 		// Sort rows by the column
 		rows.sort_by(|a, b| {
-			let val_a = &a[col_index];
-			let val_b = &b[col_index];
-			
-			// Try numeric comparison first
-			if let (Ok(num_a), Ok(num_b)) = (val_a.parse::<f64>(), val_b.parse::<f64>()) {
-				let cmp = num_a.partial_cmp(&num_b).unwrap_or(core::cmp::Ordering::Equal);
-				if ascending { cmp } else { cmp.reverse() }
-			} else {
-				// String comparison
-				let cmp = val_a.cmp(val_b);
-				if ascending { cmp } else { cmp.reverse() }
-			}
+			let cmp = record::compare_values(&a[col_index], &b[col_index]);
+			if ascending { cmp } else { cmp.reverse() }
 		});
 		
 		Ok(rows)
 	}
 
-	/// Select rows grouped by a column
-	pub fn select_grouped(&self, where_clause: Option<&str>, group_by_column: &str) -> Result<HashMap<String, Vec<Row>>> {
-		let rows = self.select(where_clause)?;
-		
-		// Find column index
-		let col_index = self.columns.iter()
-			.position(|c| c.name == group_by_column)
-			.ok_or_else(|| Error::NotFound(format!("Column '{}' not found", group_by_column)))?;
-		
-		// Group rows by column value
-		let mut groups = HashMap::new();
-		for row in rows {
-			let key = row[col_index].clone();
-			groups.entry(key).or_insert_with(Vec::new).push(row);
+	/// Select rows grouped by a column, keyed by the text of its value
+	pub fn select_grouped(&self, pager: &mut Pager, condition: Option<&Expr>, group_by_column: &str) -> Result<HashMap<String, Vec<Row>>> {
+		let rows = self.select(pager, condition)?;
+		
+		// Find column index
+		let col_index = self.columns.iter()
+			.position(|c| c.name == group_by_column)
+			.ok_or_else(|| Error::NotFound(format!("Column '{}' not found", group_by_column)))?;
+		
+		// Group rows by column value
+		let mut groups = HashMap::new();
+		for row in rows {
+			let key = row[col_index].to_string();
+			groups.entry(key).or_insert_with(Vec::new).push(row);
+		}
+		
+		Ok(groups)
+	}
+
+
+	/// Update rows matching a condition, setting each column to the value
+	/// of its expression over the row as it was before
+	pub fn update(&self, pager: &mut Pager, condition: Option<&Expr>, updates: &[(String, Expr)]) -> Result<usize> {
+		self.check_writable()?;
+
+		// Find column indexes for updates
+		let scope = Scope::new(&self.name, &self.columns);
+		let mut col_updates = Vec::new();
+		for (col_name, value) in updates {
+			value.resolve(&scope)?;
+			col_updates.push((scope.position(None, col_name)?, value));
+		}
+
+		// Collect matching rows first; the tree must not change under the cursor
+		let matches = self.scan(pager, condition)?;
+		let mut btree = self.btree();
+		for (rowid, old) in &matches {
+			let rowid = *rowid;
+			let mut row = old.clone();
+			for (col_idx, value) in &col_updates {
+				row[*col_idx] = value.evaluate(&scope, old)?;
+			}
+			self.unindex(pager, rowid)?;
+
+			// Changing an INTEGER PRIMARY KEY moves the row
+			let mut values = self.to_values(row);
+			let new_rowid = self.take_rowid(&mut values)?.unwrap_or(rowid);
+			if new_rowid != rowid {
+				if btree.search(pager, new_rowid)?.is_some() {
+					return Err(self.unique_violation(&[self.rowid_alias.unwrap_or(0)]));
+				}
+				btree.delete(pager, rowid)?;
+			}
+			self.store(pager, new_rowid, &values)?;
+		}
+
+		Ok(matches.len())
+	}
+
+	/// Delete rows matching a condition
+	pub fn delete(&self, pager: &mut Pager, condition: Option<&Expr>) -> Result<usize> {
+		self.check_writable()?;
+		let matches = self.scan(pager, condition)?;
+		let mut btree = self.btree();
+		for (rowid, _) in &matches {
+			self.unindex(pager, *rowid)?;
+			btree.delete(pager, *rowid)?;
+		}
+		Ok(matches.len())
+	}
+
+	/// Fail if the table has an index whose keys we cannot compute
+	fn check_writable(&self) -> Result<()> {
+		match self.indexes.iter().find(|index| index.columns.is_none()) {
+			Some(index) => Err(Error::NotSupported(format!(
+				"Cannot update index '{}' on table '{}'",
+				index.name, self.name
+			))),
+			None => Ok(()),
+		}
+	}
+
+	/// Values of a row as they are stored, converted to each column's
+	/// affinity as SQLite does
+	fn to_values(&self, row: Row) -> Vec<ValueType> {
+		row.into_iter()
+			.enumerate()
+			.map(|(i, value)| match self.columns.get(i) {
+				Some(column) => column.data_type.affinity().apply(value),
+				None => value,
+			})
+			.collect()
+	}
+
+	/// Take the rowid from the INTEGER PRIMARY KEY column, leaving NULL in
+	/// its place; None means a rowid should be assigned
+	fn take_rowid(&self, values: &mut [ValueType]) -> Result<Option<i64>> {
+		let Some(value) = self.rowid_alias.and_then(|i| values.get_mut(i)) else {
+			return Ok(None);
+		};
+		match core::mem::replace(value, ValueType::Null) {
+			ValueType::Null => Ok(None),
+			ValueType::I64(rowid) => Ok(Some(rowid)),
+			other => Err(Error::TypeMismatch(format!(
+				"Rowid of table '{}' must be an integer, got {:?}",
+				self.name, other
+			))),
+		}
+	}
+
+	/// Write a row and its index entries, enforcing UNIQUE indexes
+	fn store(&self, pager: &mut Pager, rowid: i64, values: &[ValueType]) -> Result<()> {
+		for index in self.indexes.iter().filter(|index| index.unique) {
+			let key = self.index_values(index, rowid, values);
+			if key.iter().any(|v| matches!(v, ValueType::Null)) {
+				continue;
+			}
+			let btree = index.btree();
+			let mut cursor = btree.cursor();
+			if btree.seek_key(pager, &mut cursor, &record::encode(&key)?)? {
+				let existing = record::decode(&btree.payload(pager, &cursor)?)?;
+				let equal = key.iter().zip(&existing).all(|(a, b)| record::compare_values(a, b).is_eq());
+				if equal && existing.len() > key.len() {
+					return Err(self.unique_violation(index.columns.as_deref().unwrap_or_default()));
+				}
+			}
+		}
+
+		self.btree().insert(pager, rowid, &record::encode(values)?)?;
+		for index in &self.indexes {
+			let mut key = self.index_values(index, rowid, values);
+			key.push(ValueType::I64(rowid));
+			index.btree().insert_key(pager, &record::encode(&key)?)?;
+		}
+		Ok(())
+	}
+
+	/// Remove the index entries of a stored row
+	fn unindex(&self, pager: &mut Pager, rowid: i64) -> Result<()> {
+		if self.indexes.is_empty() {
+			return Ok(());
+		}
+		let Some(payload) = self.btree().search(pager, rowid)? else {
+			return Ok(());
+		};
+		let values = self.decode(&payload)?;
+		for index in &self.indexes {
+			let mut key = self.index_values(index, rowid, &values);
+			key.push(ValueType::I64(rowid));
+			index.btree().delete_key(pager, &record::encode(&key)?)?;
+		}
+		Ok(())
+	}
+
+	/// Key columns of a row for an index, without the trailing rowid
+	fn index_values(&self, index: &SchemaIndex, rowid: i64, values: &[ValueType]) -> Vec<ValueType> {
+		let columns = index.columns.as_deref().unwrap_or_default();
+		columns
+			.iter()
+			.map(|&i| match values.get(i) {
+				_ if Some(i) == self.rowid_alias => ValueType::I64(rowid),
+				Some(value) => value.clone(),
+				None => ValueType::Null,
+			})
+			.collect()
+	}
+
+	fn unique_violation(&self, columns: &[usize]) -> Error {
+		let names: Vec<String> = columns
+			.iter()
+			.filter_map(|&i| self.columns.get(i))
+			.map(|c| format!("{}.{}", self.name, c.name))
+			.collect();
+		Error::Constraint(format!("UNIQUE constraint failed: {}", names.join(", ")))
+	}
+}
+
+/// An index recorded in a SQLite 3 schema
+#[derive(Debug, Clone)]
+pub struct SchemaIndex {
+	pub name: String,
+	pub table: String,
+	/// Root page of the index b-tree
+	pub root_page: u32,
+	/// CREATE INDEX statement; None for automatic indexes
+	pub sql: Option<String>,
+	/// Table columns making up the key, or None if the key is not a list of
+	/// plain columns
+	pub columns: Option<Vec<usize>>,
+	pub unique: bool,
+}
+
+impl SchemaIndex {
+	fn btree(&self) -> BTree {
+		BTree::new_index(self.root_page).with_comparator(record::compare)
+	}
+}
+
+/// Schema state to restore when a transaction or savepoint rolls back
+#[derive(Debug)]
+struct Savepoint {
+	/// Name given by SAVEPOINT; None for BEGIN and for single statements
+	name: Option<String>,
+	tables: HashMap<String, Table>,
+	dirty: bool,
+	schema_changed: bool,
+	auto_vacuum: AutoVacuum,
+}
+
+/// Storage manager with optional disk persistence
+///
+/// On disk, page 1 holds the database header followed by the catalog chain,
+/// which lists each table and the root page of its b-tree. Without a file
+/// the pager's cache holds every page. SQLite 3 files instead keep their
+/// schema in the b-tree rooted at page 1, as SQLite does.
+#[derive(Debug)]
+pub struct StorageManager {
+	tables: HashMap<String, Table>,
+	pager: Pager,
+	format: FileFormat,
+	dirty: bool,
+	/// The schema changed since the last save
+	schema_changed: bool,
+	auto_vacuum: AutoVacuum,
+	/// The open transaction followed by any savepoints inside it
+	savepoints: Vec<Savepoint>,
+	/// Objects of a SQLite 3 schema that rows cannot be read from or
+	/// written to, by name, with the kind of object each is
+	unsupported: Vec<(String, &'static str)>,
+}
+
+impl StorageManager {
+	pub fn new() -> Self {
+		Self::with_pager(Pager::default())
+	}
+
+	/// Create a storage manager with disk persistence
+	pub fn with_pager(pager: Pager) -> Self {
+		StorageManager {
+			tables: HashMap::new(),
+			pager,
+			format: FileFormat::EPLiteV1,
+			dirty: false,
+			schema_changed: false,
+			auto_vacuum: AutoVacuum::None,
+			savepoints: Vec::new(),
+			unsupported: Vec::new(),
+		}
+	}
+
+	/// Create a new, empty database in the given format
+	pub fn create(pager: Pager, format: FileFormat) -> Result<Self> {
+		if pager.page_count() != 0 {
+			return Err(Error::AlreadyExists("Database file is not empty".to_string()));
+		}
+		let mut storage = Self::with_pager(pager);
+		storage.format = format;
+
+		// A SQLite 3 file starts with page 1 holding an empty schema table
+		if format == FileFormat::SQLiteV3 {
+			storage.pager.allocate_page()?;
+			let mut header = DatabaseHeader::new_sqlite();
+			header.page_size = storage.pager.page_size();
+			storage.pager.get_page_mut(1)?.write(0, &header.to_bytes())?;
+			BTree::create_at(&mut storage.pager, 1, BTreeKind::Table)?;
+			storage.mark_dirty();
+			storage.save_to_disk()?;
+		}
+		Ok(storage)
+	}
+
+	/// Load the catalog from disk if the pager has a file
+	pub fn load_from_disk(&mut self) -> Result<()> {
+		let pager = &mut self.pager;
+
+		// A new database has no pages yet
+		if pager.page_count() == 0 {
+			return Ok(());
+		}
+
+		let page = pager.get_page(1)?;
+		let header = DatabaseHeader::from_bytes(&page.data[..DATABASE_HEADER_SIZE])?;
+		if header.page_size != pager.page_size() {
+			return Err(Error::Corrupt(format!(
+				"Header page size {} does not match pager page size {}",
+				header.page_size,
+				pager.page_size()
+			)));
+		}
+
+		self.format = header.format;
+		pager.set_payload_fractions(header.payload_fractions());
+		// As in SQLite, file format version 2 marks a database in WAL mode.
+		// Other connections may have committed to the log, so the database
+		// is read again from there.
+		if header.write_version == 2 && pager.journal_mode() != JournalMode::Wal {
+			pager.set_journal_mode(JournalMode::Wal)?;
+			if pager.journal_mode() == JournalMode::Wal {
+				pager.begin_read()?;
+				let loaded = self.load_from_disk();
+				self.pager.end_read()?;
+				return loaded;
+			}
+		}
+
+		// Our own files record the auto-vacuum mode the way SQLite does
+		self.auto_vacuum = match (header.largest_root_page, header.incremental_vacuum) {
+			(0, _) => AutoVacuum::None,
+			(_, false) => AutoVacuum::Full,
+			(_, true) => AutoVacuum::Incremental,
+		};
+		if header.format == FileFormat::SQLiteV3 {
+			return self.load_sqlite_schema(&header);
+		}
+
+		let catalog_bytes = chain::read(pager, 1, DATABASE_HEADER_SIZE)?;
+		let catalog: Vec<Table> = bincode::deserialize(&catalog_bytes)
+			.map_err(|e| Error::Corrupt(format!("Failed to read catalog: {}", e)))?;
+
+		self.tables = catalog
+			.into_iter()
+			.map(|table| (table.name.clone(), table))
+			.collect();
+		Ok(())
+	}
+
+	/// Load tables and indexes from the schema table of a SQLite 3 file
+	fn load_sqlite_schema(&mut self, header: &DatabaseHeader) -> Result<()> {
+		if header.text_encoding != TextEncoding::Utf8 {
+			return Err(Error::NotSupported(
+				"Only UTF-8 SQLite 3 databases are supported".to_string(),
+			));
+		}
+		self.pager.set_reserved_space(header.reserved_space);
+		self.unsupported.clear();
+
+		let mut unique_keys = HashMap::new();
+		let mut indexes = Vec::new();
+		for (_, entry) in self.schema_entries()? {
+			let text = |i: usize| match entry.get(i) {
+				Some(ValueType::StringUtf8(text)) => Some(text.clone()),
+				_ => None,
+			};
+			let root_page = match entry.get(3) {
+				Some(ValueType::I64(page)) => u32::try_from(*page).unwrap_or(0),
+				_ => 0,
+			};
+			let (Some(kind), Some(name), Some(table)) = (text(0), text(1), text(2)) else {
+				return Err(Error::Corrupt("Malformed schema entry".to_string()));
+			};
+
+			match kind.as_str() {
+				// Virtual tables have no b-tree
+				"table" if root_page == 0 => self.unsupported.push((name, "virtual table")),
+				"table" => {
+					let sql = text(4).unwrap_or_default();
+					let definition = schema::parse_create_table(&sql)?;
+					if definition.without_rowid {
+						self.unsupported.push((name, "WITHOUT ROWID table"));
+						continue;
+					}
+					let mut table = Table::new(name.clone(), definition.columns, root_page);
+					table.rowid_alias = definition.rowid_alias;
+					table.defaults = definition.defaults;
+					table.format = FileFormat::SQLiteV3;
+					unique_keys.insert(name.clone(), definition.unique_keys);
+					self.tables.insert(name, table);
+				}
+				"index" => indexes.push(SchemaIndex {
+					name,
+					table,
+					root_page,
+					sql: text(4),
+					columns: None,
+					unique: false,
+				}),
+				"view" => self.unsupported.push((name, "view")),
+				_ => {}
+			}
+		}
+
+		// Match each index with its table's columns
+		for mut index in indexes {
+			let Some(table) = self.table_key(&index.table).and_then(|key| self.tables.get_mut(&key)) else {
+				continue;
+			};
+			match &index.sql {
+				Some(sql) => {
+					if let Some((names, unique)) = schema::parse_create_index(sql) {
+						index.columns = names
+							.iter()
+							.map(|name| table.columns.iter().position(|c| c.name.eq_ignore_ascii_case(name)))
+							.collect();
+						index.unique = unique;
+					}
+				}
+				None => {
+					// Automatic indexes are numbered from 1 in constraint order
+					let prefix = format!("sqlite_autoindex_{}_", index.table);
+					let number = index.name.strip_prefix(&prefix).and_then(|n| n.parse::<usize>().ok());
+					let keys = unique_keys.get(&index.table);
+					index.columns = number.and_then(|n| keys?.get(n.checked_sub(1)?).cloned());
+					index.unique = true;
+				}
+			}
+			table.indexes.push(index);
+		}
+		Ok(())
+	}
+
+	/// Rows of the SQLite 3 schema table with their rowids
+	fn schema_entries(&mut self) -> Result<Vec<(i64, Vec<ValueType>)>> {
+		let schema = BTree::new(1);
+		let mut cursor = schema.cursor();
+		let mut entries = Vec::new();
+		while schema.next(&mut self.pager, &mut cursor)? {
+			let rowid = schema.rowid(&mut self.pager, &cursor)?;
+			entries.push((rowid, record::decode(&schema.payload(&mut self.pager, &cursor)?)?));
+		}
+		Ok(entries)
+	}
+
+	/// Append a row of (type, name, tbl_name, rootpage, sql) to the SQLite 3
+	/// schema table
+	fn add_schema_entry(&mut self, kind: &str, name: &str, table: &str, root_page: u32, sql: Option<String>) -> Result<()> {
+		let mut schema = BTree::new(1);
+		let rowid = schema.last_rowid(&mut self.pager)?.unwrap_or(0) + 1;
+		let entry = record::encode(&[
+			ValueType::StringUtf8(kind.to_string()),
+			ValueType::StringUtf8(name.to_string()),
+			ValueType::StringUtf8(table.to_string()),
+			ValueType::I64(root_page as i64),
+			sql.map_or(ValueType::Null, ValueType::StringUtf8),
+		])?;
+		schema.insert(&mut self.pager, rowid, &entry)?;
+		self.schema_changed = true;
+		Ok(())
+	}
+
+	/// Format of the database file
+	pub fn format(&self) -> FileFormat {
+		self.format
+	}
+
+	/// Get an index by name
+	pub fn get_index(&self, name: &str) -> Option<&SchemaIndex> {
+		self.tables
+			.values()
+			.flat_map(|table| table.indexes.iter())
+			.find(|index| index.name.eq_ignore_ascii_case(name))
+	}
+
+	/// Save the catalog and all modified pages to disk if the pager has a file
+	pub fn save_to_disk(&mut self) -> Result<()> {
+		// An open transaction writes its changes when it commits
+		if !self.dirty || !self.savepoints.is_empty() {
+			return Ok(());
+		}
+		if !self.pager.has_file() {
+			self.dirty = false;
+			return Ok(());
+		}
+		// SQLite's auto-vacuum files carry pointer-map pages, which we do not
+		// maintain, so they can be read but not written
+		if self.format == FileFormat::SQLiteV3 && self.auto_vacuum != AutoVacuum::None {
+			return Err(Error::NotSupported(
+				"Cannot write to a SQLite auto-vacuum database; run VACUUM first".to_string(),
+			));
+		}
+		// Make sure page 1 exists before any table pages are allocated
+		if self.pager.page_count() == 0 {
+			self.pager.allocate_page()?;
+		}
+
+		// Write the catalog after the header on page 1. Moving a root page
+		// changes the catalog but not its size, so rewriting it in place
+		// frees no pages.
+		self.write_catalog()?;
+		if self.auto_vacuum == AutoVacuum::Full && self.compact(None)? {
+			self.write_catalog()?;
+		}
+
+		// Update the header last so it records the final database size
+		let largest_root_page = self.largest_root_page();
+		let pager = &mut self.pager;
+		let page_size = pager.page_size();
+		let page_count = pager.page_count();
+		let version = if pager.journal_mode() == JournalMode::Wal { 2 } else { 1 };
+		let page = pager.get_page_mut(1)?;
+		let mut header = DatabaseHeader::from_bytes(&page.data[..DATABASE_HEADER_SIZE])
+			.unwrap_or_else(|_| DatabaseHeader::new_eplite());
+		header.page_size = page_size;
+		header.database_size = page_count;
+		header.write_version = version;
+		header.read_version = version;
+		header.change_counter = header.change_counter.wrapping_add(1);
+		if header.format == FileFormat::EPLiteV1 {
+			header.largest_root_page = largest_root_page;
+			header.incremental_vacuum = self.auto_vacuum == AutoVacuum::Incremental;
+		} else {
+			// SQLite trusts database_size only when this matches the counter
+			header.version_valid_for = header.change_counter;
+			header.sqlite_version = SQLITE_VERSION_NUMBER;
+			if self.schema_changed {
+				header.schema_cookie = header.schema_cookie.wrapping_add(1);
+			}
+		}
+		page.write(0, &header.to_bytes())?;
+
+		pager.flush()?;
+		self.dirty = false;
+		self.schema_changed = false;
+		Ok(())
+	}
+
+	/// Write the catalog of an EPLite file after the header on page 1
+	fn write_catalog(&mut self) -> Result<()> {
+		if self.format != FileFormat::EPLiteV1 {
+			return Ok(());
+		}
+		let mut catalog: Vec<&Table> = self.tables.values().collect();
+		catalog.sort_by(|a, b| a.name.cmp(&b.name));
+		let serialized = bincode::serialize(&catalog)
+			.map_err(|e| Error::Internal(format!("Failed to serialize catalog: {}", e)))?;
+		chain::write(&mut self.pager, 1, DATABASE_HEADER_SIZE, &serialized)
+	}
+
+	/// Header value marking an auto-vacuum database: the largest root page,
+	/// or zero when auto-vacuum is off
+	fn largest_root_page(&self) -> u32 {
+		if self.auto_vacuum == AutoVacuum::None {
+			return 0;
+		}
+		self.tables
+			.values()
+			.flat_map(|table| core::iter::once(table.root_page).chain(table.indexes.iter().map(|i| i.root_page)))
+			.fold(1, u32::max)
+	}
+
+	/// Current auto-vacuum mode
+	pub fn auto_vacuum(&self) -> AutoVacuum {
+		self.auto_vacuum
+	}
+
+	/// Set the auto-vacuum mode, which EPLite files record in their header
+	///
+	/// The mode of a SQLite 3 file cannot be changed: SQLite's auto-vacuum
+	/// files carry pointer-map pages, which we do not write.
+	pub fn set_auto_vacuum(&mut self, mode: AutoVacuum) -> Result<()> {
+		if self.format == FileFormat::SQLiteV3 && mode != self.auto_vacuum {
+			return Err(Error::NotSupported(
+				"auto_vacuum cannot be changed in a SQLite 3 file".to_string(),
+			));
+		}
+		self.auto_vacuum = mode;
+		self.mark_dirty();
+		self.save_to_disk()
+	}
+
+	/// Remove up to `pages` free pages from the file, or all of them if
+	/// None; does nothing unless auto-vacuum is incremental, as in SQLite
+	pub fn incremental_vacuum(&mut self, pages: Option<u32>) -> Result<()> {
+		if self.auto_vacuum != AutoVacuum::Incremental {
+			return Ok(());
+		}
+		self.compact(pages)?;
+		self.mark_dirty();
+		self.save_to_disk()
+	}
+
+	/// Current journal mode
+	pub fn journal_mode(&self) -> JournalMode {
+		self.pager.journal_mode()
+	}
+
+	/// Set the journal mode, returning the mode now in effect; in-memory
+	/// databases stay in MEMORY mode
+	///
+	/// WAL mode is recorded in the file and stays on when it is reopened.
+	/// Other modes last for this connection only.
+	pub fn set_journal_mode(&mut self, mode: JournalMode) -> Result<JournalMode> {
+		let current = self.pager.journal_mode();
+		if (current == JournalMode::Wal) == (mode == JournalMode::Wal) {
+			return self.pager.set_journal_mode(mode);
+		}
+		// Only the statement's own savepoint may be open
+		if self.savepoints.len() > 1 {
+			return Err(Error::InvalidOperation(format!(
+				"cannot change {} wal mode from within a transaction",
+				if mode == JournalMode::Wal { "into" } else { "out of" }
+			)));
+		}
+		let mode = self.pager.set_journal_mode(mode)?;
+		if mode != current {
+			self.mark_dirty();
+			self.save_to_disk()?;
+		}
+		Ok(mode)
+	}
+
+	/// Log frames after which a commit in WAL mode checkpoints
+	pub fn wal_autocheckpoint(&self) -> u32 {
+		self.pager.wal_autocheckpoint()
+	}
+
+	/// Set the frames after which a commit in WAL mode checkpoints; 0 turns
+	/// automatic checkpoints off
+	pub fn set_wal_autocheckpoint(&mut self, frames: u32) {
+		self.pager.set_wal_autocheckpoint(frames);
+	}
+
+	/// Copy the write-ahead log into the database file, returning the
+	/// frame counts, or None when the database is not in WAL mode
+	pub fn checkpoint(&mut self, mode: CheckpointMode) -> Result<Option<CheckpointResult>> {
+		self.pager.checkpoint(mode)
+	}
+
+	/// The page cache size as set: pages, or KiB when negative
+	pub fn cache_size(&self) -> i64 {
+		self.pager.cache_size()
+	}
+
+	/// Set the page cache size in pages, or in KiB when negative
+	pub fn set_cache_size(&mut self, size: i64) {
+		self.pager.set_cache_size(size);
+	}
+
+	/// Hit, miss and eviction counts of the page cache
+	pub fn cache_stats(&self) -> CacheStats {
+		self.pager.cache_stats()
+	}
+
+	/// Bytes of the database file read through a memory map
+	pub fn mmap_size(&self) -> u64 {
+		self.pager.mmap_size()
+	}
+
+	/// Set how many bytes of the file to memory-map, returning the size in
+	/// effect
+	pub fn set_mmap_size(&mut self, size: u64) -> Result<u64> {
+		self.pager.set_mmap_size(size)
+	}
+
+	/// Size of each page in bytes
+	pub fn page_size(&self) -> u32 {
+		self.pager.page_size()
+	}
+
+	/// Number of pages in the database
+	pub fn page_count(&self) -> u32 {
+		self.pager.page_count()
+	}
+
+	/// Number of unused pages on the freelist
+	pub fn freelist_count(&mut self) -> Result<u32> {
+		self.pager.freelist_count()
+	}
+
+	/// Rebuild the database so it takes as few pages as possible
+	pub fn vacuum(&mut self) -> Result<()> {
+		if self.pager.page_count() == 0 {
+			return Ok(());
+		}
+		let mut target = Pager::new(self.pager.page_size())?;
+		self.tables = self.rebuild(&mut target)?;
+		if self.format == FileFormat::SQLiteV3 {
+			self.auto_vacuum = AutoVacuum::None;
+		}
+
+		// Copy the rebuilt pages over the old ones and drop the rest
+		let page_count = target.page_count();
+		for page_number in 1..=page_count {
+			let data = target.get_page(page_number)?.data.clone();
+			self.pager.get_page_mut(page_number)?.write(0, &data)?;
+		}
+		self.pager.truncate(page_count)?;
+		self.schema_changed = true;
+		self.mark_dirty();
+		self.save_to_disk()
+	}
+
+	/// Write a rebuilt copy of the database to a new file, which must not
+	/// exist or be empty
+	#[cfg(feature = "std")]
+	pub fn vacuum_into(&mut self, path: &str) -> Result<()> {
+		let mut file = DefaultFile::open(path, true, true, true)?;
+		if file.file_size()? != 0 {
+			return Err(Error::AlreadyExists(format!("Output file '{}' is not empty", path)));
 		}
-		
-		Ok(groups)
+		let mut copy = StorageManager::with_pager(Pager::with_file(self.pager.page_size(), Box::new(file))?);
+		copy.format = self.format;
+		if self.format == FileFormat::EPLiteV1 {
+			copy.auto_vacuum = self.auto_vacuum;
+		}
+		copy.tables = self.rebuild(&mut copy.pager)?;
+		copy.schema_changed = true;
+		copy.mark_dirty();
+		copy.save_to_disk()
 	}
 
-	/// Update rows matching a condition
-	pub fn update(&mut self, condition: Option<&str>, updates: &[(String, String)]) -> Result<usize> {
-		let mut count = 0;
-		
-		// Parse WHERE clause if provided
-		let where_cond = if let Some(clause) = condition {
-			Some(where_clause::Condition::parse(clause)?)
+	/// Copy the database into an empty pager, rebuilding each b-tree with
+	/// its pages packed. Returns the tables with their new root pages.
+	fn rebuild(&mut self, target: &mut Pager) -> Result<HashMap<String, Table>> {
+		let reserved_space = self.pager.page_size() as usize - self.pager.usable_size();
+		target.set_reserved_space(reserved_space as u8);
+		target.set_payload_fractions(self.pager.payload_fractions());
+
+		// Page 1 starts with the header, less the freelist
+		let mut header = match self.pager.page_count() {
+			0 => DatabaseHeader::new_eplite(),
+			_ => DatabaseHeader::from_bytes(&self.pager.get_page(1)?.data[..DATABASE_HEADER_SIZE])
+				.unwrap_or_else(|_| DatabaseHeader::new_eplite()),
+		};
+		header.page_size = target.page_size();
+		header.first_freelist_page = 0;
+		header.freelist_pages = 0;
+		if self.format == FileFormat::SQLiteV3 {
+			// The copy has no pointer-map pages, even if the original did
+			header.largest_root_page = 0;
+			header.incremental_vacuum = false;
+		}
+		target.allocate_page()?;
+		target.get_page_mut(1)?.write(0, &header.to_bytes())?;
+
+		let mut roots = HashMap::new();
+		if self.format == FileFormat::SQLiteV3 {
+			// Copy every b-tree named by the schema, then the schema itself
+			// with the new root pages
+			let mut schema = BTree::create_at(target, 1, BTreeKind::Table)?;
+			for (rowid, mut entry) in self.schema_entries()? {
+				if let Some(ValueType::I64(root_page)) = entry.get_mut(3) {
+					if *root_page > 0 {
+						let old = *root_page as u32;
+						let copy = self.open_tree(old)?.copy(&mut self.pager, target)?;
+						*root_page = copy.root_page() as i64;
+						roots.insert(old, copy.root_page());
+					}
+				}
+				schema.insert(target, rowid, &record::encode(&entry)?)?;
+			}
 		} else {
-			None
+			let mut names: Vec<&String> = self.tables.keys().collect();
+			names.sort();
+			for name in names {
+				let table = &self.tables[name];
+				let copy = table.btree().copy(&mut self.pager, target)?;
+				roots.insert(table.root_page, copy.root_page());
+			}
+		}
+
+		let moved = |page: u32| {
+			roots
+				.get(&page)
+				.copied()
+				.ok_or_else(|| Error::Internal(format!("Root page {} was not copied", page)))
 		};
-		
-		// Find column indexes for updates
-		let mut col_updates = Vec::new();
-		for (col_name, value) in updates {
-			if let Some(pos) = self.columns.iter().position(|c| &c.name == col_name) {
-				col_updates.push((pos, value.clone()));
+		let mut tables = self.tables.clone();
+		for table in tables.values_mut() {
+			table.root_page = moved(table.root_page)?;
+			for index in &mut table.indexes {
+				index.root_page = moved(index.root_page)?;
 			}
 		}
+		Ok(tables)
+	}
 
-		// Update matching rows
-		for row in &mut self.rows {
-			// Check if row matches WHERE clause
-			if let Some(ref cond) = where_cond {
-				if !cond.evaluate(row, &self.columns) {
-					continue;
+	/// Open a b-tree named in the schema, which may be a table or an index
+	fn open_tree(&mut self, root_page: u32) -> Result<BTree> {
+		let tree = BTree::open(&mut self.pager, root_page)?;
+		Ok(match tree.kind() {
+			BTreeKind::Table => tree,
+			BTreeKind::Index => tree.with_comparator(record::compare),
+		})
+	}
+
+	/// Move pages in use into free pages and truncate the file, removing at
+	/// most `limit` free pages, or all of them if None. Returns whether any
+	/// root page moved.
+	fn compact(&mut self, limit: Option<u32>) -> Result<bool> {
+		if self.pager.freelist_count()? == 0 {
+			return Ok(false);
+		}
+		let refs = self.page_refs()?;
+		let roots: HashMap<u32, u32> = self.pager.relocate(&refs, limit)?.into_iter().collect();
+		if roots.is_empty() {
+			return Ok(false);
+		}
+
+		for table in self.tables.values_mut() {
+			if let Some(&root_page) = roots.get(&table.root_page) {
+				table.root_page = root_page;
+			}
+			for index in &mut table.indexes {
+				if let Some(&root_page) = roots.get(&index.root_page) {
+					index.root_page = root_page;
 				}
 			}
+		}
 
-			// Update the row
-			for (col_idx, value) in &col_updates {
-				if *col_idx < row.len() {
-					row[*col_idx] = value.clone();
+		// A smaller page number never needs more space in the schema record
+		if self.format == FileFormat::SQLiteV3 {
+			let mut schema = BTree::new(1);
+			for (rowid, mut entry) in self.schema_entries()? {
+				if let Some(ValueType::I64(root_page)) = entry.get_mut(3) {
+					if let Some(&moved) = roots.get(&(*root_page as u32)) {
+						*root_page = moved as i64;
+						schema.insert(&mut self.pager, rowid, &record::encode(&entry)?)?;
+					}
 				}
 			}
-			count += 1;
+			self.schema_changed = true;
 		}
-
-		Ok(count)
+		Ok(true)
 	}
 
-	/// Delete rows matching a condition
-	pub fn delete(&mut self, condition: Option<&str>) -> Result<usize> {
-		// Parse WHERE clause if provided
-		let where_cond = if let Some(clause) = condition {
-			Some(where_clause::Condition::parse(clause)?)
+	/// Every page in use other than page 1, with where its number is kept
+	fn page_refs(&mut self) -> Result<HashMap<u32, PageRef>> {
+		let mut refs = Vec::new();
+		if self.format == FileFormat::SQLiteV3 {
+			let header = DatabaseHeader::from_bytes(&self.pager.get_page(1)?.data[..DATABASE_HEADER_SIZE])?;
+			if header.largest_root_page != 0 {
+				return Err(Error::NotSupported(
+					"Cannot move pages of a SQLite auto-vacuum database; run VACUUM first".to_string(),
+				));
+			}
+			BTree::new(1).page_refs(&mut self.pager, &mut refs)?;
+			for (_, entry) in self.schema_entries()? {
+				if let Some(&ValueType::I64(root_page)) = entry.get(3) {
+					if root_page > 0 {
+						self.open_tree(root_page as u32)?.page_refs(&mut self.pager, &mut refs)?;
+					}
+				}
+			}
 		} else {
-			None
-		};
+			chain::page_refs(&mut self.pager, 1, DATABASE_HEADER_SIZE, &mut refs)?;
+			for table in self.tables.values() {
+				table.btree().page_refs(&mut self.pager, &mut refs)?;
+			}
+		}
 
-		let original_count = self.rows.len();
+		let mut map = HashMap::new();
+		for (page_number, page_ref) in refs {
+			if page_number != 1 && map.insert(page_number, page_ref).is_some() {
+				return Err(Error::Corrupt(format!("Page {} is used twice", page_number)));
+			}
+		}
+		Ok(map)
+	}
 
-		// Filter out rows that match the condition
-		if let Some(cond) = where_cond {
-			self.rows.retain(|row| !cond.evaluate(row, &self.columns));
-		} else {
-			// No WHERE clause means delete all
-			self.rows.clear();
+	/// Start a transaction; nothing reaches the file until it commits
+	///
+	/// Immediate and exclusive transactions take the write lock now, and
+	/// do not start if another connection holds it.
+	pub fn begin(&mut self, mode: TransactionMode) -> Result<()> {
+		if self.in_transaction() {
+			return Err(Error::InvalidOperation(
+				"cannot start a transaction within a transaction".to_string(),
+			));
+		}
+		self.push_savepoint(None)?;
+		if mode != TransactionMode::Deferred {
+			if let Err(e) = self.pager.lock_for_write(mode == TransactionMode::Exclusive) {
+				self.rollback()?;
+				return Err(e);
+			}
 		}
+		Ok(())
+	}
 
-		Ok(original_count - self.rows.len())
+	/// Write the open transaction's changes to disk and end it, releasing
+	/// any savepoints
+	///
+	/// If writing fails the transaction stays open so it can be rolled back.
+	pub fn commit(&mut self) -> Result<()> {
+		if !self.in_transaction() {
+			return Err(Error::InvalidOperation(
+				"cannot commit - no transaction is active".to_string(),
+			));
+		}
+		let savepoints = core::mem::take(&mut self.savepoints);
+		if let Err(e) = self.save_to_disk() {
+			self.savepoints = savepoints;
+			return Err(e);
+		}
+		self.pager.commit_transaction()?;
+		self.pager.end_read()
 	}
-}
 
-/// Storage manager with optional disk persistence
-#[derive(Debug)]
-pub struct StorageManager {
-	tables: HashMap<String, Table>,
-	pager: Option<Pager>,
-	dirty: bool,
-}
+	/// Discard the open transaction, restoring the state before it began
+	pub fn rollback(&mut self) -> Result<()> {
+		if !self.in_transaction() {
+			return Err(Error::InvalidOperation(
+				"cannot rollback - no transaction is active".to_string(),
+			));
+		}
+		self.rollback_to_depth(0)?;
+		self.savepoints.clear();
+		self.pager.rollback_transaction()?;
+		self.pager.end_read()
+	}
 
-impl StorageManager {
-	pub fn new() -> Self {
-		StorageManager {
-			tables: HashMap::new(),
-			pager: None,
-			dirty: false,
+	/// Whether a transaction is open
+	pub fn in_transaction(&self) -> bool {
+		!self.savepoints.is_empty()
+	}
+
+	/// Start reading the latest snapshot of the database, reloading the
+	/// schema if another connection changed it; an open transaction keeps
+	/// reading its own snapshot
+	pub fn begin_read(&mut self) -> Result<()> {
+		if !self.in_transaction() && self.pager.begin_read()? {
+			self.tables.clear();
+			self.load_from_disk()?;
 		}
+		Ok(())
 	}
 
-	/// Create a storage manager with disk persistence
-	pub fn with_pager(pager: Pager) -> Self {
-		StorageManager {
-			tables: HashMap::new(),
-			pager: Some(pager),
-			dirty: false,
+	/// Release the snapshot taken by `begin_read`, unless a transaction
+	/// still reads it
+	pub fn end_read(&mut self) -> Result<()> {
+		if self.in_transaction() {
+			return Ok(());
 		}
+		self.pager.end_read()
 	}
 
-	/// Load tables from disk if pager is available
-	pub fn load_from_disk(&mut self) -> Result<()> {
-		if let Some(pager) = &mut self.pager {
-			// Try to load from page 1 (page 0 is header)
-			if let Ok(page) = pager.get_page(1) {
-				// Deserialize the tables from the page data
-				if !page.data.is_empty() && page.data[0] != 0 {
-					match bincode::deserialize::<HashMap<String, Table>>(&page.data) {
-						Ok(tables) => {
-							self.tables = tables;
-							return Ok(());
-						}
-						Err(_) => {
-							// Page exists but can't deserialize - might be empty/new database
-						}
-					}
-				}
-			}
+	/// Take a named savepoint; outside a transaction this starts one, which
+	/// commits when the savepoint is released
+	pub fn savepoint(&mut self, name: &str) -> Result<()> {
+		self.push_savepoint(Some(name.to_string()))
+	}
+
+	/// Release the most recent savepoint with this name and those taken
+	/// after it, keeping their changes in the transaction
+	pub fn release(&mut self, name: &str) -> Result<()> {
+		let depth = self.find_savepoint(name)?;
+		if depth == 0 {
+			return self.commit();
 		}
+		self.savepoints.truncate(depth);
+		self.pager.release(depth);
 		Ok(())
 	}
 
-	/// Save tables to disk if pager is available
-	pub fn save_to_disk(&mut self) -> Result<()> {
-		if self.dirty && self.pager.is_some() {
-			if let Some(pager) = &mut self.pager {
-				// Serialize the tables
-				let serialized = bincode::serialize(&self.tables).map_err(|e| {
-					Error::Internal(format!("Failed to serialize tables: {}", e))
-				})?;
-
-				// Get or create page 1
-				let page = pager.get_page_mut(1)?;
-				
-				// Write serialized data
-				if serialized.len() <= page.data.len() {
-					page.data[..serialized.len()].copy_from_slice(&serialized);
-					page.mark_dirty();
-				} else {
-					return Err(Error::Internal(format!(
-						"Serialized data too large: {} bytes (max: {})",
-						serialized.len(),
-						page.data.len()
-					)));
-				}
+	/// Undo everything since the most recent savepoint with this name,
+	/// which stays open
+	pub fn rollback_to(&mut self, name: &str) -> Result<()> {
+		let depth = self.find_savepoint(name)?;
+		self.rollback_to_depth(depth)
+	}
 
-				// Flush to disk
-				pager.flush()?;
-				self.dirty = false;
+	/// Run a change as a unit: on failure everything it did is undone
+	///
+	/// Outside a transaction the change is its own transaction and is
+	/// written to disk if it succeeds. Inside one, it is undone back to a
+	/// savepoint and the transaction carries on.
+	pub fn atomic<T, F>(&mut self, change: F) -> Result<T>
+	where
+		F: FnOnce(&mut Self) -> Result<T>,
+	{
+		let depth = self.savepoints.len();
+		self.push_savepoint(None)?;
+		let result = change(self);
+		match (&result, depth) {
+			(Ok(_), 0) => {
+				if let Err(e) = self.commit() {
+					self.rollback()?;
+					return Err(e);
+				}
+			}
+			(Ok(_), _) => {
+				self.savepoints.truncate(depth);
+				self.pager.release(depth);
 			}
+			(Err(_), 0) => self.rollback()?,
+			(Err(_), _) => {
+				self.rollback_to_depth(depth)?;
+				self.savepoints.truncate(depth);
+				self.pager.release(depth);
+			}
+		}
+		result
+	}
+
+	fn push_savepoint(&mut self, name: Option<String>) -> Result<()> {
+		if self.in_transaction() {
+			self.pager.savepoint();
+		} else {
+			self.begin_read()?;
+			self.pager.begin_transaction()?;
 		}
+		self.savepoints.push(Savepoint {
+			name,
+			tables: self.tables.clone(),
+			dirty: self.dirty,
+			schema_changed: self.schema_changed,
+			auto_vacuum: self.auto_vacuum,
+		});
 		Ok(())
 	}
 
+	/// Depth of the most recent savepoint with this name
+	fn find_savepoint(&self, name: &str) -> Result<usize> {
+		self.savepoints
+			.iter()
+			.rposition(|savepoint| savepoint.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+			.ok_or_else(|| Error::NotFound(format!("no such savepoint: {}", name)))
+	}
+
+	/// Restore the state at the savepoint at `depth`, discarding those
+	/// taken after it
+	fn rollback_to_depth(&mut self, depth: usize) -> Result<()> {
+		self.savepoints.truncate(depth + 1);
+		let Some(savepoint) = self.savepoints.last() else {
+			return Ok(());
+		};
+		self.tables = savepoint.tables.clone();
+		self.dirty = savepoint.dirty;
+		self.schema_changed = savepoint.schema_changed;
+		self.auto_vacuum = savepoint.auto_vacuum;
+		self.pager.rollback_to(depth)
+	}
+
 	/// Mark storage as dirty (needs save)
 	fn mark_dirty(&mut self) {
 		self.dirty = true;
@@ -399,36 +1639,88 @@ impl StorageManager {
 
 	/// Create a table
 	pub fn create_table(&mut self, stmt: CreateTableStatement) -> Result<()> {
-		if self.tables.contains_key(&stmt.name) {
+		// Names are unique across a SQLite 3 schema, ignoring case
+		if self.table_exists(&stmt.name) {
 			return Err(Error::Constraint(format!(
 				"Table '{}' already exists",
 				stmt.name
 			)));
 		}
+		if self.format == FileFormat::SQLiteV3 && self.pager.page_count() > 0 {
+			for (_, entry) in self.schema_entries()? {
+				if let (Some(ValueType::StringUtf8(kind)), Some(ValueType::StringUtf8(name))) = (entry.first(), entry.get(1)) {
+					if name.eq_ignore_ascii_case(&stmt.name) {
+						return Err(Error::Constraint(format!("There is already a {} named '{}'", kind, name)));
+					}
+				}
+			}
+		}
+
+		// Page 1 is reserved for the header and catalog
+		if self.pager.page_count() == 0 {
+			self.pager.allocate_page()?;
+		}
+		let btree = BTree::create(&mut self.pager, BTreeKind::Table)?;
 
-		let table = Table::new(stmt.name.clone(), stmt.columns);
+		let mut table = Table::new(stmt.name.clone(), stmt.columns, btree.root_page());
+		if self.format == FileFormat::SQLiteV3 {
+			self.add_sqlite_table(&mut table)?;
+		}
 		self.tables.insert(stmt.name, table);
 		self.mark_dirty();
 		self.save_to_disk()?;
 		Ok(())
 	}
 
+	/// Record a new table in the SQLite 3 schema along with the automatic
+	/// indexes for its PRIMARY KEY and UNIQUE constraints
+	fn add_sqlite_table(&mut self, table: &mut Table) -> Result<()> {
+		let sql = schema::create_table_sql(&table.name, &table.columns);
+		let definition = schema::parse_create_table(&sql)?;
+		table.rowid_alias = definition.rowid_alias;
+		table.format = FileFormat::SQLiteV3;
+		self.add_schema_entry("table", &table.name, &table.name, table.root_page, Some(sql))?;
+
+		for (i, key) in definition.unique_keys.into_iter().enumerate() {
+			let btree = BTree::create(&mut self.pager, BTreeKind::Index)?;
+			let index = SchemaIndex {
+				name: format!("sqlite_autoindex_{}_{}", table.name, i + 1),
+				table: table.name.clone(),
+				root_page: btree.root_page(),
+				sql: None,
+				columns: Some(key),
+				unique: true,
+			};
+			self.add_schema_entry("index", &index.name, &table.name, index.root_page, None)?;
+			table.indexes.push(index);
+		}
+		Ok(())
+	}
+
+	/// Name a table is kept under; as in SQLite, table names match
+	/// without regard to case
+	fn table_key(&self, name: &str) -> Option<String> {
+		if self.tables.contains_key(name) {
+			return Some(name.to_string());
+		}
+		self.tables.keys().find(|key| key.eq_ignore_ascii_case(name)).cloned()
+	}
+
 	/// Get a table
 	pub fn get_table(&self, name: &str) -> Option<&Table> {
-		self.tables.get(name)
+		self.tables.get(&self.table_key(name)?)
 	}
 
 	/// Get a mutable table
 	pub fn get_table_mut(&mut self, name: &str) -> Option<&mut Table> {
-		if self.tables.contains_key(name) {
-			self.mark_dirty();
-		}
-		self.tables.get_mut(name)
+		let key = self.table_key(name)?;
+		self.mark_dirty();
+		self.tables.get_mut(&key)
 	}
 
 	/// Check if a table exists
 	pub fn table_exists(&self, name: &str) -> bool {
-		self.tables.contains_key(name)
+		self.table_key(name).is_some()
 	}
 
 	/// List all table names
@@ -438,12 +1730,27 @@ impl StorageManager {
 
 	/// Drop a table
 	pub fn drop_table(&mut self, name: &str) -> Result<()> {
-		if self.tables.remove(name).is_some() {
+		if let Some(table) = self.table_key(name).and_then(|key| self.tables.remove(&key)) {
+			table.btree().destroy(&mut self.pager)?;
+			for index in &table.indexes {
+				index.btree().destroy(&mut self.pager)?;
+			}
+
+			// Remove the table and its indexes from a SQLite 3 schema
+			if self.format == FileFormat::SQLiteV3 {
+				let mut schema = BTree::new(1);
+				for (rowid, entry) in self.schema_entries()? {
+					if matches!(entry.get(2), Some(ValueType::StringUtf8(owner)) if owner.eq_ignore_ascii_case(&table.name)) {
+						schema.delete(&mut self.pager, rowid)?;
+					}
+				}
+				self.schema_changed = true;
+			}
 			self.mark_dirty();
 			self.save_to_disk()?;
 			Ok(())
 		} else {
-			Err(Error::NotFound(format!("Table '{}' not found", name)))
+			Err(self.missing_table(name))
 		}
 	}
 
@@ -452,17 +1759,99 @@ impl StorageManager {
 		self.save_to_disk()
 	}
 
+	/// Flush pending changes and bring the database file up to date with
+	/// the write-ahead log, which is deleted
+	pub fn close(&mut self) -> Result<()> {
+		self.save_to_disk()?;
+		#[cfg(feature = "std")]
+		self.pager.close()?;
+		Ok(())
+	}
+
+	/// Look up a table together with the pager holding its pages
+	fn table_and_pager(&mut self, name: &str) -> Result<(&Table, &mut Pager)> {
+		let Some(key) = self.table_key(name) else {
+			return Err(self.missing_table(name));
+		};
+		Ok((&self.tables[&key], &mut self.pager))
+	}
+
+	/// Error for a name that is not a table: one of the schema objects we
+	/// cannot use, or nothing at all
+	fn missing_table(&self, name: &str) -> Error {
+		match self.unsupported.iter().find(|(object, _)| object.eq_ignore_ascii_case(name)) {
+			Some((object, kind)) => {
+				Error::NotSupported(format!("'{}' is a {}, which is not supported", object, kind))
+			}
+			None => Error::NotFound(format!("Table '{}' not found", name)),
+		}
+	}
+
+	/// Number of rows in a table
+	pub fn row_count(&mut self, table_name: &str) -> Result<usize> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		table.row_count(pager)
+	}
+
+	/// Insert a row into a table
+	pub fn insert(&mut self, table_name: &str, row: Row) -> Result<()> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		table.insert(pager, row)?;
+		self.mark_dirty();
+		Ok(())
+	}
+
+	/// Select all rows from a table
+	pub fn select_all(&mut self, table_name: &str) -> Result<Vec<Row>> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		table.select_all(pager)
+	}
+
+	/// Select rows from a table with WHERE clause filtering
+	pub fn select(&mut self, table_name: &str, condition: Option<&Expr>) -> Result<Vec<Row>> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		table.select(pager, condition)
+	}
+
+	/// Select rows from a table ordered by a column
+	pub fn select_ordered(&mut self, table_name: &str, condition: Option<&Expr>, order_by_column: &str, ascending: bool) -> Result<Vec<Row>> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		table.select_ordered(pager, condition, order_by_column, ascending)
+	}
+
+	/// Select rows from a table grouped by a column
+	pub fn select_grouped(&mut self, table_name: &str, condition: Option<&Expr>, group_by_column: &str) -> Result<HashMap<String, Vec<Row>>> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		table.select_grouped(pager, condition, group_by_column)
+	}
+
+	/// Update rows of a table matching a condition
+	pub fn update(&mut self, table_name: &str, condition: Option<&Expr>, updates: &[(String, Expr)]) -> Result<usize> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		let count = table.update(pager, condition, updates)?;
+		self.mark_dirty();
+		Ok(count)
+	}
+
+	/// Delete rows of a table matching a condition
+	pub fn delete(&mut self, table_name: &str, condition: Option<&Expr>) -> Result<usize> {
+		let (table, pager) = self.table_and_pager(table_name)?;
+		let count = table.delete(pager, condition)?;
+		self.mark_dirty();
+		Ok(count)
+	}
+
 	/// Perform a simple CROSS JOIN between two tables (Cartesian product)
-	pub fn cross_join(&self, table1_name: &str, table2_name: &str) -> Result<(Vec<Vec<String>>, Vec<String>)> {
-		let table1 = self.tables.get(table1_name)
+	pub fn cross_join(&mut self, table1_name: &str, table2_name: &str) -> Result<(Vec<Row>, Vec<String>)> {
+		// Get all rows from both tables
+		let rows1 = self.select_all(table1_name)?;
+		let rows2 = self.select_all(table2_name)?;
+
+		let table1 = self.get_table(table1_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table1_name)))?;
-		let table2 = self.tables.get(table2_name)
+		let table2 = self.get_table(table2_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table2_name)))?;
 
-		// Get all rows from both tables
-		let rows1 = table1.select_all();
-		let rows2 = table2.select_all();
-
 		// Build column names with table prefixes
 		let mut column_names = Vec::new();
 		for col in &table1.columns {
@@ -486,22 +1875,19 @@ impl StorageManager {
 	}
 
 	/// Perform INNER JOIN between two tables with ON condition
-	pub fn inner_join(&self, table1_name: &str, table2_name: &str, on_condition: &str) -> Result<(Vec<Vec<String>>, Vec<String>)> {
-		let table1 = self.tables.get(table1_name)
+	pub fn inner_join(&mut self, table1_name: &str, table2_name: &str, on_condition: &Expr) -> Result<(Vec<Row>, Vec<String>)> {
+		// Get all rows from both tables
+		let rows1 = self.select_all(table1_name)?;
+		let rows2 = self.select_all(table2_name)?;
+
+		let table1 = self.get_table(table1_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table1_name)))?;
-		let table2 = self.tables.get(table2_name)
+		let table2 = self.get_table(table2_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table2_name)))?;
 
-		// Parse ON condition (e.g., "table1.id = table2.user_id")
-		let (left_col, right_col) = self.parse_join_condition(on_condition)?;
-
-		// Find column indices
-		let left_idx = self.find_column_in_table(table1, table1_name, &left_col)?;
-		let right_idx = self.find_column_in_table(table2, table2_name, &right_col)?;
-
-		// Get all rows from both tables
-		let rows1 = table1.select_all();
-		let rows2 = table2.select_all();
+		// The ON condition sees the columns of both tables
+		let scope = Scope::new(table1_name, &table1.columns).join(table2_name, &table2.columns);
+		on_condition.resolve(&scope)?;
 
 		// Build column names with table prefixes
 		let mut column_names = Vec::new();
@@ -516,9 +1902,9 @@ impl StorageManager {
 		let mut result_rows = Vec::new();
 		for row1 in &rows1 {
 			for row2 in &rows2 {
-				if left_idx < row1.len() && right_idx < row2.len() && row1[left_idx] == row2[right_idx] {
-					let mut combined_row = row1.clone();
-					combined_row.extend(row2.clone());
+				let mut combined_row = row1.clone();
+				combined_row.extend(row2.clone());
+				if on_condition.matches(&scope, &combined_row)? {
 					result_rows.push(combined_row);
 				}
 			}
@@ -528,22 +1914,19 @@ impl StorageManager {
 	}
 
 	/// Perform LEFT JOIN between two tables with ON condition
-	pub fn left_join(&self, table1_name: &str, table2_name: &str, on_condition: &str) -> Result<(Vec<Vec<String>>, Vec<String>)> {
-		let table1 = self.tables.get(table1_name)
+	pub fn left_join(&mut self, table1_name: &str, table2_name: &str, on_condition: &Expr) -> Result<(Vec<Row>, Vec<String>)> {
+		// Get all rows from both tables
+		let rows1 = self.select_all(table1_name)?;
+		let rows2 = self.select_all(table2_name)?;
+
+		let table1 = self.get_table(table1_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table1_name)))?;
-		let table2 = self.tables.get(table2_name)
+		let table2 = self.get_table(table2_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table2_name)))?;
 
-		// Parse ON condition (e.g., "table1.id = table2.user_id")
-		let (left_col, right_col) = self.parse_join_condition(on_condition)?;
-
-		// Find column indices
-		let left_idx = self.find_column_in_table(table1, table1_name, &left_col)?;
-		let right_idx = self.find_column_in_table(table2, table2_name, &right_col)?;
-
-		// Get all rows from both tables
-		let rows1 = table1.select_all();
-		let rows2 = table2.select_all();
+		// The ON condition sees the columns of both tables
+		let scope = Scope::new(table1_name, &table1.columns).join(table2_name, &table2.columns);
+		on_condition.resolve(&scope)?;
 
 		// Build column names with table prefixes
 		let mut column_names = Vec::new();
@@ -559,9 +1942,9 @@ impl StorageManager {
 		for row1 in &rows1 {
 			let mut matched = false;
 			for row2 in &rows2 {
-				if left_idx < row1.len() && right_idx < row2.len() && row1[left_idx] == row2[right_idx] {
-					let mut combined_row = row1.clone();
-					combined_row.extend(row2.clone());
+				let mut combined_row = row1.clone();
+				combined_row.extend(row2.clone());
+				if on_condition.matches(&scope, &combined_row)? {
 					result_rows.push(combined_row);
 					matched = true;
 				}
@@ -570,7 +1953,7 @@ impl StorageManager {
 			if !matched {
 				let mut combined_row = row1.clone();
 				for _ in 0..table2.columns.len() {
-					combined_row.push("NULL".to_string());
+					combined_row.push(ValueType::Null);
 				}
 				result_rows.push(combined_row);
 			}
@@ -580,22 +1963,19 @@ impl StorageManager {
 	}
 
 	/// Perform RIGHT JOIN between two tables with ON condition
-	pub fn right_join(&self, table1_name: &str, table2_name: &str, on_condition: &str) -> Result<(Vec<Vec<String>>, Vec<String>)> {
-		let table1 = self.tables.get(table1_name)
+	pub fn right_join(&mut self, table1_name: &str, table2_name: &str, on_condition: &Expr) -> Result<(Vec<Row>, Vec<String>)> {
+		// Get all rows from both tables
+		let rows1 = self.select_all(table1_name)?;
+		let rows2 = self.select_all(table2_name)?;
+
+		let table1 = self.get_table(table1_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table1_name)))?;
-		let table2 = self.tables.get(table2_name)
+		let table2 = self.get_table(table2_name)
 			.ok_or_else(|| Error::NotFound(format!("Table '{}' not found", table2_name)))?;
 
-		// Parse ON condition (e.g., "table1.id = table2.user_id")
-		let (left_col, right_col) = self.parse_join_condition(on_condition)?;
-
-		// Find column indices
-		let left_idx = self.find_column_in_table(table1, table1_name, &left_col)?;
-		let right_idx = self.find_column_in_table(table2, table2_name, &right_col)?;
-
-		// Get all rows from both tables
-		let rows1 = table1.select_all();
-		let rows2 = table2.select_all();
+		// The ON condition sees the columns of both tables
+		let scope = Scope::new(table1_name, &table1.columns).join(table2_name, &table2.columns);
+		on_condition.resolve(&scope)?;
 
 		// Build column names with table prefixes
 		let mut column_names = Vec::new();
@@ -611,9 +1991,9 @@ impl StorageManager {
 		for row2 in &rows2 {
 			let mut matched = false;
 			for row1 in &rows1 {
-				if left_idx < row1.len() && right_idx < row2.len() && row1[left_idx] == row2[right_idx] {
-					let mut combined_row = row1.clone();
-					combined_row.extend(row2.clone());
+				let mut combined_row = row1.clone();
+				combined_row.extend(row2.clone());
+				if on_condition.matches(&scope, &combined_row)? {
 					result_rows.push(combined_row);
 					matched = true;
 				}
@@ -622,7 +2002,7 @@ impl StorageManager {
 			if !matched {
 				let mut combined_row = Vec::new();
 				for _ in 0..table1.columns.len() {
-					combined_row.push("NULL".to_string());
+					combined_row.push(ValueType::Null);
 				}
 				combined_row.extend(row2.clone());
 				result_rows.push(combined_row);
@@ -631,39 +2011,6 @@ impl StorageManager {
 
 		Ok((result_rows, column_names))
 	}
-
-	/// Parse JOIN ON condition like "table1.col1 = table2.col2"
-	fn parse_join_condition(&self, condition: &str) -> Result<(String, String)> {
-		let parts: Vec<&str> = condition.split('=').map(|s| s.trim()).collect();
-		if parts.len() != 2 {
-			return Err(Error::Syntax(format!("Invalid JOIN condition: {}", condition)));
-		}
-		Ok((parts[0].to_string(), parts[1].to_string()))
-	}
-
-	/// Find column index in table, supporting table.column notation
-	fn find_column_in_table(&self, table: &Table, table_name: &str, column_ref: &str) -> Result<usize> {
-		// Handle table.column or just column
-		let column_ref = column_ref.trim();
-		let column_name = if column_ref.contains('.') {
-			let parts: Vec<&str> = column_ref.split('.').map(|s| s.trim()).collect();
-			if parts.len() == 2 {
-				// Verify table name matches
-				if parts[0] != table_name {
-					return Err(Error::Syntax(format!("Table name mismatch: {} vs {}", parts[0], table_name)));
-				}
-				parts[1]
-			} else {
-				return Err(Error::Syntax(format!("Invalid column reference: {}", column_ref)));
-			}
-		} else {
-			column_ref
-		};
-
-		table.columns.iter()
-			.position(|c| c.name == column_name)
-			.ok_or_else(|| Error::NotFound(format!("Column '{}' not found in table '{}'", column_name, table_name)))
-	}
 }
 
 impl Default for StorageManager {
@@ -675,9 +2022,22 @@ impl Default for StorageManager {
 #[cfg(test)]
 mod tests {
 	use super::*;
+
+	fn int(v: i64) -> ValueType {
+		ValueType::I64(v)
+	}
+
+	fn text(s: &str) -> ValueType {
+		ValueType::StringUtf8(s.to_string())
+	}
+
+	fn condition(sql: &str) -> Expr {
+		Parser::new().parse_expression(sql).unwrap()
+	}
+	use crate::eplite::command::parser::Parser;
 	use crate::eplite::types::column::ColumnType;
 
-	fn create_test_table() -> Table {
+	fn create_test_manager() -> StorageManager {
 		let columns = vec![
 			ColumnDefinition {
 				name: "id".to_string(),
@@ -690,36 +2050,43 @@ mod tests {
 				constraints: vec![],
 			},
 		];
-		Table::new("users".to_string(), columns)
+		let mut mgr = StorageManager::new();
+		mgr.create_table(CreateTableStatement {
+			name: "users".to_string(),
+			columns,
+		})
+		.unwrap();
+		mgr
 	}
 
 	#[test]
 	fn test_table_creation() {
-		let table = create_test_table();
+		let mut mgr = create_test_manager();
+		let table = mgr.get_table("users").unwrap();
 		assert_eq!(table.name, "users");
 		assert_eq!(table.columns.len(), 2);
-		assert_eq!(table.row_count(), 0);
+		assert_eq!(mgr.row_count("users").unwrap(), 0);
 	}
 
 	#[test]
 	fn test_table_insert() {
-		let mut table = create_test_table();
-		let row = vec!["1".to_string(), "Alice".to_string()];
-		table.insert(row).unwrap();
-		assert_eq!(table.row_count(), 1);
+		let mut mgr = create_test_manager();
+		let row = vec![int(1), text("Alice")];
+		mgr.insert("users", row).unwrap();
+		assert_eq!(mgr.row_count("users").unwrap(), 1);
 	}
 
 	#[test]
 	fn test_table_select_all() {
-		let mut table = create_test_table();
-		table
-			.insert(vec!["1".to_string(), "Alice".to_string()])
+		let mut mgr = create_test_manager();
+		mgr
+			.insert("users", vec![int(1), text("Alice")])
 			.unwrap();
-		table
-			.insert(vec!["2".to_string(), "Bob".to_string()])
+		mgr
+			.insert("users", vec![int(2), text("Bob")])
 			.unwrap();
 		
-		let rows = table.select_all();
+		let rows = mgr.select_all("users").unwrap();
 		assert_eq!(rows.len(), 2);
 	}
 
@@ -776,80 +2143,184 @@ mod tests {
 		assert!(tables.contains(&"posts".to_string()));
 	}
 
+	#[test]
+	fn test_tables_span_multiple_pages() {
+		use crate::eplite::os::file::DefaultFile;
+
+		let temp_path = "/tmp/epiloglite_test_storage_pages.db";
+		let _ = std::fs::remove_file(temp_path);
+
+		let stmt = CreateTableStatement {
+			name: "documents".to_string(),
+			columns: vec![
+				ColumnDefinition {
+					name: "id".to_string(),
+					data_type: ColumnType::Int32,
+					constraints: vec![],
+				},
+				ColumnDefinition {
+					name: "body".to_string(),
+					data_type: ColumnType::Text,
+					constraints: vec![],
+				},
+			],
+		};
+
+		{
+			let file = Box::new(DefaultFile::open(temp_path, true, true, true).unwrap());
+			let mut mgr = StorageManager::with_pager(Pager::with_file(1024, file).unwrap());
+			mgr.create_table(stmt).unwrap();
+			for i in 0..200 {
+				mgr.insert("documents", vec![int(i), text(&"x".repeat(100))]).unwrap();
+			}
+			mgr.flush().unwrap();
+			assert!(mgr.pager.page_count() > 20);
+		}
+
+		{
+			let file = Box::new(DefaultFile::open(temp_path, true, true, false).unwrap());
+			let mut mgr = StorageManager::with_pager(Pager::open(file).unwrap());
+			mgr.load_from_disk().unwrap();
+			assert_eq!(mgr.row_count("documents").unwrap(), 200);
+			let rows = mgr.select_all("documents").unwrap();
+			assert_eq!(rows[199][0], int(199));
+			assert_eq!(rows[199][1], text(&"x".repeat(100)));
+		}
+
+		std::fs::remove_file(temp_path).unwrap();
+	}
+
+	#[test]
+	fn test_dropped_table_pages_are_reused() {
+		let mut mgr = create_test_manager();
+		for i in 0..300 {
+			mgr.insert("users", vec![int(i), text(&format!("user number {}", i))]).unwrap();
+		}
+		let pages = mgr.pager.page_count();
+		let columns = mgr.get_table("users").unwrap().columns.clone();
+
+		mgr.drop_table("users").unwrap();
+		assert_eq!(mgr.pager.freelist_count().unwrap(), pages - 1);
+
+		// A new table of the same size fits in the freed pages
+		mgr.create_table(CreateTableStatement {
+			name: "users".to_string(),
+			columns,
+		})
+		.unwrap();
+		for i in 0..300 {
+			mgr.insert("users", vec![int(i), text(&format!("user number {}", i))]).unwrap();
+		}
+		assert_eq!(mgr.pager.page_count(), pages);
+		assert_eq!(mgr.pager.freelist_count().unwrap(), 0);
+	}
+
 	#[test]
 	fn test_where_clause_equal() {
-		let mut table = create_test_table();
-		table.insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		table.insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
-		table.insert(vec!["3".to_string(), "Charlie".to_string()]).unwrap();
+		let mut mgr = create_test_manager();
+		mgr.insert("users", vec![int(1), text("Alice")]).unwrap();
+		mgr.insert("users", vec![int(2), text("Bob")]).unwrap();
+		mgr.insert("users", vec![int(3), text("Charlie")]).unwrap();
 
-		let rows = table.select(Some("id = 2")).unwrap();
+		let rows = mgr.select("users", Some(&condition("id = 2"))).unwrap();
 		assert_eq!(rows.len(), 1);
-		assert_eq!(rows[0][0], "2");
-		assert_eq!(rows[0][1], "Bob");
+		assert_eq!(rows[0][0], int(2));
+		assert_eq!(rows[0][1], text("Bob"));
 	}
 
 	#[test]
 	fn test_where_clause_greater_than() {
-		let mut table = create_test_table();
-		table.insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		table.insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
-		table.insert(vec!["3".to_string(), "Charlie".to_string()]).unwrap();
+		let mut mgr = create_test_manager();
+		mgr.insert("users", vec![int(1), text("Alice")]).unwrap();
+		mgr.insert("users", vec![int(2), text("Bob")]).unwrap();
+		mgr.insert("users", vec![int(3), text("Charlie")]).unwrap();
 
-		let rows = table.select(Some("id > 1")).unwrap();
+		let rows = mgr.select("users", Some(&condition("id > 1"))).unwrap();
 		assert_eq!(rows.len(), 2);
 	}
 
 	#[test]
 	fn test_where_clause_less_than() {
-		let mut table = create_test_table();
-		table.insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		table.insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
-		table.insert(vec!["3".to_string(), "Charlie".to_string()]).unwrap();
+		let mut mgr = create_test_manager();
+		mgr.insert("users", vec![int(1), text("Alice")]).unwrap();
+		mgr.insert("users", vec![int(2), text("Bob")]).unwrap();
+		mgr.insert("users", vec![int(3), text("Charlie")]).unwrap();
 
-		let rows = table.select(Some("id < 3")).unwrap();
+		let rows = mgr.select("users", Some(&condition("id < 3"))).unwrap();
 		assert_eq!(rows.len(), 2);
 	}
 
 	#[test]
 	fn test_where_clause_string_equal() {
-		let mut table = create_test_table();
-		table.insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		table.insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
+		let mut mgr = create_test_manager();
+		mgr.insert("users", vec![int(1), text("Alice")]).unwrap();
+		mgr.insert("users", vec![int(2), text("Bob")]).unwrap();
 
-		let rows = table.select(Some("name = 'Alice'")).unwrap();
+		let rows = mgr.select("users", Some(&condition("name = 'Alice'"))).unwrap();
 		assert_eq!(rows.len(), 1);
-		assert_eq!(rows[0][1], "Alice");
+		assert_eq!(rows[0][1], text("Alice"));
 	}
 
 	#[test]
 	fn test_update_with_where() {
-		let mut table = create_test_table();
-		table.insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		table.insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
+		let mut mgr = create_test_manager();
+		mgr.insert("users", vec![int(1), text("Alice")]).unwrap();
+		mgr.insert("users", vec![int(2), text("Bob")]).unwrap();
 
-		let updates = vec![("name".to_string(), "Bobby".to_string())];
-		let count = table.update(Some("id = 2"), &updates).unwrap();
+		let updates = vec![("name".to_string(), condition("'Bobby'"))];
+		let count = mgr.update("users", Some(&condition("id = 2")), &updates).unwrap();
 		assert_eq!(count, 1);
 
-		let rows = table.select(Some("id = 2")).unwrap();
-		assert_eq!(rows[0][1], "Bobby");
+		let rows = mgr.select("users", Some(&condition("id = 2"))).unwrap();
+		assert_eq!(rows[0][1], text("Bobby"));
+
+		// Values are computed from each row, and columns found ignoring case
+		let updates = vec![("NAME".to_string(), condition("name || id"))];
+		assert_eq!(mgr.update("users", None, &updates).unwrap(), 2);
+		let rows = mgr.select("users", None).unwrap();
+		assert_eq!(rows[0][1], text("Alice1"));
+		assert_eq!(rows[1][1], text("Bobby2"));
+
+		let updates = vec![("missing".to_string(), condition("1"))];
+		assert!(matches!(mgr.update("users", None, &updates), Err(Error::NotFound(_))));
 	}
 
 	#[test]
 	fn test_delete_with_where() {
-		let mut table = create_test_table();
-		table.insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		table.insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
-		table.insert(vec!["3".to_string(), "Charlie".to_string()]).unwrap();
+		let mut mgr = create_test_manager();
+		mgr.insert("users", vec![int(1), text("Alice")]).unwrap();
+		mgr.insert("users", vec![int(2), text("Bob")]).unwrap();
+		mgr.insert("users", vec![int(3), text("Charlie")]).unwrap();
 
-		let count = table.delete(Some("id = 2")).unwrap();
+		let count = mgr.delete("users", Some(&condition("id = 2"))).unwrap();
 		assert_eq!(count, 1);
-		assert_eq!(table.row_count(), 2);
+		assert_eq!(mgr.row_count("users").unwrap(), 2);
 
 		// Verify Bob is gone
-		let rows = table.select(None).unwrap();
-		assert!(!rows.iter().any(|r| r[1] == "Bob"));
+		let rows = mgr.select("users", None).unwrap();
+		assert!(!rows.iter().any(|r| r[1] == text("Bob")));
+	}
+
+	#[test]
+	fn test_rows_take_column_affinity() {
+		let mut mgr = create_test_manager();
+		mgr.insert("users", vec![text("10"), int(7)]).unwrap();
+		mgr.insert("users", vec![int(9), ValueType::Null]).unwrap();
+
+		let rows = mgr.select_all("users").unwrap();
+		assert_eq!(rows[0], vec![int(10), text("7")]);
+		assert_eq!(rows[1], vec![int(9), ValueType::Null]);
+
+		// Numbers compare as numbers, not as text
+		assert_eq!(mgr.select("users", Some(&condition("id > 9"))).unwrap().len(), 1);
+		assert_eq!(mgr.select("users", Some(&condition("id = '10'"))).unwrap().len(), 1);
+		assert_eq!(mgr.select("users", Some(&condition("name = 7"))).unwrap().len(), 1);
+		// NULL is never equal to anything
+		assert_eq!(mgr.select("users", Some(&condition("name = NULL"))).unwrap().len(), 0);
+		assert_eq!(mgr.select("users", Some(&condition("name <> 'x'"))).unwrap().len(), 1);
+
+		let ordered = mgr.select_ordered("users", None, "id", true).unwrap();
+		assert_eq!(ordered[0][0], int(9));
 	}
 
 	#[test]
@@ -893,23 +2364,23 @@ mod tests {
 		manager.create_table(orders_def).unwrap();
 
 		// Insert test data
-		manager.get_table_mut("users").unwrap().insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		manager.get_table_mut("users").unwrap().insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
-		manager.get_table_mut("users").unwrap().insert(vec!["3".to_string(), "Charlie".to_string()]).unwrap();
-		manager.get_table_mut("orders").unwrap().insert(vec!["101".to_string(), "1".to_string()]).unwrap();
-		manager.get_table_mut("orders").unwrap().insert(vec!["102".to_string(), "2".to_string()]).unwrap();
+		manager.insert("users", vec![int(1), text("Alice")]).unwrap();
+		manager.insert("users", vec![int(2), text("Bob")]).unwrap();
+		manager.insert("users", vec![int(3), text("Charlie")]).unwrap();
+		manager.insert("orders", vec![int(101), int(1)]).unwrap();
+		manager.insert("orders", vec![int(102), int(2)]).unwrap();
 
 		// Perform LEFT JOIN
-		let (rows, columns) = manager.left_join("users", "orders", "users.id = orders.user_id").unwrap();
+		let (rows, columns) = manager.left_join("users", "orders", &condition("users.id = orders.user_id")).unwrap();
 
 		// Should have 3 rows (all users, Charlie with NULL for orders)
 		assert_eq!(rows.len(), 3);
 		assert_eq!(columns.len(), 4); // 2 from users + 2 from orders
 
 		// Check that Charlie (id=3) has NULLs for order data
-		let charlie_row = rows.iter().find(|r| r[0] == "3").unwrap();
-		assert_eq!(charlie_row[2], "NULL"); // order_id should be NULL
-		assert_eq!(charlie_row[3], "NULL"); // user_id should be NULL
+		let charlie_row = rows.iter().find(|r| r[0] == int(3)).unwrap();
+		assert_eq!(charlie_row[2], ValueType::Null); // order_id should be NULL
+		assert_eq!(charlie_row[3], ValueType::Null); // user_id should be NULL
 	}
 
 	#[test]
@@ -953,22 +2424,75 @@ mod tests {
 		manager.create_table(orders_def).unwrap();
 
 		// Insert test data - note order 103 has no matching user
-		manager.get_table_mut("users").unwrap().insert(vec!["1".to_string(), "Alice".to_string()]).unwrap();
-		manager.get_table_mut("users").unwrap().insert(vec!["2".to_string(), "Bob".to_string()]).unwrap();
-		manager.get_table_mut("orders").unwrap().insert(vec!["101".to_string(), "1".to_string()]).unwrap();
-		manager.get_table_mut("orders").unwrap().insert(vec!["102".to_string(), "2".to_string()]).unwrap();
-		manager.get_table_mut("orders").unwrap().insert(vec!["103".to_string(), "99".to_string()]).unwrap(); // No matching user
+		manager.insert("users", vec![int(1), text("Alice")]).unwrap();
+		manager.insert("users", vec![int(2), text("Bob")]).unwrap();
+		manager.insert("orders", vec![int(101), int(1)]).unwrap();
+		manager.insert("orders", vec![int(102), int(2)]).unwrap();
+		manager.insert("orders", vec![int(103), int(99)]).unwrap(); // No matching user
 
 		// Perform RIGHT JOIN
-		let (rows, columns) = manager.right_join("users", "orders", "users.id = orders.user_id").unwrap();
+		let (rows, columns) = manager.right_join("users", "orders", &condition("users.id = orders.user_id")).unwrap();
 
 		// Should have 3 rows (all orders, order 103 with NULL for user data)
 		assert_eq!(rows.len(), 3);
 		assert_eq!(columns.len(), 4); // 2 from users + 2 from orders
 
 		// Check that order 103 has NULLs for user data
-		let orphan_order = rows.iter().find(|r| r[2] == "103").unwrap();
-		assert_eq!(orphan_order[0], "NULL"); // user id should be NULL
-		assert_eq!(orphan_order[1], "NULL"); // user name should be NULL
+		let orphan_order = rows.iter().find(|r| r[2] == int(103)).unwrap();
+		assert_eq!(orphan_order[0], ValueType::Null); // user id should be NULL
+		assert_eq!(orphan_order[1], ValueType::Null); // user name should be NULL
+	}
+
+	#[test]
+	fn test_parse_sqlite_schema() {
+		let definition = schema::parse_create_table(
+			"CREATE TABLE \"notes\" (note_id INTEGER, body VARCHAR(255) DEFAULT '', \
+			 user_id INTEGER REFERENCES users (id), PRIMARY KEY (note_id))",
+		)
+		.unwrap();
+		let names: Vec<&str> = definition.columns.iter().map(|c| c.name.as_str()).collect();
+		assert_eq!(names, vec!["note_id", "body", "user_id"]);
+		assert_eq!(definition.columns[1].data_type, ColumnType::Text);
+		assert_eq!(definition.defaults, vec![ValueType::Null, text(""), ValueType::Null]);
+		assert_eq!(definition.rowid_alias, Some(0));
+		assert!(!definition.without_rowid);
+
+		let definition = schema::parse_create_table(
+			"CREATE TABLE [t] (\"a b\" TEXT NOT NULL, id INTEGER PRIMARY KEY)",
+		)
+		.unwrap();
+		assert_eq!(definition.columns[0].name, "a b");
+		assert_eq!(definition.columns[0].constraints, vec!["NOT NULL".to_string()]);
+		assert_eq!(definition.rowid_alias, Some(1));
+
+		let definition =
+			schema::parse_create_table("CREATE TABLE t (a INT DEFAULT -1, b DEFAULT (2 * 3), c TEXT DEFAULT \"x\")").unwrap();
+		assert_eq!(definition.defaults, vec![int(-1), int(6), text("x")]);
+
+		let definition =
+			schema::parse_create_table("CREATE TABLE kv (k INT PRIMARY KEY, v) WITHOUT ROWID").unwrap();
+		assert!(definition.without_rowid);
+		assert_eq!(definition.rowid_alias, None);
+		assert_eq!(definition.columns[1].data_type, ColumnType::Blob);
+	}
+
+	#[test]
+	fn test_sqlite_schema_keys() {
+		let definition =
+			schema::parse_create_table("CREATE TABLE t (a TEXT, b INT, c UNIQUE, PRIMARY KEY (a, b))").unwrap();
+		assert_eq!(definition.unique_keys, vec![vec![2], vec![0, 1]]);
+		assert_eq!(definition.rowid_alias, None);
+
+		let columns = definition.columns;
+		let sql = schema::create_table_sql("my table", &columns);
+		assert_eq!(sql, "CREATE TABLE \"my table\" (a TEXT, b INTEGER, c BLOB UNIQUE, PRIMARY KEY (a, b))");
+
+		assert_eq!(
+			schema::parse_create_index("CREATE UNIQUE INDEX i ON t (a, \"b\" ASC)"),
+			Some((vec!["a".to_string(), "b".to_string()], true))
+		);
+		assert_eq!(schema::parse_create_index("CREATE INDEX i ON t (a DESC)"), None);
+		assert_eq!(schema::parse_create_index("CREATE INDEX i ON t (lower(a))"), None);
+		assert_eq!(schema::parse_create_index("CREATE INDEX i ON t (a) WHERE a > 1"), None);
 	}
 }
@@ -1,5 +1,7 @@
 use flagset::{flags, FlagSet};
 
+#[cfg(feature = "mmap")]
+use crate::eplite::os::mmap::MemoryMap;
 #[cfg(feature = "std")]
 use std::io;
 #[cfg(feature = "std")]
@@ -18,10 +20,11 @@ pub type IoError = io::Error;
 pub type IoError = i32;
 
 #[cfg(feature = "std")]
-pub trait File: fmt::Debug {
+pub trait File: fmt::Debug + Send {
     fn close(&mut self) -> Result<(), IoError>;
     fn read(&mut self, offset: u64) -> Result<Vec<u8>, IoError>;
-    fn write(&mut self, data: &Vec<u8>, offset: u64) -> Result<(), IoError>;
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, IoError>;
+    fn write(&mut self, data: &[u8], offset: u64) -> Result<(), IoError>;
     fn truncate(&mut self, offset: u64) -> Result<(), IoError>;
     fn sync(
         &mut self,
@@ -31,6 +34,13 @@ pub trait File: fmt::Debug {
     fn lock(&mut self, lock_type: LockType) -> Result<(), IoError>;
     fn unlock(&mut self, unlock_type: UnlockType) -> Result<(), IoError>;
     fn check_reserved_lock(&mut self) -> Result<u64, IoError>;
+
+    /// Map the first `len` bytes of the file read-only, or return None if
+    /// the file cannot be mapped
+    #[cfg(feature = "mmap")]
+    fn map(&self, _len: usize) -> Result<Option<MemoryMap>, IoError> {
+        Ok(None)
+    }
 }
 
 flags! {
@@ -41,13 +51,21 @@ flags! {
     }
 }
 
+/// Lock levels of SQLite's locking protocol, from weakest to strongest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LockType {
+    /// Reading; any number of connections may hold it
     Shared,
+    /// Planning to write; one connection at a time, alongside readers
     Reserved,
+    /// Waiting for readers to finish before writing; no new readers
     Pending,
+    /// Writing the database file; no other locks
     Exclusive,
 }
 
+/// Level to drop a lock to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnlockType {
     None,
     Shared,
@@ -4,8 +4,12 @@ pub mod orderby;
 pub mod virtualtable;
 
 #[cfg(not(feature = "std"))]
-use alloc::{string::String, vec::Vec};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
 
+use core::fmt;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
@@ -50,7 +54,7 @@ impl Value {
 }
 
 /// All possible value types in EpilogLite
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ValueType {
     /// NULL value
     Null,
@@ -128,6 +132,93 @@ impl ValueType {
     pub fn is_null(&self) -> bool {
         matches!(self, ValueType::Null)
     }
+
+    /// Value of a SQL literal: NULL, a number, a quoted string or an X'..' blob
+    ///
+    /// Any other text is taken as a string. No type affinity is applied.
+    pub fn from_literal(text: &str) -> Self {
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("NULL") {
+            return ValueType::Null;
+        }
+        if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+            return ValueType::StringUtf8(text[1..text.len() - 1].replace("''", "'"));
+        }
+        if let Some(hex) = text.strip_prefix("X'").or_else(|| text.strip_prefix("x'")) {
+            let hex = hex.strip_suffix('\'').unwrap_or(hex);
+            let bytes: Option<Vec<u8>> = (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                .collect();
+            if let Some(bytes) = bytes {
+                return ValueType::Blob(bytes);
+            }
+        }
+        Self::parse_number(text).unwrap_or_else(|| ValueType::StringUtf8(text.to_string()))
+    }
+
+    /// Parse text that looks like a number as an integer or a real
+    pub fn parse_number(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if !text.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '+' | '-' | '.')) {
+            return None;
+        }
+        if let Ok(v) = text.parse::<i64>() {
+            return Some(ValueType::I64(v));
+        }
+        text.parse::<f64>().ok().filter(|v| v.is_finite()).map(ValueType::F64)
+    }
+}
+
+/// Values render the way query results show them: reals always have a
+/// decimal point and blobs are written as X'..' literals
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Null => f.write_str("NULL"),
+            ValueType::Bool(v) => write!(f, "{}", *v as u8),
+            ValueType::I8(v) => write!(f, "{}", v),
+            ValueType::U8(v) => write!(f, "{}", v),
+            ValueType::I16(v) => write!(f, "{}", v),
+            ValueType::U16(v) => write!(f, "{}", v),
+            ValueType::I32(v) => write!(f, "{}", v),
+            ValueType::U32(v) => write!(f, "{}", v),
+            ValueType::I64(v) => write!(f, "{}", v),
+            ValueType::U64(v) => write!(f, "{}", v),
+            ValueType::I128(v) => write!(f, "{}", v),
+            ValueType::U128(v) => write!(f, "{}", v),
+            ValueType::ISize(v) => write!(f, "{}", v),
+            ValueType::USize(v) => write!(f, "{}", v),
+            ValueType::F32(v) => write_real(f, *v as f64),
+            ValueType::F64(v) => write_real(f, *v),
+            ValueType::StringUtf8(text) => f.write_str(text),
+            ValueType::StringUtf16Le(bytes) => write_utf16(f, bytes, u16::from_le_bytes),
+            ValueType::StringUtf16Be(bytes) => write_utf16(f, bytes, u16::from_be_bytes),
+            ValueType::Blob(bytes) | ValueType::Bytes(bytes) => {
+                f.write_str("X'")?;
+                for b in bytes {
+                    write!(f, "{:02X}", b)?;
+                }
+                f.write_str("'")
+            }
+        }
+    }
+}
+
+fn write_real(f: &mut fmt::Formatter<'_>, v: f64) -> fmt::Result {
+    if v.fract() == 0.0 && v.abs() < 1e15 {
+        write!(f, "{:.1}", v)
+    } else {
+        write!(f, "{}", v)
+    }
+}
+
+fn write_utf16(f: &mut fmt::Formatter<'_>, bytes: &[u8], unit: fn([u8; 2]) -> u16) -> fmt::Result {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    for c in char::decode_utf16(units) {
+        write!(f, "{}", c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -177,4 +268,36 @@ mod tests {
         assert!(ValueType::Null.is_null());
         assert!(!ValueType::I32(0).is_null());
     }
+
+    #[test]
+    fn test_from_literal() {
+        assert_eq!(ValueType::from_literal("null"), ValueType::Null);
+        assert_eq!(ValueType::from_literal("42"), ValueType::I64(42));
+        assert_eq!(ValueType::from_literal("-1.5"), ValueType::F64(-1.5));
+        assert_eq!(
+            ValueType::from_literal("'it''s'"),
+            ValueType::StringUtf8("it's".to_string())
+        );
+        assert_eq!(
+            ValueType::from_literal("'42'"),
+            ValueType::StringUtf8("42".to_string())
+        );
+        assert_eq!(
+            ValueType::from_literal("X'00FF'"),
+            ValueType::Blob(vec![0, 255])
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(ValueType::Null.to_string(), "NULL");
+        assert_eq!(ValueType::I64(-7).to_string(), "-7");
+        assert_eq!(ValueType::F64(10.0).to_string(), "10.0");
+        assert_eq!(ValueType::F64(2.5).to_string(), "2.5");
+        assert_eq!(ValueType::Blob(vec![0xDE, 0xAD]).to_string(), "X'DEAD'");
+        assert_eq!(
+            ValueType::StringUtf16Le(vec![b'h', 0, b'i', 0]).to_string(),
+            "hi"
+        );
+    }
 }
@@ -1,7 +1,11 @@
 /// Column type definitions
 
+use crate::eplite::types::ValueType;
 use serde::{Deserialize, Serialize};
 
+#[cfg(not(feature = "std"))]
+use alloc::string::ToString;
+
 /// SQL column data types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ColumnType {
@@ -37,6 +41,61 @@ pub enum ColumnType {
 	Text,
 	/// Variable length binary data (BLOB)
 	Blob,
+	/// Integer or floating point number, whichever holds the value
+	/// exactly (NUMERIC)
+	Numeric,
+}
+
+/// SQLite type affinity: the storage class a column prefers for its values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Affinity {
+	Integer,
+	Real,
+	Numeric,
+	Text,
+	Blob,
+}
+
+impl Affinity {
+	/// Affinity of a declared type name, following SQLite's rules in order
+	pub fn of_declared_type(declared: &str) -> Self {
+		let upper = declared.to_uppercase();
+		if upper.contains("INT") {
+			Affinity::Integer
+		} else if upper.contains("CHAR") || upper.contains("CLOB") || upper.contains("TEXT") {
+			Affinity::Text
+		} else if upper.contains("BLOB") || upper.trim().is_empty() {
+			Affinity::Blob
+		} else if upper.contains("REAL") || upper.contains("FLOA") || upper.contains("DOUB") {
+			Affinity::Real
+		} else {
+			Affinity::Numeric
+		}
+	}
+
+	/// Convert a value for storage in a column with this affinity, as SQLite does
+	///
+	/// Text that looks like a number becomes one in numeric columns and numbers
+	/// become text in text columns. NULLs and blobs are never converted.
+	pub fn apply(&self, value: ValueType) -> ValueType {
+		match (self, value) {
+			(Affinity::Integer | Affinity::Real | Affinity::Numeric, ValueType::StringUtf8(text)) => {
+				match ValueType::parse_number(&text) {
+					Some(number) => self.apply(number),
+					None => ValueType::StringUtf8(text),
+				}
+			}
+			(Affinity::Real, ValueType::I64(v)) => ValueType::F64(v as f64),
+			// Reals without a fractional part are stored as integers
+			(Affinity::Integer | Affinity::Numeric, ValueType::F64(v)) if v.fract() == 0.0 && v.abs() < 9.2e18 => {
+				ValueType::I64(v as i64)
+			}
+			(Affinity::Text, number @ (ValueType::I64(_) | ValueType::F64(_))) => {
+				ValueType::StringUtf8(number.to_string())
+			}
+			(_, value) => value,
+		}
+	}
 }
 
 impl ColumnType {
@@ -59,6 +118,7 @@ impl ColumnType {
 			ColumnType::Float64 => "DOUBLE",
 			ColumnType::Text => "TEXT",
 			ColumnType::Blob => "BLOB",
+			ColumnType::Numeric => "NUMERIC",
 		}
 	}
 
@@ -81,10 +141,35 @@ impl ColumnType {
 			"FLOAT64" | "DOUBLE" | "DOUBLE PRECISION" => Some(ColumnType::Float64),
 			"TEXT" | "VARCHAR" | "CHAR" | "STRING" => Some(ColumnType::Text),
 			"BLOB" | "BINARY" | "BYTES" => Some(ColumnType::Blob),
+			"NUMERIC" | "DECIMAL" => Some(ColumnType::Numeric),
 			_ => None,
 		}
 	}
 
+	/// Column type for a type name declared in a SQLite 3 schema
+	///
+	/// Known names map directly; others follow SQLite's affinity rules, so
+	/// `VARCHAR(255)` is text and `DECIMAL(10,5)` is numeric.
+	pub fn from_declared_type(declared: &str) -> Self {
+		let base = declared.split('(').next().unwrap_or_default().trim();
+		if let Some(column_type) = Self::from_sql_name(base) {
+			return column_type;
+		}
+
+		match Affinity::of_declared_type(base) {
+			Affinity::Integer => ColumnType::Int64,
+			Affinity::Text => ColumnType::Text,
+			Affinity::Blob => ColumnType::Blob,
+			Affinity::Real => ColumnType::Float64,
+			Affinity::Numeric => ColumnType::Numeric,
+		}
+	}
+
+	/// Type affinity of the column's SQL type name
+	pub fn affinity(&self) -> Affinity {
+		Affinity::of_declared_type(self.sql_name())
+	}
+
 	/// Check if this is a numeric type
 	pub fn is_numeric(&self) -> bool {
 		matches!(
@@ -101,6 +186,7 @@ impl ColumnType {
 				| ColumnType::UInt128
 				| ColumnType::Float32
 				| ColumnType::Float64
+				| ColumnType::Numeric
 		)
 	}
 
@@ -165,6 +251,47 @@ mod tests {
 		assert_eq!(ColumnType::from_sql_name("INVALID"), None);
 	}
 
+	#[test]
+	fn test_from_declared_type() {
+		assert_eq!(ColumnType::from_declared_type("INTEGER"), ColumnType::Int32);
+		assert_eq!(ColumnType::from_declared_type("VARCHAR(255)"), ColumnType::Text);
+		assert_eq!(ColumnType::from_declared_type("UNSIGNED BIG INT"), ColumnType::Int64);
+		assert_eq!(ColumnType::from_declared_type("NATIVE CHARACTER(70)"), ColumnType::Text);
+		assert_eq!(ColumnType::from_declared_type(""), ColumnType::Blob);
+		assert_eq!(ColumnType::from_declared_type("DECIMAL(10,5)"), ColumnType::Numeric);
+		assert_eq!(ColumnType::from_declared_type("MONEY"), ColumnType::Numeric);
+		assert_eq!(ColumnType::from_declared_type("FLOAT8"), ColumnType::Float64);
+	}
+
+	#[test]
+	fn test_affinity() {
+		assert_eq!(ColumnType::Int32.affinity(), Affinity::Integer);
+		assert_eq!(ColumnType::UInt8.affinity(), Affinity::Integer);
+		assert_eq!(ColumnType::Float32.affinity(), Affinity::Real);
+		assert_eq!(ColumnType::Float64.affinity(), Affinity::Real);
+		assert_eq!(ColumnType::Text.affinity(), Affinity::Text);
+		assert_eq!(ColumnType::Boolean.affinity(), Affinity::Numeric);
+		assert_eq!(ColumnType::Numeric.affinity(), Affinity::Numeric);
+		assert_eq!(Affinity::of_declared_type("CHARINT"), Affinity::Integer);
+		assert_eq!(Affinity::of_declared_type("DECIMAL(10,5)"), Affinity::Numeric);
+	}
+
+	#[test]
+	fn test_apply_affinity() {
+		let text = |s: &str| ValueType::StringUtf8(s.to_string());
+		assert_eq!(Affinity::Integer.apply(text("12")), ValueType::I64(12));
+		assert_eq!(Affinity::Integer.apply(ValueType::F64(3.0)), ValueType::I64(3));
+		assert_eq!(Affinity::Integer.apply(text("x")), text("x"));
+		assert_eq!(Affinity::Real.apply(ValueType::I64(2)), ValueType::F64(2.0));
+		assert_eq!(Affinity::Numeric.apply(text(" 2.5 ")), ValueType::F64(2.5));
+		assert_eq!(Affinity::Numeric.apply(text("1e2")), ValueType::I64(100));
+		assert_eq!(Affinity::Numeric.apply(text("5")), ValueType::I64(5));
+		assert_eq!(Affinity::Text.apply(ValueType::F64(1.0)), text("1.0"));
+		assert_eq!(Affinity::Text.apply(ValueType::Null), ValueType::Null);
+		assert_eq!(Affinity::Blob.apply(text("7")), text("7"));
+		assert_eq!(Affinity::Integer.apply(ValueType::Blob(vec![1])), ValueType::Blob(vec![1]));
+	}
+
 	#[test]
 	fn test_type_checks() {
 		assert!(ColumnType::Int32.is_numeric());
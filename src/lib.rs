@@ -10,12 +10,18 @@ pub mod capi;
 
 pub use eplite::command::processor::{ExecutionResult, Processor};
 pub use eplite::database::Database;
+#[cfg(feature = "std")]
+pub use eplite::database::ReadTransaction;
 pub use eplite::error::{Error, Result};
 pub use eplite::graph::{Edge, EdgeId, Graph, GraphManager, Node, NodeId};
+pub use eplite::persistence::cache::CacheStats;
+pub use eplite::persistence::header::FileFormat;
+pub use eplite::persistence::wal::{CheckpointMode, CheckpointResult};
+pub use eplite::statement::{ParameterIndex, Statement};
 pub use eplite::query_builder::{
 	CreateTableBuilder, DeleteBuilder, InsertBuilder, SelectBuilder, UpdateBuilder,
 };
-pub use eplite::{SchemaFormat, TextEncoding};
+pub use eplite::{AutoVacuum, JournalMode, SchemaFormat, TextEncoding, ValueType};
 
 #[cfg(feature = "server")]
 pub use eplite::server::{
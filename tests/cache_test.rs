@@ -0,0 +1,118 @@
+/// Tests for the page cache size and its statistics
+
+mod common;
+
+use common::{fresh_path, select};
+use epiloglite::eplite::config::DatabaseConfig;
+use epiloglite::{CacheStats, Database, Result};
+
+fn fill(db: &mut Database, rows: i64) -> Result<()> {
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	db.execute("BEGIN")?;
+	for i in 1..=rows {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	db.execute("COMMIT")?;
+	Ok(())
+}
+
+#[test]
+fn test_cache_size_pragma() -> Result<()> {
+	let path = fresh_path("pragma");
+	let mut db = Database::open(&path)?;
+	assert_eq!(select(&mut db, "PRAGMA cache_size")?, vec![vec!["100"]]);
+	assert_eq!(db.cache_stats().capacity, 100);
+
+	// Negative sizes are in KiB of pages
+	db.execute("PRAGMA cache_size = -64")?;
+	assert_eq!(select(&mut db, "PRAGMA cache_size")?, vec![vec!["-64"]]);
+	assert_eq!(db.cache_stats().capacity, 16);
+	assert!(db.execute("PRAGMA cache_size = lots").is_err());
+
+	let config = DatabaseConfig { cache_size: -1024, ..Default::default() };
+	let db = Database::open_with_config(fresh_path("config"), &config)?;
+	assert_eq!(db.cache_stats().capacity, 256);
+	Ok(())
+}
+
+#[test]
+fn test_cache_stats() -> Result<()> {
+	let path = fresh_path("stats");
+	let mut db = Database::open(&path)?;
+	fill(&mut db, 2000)?;
+
+	let before = db.cache_stats();
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["2000"]]);
+	let after = db.cache_stats();
+	assert!(after.hits > before.hits);
+
+	let rows = select(&mut db, "PRAGMA cache_stats")?;
+	assert_eq!(rows[0].len(), 5);
+	assert_eq!(rows[0][4], "100");
+	Ok(())
+}
+
+#[test]
+fn test_small_cache_evicts() -> Result<()> {
+	let path = fresh_path("evicts");
+	let mut db = Database::open(&path)?;
+	db.execute("PRAGMA cache_size = 4")?;
+
+	// A transaction writes its changes to the file once the cache is full
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	db.execute("BEGIN")?;
+	for i in 1..=2000 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	let stats = db.cache_stats();
+	assert!(stats.pages <= stats.capacity, "{:?}", stats);
+	db.execute("COMMIT")?;
+	let stats = db.cache_stats();
+	assert!(stats.pages <= 4, "{:?}", stats);
+
+	// Reading every page through a small cache evicts as it goes
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["2000"]]);
+	assert_eq!(select(&mut db, "SELECT * FROM logs WHERE id = 1999")?, vec![vec!["1999", "message number 1999"]]);
+	let CacheStats { evictions, pages, capacity, .. } = db.cache_stats();
+	assert!(evictions > stats.evictions);
+	assert!(pages <= capacity);
+
+	// Writes outside a transaction go through the small cache too
+	for i in 2001..=2100 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	drop(db);
+	let mut db = Database::open(&path)?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["2100"]]);
+	Ok(())
+}
+
+#[test]
+fn test_rollback_after_changes_reach_the_file() -> Result<()> {
+	let path = fresh_path("spill");
+	let mut db = Database::open(&path)?;
+	fill(&mut db, 500)?;
+	db.execute("PRAGMA cache_size = 4")?;
+	let size = std::fs::metadata(&path)?.len();
+
+	db.execute("BEGIN")?;
+	db.execute("UPDATE logs SET message = 'changed'")?;
+	for i in 501..=1500 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	// Changes undone by a savepoint stay undone once they reach the file
+	db.execute("SAVEPOINT extra")?;
+	db.execute("DELETE FROM logs WHERE id > 100")?;
+	db.execute("ROLLBACK TO extra")?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs WHERE message = 'changed'")?, vec![vec!["500"]]);
+	assert!(db.cache_stats().pages <= 4);
+	db.execute("ROLLBACK")?;
+
+	assert_eq!(std::fs::metadata(&path)?.len(), size);
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["500"]]);
+	assert_eq!(select(&mut db, "SELECT message FROM logs WHERE id = 7")?, vec![vec!["message number 7"]]);
+	drop(db);
+	let mut db = Database::open(&path)?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs WHERE message = 'changed'")?, vec![vec!["0"]]);
+	Ok(())
+}
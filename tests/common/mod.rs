@@ -0,0 +1,56 @@
+//! Helpers shared by the integration tests
+//!
+//! Each test file is its own crate and uses only some of these.
+
+#![allow(dead_code)]
+
+use epiloglite::{Database, ExecutionResult, Result};
+use std::process::Command;
+
+/// A path in the temporary directory for a database of this test file,
+/// with any database, journal or log an earlier run left there removed
+pub fn fresh_path(name: &str) -> String {
+	let file = format!("epiloglite_{}_{}.db", env!("CARGO_CRATE_NAME"), name);
+	let path = std::env::temp_dir().join(file).to_str().expect("temporary path is not UTF-8").to_string();
+	for suffix in ["", "-journal", "-wal", "-shm"] {
+		let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+	}
+	path
+}
+
+/// Rows of a query with each value rendered as text
+pub fn select(db: &mut Database, sql: &str) -> Result<Vec<Vec<String>>> {
+	match db.execute(sql)? {
+		ExecutionResult::Select { rows, .. } => Ok(rows
+			.iter()
+			.map(|row| row.iter().map(|value| value.to_string()).collect())
+			.collect()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+/// First column of each row of a query
+pub fn firsts(db: &mut Database, sql: &str) -> Result<Vec<String>> {
+	Ok(select(db, sql)?.into_iter().map(|row| row[0].clone()).collect())
+}
+
+/// Create a table and insert rows into it, each written as the list of
+/// values of an INSERT
+pub fn create_table(db: &mut Database, table: &str, columns: &str, rows: &[&str]) -> Result<()> {
+	db.execute(&format!("CREATE TABLE {} ({})", table, columns))?;
+	for row in rows {
+		db.execute(&format!("INSERT INTO {} VALUES ({})", table, row))?;
+	}
+	Ok(())
+}
+
+/// Run SQL with the sqlite3 tool, or return None if it is not installed
+pub fn sqlite3(path: &str, sql: &str) -> Option<String> {
+	let output = Command::new("sqlite3").arg(path).arg(sql).output().ok()?;
+	assert!(
+		output.status.success(),
+		"sqlite3 failed: {}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+	Some(String::from_utf8(output.stdout).unwrap())
+}
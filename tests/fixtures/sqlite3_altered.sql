@@ -0,0 +1,21 @@
+-- Source for sqlite3_altered.db, a database whose first rows were written
+-- before ALTER TABLE added columns to their table, so their records are
+-- short, next to tables we cannot read.
+-- Regenerate with: sqlite3 tests/fixtures/sqlite3_altered.db < tests/fixtures/sqlite3_altered.sql
+PRAGMA page_size = 1024;
+
+CREATE TABLE items (a INTEGER, b TEXT);
+INSERT INTO items VALUES (1, 'one');
+INSERT INTO items VALUES (2, NULL);
+ALTER TABLE items ADD COLUMN c TEXT DEFAULT 'none';
+ALTER TABLE items ADD COLUMN d INTEGER DEFAULT -1;
+ALTER TABLE items ADD COLUMN e REAL;
+INSERT INTO items VALUES (3, 'three', 'set', 7, 0.5);
+
+CREATE TABLE wr (k TEXT PRIMARY KEY, v INTEGER) WITHOUT ROWID;
+INSERT INTO wr VALUES ('x', 1);
+
+CREATE VIEW v1 AS SELECT a, b FROM items;
+
+CREATE VIRTUAL TABLE vt USING rtree(id, x0, x1);
+INSERT INTO vt VALUES (1, 0, 10);
@@ -0,0 +1,9 @@
+-- Source for sqlite3_auto_vacuum.db, a database with auto-vacuum on, so it
+-- has pointer-map pages.
+-- Regenerate with: sqlite3 tests/fixtures/sqlite3_auto_vacuum.db < tests/fixtures/sqlite3_auto_vacuum.sql
+PRAGMA page_size = 1024;
+PRAGMA auto_vacuum = FULL;
+
+CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+INSERT INTO items VALUES (1, 'one');
+INSERT INTO items VALUES (2, 'two');
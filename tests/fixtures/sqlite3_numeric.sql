@@ -0,0 +1,7 @@
+-- Source for sqlite3_numeric.db, a database with columns of NUMERIC
+-- affinity, which keep numbers as integers when they can.
+-- Regenerate with: sqlite3 tests/fixtures/sqlite3_numeric.db < tests/fixtures/sqlite3_numeric.sql
+PRAGMA page_size = 1024;
+
+CREATE TABLE amounts (id INTEGER PRIMARY KEY, n NUMERIC, d DECIMAL(10,2), r REAL);
+INSERT INTO amounts VALUES (1, '1e2', '5', '5');
@@ -0,0 +1,8 @@
+-- Source for sqlite3_quoted.db, a database whose table and column names
+-- must be quoted.
+-- Regenerate with: sqlite3 tests/fixtures/sqlite3_quoted.db < tests/fixtures/sqlite3_quoted.sql
+PRAGMA page_size = 1024;
+
+CREATE TABLE "My Table" (id INTEGER PRIMARY KEY, "first name" TEXT, "order" INTEGER);
+INSERT INTO "My Table" VALUES (1, 'Ada', 2);
+INSERT INTO "My Table" VALUES (2, 'Alan', 1);
@@ -0,0 +1,29 @@
+-- Source for sqlite3_sample.db, a database written by the stock sqlite3 tool.
+-- Regenerate with: sqlite3 tests/fixtures/sqlite3_sample.db < tests/fixtures/sqlite3_sample.sql
+PRAGMA page_size = 1024;
+
+CREATE TABLE users (
+	id INTEGER PRIMARY KEY,
+	name TEXT NOT NULL,
+	age INTEGER,
+	score REAL,
+	avatar BLOB
+);
+CREATE INDEX idx_users_name ON users (name);
+
+CREATE TABLE notes (
+	note_id INTEGER,
+	user_id INTEGER REFERENCES users (id),
+	body VARCHAR(255) DEFAULT '',
+	PRIMARY KEY (note_id)
+);
+
+CREATE VIEW adults AS SELECT * FROM users WHERE age >= 18;
+
+WITH RECURSIVE seq(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM seq WHERE i < 300)
+INSERT INTO users (id, name, age, score, avatar)
+SELECT i, 'user' || i, i % 90, i * 1.5, CASE WHEN i % 100 = 0 THEN x'DEADBEEF' ELSE NULL END FROM seq;
+
+INSERT INTO notes VALUES (1, 1, 'short note');
+INSERT INTO notes VALUES (2, 2, replace(printf('%.5000c', '*'), '*', 'x'));
+INSERT INTO notes VALUES (3, 300, NULL);
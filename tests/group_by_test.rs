@@ -0,0 +1,165 @@
+/// Tests for GROUP BY, HAVING, DISTINCT and aggregates of distinct values
+
+mod common;
+
+use common::{create_table, firsts, select};
+use epiloglite::{Database, ExecutionResult, Result, ValueType};
+
+fn create() -> Result<Database> {
+	let mut db = Database::open(":memory:")?;
+	create_table(
+		&mut db,
+		"sales",
+		"id INTEGER PRIMARY KEY, region TEXT, product TEXT, quantity INTEGER, price INTEGER",
+		&[
+			"1, 'north', 'apple', 3, 2",
+			"2, 'north', 'pear', 1, 5",
+			"3, 'south', 'apple', 2, 2",
+			"4, 'south', 'apple', 4, 2",
+			"5, 'north', 'apple', NULL, 2",
+			"6, NULL, 'pear', 2, 5",
+			"7, 'south', 'pear', 1, 4",
+		],
+	)?;
+	Ok(db)
+}
+
+#[test]
+fn test_group_by_several_columns() -> Result<()> {
+	let mut db = create()?;
+	// Groups come in the order of their values, NULL first
+	assert_eq!(
+		select(&mut db, "SELECT region, product, COUNT(*), SUM(quantity) FROM sales GROUP BY region, product")?,
+		vec![
+			vec!["NULL", "pear", "1", "2"],
+			vec!["north", "apple", "2", "3"],
+			vec!["north", "pear", "1", "1"],
+			vec!["south", "apple", "2", "6"],
+			vec!["south", "pear", "1", "1"],
+		]
+	);
+
+	// Columns outside aggregates read the last row of their group
+	assert_eq!(
+		select(&mut db, "SELECT product, id, MAX(quantity) FROM sales GROUP BY product")?,
+		vec![vec!["apple", "5", "4"], vec!["pear", "7", "2"]]
+	);
+
+	// The select list decides the result's columns, not GROUP BY
+	match db.execute("SELECT COUNT(*) FROM sales GROUP BY region, product")? {
+		ExecutionResult::Select { rows, columns } => {
+			assert_eq!(columns, vec!["COUNT(*)"]);
+			assert_eq!(rows.len(), 5);
+		}
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+	Ok(())
+}
+
+#[test]
+fn test_group_by_expressions() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(
+		select(&mut db, "SELECT quantity % 2 AS parity, COUNT(*) FROM sales GROUP BY parity")?,
+		vec![vec!["NULL", "1"], vec!["0", "3"], vec!["1", "3"]]
+	);
+	assert_eq!(
+		select(&mut db, "SELECT product, SUM(quantity * price) AS revenue FROM sales GROUP BY 1")?,
+		vec![vec!["apple", "18"], vec!["pear", "19"]]
+	);
+	assert_eq!(
+		select(&mut db, "SELECT price > 3, COUNT(*) * 10 FROM sales GROUP BY price > 3")?,
+		vec![vec!["0", "40"], vec!["1", "30"]]
+	);
+
+	// Aggregates with no rows give one row without GROUP BY, and none with it
+	assert_eq!(select(&mut db, "SELECT COUNT(*), SUM(quantity) FROM sales WHERE id > 100")?, vec![vec!["0", "NULL"]]);
+	assert!(select(&mut db, "SELECT COUNT(*) FROM sales WHERE id > 100 GROUP BY region")?.is_empty());
+	Ok(())
+}
+
+#[test]
+fn test_having() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(
+		select(&mut db, "SELECT region, COUNT(*) FROM sales GROUP BY region HAVING COUNT(*) > 1")?,
+		vec![vec!["north", "3"], vec!["south", "3"]]
+	);
+	assert_eq!(firsts(&mut db, "SELECT region FROM sales GROUP BY region HAVING SUM(quantity) >= 7")?, vec!["south"]);
+	assert_eq!(firsts(&mut db, "SELECT region FROM sales GROUP BY region HAVING region LIKE 'n%'")?, vec!["north"]);
+	assert!(select(&mut db, "SELECT COUNT(*) FROM sales HAVING COUNT(*) > 100")?.is_empty());
+
+	// ORDER BY can sort groups by aggregates, selected or not
+	assert_eq!(
+		firsts(&mut db, "SELECT product FROM sales GROUP BY product ORDER BY MIN(price) DESC")?,
+		vec!["pear", "apple"]
+	);
+	assert_eq!(
+		select(&mut db, "SELECT product, COUNT(*) AS n FROM sales GROUP BY product HAVING n > 1 ORDER BY n, 1 DESC LIMIT 1")?,
+		vec![vec!["pear", "3"]]
+	);
+
+	let mut query = db.prepare("SELECT product FROM sales GROUP BY product HAVING COUNT(*) > ?")?;
+	query.bind(1, ValueType::I64(3))?;
+	assert_eq!(query.step()?, Some(vec![ValueType::StringUtf8("apple".to_string())]));
+	assert_eq!(query.step()?, None);
+	Ok(())
+}
+
+#[test]
+fn test_distinct() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(firsts(&mut db, "SELECT DISTINCT product FROM sales")?, vec!["apple", "pear"]);
+	assert_eq!(firsts(&mut db, "SELECT DISTINCT region FROM sales ORDER BY region DESC")?, vec!["south", "north", "NULL"]);
+	assert_eq!(firsts(&mut db, "SELECT DISTINCT price FROM sales LIMIT 2 OFFSET 1")?, vec!["5", "4"]);
+	assert_eq!(select(&mut db, "SELECT DISTINCT region, product FROM sales")?.len(), 5);
+	assert_eq!(select(&mut db, "SELECT DISTINCT * FROM sales")?.len(), 7);
+	assert_eq!(
+		firsts(&mut db, "SELECT DISTINCT COUNT(*) FROM sales GROUP BY region, product")?,
+		vec!["1", "2"]
+	);
+	Ok(())
+}
+
+#[test]
+fn test_aggregates_of_distinct_values() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(
+		select(&mut db, "SELECT COUNT(DISTINCT product), SUM(DISTINCT price), COUNT(DISTINCT region), COUNT(region) FROM sales")?,
+		vec![vec!["2", "11", "2", "6"]]
+	);
+	assert_eq!(
+		select(&mut db, "SELECT region, COUNT(DISTINCT product), AVG(DISTINCT price) FROM sales GROUP BY region")?,
+		vec![vec!["NULL", "1", "5.0"], vec!["north", "2", "3.5"], vec!["south", "2", "3.0"]]
+	);
+	Ok(())
+}
+
+#[test]
+fn test_grouped_join() -> Result<()> {
+	let mut db = create()?;
+	db.execute("CREATE TABLE targets (area TEXT PRIMARY KEY, goal INTEGER)")?;
+	db.execute("INSERT INTO targets VALUES ('north', 5)")?;
+	db.execute("INSERT INTO targets VALUES ('south', 5)")?;
+	assert_eq!(
+		select(
+			&mut db,
+			"SELECT area, goal, SUM(quantity) FROM targets JOIN sales ON area = region GROUP BY area HAVING SUM(quantity) >= goal"
+		)?,
+		vec![vec!["south", "5", "7"]]
+	);
+	Ok(())
+}
+
+#[test]
+fn test_invalid_grouping() -> Result<()> {
+	let mut db = create()?;
+	assert!(db.execute("SELECT * FROM sales WHERE COUNT(*) > 1").is_err());
+	assert!(db.execute("SELECT COUNT(*) FROM sales GROUP BY COUNT(*)").is_err());
+	assert!(db.execute("SELECT region, COUNT(*) FROM sales GROUP BY 3").is_err());
+	assert!(db.execute("SELECT COUNT(*) FROM sales GROUP BY missing").is_err());
+	assert!(db.execute("SELECT region FROM sales GROUP BY region HAVING missing > 1").is_err());
+	assert!(db.execute("SELECT SUM(SUM(quantity)) FROM sales").is_err());
+	assert!(db.execute("SELECT id FROM sales ORDER BY COUNT(*)").is_err());
+	Ok(())
+}
@@ -1,6 +1,6 @@
 /// Integration tests for EpilogLite
 
-use epiloglite::{Database, ExecutionResult, Result};
+use epiloglite::{Database, ExecutionResult, Result, ValueType};
 
 #[test]
 fn test_complete_workflow() -> Result<()> {
@@ -19,7 +19,7 @@ fn test_complete_workflow() -> Result<()> {
 	let result = db.execute("SELECT * FROM users")?;
 	match result {
 		ExecutionResult::Select { rows, columns } => {
-			assert_eq!(columns.len(), 1); // "*"
+			assert_eq!(columns, vec!["id", "name", "age"]);
 			assert_eq!(rows.len(), 3);
 		}
 		_ => panic!("Expected Select result"),
@@ -64,7 +64,7 @@ fn test_aggregate_functions() -> Result<()> {
 	match result {
 		ExecutionResult::Select { rows, columns } => {
 			assert_eq!(columns[0], "COUNT(*)");
-			assert_eq!(rows[0][0], "3");
+			assert_eq!(rows[0][0], ValueType::I64(3));
 		}
 		_ => panic!("Expected Select result"),
 	}
@@ -73,7 +73,7 @@ fn test_aggregate_functions() -> Result<()> {
 	let result = db.execute("SELECT SUM(price) FROM products")?;
 	match result {
 		ExecutionResult::Select { rows, .. } => {
-			assert_eq!(rows[0][0], "450");
+			assert_eq!(rows[0][0], ValueType::I64(450));
 		}
 		_ => panic!("Expected Select result"),
 	}
@@ -82,8 +82,7 @@ fn test_aggregate_functions() -> Result<()> {
 	let result = db.execute("SELECT AVG(price) FROM products")?;
 	match result {
 		ExecutionResult::Select { rows, .. } => {
-			let avg: f64 = rows[0][0].parse().unwrap();
-			assert!((avg - 150.0).abs() < 0.01);
+			assert_eq!(rows[0][0], ValueType::F64(150.0));
 		}
 		_ => panic!("Expected Select result"),
 	}
@@ -92,7 +91,7 @@ fn test_aggregate_functions() -> Result<()> {
 	let result = db.execute("SELECT MIN(price) FROM products")?;
 	match result {
 		ExecutionResult::Select { rows, .. } => {
-			assert_eq!(rows[0][0], "100");
+			assert_eq!(rows[0][0], ValueType::I64(100));
 		}
 		_ => panic!("Expected Select result"),
 	}
@@ -101,7 +100,7 @@ fn test_aggregate_functions() -> Result<()> {
 	let result = db.execute("SELECT MAX(price) FROM products")?;
 	match result {
 		ExecutionResult::Select { rows, .. } => {
-			assert_eq!(rows[0][0], "200");
+			assert_eq!(rows[0][0], ValueType::I64(200));
 		}
 		_ => panic!("Expected Select result"),
 	}
@@ -110,7 +109,7 @@ fn test_aggregate_functions() -> Result<()> {
 	let result = db.execute("SELECT COUNT(*) FROM products WHERE price > 100")?;
 	match result {
 		ExecutionResult::Select { rows, .. } => {
-			assert_eq!(rows[0][0], "2");
+			assert_eq!(rows[0][0], ValueType::I64(2));
 		}
 		_ => panic!("Expected Select result"),
 	}
@@ -173,6 +172,43 @@ fn test_disk_persistence() -> Result<()> {
 	Ok(())
 }
 
+#[test]
+fn test_disk_persistence_beyond_one_page() -> Result<()> {
+	let test_db_path = "/tmp/test_epiloglite_multi_page.db";
+	let _ = std::fs::remove_file(test_db_path);
+
+	// Well over the 4 KiB that used to be the limit for the whole database
+	{
+		let mut db = Database::open(test_db_path)?;
+		db.execute("CREATE TABLE notes (id INTEGER, body TEXT)")?;
+		db.execute("CREATE TABLE tags (id INTEGER, name TEXT)")?;
+		for i in 0..150 {
+			db.execute(&format!(
+				"INSERT INTO notes VALUES ({}, 'note number {} with some padding text to fill pages')",
+				i, i
+			))?;
+		}
+		db.execute("INSERT INTO tags VALUES (1, 'rust')")?;
+		db.close()?;
+	}
+
+	{
+		let mut db = Database::open(test_db_path)?;
+		match db.execute("SELECT * FROM notes")? {
+			ExecutionResult::Select { rows, .. } => assert_eq!(rows.len(), 150),
+			_ => panic!("Expected Select result"),
+		}
+		match db.execute("SELECT * FROM tags")? {
+			ExecutionResult::Select { rows, .. } => assert_eq!(rows.len(), 1),
+			_ => panic!("Expected Select result"),
+		}
+		db.close()?;
+	}
+
+	std::fs::remove_file(test_db_path)?;
+	Ok(())
+}
+
 #[test]
 fn test_transactions() -> Result<()> {
 	let mut db = Database::open(":memory:")?;
@@ -251,9 +287,9 @@ fn test_order_by() -> Result<()> {
 		ExecutionResult::Select { rows, .. } => {
 			assert_eq!(rows.len(), 3);
 			// Should be ordered by price: Gadget (50), Widget (100), Doohickey (150)
-			assert_eq!(rows[0][2], "50");
-			assert_eq!(rows[1][2], "100");
-			assert_eq!(rows[2][2], "150");
+			assert_eq!(rows[0][2], ValueType::I64(50));
+			assert_eq!(rows[1][2], ValueType::I64(100));
+			assert_eq!(rows[2][2], ValueType::I64(150));
 		}
 		_ => panic!("Expected Select result"),
 	}
@@ -273,15 +309,15 @@ fn test_group_by_with_aggregates() -> Result<()> {
 	db.execute("INSERT INTO sales VALUES ('Widget', 15, 150)")?;
 	db.execute("INSERT INTO sales VALUES ('Gadget', 8, 80)")?;
 
-	// GROUP BY product with SUM(quantity)
-	let result = db.execute("SELECT COUNT(*) FROM sales GROUP BY product")?;
+	// GROUP BY product with COUNT(*)
+	let result = db.execute("SELECT product, COUNT(*) FROM sales GROUP BY product")?;
 	match result {
 		ExecutionResult::Select { rows, columns } => {
 			assert_eq!(rows.len(), 2); // 2 products
 			assert_eq!(columns.len(), 2); // product, COUNT(*)
 			// Each product should have 2 rows
 			for row in rows {
-				assert_eq!(row[1], "2");
+				assert_eq!(row[1], ValueType::I64(2));
 			}
 		}
 		_ => panic!("Expected Select result"),
@@ -402,11 +438,11 @@ match result {
 ExecutionResult::Select { rows, .. } => {
 assert_eq!(rows.len(), 4); // All users, some with multiple orders
 // Check that Charlie has a row with NULLs for order data
-let charlie_row = rows.iter().find(|r| r.len() > 1 && r[1] == "'Charlie'");
+let charlie_row = rows.iter().find(|r| r.len() > 1 && r[1] == ValueType::StringUtf8("Charlie".to_string()));
 assert!(charlie_row.is_some(), "Charlie row not found");
 let charlie_row = charlie_row.unwrap();
-assert_eq!(charlie_row[2], "NULL"); // order_id should be NULL
-assert_eq!(charlie_row[3], "NULL"); // user_id should be NULL
+assert_eq!(charlie_row[2], ValueType::Null); // order_id should be NULL
+assert_eq!(charlie_row[3], ValueType::Null); // user_id should be NULL
 }
 _ => panic!("Expected Select result"),
 }
@@ -417,9 +453,9 @@ match result {
 ExecutionResult::Select { rows, .. } => {
 assert_eq!(rows.len(), 4); // All orders
 // Order 104 should have NULLs for user data
-let orphan_orders: Vec<_> = rows.iter().filter(|r| r[2] == "104").collect();
+let orphan_orders: Vec<_> = rows.iter().filter(|r| r[2] == ValueType::I64(104)).collect();
 assert!(orphan_orders.len() > 0);
-assert!(orphan_orders[0][0] == "NULL"); // user id should be NULL
+assert!(orphan_orders[0][0] == ValueType::Null); // user id should be NULL
 }
 _ => panic!("Expected Select result"),
 }
@@ -0,0 +1,125 @@
+/// Tests for the rollback journal and hot-journal recovery
+///
+/// SQLite 3 files are also checked with the sqlite3 command line tool when it
+/// is installed.
+
+mod common;
+
+use common::fresh_path;
+use epiloglite::eplite::persistence::journal::Journal;
+use epiloglite::{Database, ExecutionResult, FileFormat, Result, ValueType};
+use std::path::Path;
+use std::process::Command;
+
+fn journal_path(path: &str) -> String {
+	format!("{}-journal", path)
+}
+
+fn journal_mode(db: &mut Database, sql: &str) -> Result<String> {
+	match db.execute(sql)? {
+		ExecutionResult::Select { rows, .. } => Ok(rows[0][0].to_string()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+fn count(db: &mut Database) -> Result<ValueType> {
+	match db.execute("SELECT COUNT(*) FROM logs")? {
+		ExecutionResult::Select { rows, .. } => Ok(rows[0][0].clone()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+/// Leave the database as a crash would part way through a commit: the
+/// journal holds the committed pages and the file has been overwritten
+fn crash_during_commit(path: &str, committed: &[u8], written: &[u8]) {
+	let page_size = u16::from_be_bytes([committed[16], committed[17]]) as u32;
+	let pages: Vec<(u32, Vec<u8>)> = committed
+		.chunks(page_size as usize)
+		.enumerate()
+		.map(|(i, data)| (i as u32 + 1, data.to_vec()))
+		.collect();
+	Journal::new(path).write(page_size, pages.len() as u32, &pages).unwrap();
+	std::fs::write(path, written).unwrap();
+}
+
+#[test]
+fn test_journal_modes() -> Result<()> {
+	let path = fresh_path("modes");
+	let mut db = Database::open(&path)?;
+	let journal = journal_path(&path);
+	assert_eq!(journal_mode(&mut db, "PRAGMA journal_mode")?, "delete");
+	db.execute("CREATE TABLE logs (id INTEGER, message TEXT)")?;
+	assert!(!Path::new(&journal).exists());
+
+	assert_eq!(journal_mode(&mut db, "PRAGMA journal_mode = TRUNCATE")?, "truncate");
+	db.execute("INSERT INTO logs VALUES (1, 'truncate')")?;
+	assert_eq!(std::fs::metadata(&journal).unwrap().len(), 0);
+
+	assert_eq!(journal_mode(&mut db, "PRAGMA journal_mode = persist")?, "persist");
+	db.execute("INSERT INTO logs VALUES (2, 'persist')")?;
+	assert!(std::fs::metadata(&journal).unwrap().len() > 0);
+
+	// Leaving PERSIST mode removes the journal it kept
+	assert_eq!(journal_mode(&mut db, "PRAGMA journal_mode = OFF")?, "off");
+	assert!(!Path::new(&journal).exists());
+	db.execute("INSERT INTO logs VALUES (3, 'off')")?;
+	assert!(!Path::new(&journal).exists());
+	assert!(db.execute("PRAGMA journal_mode = sometimes").is_err());
+	db.close()?;
+
+	// The mode belongs to the connection
+	let mut db = Database::open(&path)?;
+	assert_eq!(journal_mode(&mut db, "PRAGMA journal_mode")?, "delete");
+	assert_eq!(count(&mut db)?, ValueType::I64(3));
+	Ok(())
+}
+
+#[test]
+fn test_hot_journal_is_rolled_back() -> Result<()> {
+	for (name, format) in [("hot_eplite", FileFormat::EPLiteV1), ("hot_sqlite3", FileFormat::SQLiteV3)] {
+		let path = fresh_path(name);
+		let mut db = Database::create_with_format(&path, format)?;
+		db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+		db.execute("INSERT INTO logs VALUES (1, 'committed')")?;
+		db.close()?;
+		let committed = std::fs::read(&path).unwrap();
+
+		let mut db = Database::open(&path)?;
+		db.execute("BEGIN")?;
+		for i in 2..=500 {
+			db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+		}
+		db.execute("COMMIT")?;
+		db.close()?;
+		let written = std::fs::read(&path).unwrap();
+		assert!(written.len() > committed.len());
+
+		crash_during_commit(&path, &committed, &written);
+		let mut db = Database::open(&path)?;
+		assert!(!Path::new(&journal_path(&path)).exists());
+		assert_eq!(std::fs::read(&path).unwrap(), committed);
+		assert_eq!(count(&mut db)?, ValueType::I64(1));
+		db.execute("INSERT INTO logs VALUES (2, 'after recovery')")?;
+		assert_eq!(count(&mut db)?, ValueType::I64(2));
+	}
+	Ok(())
+}
+
+#[test]
+fn test_sqlite_rolls_back_our_journal() {
+	let path = fresh_path("sqlite3_reads");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3).unwrap();
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)").unwrap();
+	db.execute("INSERT INTO logs VALUES (1, 'committed')").unwrap();
+	let committed = std::fs::read(&path).unwrap();
+	db.execute("DELETE FROM logs").unwrap();
+	db.close().unwrap();
+	let written = std::fs::read(&path).unwrap();
+
+	crash_during_commit(&path, &committed, &written);
+	let Ok(output) = Command::new("sqlite3").arg(&path).arg("PRAGMA integrity_check; SELECT message FROM logs").output() else {
+		return;
+	};
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "ok\ncommitted\n");
+	assert!(!Path::new(&journal_path(&path)).exists());
+}
@@ -0,0 +1,312 @@
+/// Tests for file locking between connections to one database
+///
+/// The locks follow SQLite's protocol, so when the sqlite3 command line tool
+/// is installed it is also checked to wait for our locks and we for its.
+
+mod common;
+
+use common::fresh_path;
+use epiloglite::eplite::config::DatabaseConfig;
+use epiloglite::eplite::os::file::{DefaultFile, RangeLock};
+use epiloglite::eplite::persistence::journal::Journal;
+use epiloglite::eplite::PENDING_BYTE;
+use epiloglite::{Database, Error, ExecutionResult, FileFormat, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+fn count(db: &mut Database) -> Result<String> {
+	match db.execute("SELECT COUNT(*) FROM logs")? {
+		ExecutionResult::Select { rows, .. } => Ok(rows[0][0].to_string()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+fn insert(db: &mut Database, id: i64) -> Result<()> {
+	db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", id, id))?;
+	Ok(())
+}
+
+/// Whether a connection holds the reserved lock, which writers take
+fn reserved(path: &str) -> bool {
+	let file = DefaultFile::open(path, true, false, false).unwrap();
+	!file.lock_range(PENDING_BYTE + 1, 1, RangeLock::Shared, false).unwrap()
+}
+
+/// Hold the write lock from another thread for `hold`, returning once it is
+/// taken
+fn hold_write_lock(path: &str, hold: Duration) -> JoinHandle<Result<()>> {
+	let path = path.to_string();
+	let (locked, wait) = mpsc::channel();
+	let holder = std::thread::spawn(move || {
+		let mut db = Database::open(&path)?;
+		db.execute("BEGIN IMMEDIATE")?;
+		insert(&mut db, 100)?;
+		locked.send(()).unwrap();
+		std::thread::sleep(hold);
+		db.execute("COMMIT")?;
+		Ok(())
+	});
+	wait.recv().unwrap();
+	holder
+}
+
+fn create(path: &str) -> Result<Database> {
+	let mut db = Database::open(path)?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	insert(&mut db, 1)?;
+	Ok(db)
+}
+
+#[test]
+fn test_one_writer_at_a_time() -> Result<()> {
+	let path = fresh_path("writers");
+	let mut first = create(&path)?;
+	let mut second = Database::open(&path)?;
+
+	first.execute("BEGIN")?;
+	insert(&mut first, 2)?;
+	assert!(matches!(insert(&mut second, 3), Err(Error::Busy)));
+	// Readers carry on while the writer has not started writing the file
+	assert_eq!(count(&mut second)?, "1");
+	first.execute("COMMIT")?;
+
+	assert_eq!(count(&mut second)?, "2");
+	insert(&mut second, 3)?;
+	assert_eq!(count(&mut first)?, "3");
+	Ok(())
+}
+
+#[test]
+fn test_commit_waits_for_readers() -> Result<()> {
+	let path = fresh_path("readers");
+	let mut writer = create(&path)?;
+	let mut reader = Database::open(&path)?;
+
+	reader.execute("BEGIN")?;
+	assert_eq!(count(&mut reader)?, "1");
+	assert!(matches!(insert(&mut writer, 2), Err(Error::Busy)));
+	assert_eq!(count(&mut writer)?, "1");
+
+	// A commit that failed can be retried once the reader is done
+	writer.execute("BEGIN")?;
+	insert(&mut writer, 2)?;
+	assert!(matches!(writer.execute("COMMIT"), Err(Error::Busy)));
+	assert_eq!(count(&mut reader)?, "1");
+	reader.execute("COMMIT")?;
+	writer.execute("COMMIT")?;
+	assert_eq!(count(&mut reader)?, "2");
+	Ok(())
+}
+
+#[test]
+fn test_schema_changes_are_seen() -> Result<()> {
+	let path = fresh_path("schema");
+	let mut first = Database::open(&path)?;
+	let mut second = Database::open(&path)?;
+
+	first.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	insert(&mut second, 1)?;
+	first.execute("DROP TABLE logs")?;
+	assert!(second.execute("SELECT * FROM logs").is_err());
+	Ok(())
+}
+
+#[test]
+fn test_live_journal_is_left_alone() -> Result<()> {
+	let path = fresh_path("live_journal");
+	let mut writer = create(&path)?;
+	let old = std::fs::read(&path).unwrap();
+	insert(&mut writer, 2)?;
+	writer.execute("BEGIN")?;
+	insert(&mut writer, 3)?;
+
+	// While its writer holds the reserved lock, a journal belongs to a commit
+	// in progress and is not rolled back
+	let pages: Vec<(u32, Vec<u8>)> = old
+		.chunks(4096)
+		.enumerate()
+		.map(|(i, data)| (i as u32 + 1, data.to_vec()))
+		.collect();
+	Journal::new(&path).write(4096, pages.len() as u32, &pages)?;
+	assert!(reserved(&path));
+	let mut reader = Database::open(&path)?;
+	assert_eq!(count(&mut reader)?, "2");
+	assert!(Path::new(&format!("{}-journal", path)).exists());
+
+	writer.execute("COMMIT")?;
+	assert_eq!(count(&mut reader)?, "3");
+	Ok(())
+}
+
+#[test]
+fn test_open_waits_to_roll_back_a_hot_journal() -> Result<()> {
+	let path = fresh_path("hot_journal_busy");
+	let mut writer = create(&path)?;
+	let old = std::fs::read(&path).unwrap();
+	insert(&mut writer, 2)?;
+	drop(writer);
+
+	// A journal left by a crash cannot be rolled back while a reader holds
+	// the file shared
+	let reader = Database::open(&path)?.begin_read()?;
+	let pages: Vec<(u32, Vec<u8>)> = old
+		.chunks(4096)
+		.enumerate()
+		.map(|(i, data)| (i as u32 + 1, data.to_vec()))
+		.collect();
+	Journal::new(&path).write(4096, pages.len() as u32, &pages)?;
+	assert!(matches!(Database::open(&path), Err(Error::Busy)));
+
+	// With a busy timeout opening waits for the reader to finish
+	let holder = std::thread::spawn(move || {
+		std::thread::sleep(Duration::from_millis(200));
+		reader.end()
+	});
+	let config = DatabaseConfig {
+		busy_timeout: 10_000,
+		..DatabaseConfig::default()
+	};
+	let start = Instant::now();
+	let mut db = Database::open_with_config(&path, &config)?;
+	assert!(start.elapsed() >= Duration::from_millis(100));
+	holder.join().unwrap()?;
+	assert_eq!(count(&mut db)?, "1");
+	assert!(!Path::new(&format!("{}-journal", path)).exists());
+	Ok(())
+}
+
+#[test]
+fn test_sqlite_takes_part_in_locking() -> Result<()> {
+	let path = fresh_path("sqlite3");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3)?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	insert(&mut db, 1)?;
+
+	// SQLite cannot write while we hold the reserved lock
+	db.execute("BEGIN")?;
+	insert(&mut db, 2)?;
+	let Ok(output) = Command::new("sqlite3").arg(&path).arg("INSERT INTO logs VALUES (3, 'from sqlite3')").output() else {
+		return Ok(());
+	};
+	assert!(String::from_utf8_lossy(&output.stderr).contains("database is locked"));
+	db.execute("COMMIT")?;
+
+	// Nor can we while SQLite holds it
+	let mut sqlite3 = Command::new("sqlite3")
+		.arg(&path)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::null())
+		.spawn()
+		.unwrap();
+	let mut input = sqlite3.stdin.take().unwrap();
+	writeln!(input, "BEGIN IMMEDIATE; INSERT INTO logs VALUES (3, 'from sqlite3');").unwrap();
+	input.flush().unwrap();
+	let start = Instant::now();
+	while !reserved(&path) {
+		assert!(start.elapsed() < Duration::from_secs(10), "sqlite3 never took its lock");
+		std::thread::sleep(Duration::from_millis(10));
+	}
+	assert!(matches!(insert(&mut db, 4), Err(Error::Busy)));
+	writeln!(input, "COMMIT;").unwrap();
+	drop(input);
+	assert!(sqlite3.wait().unwrap().success());
+
+	assert_eq!(count(&mut db)?, "3");
+	insert(&mut db, 4)?;
+	assert_eq!(count(&mut db)?, "4");
+	Ok(())
+}
+
+#[test]
+fn test_busy_timeout() -> Result<()> {
+	let path = fresh_path("busy_timeout");
+	let mut db = create(&path)?;
+
+	// The insert waits until the other connection commits
+	db.busy_timeout(Duration::from_secs(10));
+	let holder = hold_write_lock(&path, Duration::from_millis(200));
+	let start = Instant::now();
+	insert(&mut db, 2)?;
+	assert!(start.elapsed() >= Duration::from_millis(100));
+	holder.join().unwrap()?;
+	assert_eq!(count(&mut db)?, "3");
+
+	// Or gives up once the timeout has passed
+	db.busy_timeout(Duration::from_millis(50));
+	let holder = hold_write_lock(&path, Duration::from_millis(500));
+	let start = Instant::now();
+	assert!(matches!(insert(&mut db, 3), Err(Error::Busy)));
+	assert!(start.elapsed() >= Duration::from_millis(50));
+	assert!(start.elapsed() < Duration::from_millis(400));
+	holder.join().unwrap()?;
+
+	db.busy_timeout(Duration::ZERO);
+	let holder = hold_write_lock(&path, Duration::from_millis(100));
+	assert!(matches!(insert(&mut db, 3), Err(Error::Busy)));
+	holder.join().unwrap()?;
+	Ok(())
+}
+
+#[test]
+fn test_busy_handler() -> Result<()> {
+	let path = fresh_path("busy_handler");
+	let mut db = create(&path)?;
+	let calls = Arc::new(Mutex::new(Vec::new()));
+	let seen = calls.clone();
+	db.busy_handler(move |retries| {
+		seen.lock().unwrap().push(retries);
+		retries < 3
+	});
+
+	let holder = hold_write_lock(&path, Duration::from_millis(200));
+	assert!(matches!(insert(&mut db, 2), Err(Error::Busy)));
+	assert_eq!(*calls.lock().unwrap(), vec![0, 1, 2, 3]);
+
+	// A write in a transaction that already read is not retried, as the
+	// writer it waits for could be waiting for its read lock
+	calls.lock().unwrap().clear();
+	db.execute("BEGIN")?;
+	assert_eq!(count(&mut db)?, "1");
+	assert!(matches!(insert(&mut db, 2), Err(Error::Busy)));
+	assert!(calls.lock().unwrap().is_empty());
+	db.execute("ROLLBACK")?;
+	holder.join().unwrap()?;
+	Ok(())
+}
+
+#[test]
+fn test_begin_immediate_and_exclusive() -> Result<()> {
+	let path = fresh_path("begin_modes");
+	let mut first = create(&path)?;
+	let mut second = Database::open(&path)?;
+
+	first.execute("BEGIN IMMEDIATE TRANSACTION")?;
+	assert!(matches!(second.execute("BEGIN IMMEDIATE"), Err(Error::Busy)));
+	assert!(matches!(second.execute("BEGIN EXCLUSIVE"), Err(Error::Busy)));
+	assert_eq!(count(&mut second)?, "1");
+	second.execute("BEGIN DEFERRED")?;
+	assert!(matches!(insert(&mut second, 2), Err(Error::Busy)));
+	second.execute("ROLLBACK")?;
+	insert(&mut first, 2)?;
+	first.execute("COMMIT")?;
+
+	// An exclusive transaction keeps readers out as well
+	first.execute("BEGIN EXCLUSIVE")?;
+	assert!(matches!(count(&mut second), Err(Error::Busy)));
+	first.execute("ROLLBACK")?;
+	assert_eq!(count(&mut second)?, "2");
+
+	// In WAL mode readers carry on regardless
+	first.execute("PRAGMA journal_mode = WAL")?;
+	first.execute("BEGIN EXCLUSIVE")?;
+	assert_eq!(count(&mut second)?, "2");
+	assert!(matches!(second.execute("BEGIN IMMEDIATE"), Err(Error::Busy)));
+	insert(&mut first, 3)?;
+	first.execute("COMMIT")?;
+	assert_eq!(count(&mut second)?, "3");
+	Ok(())
+}
@@ -0,0 +1,123 @@
+/// Tests for reading the database through a memory map
+///
+/// Maps are only made with the `mmap` feature; without it the same reads
+/// and writes go through page buffers.
+
+mod common;
+
+use common::{fresh_path, select};
+use epiloglite::{Database, Result};
+
+fn insert(db: &mut Database, from: i64, to: i64) -> Result<()> {
+	db.execute("BEGIN")?;
+	for i in from..=to {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	db.execute("COMMIT")?;
+	Ok(())
+}
+
+fn create(path: &str, journal_mode: &str) -> Result<Database> {
+	let mut db = Database::open(path)?;
+	db.execute(&format!("PRAGMA journal_mode = {}", journal_mode))?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	insert(&mut db, 1, 1000)?;
+	Ok(db)
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn test_mmap_size_pragma() -> Result<()> {
+	let mut db = Database::open(fresh_path("pragma"))?;
+	assert_eq!(select(&mut db, "PRAGMA mmap_size")?, vec![vec!["0"]]);
+	assert_eq!(select(&mut db, "PRAGMA mmap_size = 1048576")?, vec![vec!["1048576"]]);
+	assert_eq!(select(&mut db, "PRAGMA mmap_size")?, vec![vec!["1048576"]]);
+
+	// Sizes are capped, and a negative one restores the default
+	assert_eq!(select(&mut db, "PRAGMA mmap_size = 100000000000")?, vec![vec!["2147418112"]]);
+	assert_eq!(select(&mut db, "PRAGMA mmap_size = -1")?, vec![vec!["0"]]);
+	assert!(db.execute("PRAGMA mmap_size = big").is_err());
+
+	// In-memory databases have no file to map
+	let mut db = Database::open(":memory:")?;
+	assert_eq!(select(&mut db, "PRAGMA mmap_size = 1048576")?, vec![vec!["1048576"]]);
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	insert(&mut db, 1, 10)?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["10"]]);
+	Ok(())
+}
+
+#[test]
+#[cfg(not(feature = "mmap"))]
+fn test_mmap_size_pragma_without_mmap() -> Result<()> {
+	let mut db = Database::open(fresh_path("pragma"))?;
+	assert_eq!(select(&mut db, "PRAGMA mmap_size = 1048576")?, vec![vec!["0"]]);
+	assert_eq!(select(&mut db, "PRAGMA mmap_size")?, vec![vec!["0"]]);
+	Ok(())
+}
+
+#[test]
+fn test_mmap_reads_and_writes() -> Result<()> {
+	for journal_mode in ["DELETE", "WAL"] {
+		let path = fresh_path(&format!("writes_{}", journal_mode));
+		let mut db = create(&path, journal_mode)?;
+		db.execute("PRAGMA mmap_size = 268435456")?;
+		db.execute("PRAGMA cache_size = 10")?;
+
+		assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["1000"]]);
+		db.execute("UPDATE logs SET message = 'changed' WHERE id = 500")?;
+		db.execute("DELETE FROM logs WHERE id > 900")?;
+		insert(&mut db, 2000, 3000)?;
+		db.execute("BEGIN")?;
+		db.execute("DELETE FROM logs WHERE id < 100")?;
+		db.execute("ROLLBACK")?;
+		assert_eq!(select(&mut db, "SELECT * FROM logs WHERE id = 500")?, vec![vec!["500", "changed"]]);
+		assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["1901"]]);
+		drop(db);
+
+		// The file holds the same data when read without a map
+		let mut db = Database::open(&path)?;
+		assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["1901"]]);
+		assert_eq!(select(&mut db, "SELECT * FROM logs WHERE id = 2500")?, vec![vec!["2500", "message number 2500"]]);
+	}
+	Ok(())
+}
+
+#[test]
+fn test_mmap_follows_other_connections() -> Result<()> {
+	for journal_mode in ["DELETE", "WAL"] {
+		let path = fresh_path(&format!("others_{}", journal_mode));
+		let mut writer = create(&path, journal_mode)?;
+		let mut reader = Database::open(&path)?;
+		reader.execute("PRAGMA mmap_size = 268435456")?;
+		assert_eq!(select(&mut reader, "SELECT COUNT(*) FROM logs")?, vec![vec!["1000"]]);
+
+		// The file grows past the map, then shrinks under it
+		insert(&mut writer, 1001, 5000)?;
+		assert_eq!(select(&mut reader, "SELECT COUNT(*) FROM logs")?, vec![vec!["5000"]]);
+		writer.execute("DELETE FROM logs WHERE id > 10")?;
+		writer.execute("VACUUM")?;
+		writer.execute("PRAGMA wal_checkpoint(TRUNCATE)")?;
+		assert_eq!(select(&mut reader, "SELECT COUNT(*) FROM logs")?, vec![vec!["10"]]);
+		assert_eq!(select(&mut reader, "SELECT * FROM logs WHERE id = 7")?, vec![vec!["7", "message number 7"]]);
+	}
+	Ok(())
+}
+
+#[test]
+fn test_mmap_vacuum_shrinks_mapped_file() -> Result<()> {
+	let path = fresh_path("vacuum");
+	let mut db = create(&path, "DELETE")?;
+	db.execute("PRAGMA mmap_size = 268435456")?;
+	insert(&mut db, 1001, 5000)?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["5000"]]);
+
+	let size = std::fs::metadata(&path).unwrap().len();
+	db.execute("DELETE FROM logs WHERE id > 10")?;
+	db.execute("VACUUM")?;
+	assert!(std::fs::metadata(&path).unwrap().len() < size);
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["10"]]);
+	insert(&mut db, 11, 20)?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["20"]]);
+	Ok(())
+}
@@ -0,0 +1,125 @@
+/// Tests for ORDER BY, LIMIT and OFFSET
+
+mod common;
+
+use common::{create_table, firsts, select};
+use epiloglite::{Database, Result, SelectBuilder, ValueType};
+
+fn create() -> Result<Database> {
+	let mut db = Database::open(":memory:")?;
+	create_table(
+		&mut db,
+		"items",
+		"id INTEGER PRIMARY KEY, kind TEXT, price INTEGER, stock INTEGER",
+		&[
+			"1, 'fruit', 3, 10",
+			"2, 'tool', NULL, 5",
+			"3, 'fruit', 2, NULL",
+			"4, 'tool', 8, 1",
+			"5, 'fruit', 3, 20",
+			"6, NULL, 5, 5",
+		],
+	)?;
+	Ok(db)
+}
+
+#[test]
+fn test_multiple_keys_and_directions() -> Result<()> {
+	let mut db = create()?;
+	// NULLs come first ascending and last descending; ties keep scan order
+	assert_eq!(firsts(&mut db, "SELECT * FROM items ORDER BY price")?, vec!["2", "3", "1", "5", "6", "4"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items ORDER BY price DESC")?, vec!["4", "6", "1", "5", "3", "2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items ORDER BY price ASC, stock DESC")?, vec!["2", "3", "5", "1", "6", "4"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items ORDER BY kind DESC, id DESC")?, vec!["4", "2", "5", "3", "1", "6"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items ORDER BY price NULLS LAST, id")?, vec!["3", "1", "5", "6", "4", "2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items ORDER BY stock DESC NULLS FIRST")?, vec!["3", "5", "1", "2", "6", "4"]);
+	Ok(())
+}
+
+#[test]
+fn test_expressions_aliases_and_column_numbers() -> Result<()> {
+	let mut db = create()?;
+	// Sorting by a value that is not selected
+	assert_eq!(firsts(&mut db, "SELECT id FROM items WHERE stock IS NOT NULL ORDER BY price * stock DESC")?, vec!["5", "1", "6", "4", "2"]);
+	assert_eq!(firsts(&mut db, "SELECT id FROM items ORDER BY -id LIMIT 2")?, vec!["6", "5"]);
+	assert_eq!(
+		select(&mut db, "SELECT id, price * stock AS value FROM items ORDER BY value DESC NULLS LAST, 1 LIMIT 3")?,
+		vec![vec!["5", "60"], vec!["1", "30"], vec!["6", "25"]]
+	);
+	assert_eq!(firsts(&mut db, "SELECT kind, id FROM items ORDER BY 2 DESC")?, vec!["NULL", "fruit", "tool", "fruit", "tool", "fruit"]);
+	assert_eq!(
+		firsts(&mut db, "SELECT id FROM items ORDER BY CASE kind WHEN 'tool' THEN 0 ELSE 1 END, id DESC")?,
+		vec!["4", "2", "6", "5", "3", "1"]
+	);
+	assert!(db.execute("SELECT id FROM items ORDER BY 3").is_err());
+	assert!(db.execute("SELECT id FROM items ORDER BY 0").is_err());
+	assert!(db.execute("SELECT id FROM items ORDER BY missing").is_err());
+	Ok(())
+}
+
+#[test]
+fn test_limit_and_offset() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT 2")?, vec!["1", "2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT 2 OFFSET 3")?, vec!["4", "5"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT 3, 2")?, vec!["4", "5"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT 1 + 1 OFFSET 2 * 2")?, vec!["5", "6"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT -1 OFFSET 4")?, vec!["5", "6"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT 10 OFFSET -3")?.len(), 6);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT 0")?.len(), 0);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items LIMIT 5 OFFSET 10")?.len(), 0);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items ORDER BY price DESC LIMIT 2 OFFSET 1")?, vec!["6", "1"]);
+	assert_eq!(firsts(&mut db, "SELECT COUNT(*) FROM items LIMIT 0")?.len(), 0);
+	assert!(db.execute("SELECT * FROM items LIMIT 'many'").is_err());
+	assert!(db.execute("SELECT * FROM items LIMIT 1.5").is_err());
+	assert!(db.execute("SELECT * FROM items LIMIT id").is_err());
+
+	let mut query = db.prepare("SELECT id FROM items ORDER BY id DESC LIMIT ? OFFSET :skip")?;
+	query.bind(1, ValueType::I64(2))?;
+	query.bind(":skip", ValueType::I64(1))?;
+	assert_eq!(query.step()?, Some(vec![ValueType::I64(5)]));
+	assert_eq!(query.step()?, Some(vec![ValueType::I64(4)]));
+	assert_eq!(query.step()?, None);
+
+	let sql = SelectBuilder::new().column("id").from("items").order_by("id DESC").offset(4).build()?;
+	assert_eq!(firsts(&mut db, &sql)?, vec!["2", "1"]);
+	Ok(())
+}
+
+#[test]
+fn test_top_rows_of_many() -> Result<()> {
+	let mut db = Database::open(":memory:")?;
+	db.execute("CREATE TABLE numbers (id INTEGER PRIMARY KEY, value INTEGER)")?;
+	let mut insert = db.prepare("INSERT INTO numbers VALUES (?, ?)")?;
+	for id in 0..500 {
+		insert.bind(1, ValueType::I64(id))?;
+		insert.bind(2, ValueType::I64(id * 37 % 100))?;
+		insert.execute()?;
+	}
+
+	// Equal values keep the order of their rows, as a full sort would
+	let expected: Vec<String> = {
+		let mut all: Vec<(i64, i64)> = (0..500).map(|id| (id * 37 % 100, id)).collect();
+		all.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+		all.iter().skip(3).take(7).map(|(_, id)| id.to_string()).collect()
+	};
+	assert_eq!(firsts(&mut db, "SELECT id FROM numbers ORDER BY value DESC LIMIT 7 OFFSET 3")?, expected);
+	let all = firsts(&mut db, "SELECT id FROM numbers ORDER BY value DESC")?;
+	assert_eq!(all[3..10], expected[..]);
+	Ok(())
+}
+
+#[test]
+fn test_joined_rows() -> Result<()> {
+	let mut db = create()?;
+	db.execute("CREATE TABLE sales (sale_id INTEGER PRIMARY KEY, item INTEGER, quantity INTEGER)")?;
+	for (sale_id, item, quantity) in [(1, 1, 4), (2, 4, 1), (3, 1, 2), (4, 5, 9)] {
+		db.execute(&format!("INSERT INTO sales VALUES ({}, {}, {})", sale_id, item, quantity))?;
+	}
+	assert_eq!(
+		select(&mut db, "SELECT sale_id, price * quantity AS total FROM items JOIN sales ON id = item ORDER BY total DESC LIMIT 2")?,
+		vec![vec!["4", "27"], vec!["1", "12"]]
+	);
+	assert_eq!(firsts(&mut db, "SELECT sale_id FROM items JOIN sales ON id = item ORDER BY items.id, quantity")?, vec!["3", "1", "2", "4"]);
+	Ok(())
+}
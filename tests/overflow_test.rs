@@ -0,0 +1,108 @@
+/// Tests for records too large for one page
+///
+/// SQLite 3 files are also checked with the sqlite3 command line tool when it
+/// is installed.
+
+mod common;
+
+use common::{fresh_path, select, sqlite3};
+use epiloglite::{Database, FileFormat, Result};
+
+/// A text of `len` characters that differs from row to row
+fn text(id: i64, len: usize) -> String {
+	(0..len).map(|i| (b'a' + ((i as i64 + id) % 26) as u8) as char).collect()
+}
+
+/// Hex digits of a blob of `len` bytes
+fn blob(id: i64, len: usize) -> String {
+	(0..len).map(|i| format!("{:02X}", (i as i64 * 7 + id) as u8)).collect()
+}
+
+#[test]
+fn test_large_values_round_trip() -> Result<()> {
+	for (name, format) in [("eplite", FileFormat::EPLiteV1), ("sqlite3", FileFormat::SQLiteV3)] {
+		let path = fresh_path(name);
+		let mut db = Database::create_with_format(&path, format)?;
+		db.execute("CREATE TABLE docs (id INTEGER PRIMARY KEY, body TEXT, data BLOB)")?;
+		for id in 1..=3 {
+			let len = id as usize * 1_000_000;
+			db.execute(&format!("INSERT INTO docs VALUES ({}, '{}', X'{}')", id, text(id, len), blob(id, len / 10)))?;
+		}
+		db.execute("INSERT INTO docs VALUES (4, 'small', X'00')")?;
+		db.close()?;
+
+		let mut db = Database::open(&path)?;
+		let rows = select(&mut db, "SELECT * FROM docs WHERE id = 3")?;
+		assert_eq!(rows[0][1], text(3, 3_000_000));
+		assert_eq!(rows[0][2], format!("X'{}'", blob(3, 300_000)));
+
+		// Shrinking, growing and deleting records frees and reuses their pages
+		db.execute("UPDATE docs SET body = 'short' WHERE id = 1")?;
+		db.execute("DELETE FROM docs WHERE id = 2")?;
+		let rows = select(&mut db, "PRAGMA freelist_count")?;
+		assert!(rows[0][0].parse::<u32>().unwrap() > 100);
+		db.execute(&format!("UPDATE docs SET body = '{}' WHERE id = 4", text(4, 2_000_000)))?;
+		assert_eq!(select(&mut db, "SELECT * FROM docs WHERE id = 1")?[0][1], "short");
+		assert_eq!(select(&mut db, "SELECT * FROM docs WHERE id = 4")?[0][1], text(4, 2_000_000));
+		assert_eq!(select(&mut db, "SELECT COUNT(*) FROM docs")?, vec![vec!["3"]]);
+		db.close()?;
+
+		if format == FileFormat::SQLiteV3 {
+			if let Some(check) = sqlite3(&path, "PRAGMA integrity_check; SELECT id, length(body), length(data) FROM docs") {
+				assert_eq!(check, "ok\n1|5|100000\n3|3000000|300000\n4|2000000|1\n");
+			}
+		}
+	}
+	Ok(())
+}
+
+#[test]
+fn test_large_values_survive_vacuum_and_rollback() -> Result<()> {
+	let path = fresh_path("vacuum");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3)?;
+	db.execute("CREATE TABLE docs (id INTEGER PRIMARY KEY, body TEXT UNIQUE)")?;
+	for id in 1..=5 {
+		db.execute(&format!("INSERT INTO docs VALUES ({}, '{}')", id, text(id, 100_000)))?;
+	}
+
+	db.execute("BEGIN")?;
+	db.execute(&format!("INSERT INTO docs VALUES (6, '{}')", text(6, 500_000)))?;
+	db.execute("DELETE FROM docs WHERE id < 3")?;
+	db.execute("ROLLBACK")?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM docs")?, vec![vec!["5"]]);
+
+	// Unique long values are checked against the whole value
+	assert!(db.execute(&format!("INSERT INTO docs VALUES (7, '{}')", text(5, 100_000))).is_err());
+	db.execute("DELETE FROM docs WHERE id > 2")?;
+	db.execute("VACUUM")?;
+	assert_eq!(select(&mut db, "PRAGMA freelist_count")?, vec![vec!["0"]]);
+	assert_eq!(select(&mut db, "SELECT * FROM docs WHERE id = 2")?[0][1], text(2, 100_000));
+	db.close()?;
+
+	if let Some(check) = sqlite3(&path, "PRAGMA integrity_check; SELECT id, length(body) FROM docs") {
+		assert_eq!(check, "ok\n1|100000\n2|100000\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_shared_with_sqlite() -> Result<()> {
+	let path = fresh_path("shared");
+	if sqlite3(&path, "CREATE TABLE docs (id INTEGER PRIMARY KEY, body TEXT)").is_none() {
+		return Ok(());
+	}
+
+	// Records written by SQLite and by us read back the same
+	let mut db = Database::open(&path)?;
+	db.execute(&format!("INSERT INTO docs VALUES (1, '{}')", text(1, 50_000)))?;
+	db.close()?;
+	sqlite3(&path, &format!("INSERT INTO docs VALUES (2, '{}')", text(2, 50_000)));
+	let mut db = Database::open(&path)?;
+	assert_eq!(select(&mut db, "SELECT * FROM docs WHERE id = 2")?[0][1], text(2, 50_000));
+	db.close()?;
+	assert_eq!(
+		sqlite3(&path, "PRAGMA integrity_check; SELECT id, length(body) FROM docs"),
+		Some("ok\n1|50000\n2|50000\n".to_string())
+	);
+	Ok(())
+}
@@ -0,0 +1,137 @@
+/// Tests for prepared statements and parameter binding
+
+mod common;
+
+use common::{create_table, select};
+use epiloglite::{Database, Error, ExecutionResult, Result, ValueType};
+
+fn text(value: &str) -> ValueType {
+	ValueType::StringUtf8(value.to_string())
+}
+
+fn create() -> Result<Database> {
+	let mut db = Database::open(":memory:")?;
+	create_table(&mut db, "users", "id INTEGER PRIMARY KEY, name TEXT, age INTEGER", &[])?;
+	Ok(db)
+}
+
+#[test]
+fn test_insert_many_rows_with_one_statement() -> Result<()> {
+	let mut db = create()?;
+	let mut insert = db.prepare("INSERT INTO users VALUES (?, ?, ?)")?;
+	assert_eq!(insert.parameter_count(), 3);
+	for (id, name, age) in [(1, "Alice", 30), (2, "Bob", -4), (3, "O'Brien", 41)] {
+		insert.bind(1, ValueType::I64(id))?;
+		insert.bind(2, text(name))?;
+		insert.bind(3, ValueType::I64(age))?;
+		assert!(matches!(insert.execute()?, ExecutionResult::RowsAffected(1)));
+	}
+	insert.clear_bindings();
+	insert.bind(1, ValueType::I64(4))?;
+	assert_eq!(insert.step()?, None);
+
+	assert_eq!(
+		select(&mut db, "SELECT * FROM users")?,
+		vec![
+			vec!["1", "Alice", "30"],
+			vec!["2", "Bob", "-4"],
+			vec!["3", "O'Brien", "41"],
+			vec!["4", "NULL", "NULL"],
+		]
+	);
+	Ok(())
+}
+
+#[test]
+fn test_step_and_reset() -> Result<()> {
+	let mut db = create()?;
+	for (id, name, age) in [(1, "'Alice'", 30), (2, "'Bob'", 25), (3, "'Carol'", 35)] {
+		db.execute(&format!("INSERT INTO users VALUES ({}, {}, {})", id, name, age))?;
+	}
+
+	let mut query = db.prepare("SELECT * FROM users WHERE age >= ?1 AND age < ?1 + 10")?;
+	query.bind(1, ValueType::I64(30))?;
+	assert_eq!(query.step()?.unwrap()[1], text("Alice"));
+	assert_eq!(query.step()?.unwrap()[1], text("Carol"));
+	assert_eq!(query.step()?, None);
+	assert_eq!(query.step()?, None);
+
+	// Bindings wait for a reset, and outlive it
+	assert!(query.bind(1, ValueType::I64(25)).is_err());
+	query.reset();
+	query.bind(1, ValueType::I64(25))?;
+	assert_eq!(query.step()?.unwrap()[1], text("Alice"));
+	query.reset();
+	assert_eq!(query.step()?.unwrap()[1], text("Alice"));
+	assert_eq!(query.step()?.unwrap()[1], text("Bob"));
+
+	// Unbound parameters are NULL, which matches nothing
+	query.reset();
+	query.clear_bindings();
+	assert_eq!(query.step()?, None);
+	Ok(())
+}
+
+#[test]
+fn test_named_parameters() -> Result<()> {
+	let mut db = create()?;
+	let mut insert = db.prepare("INSERT INTO users VALUES (:id, @name, $age)")?;
+	assert_eq!(insert.parameter_name(2), Some("@name"));
+	insert.bind(":id", ValueType::I64(1))?;
+	insert.bind("@name", text("Alice"))?;
+	insert.bind("$age", ValueType::I64(30))?;
+	insert.execute()?;
+	assert!(matches!(insert.bind("name", text("Bob")), Err(Error::NotFound(_))));
+	assert!(insert.bind(4, ValueType::Null).is_err());
+	assert!(insert.bind(0, ValueType::Null).is_err());
+
+	// A name used twice is one parameter
+	let mut update = db.prepare("UPDATE users SET age = :age WHERE id = :id OR age = :age - 30")?;
+	assert_eq!(update.parameter_count(), 2);
+	update.bind(":age", ValueType::I64(60))?;
+	update.bind(":id", ValueType::I64(7))?;
+	assert!(matches!(update.execute()?, ExecutionResult::RowsAffected(1)));
+
+	// A bare ? follows the largest index before it
+	let mut delete = db.prepare("DELETE FROM users WHERE name = ?2 AND age = ?")?;
+	assert_eq!(delete.parameter_count(), 3);
+	assert_eq!(delete.parameter_name(1), None);
+	assert_eq!(delete.parameter_name(2), Some("?2"));
+	assert_eq!(delete.parameter_name(3), None);
+	delete.bind("?2", text("Alice"))?;
+	delete.bind(3, ValueType::I64(60))?;
+	assert!(matches!(delete.execute()?, ExecutionResult::RowsAffected(1)));
+	assert!(select(&mut db, "SELECT * FROM users")?.is_empty());
+	Ok(())
+}
+
+#[test]
+fn test_bound_values_are_never_sql() -> Result<()> {
+	let mut db = create()?;
+	db.execute("INSERT INTO users VALUES (1, 'Alice', 30)")?;
+
+	let mut query = db.prepare("SELECT * FROM users WHERE name = ?")?;
+	query.bind(1, text("x' OR '1' = '1"))?;
+	assert_eq!(query.step()?, None);
+
+	let mut insert = db.prepare("INSERT INTO users VALUES (?, ?, 0)")?;
+	insert.bind(1, ValueType::I64(2))?;
+	insert.bind(2, text("'); DELETE FROM users; --"))?;
+	insert.execute()?;
+	assert_eq!(select(&mut db, "SELECT * FROM users WHERE id = 2")?[0][1], "'); DELETE FROM users; --");
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM users")?, vec![vec!["2"]]);
+	Ok(())
+}
+
+#[test]
+fn test_invalid_statements() -> Result<()> {
+	let mut db = create()?;
+	assert!(db.prepare("SELECT * FROM users WHERE id = ?0").is_err());
+	assert!(db.prepare("SELECT * FROM users WHERE id = :").is_err());
+	assert!(db.prepare("INSERT INTO users VALUES (?, ?").is_err());
+
+	// Errors of a prepared statement surface when it runs
+	let mut query = db.prepare("SELECT * FROM missing WHERE id = ?")?;
+	assert!(query.step().is_err());
+	Ok(())
+}
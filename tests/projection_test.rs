@@ -0,0 +1,126 @@
+/// Tests for the columns and expressions a SELECT returns
+
+mod common;
+
+use common::create_table;
+use epiloglite::{Database, ExecutionResult, Result, ValueType};
+
+/// Column names and rows of a query, with each value rendered as text
+fn select(db: &mut Database, sql: &str) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+	match db.execute(sql)? {
+		ExecutionResult::Select { rows, columns } => Ok((
+			columns,
+			rows.iter()
+				.map(|row| row.iter().map(|value| value.to_string()).collect())
+				.collect(),
+		)),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+fn create() -> Result<Database> {
+	let mut db = Database::open(":memory:")?;
+	create_table(
+		&mut db,
+		"people",
+		"id INTEGER PRIMARY KEY, first TEXT, last TEXT, age INTEGER",
+		&["1, 'Ada', 'Lovelace', 36", "2, 'Alan', 'Turing', 41", "3, 'Grace', 'Hopper', NULL"],
+	)?;
+	create_table(
+		&mut db,
+		"orders",
+		"order_id INTEGER PRIMARY KEY, person INTEGER, price INTEGER, qty INTEGER",
+		&["10, 1, 5, 3", "11, 2, 7, 2"],
+	)?;
+	Ok(db)
+}
+
+#[test]
+fn test_selected_columns() -> Result<()> {
+	let mut db = create()?;
+	let (columns, rows) = select(&mut db, "SELECT last, id FROM people WHERE id < 3")?;
+	assert_eq!(columns, vec!["last", "id"]);
+	assert_eq!(rows, vec![vec!["Lovelace", "1"], vec!["Turing", "2"]]);
+
+	let (columns, rows) = select(&mut db, "SELECT people.first, age AS years, \"last\" surname FROM people WHERE id = 3")?;
+	assert_eq!(columns, vec!["first", "years", "surname"]);
+	assert_eq!(rows, vec![vec!["Grace", "NULL", "Hopper"]]);
+
+	let (columns, rows) = select(&mut db, "SELECT *, id FROM people WHERE id = 1")?;
+	assert_eq!(columns, vec!["id", "first", "last", "age", "id"]);
+	assert_eq!(rows, vec![vec!["1", "Ada", "Lovelace", "36", "1"]]);
+	Ok(())
+}
+
+#[test]
+fn test_computed_expressions() -> Result<()> {
+	let mut db = create()?;
+	let (columns, rows) = select(
+		&mut db,
+		"SELECT first || ' ' || last, age + 1 AS next, id * 10 FROM people ORDER BY id",
+	)?;
+	assert_eq!(columns, vec!["first || ' ' || last", "next", "id * 10"]);
+	assert_eq!(
+		rows,
+		vec![
+			vec!["Ada Lovelace", "37", "10"],
+			vec!["Alan Turing", "42", "20"],
+			vec!["Grace Hopper", "NULL", "30"],
+		]
+	);
+
+	let (columns, rows) = select(
+		&mut db,
+		"SELECT first, CASE WHEN age >= 40 THEN 'senior' WHEN age IS NULL THEN 'unknown' ELSE 'junior' END AS band, \
+		 CASE id WHEN 1 THEN 'one' END FROM people",
+	)?;
+	assert_eq!(columns[1], "band");
+	assert_eq!(columns[2], "CASE id WHEN 1 THEN 'one' END");
+	assert_eq!(
+		rows,
+		vec![
+			vec!["Ada", "junior", "one"],
+			vec!["Alan", "senior", "NULL"],
+			vec!["Grace", "unknown", "NULL"],
+		]
+	);
+
+	// Parameters can be selected like any other value
+	let mut query = db.prepare("SELECT first, ? AS tag FROM people WHERE id = 2")?;
+	query.bind(1, ValueType::I64(7))?;
+	assert_eq!(query.step()?.unwrap()[1], ValueType::I64(7));
+
+	// The smallest integer is a literal, not the negation of one too large
+	db.execute("INSERT INTO people VALUES (4, 'Min', 'Int', -9223372036854775808)")?;
+	let mut query = db.prepare("SELECT age, -9223372036854775808 - age FROM people WHERE id = 4")?;
+	assert_eq!(query.step()?, Some(vec![ValueType::I64(i64::MIN), ValueType::I64(0)]));
+	Ok(())
+}
+
+#[test]
+fn test_joined_columns() -> Result<()> {
+	let mut db = create()?;
+	let (columns, rows) = select(
+		&mut db,
+		"SELECT first, price * qty AS total, orders.* FROM people JOIN orders ON people.id = orders.person",
+	)?;
+	assert_eq!(columns, vec!["first", "total", "order_id", "person", "price", "qty"]);
+	assert_eq!(rows, vec![vec!["Ada", "15", "10", "1", "5", "3"], vec!["Alan", "14", "11", "2", "7", "2"]]);
+
+	let (columns, rows) = select(&mut db, "SELECT * FROM people LEFT JOIN orders ON people.id = orders.person WHERE id = 3")?;
+	assert_eq!(columns, vec!["id", "first", "last", "age", "order_id", "person", "price", "qty"]);
+	assert_eq!(rows, vec![vec!["3", "Grace", "Hopper", "NULL", "NULL", "NULL", "NULL", "NULL"]]);
+	Ok(())
+}
+
+#[test]
+fn test_invalid_select_lists() -> Result<()> {
+	let mut db = create()?;
+	assert!(db.execute("SELECT missing FROM people").is_err());
+	assert!(db.execute("SELECT orders.* FROM people").is_err());
+	assert!(db.execute("SELECT id AS FROM people").is_err());
+	assert!(db.execute("SELECT CASE END FROM people").is_err());
+	assert!(db.execute("SELECT CASE WHEN id = 1 THEN 2 FROM people").is_err());
+	assert!(db.execute("SELECT id FROM people JOIN orders ON people.id = orders.person").is_ok());
+	Ok(())
+}
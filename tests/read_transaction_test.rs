@@ -0,0 +1,166 @@
+/// Tests for read transactions holding a snapshot while the database changes
+
+mod common;
+
+use common::fresh_path;
+use epiloglite::eplite::config::DatabaseConfig;
+use epiloglite::{Database, Error, ExecutionResult, ReadTransaction, Result};
+use std::time::Duration;
+
+fn count(snapshot: &mut ReadTransaction) -> Result<String> {
+	match snapshot.query("SELECT COUNT(*) FROM logs")? {
+		ExecutionResult::Select { rows, .. } => Ok(rows[0][0].to_string()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+fn insert(db: &mut Database, id: i64) -> Result<()> {
+	db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", id, id))?;
+	Ok(())
+}
+
+fn create(path: &str, journal_mode: &str) -> Result<Database> {
+	let mut db = Database::open(path)?;
+	db.execute(&format!("PRAGMA journal_mode = {}", journal_mode))?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	insert(&mut db, 1)?;
+	Ok(db)
+}
+
+#[test]
+fn test_snapshot_outlives_commits() -> Result<()> {
+	let path = fresh_path("commits");
+	let mut db = create(&path, "WAL")?;
+
+	let mut first = db.begin_read()?;
+	insert(&mut db, 2)?;
+	let mut second = db.begin_read()?;
+	db.execute("BEGIN")?;
+	insert(&mut db, 3)?;
+	insert(&mut db, 4)?;
+	db.execute("COMMIT")?;
+
+	assert_eq!(count(&mut first)?, "1");
+	assert_eq!(count(&mut second)?, "2");
+	insert(&mut db, 5)?;
+	assert_eq!(count(&mut first)?, "1");
+	first.end()?;
+	assert_eq!(count(&mut second)?, "2");
+	second.end()?;
+
+	assert_eq!(count(&mut db.begin_read()?)?, "5");
+	Ok(())
+}
+
+#[test]
+fn test_checkpoints_keep_snapshots() -> Result<()> {
+	let path = fresh_path("checkpoints");
+	let mut db = create(&path, "WAL")?;
+	db.execute("PRAGMA wal_autocheckpoint = 1")?;
+
+	// Checkpoints after each commit stop at the pages the snapshot reads
+	let mut snapshot = db.begin_read()?;
+	for id in 2..=50 {
+		insert(&mut db, id)?;
+	}
+	assert_eq!(count(&mut snapshot)?, "1");
+	drop(snapshot);
+
+	insert(&mut db, 51)?;
+	assert_eq!(count(&mut db.begin_read()?)?, "51");
+	Ok(())
+}
+
+#[test]
+fn test_snapshots_read_on_other_threads() -> Result<()> {
+	let path = fresh_path("threads");
+	let mut db = create(&path, "WAL")?;
+
+	let readers: Vec<_> = (0..4)
+		.map(|_| {
+			let mut snapshot = db.begin_read()?;
+			Ok(std::thread::spawn(move || -> Result<()> {
+				for _ in 0..20 {
+					assert_eq!(count(&mut snapshot)?, "1");
+					std::thread::sleep(Duration::from_millis(1));
+				}
+				snapshot.end()
+			}))
+		})
+		.collect::<Result<_>>()?;
+
+	// The writer carries on while the readers hold their snapshots
+	for id in 2..=10 {
+		insert(&mut db, id)?;
+	}
+	for reader in readers {
+		reader.join().unwrap()?;
+	}
+	assert_eq!(count(&mut db.begin_read()?)?, "10");
+	Ok(())
+}
+
+#[test]
+fn test_snapshot_is_read_only() -> Result<()> {
+	let path = fresh_path("read_only");
+	let db = create(&path, "WAL")?;
+
+	let mut snapshot = db.begin_read()?;
+	for sql in [
+		"INSERT INTO logs VALUES (2, 'message number 2')",
+		"DELETE FROM logs",
+		"DROP TABLE logs",
+		"COMMIT",
+	] {
+		assert!(matches!(snapshot.query(sql), Err(Error::InvalidOperation(_))));
+	}
+	assert_eq!(count(&mut snapshot)?, "1");
+	snapshot.end()?;
+
+	assert!(matches!(Database::open(":memory:")?.begin_read(), Err(Error::NotSupported(_))));
+	Ok(())
+}
+
+#[test]
+fn test_snapshot_holds_off_writers_without_wal() -> Result<()> {
+	let path = fresh_path("rollback_journal");
+	let mut db = create(&path, "DELETE")?;
+
+	// Outside WAL mode the snapshot is kept by the shared lock, so commits
+	// wait for it
+	let mut snapshot = db.begin_read()?;
+	assert!(matches!(insert(&mut db, 2), Err(Error::Busy)));
+	assert_eq!(count(&mut snapshot)?, "1");
+
+	db.busy_timeout(Duration::from_secs(10));
+	let reader = std::thread::spawn(move || {
+		std::thread::sleep(Duration::from_millis(100));
+		snapshot.end()
+	});
+	insert(&mut db, 2)?;
+	reader.join().unwrap()?;
+	assert_eq!(count(&mut db.begin_read()?)?, "2");
+	Ok(())
+}
+
+#[test]
+fn test_snapshot_takes_connection_settings() -> Result<()> {
+	let path = fresh_path("settings");
+	create(&path, "WAL")?.close()?;
+	let config = DatabaseConfig {
+		cache_size: 7,
+		..DatabaseConfig::default()
+	};
+	let mut db = Database::open_with_config(&path, &config)?;
+
+	let snapshot = db.begin_read()?;
+	assert_eq!(snapshot.cache_stats().capacity, 7);
+	snapshot.end()?;
+
+	// Including those changed since the database was opened
+	db.execute("PRAGMA cache_size = 3")?;
+	let mut snapshot = db.begin_read()?;
+	assert_eq!(snapshot.cache_stats().capacity, 3);
+	assert_eq!(count(&mut snapshot)?, "1");
+	Ok(())
+}
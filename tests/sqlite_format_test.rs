@@ -0,0 +1,380 @@
+/// Tests for reading and writing SQLite 3 database files
+///
+/// The fixtures are built from the .sql files beside them with the
+/// sqlite3 command line tool. Tests that check our files with that tool are
+/// skipped when it is not installed.
+
+mod common;
+
+use common::{fresh_path, select, sqlite3};
+use epiloglite::{Database, Error, ExecutionResult, FileFormat, Result};
+
+/// Copy a fixture to a private path so tests cannot modify it
+fn copy_fixture(fixture: &str, name: &str) -> String {
+	let source = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), fixture);
+	let path = fresh_path(name);
+	std::fs::copy(source, &path).expect("copy fixture");
+	path
+}
+
+/// Open a private copy of the sample fixture
+fn open_fixture(name: &str) -> Result<Database> {
+	Database::open(copy_fixture("sqlite3_sample.db", name))
+}
+
+/// Create an empty SQLite 3 database at a fresh path
+fn create_database(name: &str) -> Result<(Database, String)> {
+	let path = fresh_path(name);
+	Ok((Database::create_with_format(&path, FileFormat::SQLiteV3)?, path))
+}
+
+fn header_u32(data: &[u8], offset: usize) -> u32 {
+	u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[test]
+fn test_read_sqlite3_table() -> Result<()> {
+	let mut db = open_fixture("table")?;
+	let rows = select(&mut db, "SELECT * FROM users")?;
+	assert_eq!(rows.len(), 300);
+
+	// The INTEGER PRIMARY KEY column reads back as the rowid
+	assert_eq!(rows[0], vec!["1", "user1", "1", "1.5", "NULL"]);
+	// sqlite3 stores a whole REAL as an integer, which reads back as REAL
+	assert_eq!(rows[1], vec!["2", "user2", "2", "3.0", "NULL"]);
+	assert_eq!(rows[99][0], "100");
+	assert_eq!(rows[99][4], "X'DEADBEEF'");
+	assert_eq!(rows[299][1], "user300");
+	Ok(())
+}
+
+#[test]
+fn test_query_sqlite3_table() -> Result<()> {
+	let mut db = open_fixture("query")?;
+	assert_eq!(select(&mut db, "SELECT * FROM users WHERE age > 80")?.len(), 27);
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM users")?, vec![vec!["300"]]);
+
+	let rows = select(&mut db, "SELECT * FROM users WHERE name = 'user42'")?;
+	assert_eq!(rows.len(), 1);
+	assert_eq!(rows[0][0], "42");
+	Ok(())
+}
+
+#[test]
+fn test_read_sqlite3_overflow_pages() -> Result<()> {
+	let mut db = open_fixture("overflow")?;
+	let rows = select(&mut db, "SELECT * FROM notes")?;
+	assert_eq!(rows.len(), 3);
+	assert_eq!(rows[0], vec!["1", "1", "short note"]);
+
+	// A 5000 byte value does not fit on a 1024 byte page
+	assert_eq!(rows[1][2], "x".repeat(5000));
+	assert_eq!(rows[2][2], "NULL");
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_views_are_not_tables() -> Result<()> {
+	let mut db = open_fixture("views")?;
+	assert!(matches!(db.execute("SELECT * FROM adults"), Err(Error::NotSupported(_))));
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_objects_without_rowid_tables() -> Result<()> {
+	let mut db = Database::open(copy_fixture("sqlite3_altered.db", "unsupported"))?;
+	for name in ["wr", "WR", "v1", "vt"] {
+		for sql in [
+			format!("SELECT * FROM {}", name),
+			format!("INSERT INTO {} VALUES ('y', 2)", name),
+			format!("UPDATE {} SET k = 'z'", name),
+			format!("DELETE FROM {}", name),
+			format!("DROP TABLE {}", name),
+		] {
+			assert!(matches!(db.execute(&sql), Err(Error::NotSupported(_))), "{}", sql);
+		}
+	}
+	assert!(matches!(db.execute("SELECT * FROM missing"), Err(Error::NotFound(_))));
+	// The shadow tables of a virtual table are ordinary tables
+	assert_eq!(select(&mut db, "SELECT rowid FROM vt_rowid")?, vec![vec!["1"]]);
+	Ok(())
+}
+
+#[test]
+fn test_rows_written_before_alter_table() -> Result<()> {
+	let path = copy_fixture("sqlite3_altered.db", "altered");
+	let mut db = Database::open(&path)?;
+	// Columns added after a row was written read as their defaults
+	assert_eq!(
+		select(&mut db, "SELECT * FROM items")?,
+		vec![
+			vec!["1", "one", "none", "-1", "NULL"],
+			vec!["2", "NULL", "none", "-1", "NULL"],
+			vec!["3", "three", "set", "7", "0.5"],
+		]
+	);
+	assert_eq!(select(&mut db, "SELECT a FROM items WHERE c = 'none' AND d < 0")?.len(), 2);
+
+	// Changing an old row writes every column
+	assert!(matches!(
+		db.execute("UPDATE items SET b = 'uno', e = 1.5 WHERE a = 1")?,
+		ExecutionResult::RowsAffected(1)
+	));
+	assert!(matches!(db.execute("DELETE FROM items WHERE e IS NULL")?, ExecutionResult::RowsAffected(1)));
+	assert_eq!(
+		select(&mut db, "SELECT * FROM items")?,
+		vec![vec!["1", "uno", "none", "-1", "1.5"], vec!["3", "three", "set", "7", "0.5"]]
+	);
+	db.close()?;
+
+	if let Some(output) = sqlite3(&path, "PRAGMA integrity_check; SELECT * FROM items") {
+		assert_eq!(output, "ok\n1|uno|none|-1|1.5\n3|three|set|7|0.5\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_numeric_affinity() -> Result<()> {
+	let path = copy_fixture("sqlite3_numeric.db", "numeric");
+	let mut db = Database::open(&path)?;
+	// NUMERIC and DECIMAL columns keep whole numbers as integers
+	db.execute("INSERT INTO amounts VALUES (2, '1e2', '5', '5')")?;
+	db.execute("INSERT INTO amounts VALUES (3, '2.5', 7.0, 7)")?;
+	assert_eq!(
+		select(&mut db, "SELECT * FROM amounts")?,
+		vec![
+			vec!["1", "100", "5", "5.0"],
+			vec!["2", "100", "5", "5.0"],
+			vec!["3", "2.5", "7", "7.0"],
+		]
+	);
+	db.close()?;
+
+	if let Some(output) = sqlite3(&path, "SELECT typeof(n), typeof(d), typeof(r) FROM amounts") {
+		assert_eq!(output, "integer|integer|real\ninteger|integer|real\nreal|integer|real\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_auto_vacuum_file_is_read_only() -> Result<()> {
+	let path = copy_fixture("sqlite3_auto_vacuum.db", "auto_vacuum");
+	let mut db = Database::open(&path)?;
+	assert_eq!(select(&mut db, "PRAGMA auto_vacuum")?, vec![vec!["1"]]);
+	assert_eq!(select(&mut db, "SELECT * FROM items")?, vec![vec!["1", "one"], vec!["2", "two"]]);
+
+	// Writing would leave its pointer-map pages out of date
+	for sql in [
+		"INSERT INTO items VALUES (3, 'three')",
+		"UPDATE items SET name = 'uno' WHERE id = 1",
+		"DELETE FROM items",
+		"CREATE TABLE more (id INTEGER)",
+		"DROP TABLE items",
+	] {
+		assert!(matches!(db.execute(sql), Err(Error::NotSupported(_))), "{}", sql);
+	}
+	assert_eq!(select(&mut db, "SELECT * FROM items")?, vec![vec!["1", "one"], vec!["2", "two"]]);
+	db.close()?;
+	if let Some(output) = sqlite3(&path, "PRAGMA integrity_check; SELECT count(*) FROM items") {
+		assert_eq!(output, "ok\n2\n");
+	}
+
+	// VACUUM rewrites it without pointer-map pages, after which it is writable
+	let mut db = Database::open(&path)?;
+	db.execute("VACUUM")?;
+	assert_eq!(select(&mut db, "PRAGMA auto_vacuum")?, vec![vec!["0"]]);
+	db.execute("INSERT INTO items VALUES (3, 'three')")?;
+	db.close()?;
+	if let Some(output) = sqlite3(&path, "PRAGMA integrity_check; PRAGMA auto_vacuum; SELECT name FROM items") {
+		assert_eq!(output, "ok\n0\none\ntwo\nthree\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_create_table_with_a_schema_name() -> Result<()> {
+	let path = copy_fixture("sqlite3_altered.db", "duplicate");
+	let mut db = Database::open(&path)?;
+	for sql in [
+		"CREATE TABLE wr (k TEXT)",
+		"CREATE TABLE v1 (a INTEGER)",
+		"CREATE TABLE ITEMS (a INTEGER)",
+		"CREATE TABLE VT_Node (a INTEGER)",
+	] {
+		assert!(matches!(db.execute(sql), Err(Error::Constraint(_))), "{}", sql);
+	}
+	db.close()?;
+
+	if let Some(output) = sqlite3(&path, "PRAGMA integrity_check; SELECT count(*) FROM sqlite_schema") {
+		assert_eq!(output, "ok\n7\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_quoted_names() -> Result<()> {
+	let path = copy_fixture("sqlite3_quoted.db", "quoted");
+	let mut db = Database::open(&path)?;
+	assert_eq!(
+		select(&mut db, "SELECT \"first name\" FROM \"My Table\" ORDER BY \"order\"")?,
+		vec![vec!["Alan"], vec!["Ada"]]
+	);
+	db.execute("INSERT INTO \"My Table\" VALUES (3, 'Grace', 3)")?;
+	db.execute("UPDATE \"my table\" SET \"first name\" = 'Ada L.' WHERE id = 1")?;
+	db.execute("DELETE FROM \"MY TABLE\" WHERE \"order\" = 1")?;
+	db.close()?;
+
+	if let Some(output) = sqlite3(&path, "PRAGMA integrity_check; SELECT \"first name\" FROM \"My Table\"") {
+		assert_eq!(output, "ok\nAda L.\nGrace\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_table_names_ignore_case() -> Result<()> {
+	let path = copy_fixture("sqlite3_sample.db", "names");
+	let mut db = Database::open(&path)?;
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM USERS")?, vec![vec!["300"]]);
+	db.execute("INSERT INTO Users VALUES (301, 'user301', 30, 1.5, NULL)")?;
+	db.execute("UPDATE uSeRs SET age = 31 WHERE id = 301")?;
+	db.execute("DELETE FROM USERS WHERE id = 300")?;
+	assert_eq!(
+		select(&mut db, "SELECT Users.name, NOTES.note_id FROM USERS INNER JOIN Notes ON users.id = notes.user_id")?,
+		vec![vec!["user1", "1"], vec!["user2", "2"]]
+	);
+	db.execute("DROP TABLE NOTES")?;
+	assert!(matches!(db.execute("SELECT * FROM notes"), Err(Error::NotFound(_))));
+	db.close()?;
+
+	if let Some(output) = sqlite3(&path, "PRAGMA integrity_check; SELECT age FROM users WHERE id > 299; SELECT name FROM sqlite_schema WHERE tbl_name = 'notes'") {
+		assert_eq!(output, "ok\n31\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_create_sqlite3_database() -> Result<()> {
+	let (mut db, path) = create_database("create")?;
+	db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE, age INTEGER, score REAL)")?;
+	for i in 1..=300 {
+		db.execute(&format!("INSERT INTO users VALUES ({}, 'user{}', {}, {})", i, i, i % 90, i))?;
+	}
+	db.close()?;
+
+	let data = std::fs::read(&path).unwrap();
+	assert_eq!(&data[..16], b"SQLite format 3\0");
+	let page_size = u16::from_be_bytes([data[16], data[17]]) as usize;
+	let change_counter = header_u32(&data, 24);
+	assert_eq!(header_u32(&data, 28) as usize * page_size, data.len());
+	assert_eq!(header_u32(&data, 40), 1, "schema cookie");
+	assert_eq!(header_u32(&data, 44), 4, "schema format");
+	assert_eq!(header_u32(&data, 92), change_counter, "version-valid-for");
+
+	let mut db = Database::open(&path)?;
+	let rows = select(&mut db, "SELECT * FROM users")?;
+	assert_eq!(rows.len(), 300);
+	assert_eq!(rows[9], vec!["10", "user10", "10", "10.0"]);
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_constraints() -> Result<()> {
+	let (mut db, _) = create_database("constraints")?;
+	db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE)")?;
+	db.execute("INSERT INTO users VALUES (1, 'alice')")?;
+	db.execute("INSERT INTO users VALUES (NULL, 'bob')")?;
+	assert!(db.execute("INSERT INTO users VALUES (1, 'carol')").is_err());
+	assert!(db.execute("INSERT INTO users VALUES (3, 'alice')").is_err());
+	assert!(db.execute("INSERT INTO users VALUES ('x', 'dave')").is_err());
+
+	// Moving a row to a new INTEGER PRIMARY KEY changes its rowid
+	db.execute("UPDATE users SET id = 10 WHERE name = 'bob'")?;
+	let rows = select(&mut db, "SELECT * FROM users")?;
+	assert_eq!(rows, vec![vec!["1", "alice"], vec!["10", "bob"]]);
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_tool_reads_our_files() -> Result<()> {
+	let (mut db, path) = create_database("tool")?;
+	db.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, qty INTEGER, price REAL)")?;
+	for i in 1..=500 {
+		db.execute(&format!("INSERT INTO items VALUES ({}, 'item{}', '{}', {})", i, i, i % 7, i))?;
+	}
+	db.execute("UPDATE items SET qty = 100 WHERE id = 5")?;
+	db.execute("DELETE FROM items WHERE qty = 0")?;
+	db.close()?;
+
+	let Some(check) = sqlite3(&path, "PRAGMA integrity_check") else {
+		return Ok(());
+	};
+	assert_eq!(check.trim(), "ok");
+	let summary = sqlite3(&path, "SELECT count(*), sum(qty), typeof(qty), typeof(price) FROM items").unwrap();
+	assert_eq!(summary.trim(), "429|1592|integer|real");
+	assert_eq!(sqlite3(&path, "SELECT id FROM items WHERE name = 'item5'").unwrap().trim(), "5");
+
+	// Changes made by SQLite read back here
+	sqlite3(&path, "INSERT INTO items (name, qty) VALUES ('added', 3)").unwrap();
+	let mut db = Database::open(&path)?;
+	let rows = select(&mut db, "SELECT * FROM items WHERE name = 'added'")?;
+	assert_eq!(rows, vec![vec!["501", "added", "3", "NULL"]]);
+	Ok(())
+}
+
+#[test]
+fn test_write_sqlite3_fixture() -> Result<()> {
+	let path = copy_fixture("sqlite3_sample.db", "write");
+	let mut db = Database::open(&path)?;
+	db.execute("INSERT INTO users VALUES (NULL, 'newcomer', 30, 2.5, NULL)")?;
+	db.execute("UPDATE users SET name = 'renamed' WHERE id = 7")?;
+	db.execute("DELETE FROM users WHERE age > 80")?;
+	let rows = select(&mut db, "SELECT * FROM users WHERE name = 'newcomer'")?;
+	assert_eq!(rows, vec![vec!["301", "newcomer", "30", "2.5", "NULL"]]);
+	db.close()?;
+
+	// The name index must have followed every change
+	let Some(check) = sqlite3(&path, "PRAGMA integrity_check") else {
+		return Ok(());
+	};
+	assert_eq!(check.trim(), "ok");
+	let found = sqlite3(&path, "SELECT id FROM users INDEXED BY idx_users_name WHERE name = 'renamed'").unwrap();
+	assert_eq!(found.trim(), "7");
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_freelist() -> Result<()> {
+	let (mut db, path) = create_database("freelist")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT UNIQUE)")?;
+	db.execute("CREATE TABLE scratch (id INTEGER PRIMARY KEY, data TEXT)")?;
+	for i in 1..=1000 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+		db.execute(&format!("INSERT INTO scratch VALUES ({}, 'scratch data {}')", i, i))?;
+	}
+	db.execute("DELETE FROM logs WHERE id > 100")?;
+	db.execute("DROP TABLE scratch")?;
+	db.close()?;
+
+	let data = std::fs::read(&path).unwrap();
+	let size = data.len();
+	let free_pages = header_u32(&data, 36);
+	assert!(free_pages > 10, "only {} free pages", free_pages);
+	assert_ne!(header_u32(&data, 32), 0);
+
+	// SQLite checks that every page is either in use or on the freelist
+	if let Some(check) = sqlite3(&path, "PRAGMA integrity_check; PRAGMA freelist_count") {
+		assert_eq!(check, format!("ok\n{}\n", free_pages));
+	}
+
+	// New rows fill free pages before the file grows
+	let mut db = Database::open(&path)?;
+	for i in 101..=600 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	db.close()?;
+	assert_eq!(std::fs::read(&path).unwrap().len(), size);
+	if let Some(check) = sqlite3(&path, "PRAGMA integrity_check") {
+		assert_eq!(check.trim(), "ok");
+	}
+	Ok(())
+}
@@ -0,0 +1,216 @@
+/// Tests for transactions and savepoints on database files
+
+mod common;
+
+use common::fresh_path;
+use epiloglite::{Database, ExecutionResult, FileFormat, Result, ValueType};
+
+fn count(db: &mut Database, table: &str) -> Result<ValueType> {
+	match db.execute(&format!("SELECT COUNT(*) FROM {}", table))? {
+		ExecutionResult::Select { rows, .. } => Ok(rows[0][0].clone()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+#[test]
+fn test_changes_reach_disk_on_commit() -> Result<()> {
+	for (name, format) in [("commit_eplite", FileFormat::EPLiteV1), ("commit_sqlite3", FileFormat::SQLiteV3)] {
+		let path = fresh_path(name);
+		let mut db = Database::create_with_format(&path, format)?;
+		db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+		let committed = std::fs::read(&path).unwrap();
+
+		// Enough rows to overflow the page cache
+		db.execute("BEGIN")?;
+		for i in 1..=2000 {
+			db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+		}
+		assert_eq!(count(&mut db, "logs")?, ValueType::I64(2000));
+		assert_eq!(std::fs::read(&path).unwrap(), committed);
+
+		db.execute("COMMIT")?;
+		assert!(std::fs::read(&path).unwrap().len() > committed.len());
+		db.close()?;
+
+		let mut db = Database::open(&path)?;
+		assert_eq!(count(&mut db, "logs")?, ValueType::I64(2000));
+	}
+	Ok(())
+}
+
+#[test]
+fn test_rollback_restores_file_database() -> Result<()> {
+	let path = fresh_path("rollback");
+	let mut db = Database::open(&path)?;
+	db.execute("CREATE TABLE logs (id INTEGER, message TEXT)")?;
+	db.execute("INSERT INTO logs VALUES (1, 'kept')")?;
+	let committed = std::fs::read(&path).unwrap();
+
+	db.execute("BEGIN")?;
+	for i in 2..=2000 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	db.execute("DROP TABLE logs")?;
+	db.execute("CREATE TABLE other (id INTEGER)")?;
+	db.execute("ROLLBACK")?;
+
+	assert_eq!(std::fs::read(&path).unwrap(), committed);
+	assert_eq!(count(&mut db, "logs")?, ValueType::I64(1));
+	assert!(db.execute("SELECT * FROM other").is_err());
+
+	// The connection carries on from the committed state
+	db.execute("INSERT INTO logs VALUES (2, 'added')")?;
+	db.close()?;
+	let mut db = Database::open(&path)?;
+	assert_eq!(count(&mut db, "logs")?, ValueType::I64(2));
+	Ok(())
+}
+
+#[test]
+fn test_crash_leaves_last_commit() -> Result<()> {
+	let path = fresh_path("crash");
+	let mut db = Database::open(&path)?;
+	db.execute("CREATE TABLE logs (id INTEGER, message TEXT)")?;
+	db.execute("BEGIN")?;
+	db.execute("INSERT INTO logs VALUES (1, 'first')")?;
+	db.execute("COMMIT")?;
+
+	// The process dies with a transaction open
+	db.execute("BEGIN")?;
+	for i in 2..=1000 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'lost')", i))?;
+	}
+	drop(db);
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(count(&mut db, "logs")?, ValueType::I64(1));
+	Ok(())
+}
+
+#[test]
+fn test_failed_statement_is_undone() -> Result<()> {
+	let path = fresh_path("statement");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3)?;
+	db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE)")?;
+	db.execute("INSERT INTO users VALUES (1, 'alice')")?;
+	db.execute("INSERT INTO users VALUES (2, 'bob')")?;
+
+	// The second row breaks the UNIQUE constraint after the first has changed
+	assert!(db.execute("UPDATE users SET name = 'carol'").is_err());
+	match db.execute("SELECT * FROM users")? {
+		ExecutionResult::Select { rows, .. } => {
+			assert_eq!(rows[0][1], ValueType::StringUtf8("alice".to_string()));
+			assert_eq!(rows[1][1], ValueType::StringUtf8("bob".to_string()));
+		}
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+	assert!(db.execute("VACUUM").is_ok());
+	db.execute("BEGIN")?;
+	assert!(db.execute("VACUUM").is_err());
+	Ok(())
+}
+
+fn ids(db: &mut Database) -> Result<Vec<i64>> {
+	match db.execute("SELECT * FROM items ORDER BY id")? {
+		ExecutionResult::Select { rows, .. } => Ok(rows
+			.iter()
+			.map(|row| match row[0] {
+				ValueType::I64(id) => id,
+				ref other => panic!("Expected an integer id, got {:?}", other),
+			})
+			.collect()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+#[test]
+fn test_nested_savepoints() -> Result<()> {
+	let path = fresh_path("savepoints");
+	let mut db = Database::open(&path)?;
+	db.execute("CREATE TABLE items (id INTEGER, name TEXT)")?;
+
+	db.execute("BEGIN")?;
+	db.execute("INSERT INTO items VALUES (1, 'a')")?;
+	db.execute("SAVEPOINT outer_sp")?;
+	db.execute("INSERT INTO items VALUES (2, 'b')")?;
+	db.execute("SAVEPOINT inner_sp")?;
+	db.execute("INSERT INTO items VALUES (3, 'c')")?;
+	db.execute("CREATE TABLE extra (id INTEGER)")?;
+
+	// Rolling back past inner_sp discards it too
+	db.execute("ROLLBACK TO outer_sp")?;
+	assert_eq!(ids(&mut db)?, vec![1]);
+	assert!(db.execute("SELECT * FROM extra").is_err());
+	assert!(db.execute("RELEASE inner_sp").is_err());
+
+	// outer_sp is still open and can be rolled back to again
+	db.execute("INSERT INTO items VALUES (4, 'd')")?;
+	db.execute("ROLLBACK TO SAVEPOINT outer_sp")?;
+	db.execute("INSERT INTO items VALUES (5, 'e')")?;
+	assert_eq!(ids(&mut db)?, vec![1, 5]);
+
+	// Releasing keeps the changes in the transaction, which ROLLBACK undoes
+	db.execute("RELEASE outer_sp")?;
+	assert!(db.execute("ROLLBACK TO outer_sp").is_err());
+	db.execute("ROLLBACK")?;
+	assert_eq!(ids(&mut db)?, Vec::<i64>::new());
+
+	db.execute("BEGIN")?;
+	db.execute("SAVEPOINT sp")?;
+	db.execute("INSERT INTO items VALUES (6, 'f')")?;
+	db.execute("RELEASE sp")?;
+	db.execute("COMMIT")?;
+	db.close()?;
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(ids(&mut db)?, vec![6]);
+	Ok(())
+}
+
+#[test]
+fn test_savepoint_starts_transaction() -> Result<()> {
+	let path = fresh_path("implicit");
+	let mut db = Database::open(&path)?;
+	db.execute("CREATE TABLE items (id INTEGER, name TEXT)")?;
+	let committed = std::fs::read(&path).unwrap();
+
+	db.execute("SAVEPOINT work")?;
+	assert!(db.execute("BEGIN").is_err());
+	db.execute("INSERT INTO items VALUES (1, 'a')")?;
+	db.execute("SAVEPOINT more")?;
+	db.execute("INSERT INTO items VALUES (2, 'b')")?;
+	db.execute("RELEASE more")?;
+	assert_eq!(std::fs::read(&path).unwrap(), committed);
+
+	// Releasing the savepoint that began the transaction commits it
+	db.execute("RELEASE work")?;
+	assert!(db.execute("COMMIT").is_err());
+	db.close()?;
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(ids(&mut db)?, vec![1, 2]);
+	Ok(())
+}
+
+#[test]
+fn test_failed_statement_keeps_transaction() -> Result<()> {
+	let path = fresh_path("statement_in_transaction");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3)?;
+	db.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT UNIQUE)")?;
+
+	db.execute("BEGIN")?;
+	db.execute("INSERT INTO items VALUES (1, 'a')")?;
+	db.execute("INSERT INTO items VALUES (2, 'b')")?;
+	assert!(db.execute("UPDATE items SET name = 'c'").is_err());
+	db.execute("INSERT INTO items VALUES (3, 'c')")?;
+	db.execute("COMMIT")?;
+
+	match db.execute("SELECT * FROM items")? {
+		ExecutionResult::Select { rows, .. } => {
+			let names: Vec<String> = rows.iter().map(|row| row[1].to_string()).collect();
+			assert_eq!(names, vec!["a", "b", "c"]);
+		}
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+	Ok(())
+}
@@ -0,0 +1,210 @@
+/// Tests for VACUUM, VACUUM INTO and auto-vacuum
+///
+/// SQLite 3 files are also checked with the sqlite3 command line tool when it
+/// is installed.
+
+mod common;
+
+use common::{fresh_path, select, sqlite3};
+use epiloglite::eplite::config::DatabaseConfig;
+use epiloglite::{Database, Error, FileFormat, Result};
+
+fn create(path: &str, format: FileFormat) -> Result<Database> {
+	Database::create_with_format(path, format)
+}
+
+fn pragma(db: &mut Database, name: &str) -> Result<u32> {
+	let rows = select(db, &format!("PRAGMA {}", name))?;
+	Ok(rows[0][0].parse().unwrap())
+}
+
+fn file_size(path: &str) -> u64 {
+	std::fs::metadata(path).unwrap().len()
+}
+
+/// Two tables of 1000 rows; dropping `scratch` frees about half the file
+fn fill(db: &mut Database) -> Result<()> {
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT UNIQUE)")?;
+	db.execute("CREATE TABLE scratch (id INTEGER PRIMARY KEY, data TEXT)")?;
+	for i in 1..=1000 {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+		db.execute(&format!("INSERT INTO scratch VALUES ({}, 'scratch data {}')", i, i))?;
+	}
+	Ok(())
+}
+
+#[test]
+fn test_vacuum_shrinks_file() -> Result<()> {
+	for (name, format) in [("eplite", FileFormat::EPLiteV1), ("sqlite3", FileFormat::SQLiteV3)] {
+		let path = fresh_path(name);
+		let mut db = create(&path, format)?;
+		fill(&mut db)?;
+		db.execute("DROP TABLE scratch")?;
+		db.execute("DELETE FROM logs WHERE id > 500")?;
+		let before = file_size(&path);
+		assert!(pragma(&mut db, "freelist_count")? > 10);
+
+		db.execute("VACUUM")?;
+		assert_eq!(pragma(&mut db, "freelist_count")?, 0);
+		let page_count = pragma(&mut db, "page_count")? as u64;
+		db.close()?;
+		let after = file_size(&path);
+		assert!(after * 2 < before, "{} bytes before, {} after", before, after);
+		assert_eq!(after % page_count, 0);
+
+		let mut db = Database::open(&path)?;
+		let rows = select(&mut db, "SELECT * FROM logs WHERE id = 500")?;
+		assert_eq!(rows.len(), 1);
+		assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["500"]]);
+		db.execute("INSERT INTO logs VALUES (501, 'message number 501')")?;
+		db.close()?;
+
+		if format == FileFormat::SQLiteV3 {
+			if let Some(check) = sqlite3(&path, "PRAGMA integrity_check; SELECT count(*) FROM logs") {
+				assert_eq!(check, "ok\n501\n");
+			}
+		}
+	}
+	Ok(())
+}
+
+#[test]
+fn test_vacuum_into() -> Result<()> {
+	for (name, format) in [("into_eplite", FileFormat::EPLiteV1), ("into_sqlite3", FileFormat::SQLiteV3)] {
+		let path = fresh_path(name);
+		let copy = fresh_path(&format!("{}_copy", name));
+		let mut db = create(&path, format)?;
+		fill(&mut db)?;
+		db.execute("DROP TABLE scratch")?;
+		let size = file_size(&path);
+
+		db.execute(&format!("VACUUM INTO '{}'", copy))?;
+		assert_eq!(file_size(&path), size);
+		assert!(file_size(&copy) < size);
+		// The output file must be new
+		assert!(db.execute(&format!("VACUUM INTO '{}'", copy)).is_err());
+		db.close()?;
+
+		let mut db = Database::open(&copy)?;
+		assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["1000"]]);
+		assert!(db.execute("SELECT * FROM scratch").is_err());
+		assert_eq!(pragma(&mut db, "freelist_count")?, 0);
+		db.close()?;
+
+		if format == FileFormat::SQLiteV3 {
+			if let Some(check) = sqlite3(&copy, "PRAGMA integrity_check; SELECT id FROM logs WHERE message = 'message number 77'") {
+				assert_eq!(check, "ok\n77\n");
+			}
+		}
+	}
+	Ok(())
+}
+
+#[test]
+fn test_full_auto_vacuum() -> Result<()> {
+	// A new EPLite database takes the mode from its configuration
+	let path = fresh_path("full_eplite");
+	let config = DatabaseConfig {
+		page_size: 1024,
+		auto_vacuum: true,
+		..DatabaseConfig::default()
+	};
+	let mut db = Database::open_with_config(&path, &config)?;
+	assert_eq!(pragma(&mut db, "auto_vacuum")?, 1);
+	fill(&mut db)?;
+	let full = file_size(&path);
+	db.execute("DROP TABLE scratch")?;
+	assert_eq!(pragma(&mut db, "freelist_count")?, 0);
+	db.execute("DELETE FROM logs WHERE id > 100")?;
+	assert_eq!(pragma(&mut db, "freelist_count")?, 0);
+	let page_count = pragma(&mut db, "page_count")? as u64;
+	db.close()?;
+	assert_eq!(file_size(&path), page_count * 1024);
+	assert!(file_size(&path) * 4 < full);
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(pragma(&mut db, "auto_vacuum")?, 1);
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["100"]]);
+
+	Ok(())
+}
+
+#[test]
+fn test_sqlite3_auto_vacuum_is_fixed() -> Result<()> {
+	// SQLite's auto-vacuum files need pointer-map pages, which we do not write
+	let path = fresh_path("fixed_sqlite3");
+	let mut db = create(&path, FileFormat::SQLiteV3)?;
+	for mode in ["FULL", "INCREMENTAL"] {
+		assert!(matches!(
+			db.execute(&format!("PRAGMA auto_vacuum = {}", mode)),
+			Err(Error::NotSupported(_))
+		));
+	}
+	db.execute("PRAGMA auto_vacuum = NONE")?;
+	assert_eq!(pragma(&mut db, "auto_vacuum")?, 0);
+
+	// Free pages stay on the freelist, as in SQLite
+	fill(&mut db)?;
+	db.execute("DROP TABLE scratch")?;
+	let free = pragma(&mut db, "freelist_count")?;
+	assert!(free > 5);
+	db.execute("PRAGMA incremental_vacuum")?;
+	assert_eq!(pragma(&mut db, "freelist_count")?, free);
+	db.close()?;
+
+	if let Some(check) = sqlite3(&path, "PRAGMA integrity_check; PRAGMA auto_vacuum") {
+		assert_eq!(check, "ok\n0\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_incremental_vacuum() -> Result<()> {
+	let path = fresh_path("incremental");
+	let mut db = create(&path, FileFormat::EPLiteV1)?;
+	db.execute("PRAGMA auto_vacuum = INCREMENTAL")?;
+	assert_eq!(pragma(&mut db, "auto_vacuum")?, 2);
+	fill(&mut db)?;
+	db.execute("DROP TABLE scratch")?;
+
+	// Free pages stay until asked for
+	let free = pragma(&mut db, "freelist_count")?;
+	let pages = pragma(&mut db, "page_count")?;
+	assert!(free > 5);
+	db.execute("PRAGMA incremental_vacuum(5)")?;
+	assert_eq!(pragma(&mut db, "freelist_count")?, free - 5);
+	assert_eq!(pragma(&mut db, "page_count")?, pages - 5);
+
+	db.execute("PRAGMA incremental_vacuum")?;
+	assert_eq!(pragma(&mut db, "freelist_count")?, 0);
+	assert_eq!(pragma(&mut db, "page_count")?, pages - free);
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["1000"]]);
+	db.close()?;
+	assert_eq!(file_size(&path), (pages - free) as u64 * 4096);
+	Ok(())
+}
+
+#[test]
+fn test_auto_vacuum_moves_overflow_pages() -> Result<()> {
+	let path = fresh_path("overflow");
+	let config = DatabaseConfig {
+		page_size: 1024,
+		auto_vacuum: true,
+		..DatabaseConfig::default()
+	};
+	let mut db = Database::open_with_config(&path, &config)?;
+	fill(&mut db)?;
+	// A value on a chain of overflow pages near the end of the file
+	db.execute("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)")?;
+	db.execute(&format!("INSERT INTO notes VALUES (1, '{}')", "x".repeat(5000)))?;
+	let size = file_size(&path);
+	db.execute("DROP TABLE scratch")?;
+	assert_eq!(pragma(&mut db, "freelist_count")?, 0);
+	db.close()?;
+	assert!(file_size(&path) < size);
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(select(&mut db, "SELECT body FROM notes")?, vec![vec!["x".repeat(5000)]]);
+	assert_eq!(select(&mut db, "SELECT COUNT(*) FROM logs")?, vec![vec!["1000"]]);
+	Ok(())
+}
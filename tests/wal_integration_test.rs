@@ -1,7 +1,16 @@
 /// Integration tests for WAL mode
 
-// use epiloglite::eplite::persistence::pager::Pager;
+mod common;
+
+#[cfg(feature = "std")]
+use epiloglite::eplite::os::file::DefaultFile;
+#[cfg(feature = "std")]
+use epiloglite::eplite::persistence::journal::Journal;
+#[cfg(feature = "std")]
+use epiloglite::eplite::persistence::pager::Pager;
 use epiloglite::eplite::persistence::wal::{CheckpointMode, WalFrame, WalReader, WalWriter};
+#[cfg(feature = "std")]
+use epiloglite::JournalMode;
 
 #[test]
 fn test_wal_write_read_cycle() {
@@ -105,90 +114,71 @@ fn test_wal_concurrent_readers() {
 	assert_eq!(reader2.get_page(2).unwrap()[0], 2);
 }
 
+/// A pager on a new file in the temporary directory, with a rollback
+/// journal it can switch to WAL mode
+#[cfg(feature = "std")]
+fn open_pager(name: &str) -> (Pager, String) {
+	let path = common::fresh_path(name);
+	let journal = Journal::new(&path);
+	let file = DefaultFile::open(&path, true, true, true).unwrap();
+	let mut pager = Pager::with_file(4096, Box::new(file)).unwrap();
+	pager.set_journal(journal);
+	(pager, path)
+}
+
 #[test]
-#[ignore = "WAL integration with Pager not yet implemented"]
 #[cfg(feature = "std")]
 fn test_pager_wal_mode_integration() {
-	use epiloglite::eplite::os::file::DefaultFile;
-	use epiloglite::eplite::persistence::pager::Pager;
-
-	let temp_dir = std::env::temp_dir();
-	let db_path = temp_dir.join("test_wal_db.db");
-	let wal_path = temp_dir.join("test_wal_db.db-wal");
-
-	// Create pager with WAL mode
-	let db_file = DefaultFile::open(&db_path, true, true, true).unwrap();
-	let wal_file = DefaultFile::open(&wal_path, true, true, true).unwrap();
-
-	let mut pager = Pager::with_file(4096, Box::new(db_file)).unwrap();
-	// TODO: Implement JournalMode and set_journal_mode
-	// pager
-	// 	.set_journal_mode(JournalMode::Wal, Some(Box::new(wal_file)))
-	// 	.unwrap();
-
-	// Begin transaction and write some data
-	// TODO: Implement begin_transaction
-	// pager.begin_transaction().unwrap();
-	let _ = wal_file; // Suppress unused warning
-
-	let page1 = pager.get_page_mut(1).unwrap();
-	page1.write(0, b"Hello WAL!").unwrap();
-
-	let page2 = pager.get_page_mut(2).unwrap();
-	page2.write(0, b"WAL Mode Works!").unwrap();
-
-	// Commit
-	// TODO: Implement commit_transaction
-	// pager.commit_transaction().unwrap();
-
-	// Verify data is accessible
-	let page1 = pager.get_page(1).unwrap();
-	assert_eq!(&page1.data[0..10], b"Hello WAL!");
-
-	// Cleanup
-	drop(pager);
-	let _ = std::fs::remove_file(&db_path);
-	let _ = std::fs::remove_file(&wal_path);
+	let (mut pager, path) = open_pager("mode");
+	assert_eq!(pager.set_journal_mode(JournalMode::Wal).unwrap(), JournalMode::Wal);
+
+	pager.begin_transaction().unwrap();
+	let first = pager.allocate_page().unwrap();
+	pager.get_page_mut(first).unwrap().write(0, b"Hello WAL!").unwrap();
+	let second = pager.allocate_page().unwrap();
+	pager.get_page_mut(second).unwrap().write(0, b"WAL Mode Works!").unwrap();
+	pager.commit_transaction().unwrap();
+
+	// The commit went to the log, leaving the database file as it was
+	let wal_path = format!("{}-wal", path);
+	assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+	assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+	assert_eq!(&pager.get_page(first).unwrap().data[0..10], b"Hello WAL!");
+	assert_eq!(&pager.get_page(second).unwrap().data[0..15], b"WAL Mode Works!");
+
+	// Closing checkpoints the log into the file and deletes it
+	pager.close().unwrap();
+	assert!(std::fs::metadata(&wal_path).is_err());
+	let data = std::fs::read(&path).unwrap();
+	assert_eq!(&data[4096..4096 + 15], b"WAL Mode Works!");
+	let _ = std::fs::remove_file(&path);
 }
 
 #[test]
-#[ignore = "WAL checkpoint integration with Pager not yet implemented"]
 #[cfg(feature = "std")]
 fn test_checkpoint_integration() {
-	use epiloglite::eplite::os::file::DefaultFile;
-	use epiloglite::eplite::persistence::pager::Pager;
-
-	let temp_dir = std::env::temp_dir();
-	let db_path = temp_dir.join("test_checkpoint.db");
-	let wal_path = temp_dir.join("test_checkpoint.db-wal");
-
-	// Create pager with WAL mode
-	let db_file = DefaultFile::open(&db_path, true, true, true).unwrap();
-	let wal_file = DefaultFile::open(&wal_path, true, true, true).unwrap();
-
-	let mut pager = Pager::with_file(4096, Box::new(db_file)).unwrap();
-	// TODO: Implement JournalMode and set_journal_mode
-	// pager
-	// 	.set_journal_mode(JournalMode::Wal, Some(Box::new(wal_file)))
-	// 	.unwrap();
-
-	// Write some data
-	// TODO: Implement begin_transaction
-	// pager.begin_transaction().unwrap();
-	let page = pager.get_page_mut(1).unwrap();
-	page.write(0, b"Test Data").unwrap();
-	// TODO: Implement commit_transaction
-	// pager.commit_transaction().unwrap();
-
-	// Perform checkpoint
-	// TODO: Implement checkpoint
-	// pager.checkpoint(CheckpointMode::Full).unwrap();
-	let _ = (wal_file, CheckpointMode::Full); // Suppress unused warnings
-
-	// Cleanup
-	drop(pager);
-	let _ = std::fs::remove_file(&db_path);
-	let _ = std::fs::remove_file(&wal_path);
+	let (mut pager, path) = open_pager("checkpoint");
+	// Outside WAL mode there is nothing to checkpoint
+	assert!(pager.checkpoint(CheckpointMode::Full).unwrap().is_none());
+	pager.set_journal_mode(JournalMode::Wal).unwrap();
+
+	for value in [b"Test Data", b"More Data"] {
+		pager.begin_transaction().unwrap();
+		if pager.page_count() == 0 {
+			pager.allocate_page().unwrap();
+		}
+		pager.get_page_mut(1).unwrap().write(0, value).unwrap();
+		pager.commit_transaction().unwrap();
+	}
+
+	let result = pager.checkpoint(CheckpointMode::Full).unwrap().unwrap();
+	assert_eq!(result.wal_frames, 2);
+	assert_eq!(result.checkpointed_frames, 2);
+	assert!(result.completed);
+	assert_eq!(&std::fs::read(&path).unwrap()[0..9], b"More Data");
+
+	pager.close().unwrap();
+	let _ = std::fs::remove_file(&path);
 }
 
 #[test]
@@ -0,0 +1,414 @@
+/// Tests for WAL mode on database files
+///
+/// SQLite 3 files are also checked with the sqlite3 command line tool when it
+/// is installed.
+
+mod common;
+
+use common::{fresh_path, sqlite3};
+use epiloglite::eplite::config::DatabaseConfig;
+use epiloglite::{CheckpointMode, Database, Error, ExecutionResult, FileFormat, Result, ValueType};
+use std::path::Path;
+
+fn wal_path(path: &str) -> String {
+	format!("{}-wal", path)
+}
+
+fn file_size(path: &str) -> u64 {
+	std::fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+fn value(db: &mut Database, sql: &str) -> Result<String> {
+	match db.execute(sql)? {
+		ExecutionResult::Select { rows, .. } => Ok(rows[0][0].to_string()),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+fn row(db: &mut Database, sql: &str) -> Result<String> {
+	match db.execute(sql)? {
+		ExecutionResult::Select { rows, .. } => {
+			Ok(rows[0].iter().map(|value| value.to_string()).collect::<Vec<_>>().join("|"))
+		}
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+}
+
+fn fill(db: &mut Database, rows: std::ops::RangeInclusive<i64>) -> Result<()> {
+	for i in rows {
+		db.execute(&format!("INSERT INTO logs VALUES ({}, 'message number {}')", i, i))?;
+	}
+	Ok(())
+}
+
+#[test]
+fn test_commits_go_to_wal() -> Result<()> {
+	let path = fresh_path("commits");
+	let config = DatabaseConfig {
+		enable_wal: true,
+		wal_autocheckpoint: 0,
+		..DatabaseConfig::default()
+	};
+	let mut db = Database::open_with_config(&path, &config)?;
+	assert_eq!(value(&mut db, "PRAGMA journal_mode")?, "wal");
+	let size = file_size(&path);
+
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=500)?;
+	assert_eq!(file_size(&path), size);
+	assert!(file_size(&wal_path(&path)) > 500 * 4096);
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "500");
+	match db.execute("SELECT * FROM logs WHERE id = 321")? {
+		ExecutionResult::Select { rows, .. } => assert_eq!(rows[0][1].to_string(), "message number 321"),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+
+	// Closing checkpoints the log into the database file and deletes it
+	db.close()?;
+	assert!(!Path::new(&wal_path(&path)).exists());
+	assert!(file_size(&path) > size);
+
+	// The file stays in WAL mode
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "PRAGMA journal_mode")?, "wal");
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "500");
+	Ok(())
+}
+
+#[test]
+fn test_commits_write_only_changed_pages() -> Result<()> {
+	let path = fresh_path("changed_pages");
+	let mut db = Database::open(&path)?;
+	db.execute("PRAGMA journal_mode = WAL")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	db.execute("BEGIN")?;
+	fill(&mut db, 1..=5000)?;
+	db.execute("COMMIT")?;
+	assert!(value(&mut db, "PRAGMA page_count")?.parse::<u32>().unwrap() > 30);
+	db.execute("PRAGMA wal_checkpoint(TRUNCATE)")?;
+
+	// However large the table, a commit logs the leaf it changed and the
+	// header page
+	db.execute("UPDATE logs SET message = 'changed' WHERE id = 2500")?;
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint")?, "0|2|2");
+	db.execute("DELETE FROM logs WHERE id = 10")?;
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint")?, "0|2|2");
+	fill(&mut db, 5001..=5001)?;
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint")?, "0|2|2");
+	Ok(())
+}
+
+#[test]
+fn test_auto_checkpoint() -> Result<()> {
+	let path = fresh_path("auto_checkpoint");
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "PRAGMA journal_mode = WAL")?, "wal");
+	assert_eq!(value(&mut db, "PRAGMA wal_autocheckpoint")?, "1000");
+	assert_eq!(value(&mut db, "PRAGMA wal_autocheckpoint = 20")?, "20");
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	let size = file_size(&path);
+
+	fill(&mut db, 1..=1000)?;
+	// Each checkpoint starts the log over, so it never holds many frames
+	assert!(file_size(&wal_path(&path)) < 40 * 4096);
+	assert!(file_size(&path) > size);
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "1000");
+	Ok(())
+}
+
+#[test]
+fn test_rollback_in_wal_mode() -> Result<()> {
+	let path = fresh_path("rollback");
+	let mut db = Database::open(&path)?;
+	db.execute("PRAGMA journal_mode = WAL")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=10)?;
+	let wal_size = file_size(&wal_path(&path));
+
+	db.execute("BEGIN")?;
+	fill(&mut db, 11..=2000)?;
+	assert!(db.execute("PRAGMA journal_mode = DELETE").is_err());
+	db.execute("ROLLBACK")?;
+	assert_eq!(file_size(&wal_path(&path)), wal_size);
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "10");
+	Ok(())
+}
+
+#[test]
+fn test_leaving_wal_mode() -> Result<()> {
+	let path = fresh_path("leave");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3)?;
+	db.execute("PRAGMA journal_mode = WAL")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=100)?;
+
+	assert_eq!(value(&mut db, "PRAGMA journal_mode = DELETE")?, "delete");
+	assert!(!Path::new(&wal_path(&path)).exists());
+	fill(&mut db, 101..=110)?;
+	db.close()?;
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "PRAGMA journal_mode")?, "delete");
+	match db.execute("SELECT COUNT(*) FROM logs")? {
+		ExecutionResult::Select { rows, .. } => assert_eq!(rows[0][0], ValueType::I64(110)),
+		other => panic!("Expected Select result, got {:?}", other),
+	}
+	db.close()?;
+
+	if let Some(check) = sqlite3(&path, "PRAGMA journal_mode; SELECT count(*) FROM logs") {
+		assert_eq!(check, "delete\n110\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_sqlite_reads_our_wal() -> Result<()> {
+	let path = fresh_path("sqlite3");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3)?;
+	db.execute("PRAGMA journal_mode = WAL")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=300)?;
+	db.execute("DELETE FROM logs WHERE id > 250")?;
+	assert!(file_size(&wal_path(&path)) > 0);
+
+	// Without a checkpoint SQLite has to find the rows in the log
+	drop(db);
+	if let Some(check) = sqlite3(&path, "PRAGMA integrity_check; PRAGMA journal_mode; SELECT count(*), max(id) FROM logs") {
+		assert_eq!(check, "ok\nwal\n250|250\n");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_crash_keeps_commits_in_wal() -> Result<()> {
+	for (name, format) in [("recover_eplite", FileFormat::EPLiteV1), ("recover_sqlite3", FileFormat::SQLiteV3)] {
+		let path = fresh_path(name);
+		let mut db = Database::create_with_format(&path, format)?;
+		db.execute("PRAGMA journal_mode = WAL")?;
+		db.execute("PRAGMA wal_autocheckpoint = 0")?;
+		db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+		fill(&mut db, 1..=200)?;
+		let size = file_size(&path);
+		drop(db);
+
+		// Opening replays the log into the database file
+		let mut db = Database::open(&path)?;
+		assert!(file_size(&path) > size);
+		assert_eq!(file_size(&wal_path(&path)), 0);
+		assert_eq!(value(&mut db, "PRAGMA journal_mode")?, "wal");
+		assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "200");
+		fill(&mut db, 201..=210)?;
+		db.close()?;
+
+		let mut db = Database::open(&path)?;
+		assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "210");
+	}
+	Ok(())
+}
+
+#[test]
+fn test_torn_commit_is_discarded() -> Result<()> {
+	let path = fresh_path("torn");
+	let mut db = Database::open(&path)?;
+	db.execute("PRAGMA journal_mode = WAL")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=50)?;
+	drop(db);
+
+	// The crash cut the last commit's final frame short
+	let wal = std::fs::read(wal_path(&path)).unwrap();
+	std::fs::write(wal_path(&path), &wal[..wal.len() - 100]).unwrap();
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "49");
+
+	// A log with a damaged header holds no commits
+	fill(&mut db, 50..=60)?;
+	drop(db);
+	let mut wal = std::fs::read(wal_path(&path)).unwrap();
+	wal[20] ^= 0xff;
+	std::fs::write(wal_path(&path), &wal).unwrap();
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "49");
+	Ok(())
+}
+
+#[test]
+fn test_pragma_wal_checkpoint() -> Result<()> {
+	let path = fresh_path("pragma_checkpoint");
+	let mut db = Database::open(&path)?;
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint")?, "0|-1|-1");
+	db.execute("PRAGMA journal_mode = WAL")?;
+	db.execute("PRAGMA wal_autocheckpoint = 0")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=3)?;
+	let size = file_size(&path);
+	let log = row(&mut db, "PRAGMA wal_checkpoint(PASSIVE)")?;
+	let frames: Vec<i64> = log.split('|').map(|count| count.parse().unwrap()).collect();
+	assert!(frames[1] >= 4);
+	assert_eq!(frames[1], frames[2]);
+	assert!(file_size(&path) > size);
+
+	// The log keeps its frames until the next commit starts it over
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint(restart)")?, log);
+	db.execute("INSERT INTO logs VALUES (4, 'after restart')")?;
+	// The insert wrote page 1, for the change counter, and the table's page
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint(FULL)")?, "0|2|2");
+	assert!(file_size(&wal_path(&path)) > 0);
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint(TRUNCATE)")?, "0|0|0");
+	assert_eq!(file_size(&wal_path(&path)), 0);
+	assert_eq!(row(&mut db, "PRAGMA wal_checkpoint")?, "0|0|0");
+	assert!(db.execute("PRAGMA wal_checkpoint(sometimes)").is_err());
+	drop(db);
+
+	// Everything was checkpointed, so the file alone holds every row
+	std::fs::remove_file(wal_path(&path)).unwrap();
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "4");
+	Ok(())
+}
+
+#[test]
+fn test_checkpoint_api() -> Result<()> {
+	let path = fresh_path("checkpoint_api");
+	let config = DatabaseConfig {
+		enable_wal: true,
+		wal_autocheckpoint: 0,
+		..DatabaseConfig::default()
+	};
+	let mut db = Database::open_with_config(&path, &config)?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=100)?;
+	db.execute("BEGIN")?;
+	fill(&mut db, 101..=110)?;
+
+	// Only committed frames reach the database file
+	let result = db.checkpoint(CheckpointMode::Full)?.unwrap();
+	assert!(result.completed);
+	assert_eq!(result.checkpointed_frames, result.wal_frames);
+	db.execute("ROLLBACK")?;
+	drop(db);
+	std::fs::remove_file(wal_path(&path)).unwrap();
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "100");
+	assert_eq!(value(&mut db, "PRAGMA journal_mode = DELETE")?, "delete");
+	assert!(db.checkpoint(CheckpointMode::Passive)?.is_none());
+	Ok(())
+}
+
+#[test]
+fn test_connections_share_the_log() -> Result<()> {
+	let path = fresh_path("shared");
+	let mut first = Database::open(&path)?;
+	first.execute("PRAGMA journal_mode = WAL")?;
+	first.execute("PRAGMA wal_autocheckpoint = 0")?;
+	let mut second = Database::open(&path)?;
+
+	// Commits from either connection show up in the other one
+	first.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut first, 1..=10)?;
+	assert_eq!(value(&mut second, "SELECT COUNT(*) FROM logs")?, "10");
+	fill(&mut second, 11..=20)?;
+	assert_eq!(value(&mut first, "SELECT COUNT(*) FROM logs")?, "20");
+	assert_eq!(row(&mut first, "SELECT * FROM logs WHERE id = 15")?, "15|message number 15");
+
+	// Only the last connection to close checkpoints and deletes the log
+	first.close()?;
+	assert!(file_size(&wal_path(&path)) > 0);
+	assert_eq!(value(&mut second, "SELECT COUNT(*) FROM logs")?, "20");
+	second.close()?;
+	assert!(!Path::new(&wal_path(&path)).exists());
+
+	let mut db = Database::open(&path)?;
+	assert_eq!(value(&mut db, "SELECT COUNT(*) FROM logs")?, "20");
+	Ok(())
+}
+
+#[test]
+fn test_reader_keeps_its_snapshot() -> Result<()> {
+	let path = fresh_path("snapshot");
+	let mut writer = Database::open(&path)?;
+	writer.execute("PRAGMA journal_mode = WAL")?;
+	writer.execute("PRAGMA wal_autocheckpoint = 0")?;
+	writer.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut writer, 1..=10)?;
+	let mut reader = Database::open(&path)?;
+
+	reader.execute("BEGIN")?;
+	assert_eq!(value(&mut reader, "SELECT COUNT(*) FROM logs")?, "10");
+	fill(&mut writer, 11..=15)?;
+	assert_eq!(value(&mut reader, "SELECT COUNT(*) FROM logs")?, "10");
+
+	// The checkpoint cannot copy frames the reader does not see yet
+	let result = writer.checkpoint(CheckpointMode::Full)?.unwrap();
+	assert!(!result.completed);
+	assert!(result.checkpointed_frames < result.wal_frames);
+	assert!(row(&mut writer, "PRAGMA wal_checkpoint(RESTART)")?.starts_with("1|"));
+	assert_eq!(value(&mut reader, "SELECT COUNT(*) FROM logs")?, "10");
+
+	reader.execute("COMMIT")?;
+	assert_eq!(value(&mut reader, "SELECT COUNT(*) FROM logs")?, "15");
+	let result = writer.checkpoint(CheckpointMode::Full)?.unwrap();
+	assert!(result.completed);
+	assert_eq!(result.checkpointed_frames, result.wal_frames);
+	Ok(())
+}
+
+#[test]
+fn test_one_writer_at_a_time() -> Result<()> {
+	let path = fresh_path("writers");
+	let mut first = Database::open(&path)?;
+	first.execute("PRAGMA journal_mode = WAL")?;
+	first.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	let mut second = Database::open(&path)?;
+
+	first.execute("BEGIN")?;
+	fill(&mut first, 1..=5)?;
+	assert!(matches!(fill(&mut second, 6..=6), Err(Error::Busy)));
+	first.execute("COMMIT")?;
+	fill(&mut second, 6..=6)?;
+
+	// A transaction that read an older snapshot cannot write on top of it
+	second.execute("BEGIN")?;
+	assert_eq!(value(&mut second, "SELECT COUNT(*) FROM logs")?, "6");
+	fill(&mut first, 7..=7)?;
+	assert!(matches!(fill(&mut second, 8..=8), Err(Error::Busy)));
+	second.execute("ROLLBACK")?;
+	fill(&mut second, 8..=8)?;
+	assert_eq!(value(&mut first, "SELECT COUNT(*) FROM logs")?, "8");
+
+	// Leaving WAL mode has to wait until the other connection is gone
+	assert!(matches!(first.execute("PRAGMA journal_mode = DELETE"), Err(Error::Busy)));
+	assert_eq!(value(&mut first, "PRAGMA journal_mode")?, "wal");
+	drop(second);
+	assert_eq!(value(&mut first, "PRAGMA journal_mode = DELETE")?, "delete");
+	assert!(!Path::new(&wal_path(&path)).exists());
+	Ok(())
+}
+
+#[test]
+fn test_sqlite_shares_our_wal() -> Result<()> {
+	let path = fresh_path("sqlite3_shared");
+	let mut db = Database::create_with_format(&path, FileFormat::SQLiteV3)?;
+	db.execute("PRAGMA journal_mode = WAL")?;
+	db.execute("PRAGMA wal_autocheckpoint = 0")?;
+	db.execute("CREATE TABLE logs (id INTEGER PRIMARY KEY, message TEXT)")?;
+	fill(&mut db, 1..=100)?;
+
+	// SQLite finds our commits through the wal-index while we are still open
+	let Some(check) = sqlite3(&path, "SELECT count(*) FROM logs") else {
+		return Ok(());
+	};
+	assert_eq!(check, "100\n");
+	sqlite3(&path, "INSERT INTO logs VALUES (101, 'from sqlite3')");
+	assert!(file_size(&wal_path(&path)) > 0);
+	assert_eq!(row(&mut db, "SELECT * FROM logs WHERE id = 101")?, "101|from sqlite3");
+	fill(&mut db, 102..=110)?;
+	db.close()?;
+
+	assert_eq!(
+		sqlite3(&path, "PRAGMA integrity_check; SELECT count(*) FROM logs"),
+		Some("ok\n110\n".to_string())
+	);
+	Ok(())
+}
@@ -0,0 +1,132 @@
+/// Tests for WHERE and ON conditions built from full expressions
+
+mod common;
+
+use common::{create_table, firsts, select};
+use epiloglite::{Database, ExecutionResult, Result};
+
+fn create() -> Result<Database> {
+	let mut db = Database::open(":memory:")?;
+	create_table(
+		&mut db,
+		"items",
+		"id INTEGER PRIMARY KEY, name TEXT, price INTEGER, stock INTEGER",
+		&[
+			"1, 'apple', 3, 10",
+			"2, 'a>=b', 5, 5",
+			"3, 'banana', 2, NULL",
+			"4, 'it''s = fine', 8, 1",
+			"5, 'cherry', NULL, 20",
+		],
+	)?;
+	Ok(db)
+}
+
+#[test]
+fn test_compound_conditions() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE price > 2 AND stock >= 5")?, vec!["1", "2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE price = 2 OR stock = 1")?, vec!["3", "4"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE NOT (price < 5 OR id = 5)")?, vec!["2", "4"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE id = 1 OR id = 2 AND price = 3")?, vec!["1"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE (id = 1 OR id = 2) AND price = 5")?, vec!["2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE id BETWEEN 2 AND 4 AND name LIKE 'b%'")?, vec!["3"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE id IN (1, 3, 5) AND stock IS NOT NULL")?, vec!["1", "5"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE stock IS NULL OR price IS NULL")?, vec!["3", "5"]);
+	Ok(())
+}
+
+#[test]
+fn test_arithmetic_and_column_comparisons() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE price * stock >= 25")?, vec!["1", "2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE price = stock")?, vec!["2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE stock - price > 3 * 2")?, vec!["1"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE price % 2 = 0 AND -price < -2")?, vec!["4"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE name || '!' = 'apple!'")?, vec!["1"]);
+	Ok(())
+}
+
+#[test]
+fn test_values_with_operator_characters() -> Result<()> {
+	let mut db = create()?;
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE name = 'a>=b'")?, vec!["2"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE name = 'it''s = fine'")?, vec!["4"]);
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE name <> 'a>=b' AND price < 4")?, vec!["1", "3"]);
+	assert_eq!(select(&mut db, "SELECT * FROM items WHERE id = 4")?[0][1], "it's = fine");
+	Ok(())
+}
+
+#[test]
+fn test_update_and_delete_conditions() -> Result<()> {
+	let mut db = create()?;
+	db.execute("UPDATE items SET stock = 0 WHERE price >= 5 OR name = 'apple'")?;
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE stock = 0")?, vec!["1", "2", "4"]);
+
+	match db.execute("DELETE FROM items WHERE stock = 0 AND NOT name LIKE '%>%'")? {
+		ExecutionResult::RowsAffected(count) => assert_eq!(count, 2),
+		other => panic!("Expected RowsAffected result, got {:?}", other),
+	}
+	assert_eq!(firsts(&mut db, "SELECT * FROM items")?, vec!["2", "3", "5"]);
+
+	// Unknown rows are neither deleted nor kept by mistake
+	db.execute("DELETE FROM items WHERE stock > 10")?;
+	assert_eq!(firsts(&mut db, "SELECT * FROM items")?, vec!["2", "3"]);
+	Ok(())
+}
+
+#[test]
+fn test_update_from_row_values() -> Result<()> {
+	let mut db = create()?;
+	db.execute("UPDATE items SET stock = stock + 1, Price = price * 2 WHERE stock < 10")?;
+	assert_eq!(
+		select(&mut db, "SELECT price, stock FROM items WHERE id IN (2, 4)")?,
+		vec![vec!["10", "6"], vec!["16", "2"]]
+	);
+
+	// Every SET expression reads the row as it was before the update
+	db.execute("UPDATE items SET price = stock, stock = price WHERE id = 1")?;
+	assert_eq!(select(&mut db, "SELECT price, stock FROM items WHERE id = 1")?, vec![vec!["10", "3"]]);
+	db.execute("UPDATE items SET name = upper_name WHERE id = 1").unwrap_err();
+	db.execute("UPDATE items SET missing = 1").unwrap_err();
+	assert_eq!(firsts(&mut db, "SELECT * FROM items WHERE name = 'apple'")?, vec!["1"]);
+
+	// VALUES has no row to read columns from
+	db.execute("INSERT INTO items VALUES (6, name, 1, 1)").unwrap_err();
+	Ok(())
+}
+
+#[test]
+fn test_join_conditions() -> Result<()> {
+	let mut db = create()?;
+	db.execute("CREATE TABLE orders (order_id INTEGER PRIMARY KEY, item_id INTEGER, quantity INTEGER)")?;
+	for (order_id, item_id, quantity) in [(1, 1, 2), (2, 1, 20), (3, 2, 1), (4, 9, 1)] {
+		db.execute(&format!("INSERT INTO orders VALUES ({}, {}, {})", order_id, item_id, quantity))?;
+	}
+
+	let rows = select(&mut db, "SELECT * FROM items JOIN orders ON items.id = orders.item_id AND quantity <= stock")?;
+	let orders: Vec<&str> = rows.iter().map(|row| row[4].as_str()).collect();
+	assert_eq!(orders, vec!["1", "3"]);
+
+	// WHERE filters the joined rows, and sees the NULLs a LEFT JOIN adds
+	let rows = select(&mut db, "SELECT * FROM items LEFT JOIN orders ON items.id = orders.item_id WHERE order_id IS NULL")?;
+	let items: Vec<&str> = rows.iter().map(|row| row[0].as_str()).collect();
+	assert_eq!(items, vec!["3", "4", "5"]);
+	let rows = select(&mut db, "SELECT * FROM items INNER JOIN orders ON items.id = orders.item_id WHERE orders.quantity > 1")?;
+	assert_eq!(rows.len(), 2);
+	Ok(())
+}
+
+#[test]
+fn test_invalid_conditions() -> Result<()> {
+	let mut db = create()?;
+	db.execute("CREATE TABLE orders (order_id INTEGER PRIMARY KEY, id INTEGER)")?;
+	assert!(db.execute("SELECT * FROM items WHERE missing = 1").is_err());
+	assert!(db.execute("SELECT * FROM items WHERE id = ").is_err());
+	assert!(db.execute("SELECT * FROM items WHERE (id = 1").is_err());
+	assert!(db.execute("SELECT * FROM items WHERE id BETWEEN 1").is_err());
+	assert!(db.execute("DELETE FROM items WHERE nothing > 0").is_err());
+	assert!(db.execute("SELECT * FROM items JOIN orders ON id = order_id").is_err());
+	assert_eq!(firsts(&mut db, "SELECT * FROM items")?.len(), 5);
+	Ok(())
+}